@@ -2,6 +2,10 @@
 
 uniform sampler2D t_Source;
 
+layout (std140) uniform cb_FragmentArgs {
+	float u_Threshold;
+};
+
 in vec2 v_TexCoord;
 out vec4 o_Color;
 
@@ -9,7 +13,7 @@ const float MAX_LUM = 10.0;
 
 vec4 lum_clip(float x, float y) {
 	vec4 src = texture(t_Source, vec2(x, y), 0);
-	float l = max((dot(vec3(0.2126, 0.7152, 0.0722), src.rgb) - 1.), 0.);
+	float l = max((dot(vec3(0.2126, 0.7152, 0.0722), src.rgb) - u_Threshold), 0.);
 //	return l <= 0 ? src : vec4(src.rgb / l * min(MAX_LUM, l), src.a);
 	return vec4(src.rgb * min(MAX_LUM, l), src.a);
 }
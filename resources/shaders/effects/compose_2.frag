@@ -3,10 +3,16 @@
 uniform sampler2D t_Source1;
 uniform sampler2D t_Source2;
 
+layout (std140) uniform cb_FragmentArgs {
+	float u_Bloom;
+};
+
 in vec2 v_TexCoord;
 out vec4 o_Color;
 
 void main() {
-	o_Color = texture(t_Source1, v_TexCoord, 0)
-			+ texture(t_Source2, v_TexCoord, 0);
+	vec4 hdr = texture(t_Source1, v_TexCoord, 0)
+			+ u_Bloom * texture(t_Source2, v_TexCoord, 0);
+	// Reinhard, so that bright colors roll off instead of clipping; the sRGB target applies the gamma
+	o_Color = vec4(hdr.rgb / (1.0 + hdr.rgb), hdr.a);
 }
@@ -2,12 +2,16 @@
 
 uniform sampler2D t_Source;
 
+layout (std140) uniform cb_FragmentArgs {
+	float u_Exposure;
+};
+
 in vec2 v_TexCoord;
 in float v_Exposure;
 out vec4 o_Color;
 
 void main() {
 	vec4 src = texture(t_Source, v_TexCoord, 0);
-	vec4 linear_color = v_Exposure * src;
+	vec4 linear_color = u_Exposure * v_Exposure * src;
 	o_Color = vec4(linear_color.rgb, src.a);
 }
@@ -5,25 +5,32 @@ layout (std140) uniform cb_CameraArgs {
 	uniform mat4 u_View;
 };
 
-layout (std140) uniform cb_ModelArgs {
-	uniform mat4 u_Model;
-};
-
 in vec3 a_Pos;
 in vec3 a_Normal;
 in vec3 a_Tangent;
 in vec2 a_TexCoord;
 
+// one per instance, the columns of the model matrix and the material
+in vec4 a_Model0;
+in vec4 a_Model1;
+in vec4 a_Model2;
+in vec4 a_Model3;
+in vec4 a_Emissive;
+in vec4 a_Effect;
+
 out VertexData {
 	vec4 Position;
 	vec3 Normal;
 	mat3 TBN;
 	vec2 TexCoord;
+	vec4 Emissive;
+	vec4 Effect;
 }v_Out;
 
 void main() {
-	v_Out.Position = u_Model * vec4(a_Pos, 1.0);
-	mat3 model = mat3(u_Model);
+	mat4 instance_model = mat4(a_Model0, a_Model1, a_Model2, a_Model3);
+	v_Out.Position = instance_model * vec4(a_Pos, 1.0);
+	mat3 model = mat3(instance_model);
 	vec3 normal = normalize(model * a_Normal);
 
 	v_Out.Normal = normal;
@@ -33,6 +40,8 @@ void main() {
 	v_Out.TBN = mat3(tangent, bitangent, normal);
 
 	v_Out.TexCoord = a_TexCoord;
+	v_Out.Emissive = a_Emissive;
+	v_Out.Effect = a_Effect;
 	gl_Position = u_Proj * u_View * v_Out.Position;
 }
 
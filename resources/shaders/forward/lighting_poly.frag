@@ -6,7 +6,13 @@ const float PI = 3.1415926535897932384626433832795;
 const float PI_2 = 1.57079632679489661923;
 
 layout (std140) uniform cb_FragmentArgs {
+	vec4 u_LightModel;
+	vec4 u_Ambient;
+	// the way the directional light shines, towards the scene
+	vec4 u_LightDirection;
+	vec4 u_DirectionalColor;
 	int u_LightCount;
+	int u_TwoSided;
 };
 
 struct Light {
@@ -19,16 +25,13 @@ layout (std140) uniform u_Lights {
 	Light light[MAX_NUM_TOTAL_LIGHTS];
 };
 
-layout (std140) uniform cb_MaterialArgs {
-	uniform vec4 u_Emissive;
-	uniform vec4 u_Effect;
-};
-
 in VertexData {
 	vec4 Position;
 	vec3 Normal;
 	mat3 TBN;
 	vec2 TexCoord;
+	vec4 Emissive;
+	vec4 Effect;
 }v_In;
 
 out vec4 o_Color;
@@ -42,19 +45,29 @@ void main() {
 	float dy = 2 * clamp(v_In.TexCoord.y, 0, 1) - 1;
 	float r = min(1, dx * dx + dy * dy);
 
-	float f = clamp(u_Effect.x * 2, 0, 1);
-	float e = clamp(abs(cos(r - u_Effect.y) + sin(dy - 2 * u_Effect.y)), 0, 1);
+	float f = clamp(v_In.Effect.x * 2, 0, 1);
+	float e = clamp(abs(cos(r - v_In.Effect.y) + sin(dy - 2 * v_In.Effect.y)), 0, 1);
 
-	vec4 color = u_Emissive * e * f;
+	vec4 color = v_In.Emissive * e * f;
 
 	vec3 normal = v_In.TBN * vec3(dx, dy, sqrt(1 - r));
+	// opposite windings come out facing away, turn them towards the viewer
+	if (u_TwoSided != 0 && normal.z < 0) {
+		normal = -normal;
+	}
+
+	// a base fill, so that the sides facing away from every light aren't pitch black
+	color += u_Ambient * kd;
+	float directional = max(0, dot(-normalize(u_LightDirection.xyz), normal));
+	color += u_DirectionalColor * kd * directional;
 
 	for (int i = 0; i < u_LightCount; i++) {
 		vec4 delta = light[i].center - v_In.Position;
 		float dist = length(delta);
 		float inv_dist = 1. / dist;
 		vec4 light_to_point_normal = delta * inv_dist;
-		float intensity = dot(light[i].propagation.xyz,
+		// global constant, linear, quadratic falloff scaling
+		float intensity = dot(light[i].propagation.xyz * u_LightModel.xyz,
 				vec3(1., inv_dist, inv_dist * inv_dist));
 		float lambert = max(0, dot(light_to_point_normal, vec4(normal, 0.0)));
 
@@ -0,0 +1,13 @@
+#version 150 core
+
+in vec4 v_Color;
+in float v_Edge;
+in float v_HalfWidth;
+
+out vec4 o_Color;
+
+void main() {
+	// distance in pixels from the line edge, fading over the last pixel
+	float coverage = clamp(v_HalfWidth + 0.5 - abs(v_Edge), 0.0, 1.0);
+	o_Color = vec4(v_Color.rgb, v_Color.a * coverage);
+}
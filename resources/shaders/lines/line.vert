@@ -0,0 +1,46 @@
+#version 150 core
+
+layout (std140) uniform cb_LineArgs {
+	uniform mat4 u_ProjView;
+	// width, height, 1 if positions are in pixels rather than world units
+	uniform vec4 u_Viewport;
+};
+
+in vec2 a_Start;
+in vec2 a_End;
+// x picks the end point, y the side of the line
+in vec2 a_Corner;
+in vec4 a_Color;
+in float a_Width;
+
+out vec4 v_Color;
+out float v_Edge;
+out float v_HalfWidth;
+
+vec4 to_clip(vec2 p) {
+	if (u_Viewport.z > 0.5) {
+		return vec4(p / u_Viewport.xy * 2.0 - 1.0, 0.0, 1.0);
+	}
+	return u_ProjView * vec4(p, 0.0, 1.0);
+}
+
+void main() {
+	vec4 c0 = to_clip(a_Start);
+	vec4 c1 = to_clip(a_End);
+	vec2 half_viewport = u_Viewport.xy * 0.5;
+	vec2 dir = (c1.xy / c1.w - c0.xy / c0.w) * half_viewport;
+	if (dot(dir, dir) < 1e-12) {
+		dir = vec2(1.0, 0.0);
+	}
+	vec2 normal = normalize(vec2(-dir.y, dir.x));
+
+	// one extra pixel on each side for the anti-aliased fringe
+	float extent = a_Width * 0.5 + 1.0;
+	vec4 c = mix(c0, c1, a_Corner.x);
+	vec2 offset = normal * a_Corner.y * extent / half_viewport;
+
+	v_Color = a_Color;
+	v_Edge = a_Corner.y * extent;
+	v_HalfWidth = a_Width * 0.5;
+	gl_Position = vec4(c.xy / c.w + offset, c.z / c.w, 1.0);
+}
@@ -85,6 +85,13 @@ impl input::EventMapper<glutin::Event> for GlutinEventMapper {
 				X -> X,
 				Y -> Y,
 				Z -> Z,
+				Space -> Space,
+				LBracket -> OpenBracket,
+				RBracket -> CloseBracket,
+				Minus -> Minus,
+				Equals -> Plus,
+				Subtract -> KpMinus,
+				Add -> KpPlus,
 				Escape -> Esc
 			]
 		}
@@ -111,6 +118,12 @@ impl input::EventMapper<glutin::Event> for GlutinEventMapper {
 			&glutin::Event::MouseInput(element_state, button) => {
 				mousemap(button).and_then(|key| Some(input::Event::Key(state_map(element_state), key)))
 			}
+			&glutin::Event::MouseWheel(glutin::MouseScrollDelta::LineDelta(_, dy), _) => {
+				Some(input::Event::scroll_lines(dy))
+			}
+			&glutin::Event::MouseWheel(glutin::MouseScrollDelta::PixelDelta(_, dy), _) => {
+				Some(input::Event::scroll_pixels(dy))
+			}
 			&glutin::Event::MouseMoved(x, y) => Some(input::Event::Mouse(Position::new(x as f32, y as f32))),
 			_ => None,
 		}
@@ -0,0 +1,94 @@
+//! Short lived labels rising from where something happened in the world, fading out as they go
+use core::geometry::Position;
+use backend::world::events::{Subscriber, WorldEvent};
+use frontend::render;
+
+/// Seconds a label stays up
+const TTL: f32 = 1.;
+/// World units a label rises by over its life
+const RISE: f32 = 4.;
+
+struct Floater {
+	position: Position,
+	text: String,
+	color: render::Rgba,
+	age: f32,
+	ttl: f32,
+}
+
+pub struct FloatingText {
+	floaters: Vec<Floater>,
+}
+
+impl FloatingText {
+	pub fn new() -> Self {
+		FloatingText { floaters: Vec::new() }
+	}
+
+	pub fn spawn(&mut self, position: Position, text: String, color: render::Rgba) {
+		self.floaters.push(Floater {
+			position: position,
+			text: text,
+			color: color,
+			age: 0.,
+			ttl: TTL,
+		});
+	}
+
+	/// Ages the labels by `dt`, dropping the ones past their time
+	pub fn update(&mut self, dt: f32) {
+		for floater in &mut self.floaters {
+			floater.age += dt;
+		}
+		self.floaters.retain(|floater| floater.age < floater.ttl);
+	}
+
+	#[cfg(test)]
+	pub fn len(&self) -> usize {
+		self.floaters.len()
+	}
+
+	pub fn render(&self, hud: &mut render::HudRenderer) {
+		for floater in &self.floaters {
+			let t = floater.age / floater.ttl;
+			let position = floater.position + Position::new(0., RISE * t);
+			let c = floater.color;
+			hud.colored_line(render::Anchor::World(position), &floater.text, [c[0], c[1], c[2], c[3] * (1. - t)]);
+		}
+	}
+}
+
+impl Subscriber for FloatingText {
+	fn on_event(&mut self, event: &WorldEvent) {
+		if let WorldEvent::Eaten { energy, position, .. } = *event {
+			self.spawn(position, format!("+{:.1}", energy), [0.4, 1., 0.4, 1.]);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::Position;
+	use backend::world::events::{Subscriber, WorldEvent};
+
+	#[test]
+	fn labels_are_dropped_after_their_ttl() {
+		let mut floating = FloatingText::new();
+		floating.on_event(&WorldEvent::Eaten {
+			resource: 1,
+			eater: 2,
+			energy: 0.3,
+			position: Position::new(0., 0.),
+		});
+		floating.on_event(&WorldEvent::Born(3));
+		assert_eq!(1, floating.len());
+		assert_eq!("+0.3", floating.floaters[0].text);
+		for _ in 0..59 {
+			floating.update(1. / 60.);
+		}
+		assert_eq!(1, floating.len());
+		floating.update(2. / 60.);
+		assert_eq!(0, floating.len());
+	}
+}
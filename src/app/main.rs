@@ -1,23 +1,80 @@
 use std::path;
 use frontend::render;
 use frontend::input::EventMapper;
-use frontend::render::Draw;
 use frontend::render::Renderer;
 use core::resource::filesystem::ResourceLoaderBuilder;
-use core::math::Directional;
 use app;
+use app::options::Options;
+use app::replay::Player;
+use backend::config::SimConfig;
 use app::ev::GlutinEventMapper;
 use glutin;
+use gfx_gl;
+use gfx_device_gl;
 use gfx_window_glutin;
 
-pub fn main_loop(minion_gene_pool: &str) {
-	const WIDTH: u32 = 1024;
-	const HEIGHT: u32 = 1024;
+fn read_pixels(device: &mut gfx_device_gl::Device, width: u16, height: u16) -> Vec<u8> {
+	let mut pixels = vec![0u8; width as usize * height as usize * 4];
+	unsafe {
+		device.with_gl(|gl| {
+			gl.ReadPixels(0,
+			              0,
+			              width as i32,
+			              height as i32,
+			              gfx_gl::RGBA,
+			              gfx_gl::UNSIGNED_BYTE,
+			              pixels.as_mut_ptr() as *mut _)
+		});
+	}
+	pixels
+}
+
+pub fn headless_loop(options: &Options, config: SimConfig, replay: Option<Player>) {
+	let res = ResourceLoaderBuilder::new()
+		.add(path::Path::new("resources"))
+		.build();
+	let window = options.window();
+	let mut app = app::App::new(window.width, window.height, 100.0, &res, &options.gene_pool);
+	app.start(options, config, replay);
+	if options.ticks.is_none() && options.until_population.is_none() && !options.until_extinct {
+		warn!("Running headless with no --ticks limit, only an interrupt will stop it");
+	}
+	let mut last = None;
+	while app.is_running() {
+		// with no frame rate to follow, every frame is a single tick
+		let r = app.step(app::SIM_STEP);
+		if options.until_population.map_or(false, |n| r.population >= n) ||
+		   (options.until_extinct && r.extinctions > 0) {
+			app.quit();
+		}
+		last = Some(r);
+	}
+	if let Some(r) = last {
+		info!("Ran {} ticks ({:.1}s simulated) in {:.1}s", r.tick_count, r.frame_elapsed, r.wall_clock_elapsed);
+		// the summary goes to stdout, for the scripts driving the run to pick up
+		println!("frames: {} ticks: {} extinctions: {} digest: {:x}",
+		         r.frame_count,
+		         r.tick_count,
+		         r.extinctions,
+		         app.state_digest());
+		println!("{}", app.census());
+	}
+	app.stop_input_recording();
+	app.autosave();
+}
 
+pub fn main_loop(options: &Options, config: SimConfig, replay: Option<Player>) {
+	let window_config = options.window();
 	let builder = glutin::WindowBuilder::new()
-		.with_title("Box2d + GFX".to_string())
-		.with_dimensions(WIDTH, HEIGHT)
+		.with_title(window_config.title.clone())
 		.with_vsync();
+	let builder = if window_config.fullscreen {
+		let monitor = glutin::get_primary_monitor();
+		let (width, height) = monitor.get_dimensions();
+		builder.with_dimensions(width, height).with_fullscreen(monitor)
+	} else {
+		builder.with_dimensions(window_config.width, window_config.height)
+	};
 
 	let (window, mut device, mut factory, mut frame_buffer, mut depth_buffer) =
 		gfx_window_glutin::init::<render::ColorFormat, render::DepthFormat>(builder);
@@ -38,9 +95,9 @@ pub fn main_loop(minion_gene_pool: &str) {
 		.unwrap();
 	let mapper = GlutinEventMapper::new();
 	// Create a new game and run it.
-	let mut app = app::App::new(w as u32, h as u32, 100.0, &res, minion_gene_pool);
+	let mut app = app::App::new(w as u32, h as u32, 100.0, &res, &options.gene_pool);
 
-	app.init();
+	app.start(options, config, replay);
 
 	'main: loop {
 		for event in window.poll_events() {
@@ -65,41 +122,49 @@ pub fn main_loop(minion_gene_pool: &str) {
 		// update and measure
 		let update_result = app.update();
 
-		let camera = render::Camera::ortho(app.camera.position(),
-		                                   app.viewport.scale,
-		                                   app.viewport.ratio);
+		let camera = app.camera.camera();
 
 		let environment = app.environment();
 
-		let light_positions = environment.light_positions.as_ref();
+		let emitters = environment.emitters.as_ref();
+		renderer.set_light_model(environment.light_model);
+		renderer.set_two_sided_lighting(environment.two_sided_lighting);
+		renderer.set_ambient(environment.ambient_color);
+		renderer.set_directional_light(environment.directional_light);
+		renderer.set_exposure(environment.exposure);
+		renderer.set_bloom(environment.bloom);
 		renderer.setup_frame(&camera,
 		                     environment.background_color,
 		                     environment.light_color,
-		                     light_positions);
-		// draw a frame
-		renderer.begin_frame();
-		// draw the scene
-		app.render(renderer);
-		// post-render effects and tone mapping
-		renderer.resolve_frame_buffer();
-
-		let r = update_result;
-		// draw some debug text on screen
-		renderer.draw_text(&format!("F: {} E: {:.3} FT: {:.2} SFT: {:.2} FPS: {:.1} P: {} E: {}",
-		                            r.frame_count,
-		                            r.frame_elapsed,
-		                            r.frame_time * 1000.0,
-		                            r.frame_time_smooth * 1000.0,
-		                            r.fps,
-		                            r.population,
-		                            r.extinctions),
-		                   [10, 10],
-		                   [1.0; 4]);
+		                     emitters);
+		{
+			let _render = app.profiler().scope("render");
+			// draw a frame
+			renderer.begin_frame();
+			// draw the scene
+			app.render(renderer);
+			// post-render effects and tone mapping
+			renderer.resolve_frame_buffer();
+		}
+
+		// the stats and labels go over the tone mapped frame
+		let draw_calls = renderer.draw_calls();
+		app.render_overlay(renderer, &update_result, draw_calls);
 
 		// push the commands
 		renderer.end_frame(&mut device);
 
+		if let Some(recorder) = app.recorder_mut() {
+			if recorder.wants_frame() {
+				let (w, h, _, _) = frame_buffer.get_dimensions();
+				recorder.push(w as u32, h as u32, read_pixels(&mut device, w, h));
+			}
+		}
+
 		window.swap_buffers().unwrap();
 		renderer.cleanup(&mut device);
 	}
+	app.stop_recording();
+	app.stop_input_recording();
+	app.autosave();
 }
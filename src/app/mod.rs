@@ -1,30 +1,85 @@
 mod main;
 mod ev;
+mod options;
+mod floating;
+mod replay;
 
-use core::util::Cycle;
+use core::util::{Cycle, History};
+use core::color::{ColorRamp, RAMP_NAMES};
 use core::geometry::*;
 use core::clock::*;
+use core::profiler::Profiler;
 use core::math;
-use core::math::Directional;
-use core::math::Relative;
 use core::math::Smooth;
 
 use core::resource::ResourceLoader;
 
 use backend::obj;
 use backend::obj::*;
+use backend::config::SimConfig;
+use backend::config::ConfigWatcher;
 use backend::world;
+use backend::world::gen;
 use backend::world::segment;
 use backend::world::agent;
+use backend::world::agent::TypedAgent;
+use backend::world::snapshot::WorldSnapshot;
+use backend::world::genome_log::GenomeLogger;
+use backend::world::events;
+use backend::world::diff::AgentDiff;
+use app::floating::FloatingText;
 use backend::systems;
 use backend::systems::System;
 
 use frontend::input;
 use frontend::render;
-
+use frontend::capture;
+use frontend::camera::{CameraController, CameraMode};
+
+use std::f32::consts;
+use std::path;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::process;
+use rand;
+use chrono::*;
 use cgmath;
 use cgmath::{Matrix4, SquareMatrix};
 
+/// ticks between two samples of the genome log, about a second of simulation
+const GENOME_LOG_INTERVAL: u32 = 60;
+
+/// Simulated seconds per tick, the same whatever the frame rate, so that the world plays out alike on any machine
+pub const SIM_STEP: f32 = 1. / 60.;
+/// Ticks a single frame may run to catch up; time owed past this is dropped, for the world to slow down
+/// rather than every frame falling further behind
+const MAX_TICKS_PER_FRAME: u32 = 5;
+/// how far out of view agents are still drawn, in world units
+const CULL_MARGIN: f32 = 10.;
+/// pixels along the longer side of the minimap
+const MINIMAP_SIZE: f32 = 200.;
+/// how far, in view units, the mouse may wander between press and release for it to still count as a click
+const CLICK_SLOP: f32 = 0.5;
+/// the selection outline, drawn over a dark halo
+const SELECTION_OUTLINE: Rgba = [1., 1., 1., 1.];
+const SELECTION_HALO: Rgba = [0., 0., 0., 0.8];
+/// pixels from the right edge of the window the inspector starts at
+const INSPECTOR_WIDTH: i32 = 320;
+/// the angle around its heading a sensor senses targets and threats within, all the way round
+const SENSOR_FOV: f32 = 2. * consts::PI;
+/// typed one key after the other, fills the selected minions up with energy
+const FEED_SEQUENCE: &'static [input::Key] = &[input::Key::I,
+                                               input::Key::D,
+                                               input::Key::D,
+                                               input::Key::Q,
+                                               input::Key::D];
+/// the longest pause between two keys of a sequence, in milliseconds
+const SEQUENCE_TIMEOUT_MS: u64 = 1000;
+/// frames the profiler averages the frame time over
+const FRAME_TIME_HISTORY: usize = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Event {
 	CamUp,
 	CamDown,
@@ -32,6 +87,12 @@ pub enum Event {
 	CamRight,
 
 	CamReset,
+	ToggleProjection,
+	Zoom(f32),
+	FollowSelected,
+	ToggleOverview,
+	ToggleMinimap,
+	CenterCamera(Position),
 
 	NextLight,
 	PrevLight,
@@ -39,26 +100,106 @@ pub enum Event {
 	NextBackground,
 	PrevBackground,
 
+	NextLightModel,
+	PrevLightModel,
+	ToggleTwoSidedLighting,
+
+	NextAmbient,
+	PrevAmbient,
+
+	NextExposure,
+	PrevExposure,
+
+	ToggleBloom,
+	NextBloomIntensity,
+
+	NextChargeRamp,
+
+	TogglePause,
+	StepTick,
+	ToggleAi,
+	SpeedUp,
+	SlowDown,
+
 	Reload,
+	ResetWorld,
 	DumpToFile,
+	DiffSelected,
+	ToggleRecording,
 	ToggleDebug,
+	ToggleProfiler,
+	NextSensorOverlay,
 
 	AppQuit,
 
 	NewMinion(Position),
 	RandomizeMinion(Position),
+	NewResource(Position),
+	NewEmitter(Position),
 
 	SelectMinion(Position, Id),
 	DeselectAll,
+	ShatterSelected,
+	CloneSelected,
+	FeedSelected,
 
 	BeginDrag(Position, Position),
 	Drag(Position, Position),
 	EndDrag(Position, Position, Velocity),
+
+	GrabAgent(Position, Id),
+	MoveGrabbed(Position),
+	Fling(Velocity),
+}
+
+/// What a modified left click puts in the world where it lands
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Spawn {
+	Minion,
+	Resource,
 }
 
 pub fn run(args: &[String]) {
-	let pool_file_name = args.get(1).map(|n| n.as_str()).unwrap_or("minion_gene_pool.csv");
-	main::main_loop(pool_file_name);
+	let options = match options::parse(&args[1..]) {
+		Ok(options) => options,
+		Err(options::Error::Help) => {
+			println!("{}", options::USAGE);
+			return;
+		}
+		Err(e) => {
+			let _ = writeln!(io::stderr(), "{}", e);
+			process::exit(2);
+		}
+	};
+	let config = match options.config {
+		Some(ref file) => {
+			match SimConfig::load(path::Path::new(file)) {
+				Ok(config) => config,
+				Err(e) => {
+					let _ = writeln!(io::stderr(), "Failed to read config {}: {}", file, e);
+					process::exit(1);
+				}
+			}
+		}
+		None => SimConfig::default(),
+	};
+	let replay = match options.replay {
+		Some(ref file) => {
+			match replay::Player::load(path::Path::new(file)) {
+				Ok(player) => Some(player),
+				Err(e) => {
+					let _ = writeln!(io::stderr(), "Failed to read recording {}: {}", file, e);
+					process::exit(1);
+				}
+			}
+		}
+		None => None,
+	};
+	if options.headless {
+		main::headless_loop(&options, config, replay);
+	} else {
+		main::main_loop(&options, config, replay);
+	}
 }
 
 pub struct Viewport {
@@ -78,6 +219,10 @@ impl Viewport {
 		}
 	}
 
+	fn size(&self) -> Size {
+		Size::new(self.width as f32, self.height as f32)
+	}
+
 	fn to_world(&self, pos: &Position) -> Position {
 		let dx = self.width as f32 / self.scale;
 		let tx = (pos.x - (self.width as f32 * 0.5)) / dx;
@@ -86,45 +231,59 @@ impl Viewport {
 	}
 }
 
-#[derive(Default)]
 pub struct Systems {
-	physics: systems::PhysicsSystem,
-	animation: systems::AnimationSystem,
-	game: systems::GameSystem,
-	ai: systems::AiSystem,
-	alife: systems::AlifeSystem,
-	audio: systems::AudioSystem,
+	scheduler: systems::SystemScheduler,
+	ai: systems::SystemId,
 }
 
-impl Systems {
-	fn systems(&mut self) -> Vec<&mut systems::System> {
-		vec![&mut self.animation as &mut systems::System,
-		     &mut self.audio as &mut systems::System,
-		     &mut self.game as &mut systems::System,
-		     &mut self.ai as &mut systems::System,
-		     &mut self.alife as &mut systems::System,
-		     &mut self.physics as &mut systems::System]
+impl Default for Systems {
+	fn default() -> Self {
+		let mut scheduler = systems::SystemScheduler::default();
+		scheduler.add("animation", systems::AnimationSystem::default(), 0);
+		scheduler.add("audio", systems::AudioSystem::default(), 1);
+		scheduler.add("game", systems::GameSystem::default(), 2);
+		let ai = scheduler.add("ai", systems::AiSystem::default(), 3);
+		scheduler.add("alife", systems::AlifeSystem::default(), 4);
+		scheduler.add("energy", systems::EnergySystem::default(), 5);
+		scheduler.add("reproduction", systems::ReproductionSystem::default(), 6);
+		scheduler.add("projectile", systems::ProjectileSystem::default(), 7);
+		// last, to move the bodies as everything before asked; Box2D isn't thread safe, so it stays on this thread
+		scheduler.add_local("physics", systems::PhysicsSystem::default(), 8);
+		Systems {
+			scheduler: scheduler,
+			ai: ai,
+		}
 	}
+}
 
+impl Systems {
 	fn for_each(&mut self, apply: &Fn(&mut systems::System)) {
-		for r in self.systems().as_mut_slice() {
-			apply(*r);
-		}
+		self.scheduler.for_each(apply);
 	}
 
 	fn from_world(&mut self, world: &world::World, apply: &Fn(&mut systems::System, &world::World)) {
-		for r in self.systems().as_mut_slice() {
-			apply(*r, &world);
-		}
+		self.scheduler.for_each(&|s| apply(s, world));
 	}
 
-	fn to_world(&mut self, mut world: &mut world::World, apply: &Fn(&mut systems::System, &mut world::World)) {
-		for r in self.systems().as_mut_slice() {
-			apply(*r, &mut world);
-		}
+	/// Runs a frame, overlapping the systems that don't depend on each other
+	fn update(&mut self, world: &mut world::World, dt: f32, profiler: &Profiler) {
+		self.scheduler.run_profiled(world, dt, profiler);
+	}
+
+	/// Switches the minions' minds off or back on; off, they hold on to their last intents as the world goes on
+	fn toggle_ai(&mut self) {
+		let enabled = self.scheduler.is_enabled(self.ai);
+		self.scheduler.set_enabled(self.ai, !enabled);
 	}
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum SensorOverlay {
+	Off,
+	Selected,
+	All,
+}
+
 bitflags! {
 	pub flags DebugFlags: u32 {
 		const DEBUG_TARGETS = 0x1,
@@ -135,31 +294,86 @@ bitflags! {
 pub struct App {
 	pub viewport: Viewport,
 	input_state: input::InputState,
+	sequences: input::SequenceMatcher,
 	wall_clock_start: SystemStopwatch,
 	frame_count: u32,
+	tick_count: u32,
 	frame_start: SystemStopwatch,
 	frame_elapsed: f32,
 	frame_smooth: math::MovingAverage<f32>,
+	/// the last frame times, for the profiler to show how steady they are
+	frame_times: History<f32>,
 	is_running: bool,
+	sim_clock: SimClock,
+	sim_bindings: input::KeyBindings<Event>,
+	spawn_bindings: input::KeyBindings<Spawn>,
+	/// quit after this many ticks
+	tick_limit: Option<u32>,
+	config_watcher: Option<ConfigWatcher>,
+	snapshot: WorldSnapshot,
+	/// how far between the previous and the current step frames are drawn
+	render_alpha: f32,
 	//
-	camera: math::Inertial<f32>,
+	camera: CameraController,
 	lights: Cycle<Rgba>,
 	backgrounds: Cycle<Rgba>,
+	light_models: Cycle<[f32; 4]>,
+	two_sided_lighting: bool,
+	/// the ambient fill, each with the directional light that sets the same mood
+	ambients: Cycle<(Rgba, render::DirectionalLight)>,
+	exposures: Cycle<f32>,
+	bloom: bool,
+	bloom_intensities: Cycle<f32>,
+	/// segments take their color from the charge when one of these is picked, their livery otherwise
+	charge_ramps: Cycle<Option<&'static str>>,
+	charge_ramp: Option<ColorRamp>,
 	//
+	gene_pool: gen::GenePool,
+	world_init: world::WorldInit,
 	world: world::World,
 	systems: Systems,
+	/// hear what happened in the world at the end of every tick
+	event_log: events::EventLog,
+	floating_text: FloatingText,
 	//
+	recorder: Option<capture::Recorder>,
+	/// keeps the inputs reaching the world, for `replay` to play them back
+	input_recorder: Option<replay::Recorder>,
+	replay: Option<replay::Player>,
+	genome_logger: Option<GenomeLogger>,
+	sensor_overlay: SensorOverlay,
+	minimap: bool,
+	/// the agent last picked, outlined and shown in the inspector
+	selected: Option<Id>,
+	/// the agent the left drag under way started on, grabbed once the mouse moves past `CLICK_SLOP`
+	pressed: Option<Id>,
 	debug_flags: DebugFlags,
+	/// times the systems and the frames while on, for the HUD to show
+	profiler: Profiler,
+	/// draw size of the meshes relative to their physical size, with per agent type overrides
+	render_scale: f32,
+	render_scale_overrides: HashMap<agent::AgentType, f32>,
 }
 
 pub struct Environment {
 	pub light_color: Rgba,
-	pub light_positions: Box<[Position]>,
+	pub light_model: [f32; 4],
+	pub two_sided_lighting: bool,
+	pub ambient_color: Rgba,
+	pub directional_light: render::DirectionalLight,
+	pub exposure: f32,
+	/// how strongly bright segments glow, `None` when bloom is off
+	pub bloom: Option<f32>,
+	/// the beacons with their ids, each lighting the scene
+	pub emitters: Box<[(Id, Position)]>,
 	pub background_color: Rgba,
 }
 
 pub struct Update {
 	pub frame_count: u32,
+	pub tick_count: u32,
+	/// how much faster than the wall clock the world runs, `None` when paused
+	pub sim_speed: Option<f32>,
 	pub wall_clock_elapsed: f32,
 	pub frame_elapsed: f32,
 	pub frame_time: f32,
@@ -172,33 +386,63 @@ pub struct Update {
 impl App {
 	pub fn new<R>(w: u32, h: u32, scale: f32, resource_loader: &R, minion_gene_pool: &str) -> Self
 		where R: ResourceLoader<u8> {
+		let gene_pool = world::World::load_gene_pool(resource_loader, minion_gene_pool);
 		App {
 			viewport: Viewport::rect(w, h, scale),
 			input_state: input::InputState::default(),
+			sequences: input::SequenceMatcher::new(FEED_SEQUENCE.len()),
 
-			camera: Self::init_camera(),
+			camera: CameraController::new(scale, w as f32 / h as f32),
 			lights: Self::init_lights(),
 			backgrounds: Self::init_backgrounds(),
-
-			world: world::World::new(resource_loader, minion_gene_pool),
+			light_models: Self::init_light_models(),
+			two_sided_lighting: true,
+			ambients: Self::init_ambients(),
+			exposures: Cycle::new(&[1., 2., 4., 0.25, 0.5]),
+			bloom: true,
+			bloom_intensities: Cycle::new(&[render::BLOOM, 2., 4., 0.25, 0.5]),
+			charge_ramps: Self::init_charge_ramps(),
+			charge_ramp: None,
+
+			world: world::World::new(gene_pool.clone(), rand::random()),
+			gene_pool: gene_pool,
+			world_init: world::WorldInit::default(),
 			// subsystems
 			systems: Systems::default(),
+			event_log: events::EventLog,
+			floating_text: FloatingText::new(),
 			// runtime and timing
 			frame_count: 0u32,
+			tick_count: 0u32,
 			frame_elapsed: 0.0f32,
 			frame_start: SystemStopwatch::new(),
 			wall_clock_start: SystemStopwatch::new(),
 			frame_smooth: math::MovingAverage::new(120),
+			frame_times: History::new(FRAME_TIME_HISTORY),
 			is_running: true,
+			sim_clock: SimClock::new(SIM_STEP, MAX_TICKS_PER_FRAME),
+			sim_bindings: Self::init_sim_bindings(),
+			spawn_bindings: Self::init_spawn_bindings(),
+			tick_limit: None,
+			config_watcher: None,
+			snapshot: WorldSnapshot::default(),
+			render_alpha: 1.,
 			// debug
+			recorder: None,
+			input_recorder: None,
+			replay: None,
+			genome_logger: None,
+			sensor_overlay: SensorOverlay::Off,
+			minimap: false,
+			selected: None,
+			pressed: None,
 			debug_flags: DebugFlags::empty(),
+			profiler: Profiler::default(),
+			render_scale: 1.,
+			render_scale_overrides: HashMap::new(),
 		}
 	}
 
-	fn init_camera() -> math::Inertial<f32> {
-		math::Inertial::new(10.0, 0.5, 0.5)
-	}
-
 	fn init_lights() -> Cycle<[f32; 4]> {
 		Cycle::new(&[[1.0, 1.0, 1.0, 1.0],
 		             [3.1, 3.1, 3.1, 1.0],
@@ -221,31 +465,411 @@ impl App {
 		             [0.01, 0.01, 0.01, 1.0]])
 	}
 
+	fn init_light_models() -> Cycle<[f32; 4]> {
+		// scales the constant, linear and quadratic light falloff terms
+		Cycle::new(&[[1.0, 1.0, 1.0, 1.0],
+		             [1.0, 0.0, 0.0, 1.0],
+		             [0.0, 2.0, 0.0, 1.0],
+		             [0.0, 0.0, 4.0, 1.0],
+		             [0.5, 2.0, 8.0, 1.0]])
+	}
+
+	fn init_ambients() -> Cycle<(Rgba, render::DirectionalLight)> {
+		let light = |direction: [f32; 3], color: Rgba| {
+			render::DirectionalLight {
+				direction: direction,
+				color: color,
+			}
+		};
+		Cycle::new(&[(render::AMBIENT, render::DIRECTIONAL_LIGHT),
+		             ([0.15, 0.15, 0.18, 1.0], light([0.4, -0.4, -1.0], [0.15, 0.16, 0.2, 1.0])),
+		             // a low warm sun from the side
+		             ([0.3, 0.25, 0.2, 1.0], light([1.0, -0.2, -0.5], [0.3, 0.22, 0.12, 1.0])),
+		             // moonlight from below
+		             ([0.02, 0.05, 0.1, 1.0], light([-0.3, 0.6, -1.0], [0.03, 0.05, 0.12, 1.0])),
+		             ([0., 0., 0., 1.0], light([0.4, -0.4, -1.0], [0., 0., 0., 1.0]))])
+	}
+
+	fn init_sim_bindings() -> input::KeyBindings<Event> {
+		use frontend::input::Key;
+		let mut bindings = input::KeyBindings::default();
+		bindings.bind(Event::TogglePause, &[Key::Space])
+			.bind(Event::StepTick, &[Key::CloseBracket])
+			.bind(Event::SpeedUp, &[Key::Plus])
+			.bind(Event::SpeedUp, &[Key::KpPlus])
+			.bind(Event::SlowDown, &[Key::Minus])
+			.bind(Event::SlowDown, &[Key::KpMinus]);
+		bindings
+	}
+
+	fn init_spawn_bindings() -> input::KeyBindings<Spawn> {
+		use frontend::input::Key;
+		let mut bindings = input::KeyBindings::default();
+		bindings.bind(Spawn::Minion, &[Key::LShift, Key::MouseLeft])
+			.bind(Spawn::Minion, &[Key::RShift, Key::MouseLeft])
+			.bind(Spawn::Resource, &[Key::LAlt, Key::MouseLeft])
+			.bind(Spawn::Resource, &[Key::RAlt, Key::MouseLeft]);
+		bindings
+	}
+
+	fn init_charge_ramps() -> Cycle<Option<&'static str>> {
+		let mut ramps = vec![None];
+		ramps.extend(RAMP_NAMES.iter().map(|&name| Some(name)));
+		Cycle::new(&ramps)
+	}
+
 	pub fn pick_minion(&self, pos: Position) -> Option<Id> {
-		self.systems.physics.pick(pos)
+		self.world.pick(pos)
+	}
+
+	/// Whether `pos` is somewhere an agent may be spawned, clicks past the fence are dropped
+	fn can_spawn_at(&self, pos: Position) -> bool {
+		self.world.extent.contains(pos)
 	}
 
 	fn randomize_minion(&mut self, pos: Position) {
-		self.world.randomize_minion(pos, None);
+		if self.can_spawn_at(pos) {
+			self.world.randomize_minion(pos, None);
+		}
 	}
 
 	fn new_minion(&mut self, pos: Position) {
-		self.world.new_minion(pos, None);
+		if self.can_spawn_at(pos) {
+			self.world.new_minion(pos, None);
+		}
+	}
+
+	fn new_resource(&mut self, pos: Position) {
+		if self.can_spawn_at(pos) {
+			self.world.new_resource(&Transform::from_position(pos), None);
+		}
 	}
 
 	fn deselect_all(&mut self) {
+		self.selected = None;
 		self.world.for_all_agents(&mut |agent| agent.state.deselect());
 	}
 
+	fn shatter_selected(&mut self) {
+		let selected = self.world
+			.agents(agent::AgentType::Minion)
+			.iter()
+			.filter(|&(_, a)| a.state.selected())
+			.map(|(&id, _)| id)
+			.collect::<Vec<_>>();
+		for id in selected {
+			self.world.shatter(id);
+		}
+	}
+
+	/// Puts a copy of each selected minion alongside it, handy to compare one creature against itself
+	fn clone_selected(&mut self) {
+		const OFFSET: f32 = 5.;
+		let selected = self.world
+			.agents(agent::AgentType::Minion)
+			.iter()
+			.filter(|&(_, a)| a.state.selected())
+			.map(|(&id, a)| (id, a.segments()[0].transform.position))
+			.collect::<Vec<_>>();
+		for (id, position) in selected {
+			self.world.clone_agent(id, position + Position::new(OFFSET, 0.));
+		}
+	}
+
+	/// Logs what sets each of the other selected minions apart from the first, e.g. a child from its parent
+	fn diff_selected(&self) -> Vec<AgentDiff> {
+		let selected = self.world
+			.agents(agent::AgentType::Minion)
+			.values()
+			.filter(|a| a.state.selected())
+			.collect::<Vec<_>>();
+		match selected.split_first() {
+			Some((first, others)) if !others.is_empty() => {
+				others.iter()
+					.map(|other| {
+						let diff = first.diff(other);
+						info!("Minion {} against {}:\n{}", first.id(), other.id(), diff);
+						diff
+					})
+					.collect()
+			}
+			_ => {
+				info!("Select two minions or more to compare them");
+				Vec::new()
+			}
+		}
+	}
+
+	/// Fills the selected minions up with energy, to watch one of them for longer
+	fn feed_selected(&mut self) {
+		let minions = self.world.agents_mut(agent::AgentType::Minion);
+		for (_, agent) in minions.iter_mut().filter(|&(_, ref a)| a.state.selected()) {
+			agent.state.absorb(::std::f32::MAX);
+		}
+	}
+
+	/// Keeps the first selected minion in view, or sets the camera free if none is selected
+	fn follow_selected(&mut self) {
+		let selected = self.world
+			.agents(agent::AgentType::Minion)
+			.iter()
+			.find(|&(_, a)| a.state.selected())
+			.map(|(&id, _)| id);
+		self.camera.set_mode(selected.map_or(CameraMode::Free, CameraMode::Follow));
+	}
+
 	fn select_minion(&mut self, id: Id) {
 		self.debug_flags |= DEBUG_TARGETS;
-		self.world.agent_mut(id).map(|a| a.state.toggle_selection());
+		let selected = self.world.agent_mut(id).map_or(false, |a| {
+			a.state.toggle_selection();
+			a.state.selected()
+		});
+		if selected {
+			self.selected = Some(id);
+		} else if self.selected == Some(id) {
+			self.selected = None;
+		}
+	}
+
+	/// What the inspector shows of the selected agent, `None` when nothing is selected or it's gone
+	fn inspector(&self) -> Option<Vec<String>> {
+		let agent = match self.selected.and_then(|id| self.world.agent(id)) {
+			Some(agent) => agent,
+			None => return None,
+		};
+		let brain = agent.brain();
+		let target = match agent.state.target() {
+			&Some(id) => format!("{} ({:?})", id, id.type_of()),
+			&None => "none".to_string(),
+		};
+		// the segments vary, the body as a whole feels like their average
+		let segments = agent.segments();
+		let mean = |f: &Fn(&obj::Material) -> f32| {
+			segments.iter().fold(0., |sum, s| sum + f(&s.material) / segments.len() as f32)
+		};
+		Some(vec![format!("{} ({:?})", agent.id(), agent.type_of()),
+		          format!("Energy: {:.1} ({:.0}%)", agent.state.energy(), agent.state.energy_ratio() * 100.),
+		          format!("Segments: {}", agent.segments().len()),
+		          format!("Friction: {:.2} Restitution: {:.2}", mean(&|m| m.friction), mean(&|m| m.restitution)),
+		          format!("Target: {}", target),
+		          format!("Hunger: {:.2} Haste: {:.2} Prudence: {:.2}", brain.hunger, brain.haste, brain.prudence),
+		          format!("Fear: {:.2} Rest: {:.2} Thrust: {:.2}", brain.fear, brain.rest, brain.thrust)])
+	}
+
+	/// Starts over from the gene pool loaded at launch, keeping the renderer and everything on the GPU.
+	/// Systems are rebuilt too, so that none of them holds on to ids from the old world.
+	pub fn reset(&mut self, seed: Option<u64>) {
+		let config = self.world.config().clone();
+		self.world = world::World::new(self.gene_pool.clone(), seed.unwrap_or_else(rand::random));
+		self.world.set_config(config);
+		self.systems = Systems::default();
+		self.snapshot.clear();
+		self.selected = None;
+		self.camera.reset();
+		self.init();
+		info!("Reset world with seed {}", self.world.seed());
+	}
+
+	/// Sets up the first world as the launch options ask, in place of `init`.
+	/// A `player` replays its recording, from the recorded seed and population rather than the options'
+	pub fn start(&mut self, options: &options::Options, config: SimConfig, player: Option<replay::Player>) {
+		let mut seed = options.seed;
+		let mut world_init = options.world_init();
+		if let Some(ref player) = player {
+			let start = player.start();
+			if options.seed.map_or(false, |seed| seed != start.seed) || options.load.is_some() {
+				warn!("Replaying from the recorded seed {}, ignoring --seed and --load", start.seed);
+			}
+			seed = Some(start.seed);
+			world_init.minions = start.minions;
+			world_init.resources = start.resources;
+			world_init.enemies = start.enemies;
+		}
+		let resumed = player.is_none() &&
+		              options.load.as_ref().map_or(false, |file| self.resume(path::Path::new(file)));
+		if !resumed {
+			if let Some(seed) = seed {
+				self.world = world::World::new(self.gene_pool.clone(), seed);
+			}
+		}
+		self.world.set_config(config);
+		self.world_init = world_init;
+		// headless, a replay is over once it gets where the recording ended
+		self.tick_limit = match player {
+			Some(ref player) if options.headless => options.ticks.or(player.end().map(|end| end.tick)),
+			_ => options.ticks,
+		};
+		self.bloom = !options.no_bloom;
+		self.set_render_scale(options.render_scale);
+		if let Some(scale) = options.resource_scale {
+			self.set_render_scale_for(agent::AgentType::Resource, scale);
+		}
+		self.config_watcher = options.config.as_ref().map(|file| ConfigWatcher::new(path::Path::new(file)));
+		if let Some(ref file) = options.genome_log {
+			match GenomeLogger::start(path::Path::new(file), GENOME_LOG_INTERVAL) {
+				Ok(logger) => self.genome_logger = Some(logger),
+				Err(e) => error!("Failed to start the genome log {}: {}", file, e),
+			}
+		}
+		if resumed {
+			self.init_systems();
+		} else {
+			self.init();
+		}
+		info!("Started world with seed {}", self.world.seed());
+		if let Some(ref file) = options.record {
+			if player.is_some() || resumed {
+				warn!("Not recording to {}, a recording has to start from a fresh world", file);
+			} else {
+				let start = replay::Start {
+					seed: self.world.seed(),
+					minions: self.world_init.minions,
+					resources: self.world_init.resources,
+					enemies: self.world_init.enemies,
+				};
+				match replay::Recorder::start(path::Path::new(file), &start) {
+					Ok(recorder) => self.input_recorder = Some(recorder),
+					Err(e) => error!("Failed to start recording to {}: {}", file, e),
+				}
+			}
+		}
+		self.replay = player;
+	}
+
+	/// Ends the recording on the state the world got to, for the replay to check against
+	pub fn stop_input_recording(&mut self) {
+		if let Some(recorder) = self.input_recorder.take() {
+			match recorder.stop(self.tick_count, self.world.state_digest()) {
+				Ok(inputs) => info!("Recorded {} inputs over {} ticks", inputs, self.tick_count),
+				Err(e) => error!("Failed to finish the recording: {}", e),
+			}
+		}
+	}
+
+	fn record_input(&mut self, e: Event) {
+		if !replay::is_recorded(&e) {
+			return;
+		}
+		let input = match e {
+			// the new seed is random, the replay needs to know which one it was
+			Event::ResetWorld => replay::Input::Reset(self.world.seed()),
+			e => replay::Input::Event(e),
+		};
+		let failed = match self.input_recorder {
+			Some(ref mut recorder) => recorder.record(self.tick_count, &input).is_err(),
+			None => false,
+		};
+		if failed {
+			error!("Failed to record an input, recording stopped");
+			self.input_recorder = None;
+		}
+	}
+
+	/// Feeds the world the inputs recorded before the coming tick
+	fn play_back(&mut self) {
+		let inputs = match self.replay {
+			Some(ref mut player) => player.take(self.tick_count),
+			None => return,
+		};
+		for input in inputs {
+			match input {
+				replay::Input::Event(e) => self.on_app_event(e),
+				replay::Input::Reset(seed) => self.reset(Some(seed)),
+			}
+		}
+	}
+
+	fn finish_replay(&mut self) {
+		if let Some(end) = self.replay.take().and_then(|player| player.end()) {
+			let digest = self.world.state_digest();
+			if digest == end.digest {
+				info!("Replay matches the recording after {} ticks", end.tick);
+			} else {
+				error!("Replay diverged from the recording: state digest {:x} after {} ticks, {:x} recorded",
+				       digest,
+				       end.tick,
+				       end.digest);
+			}
+		}
+	}
+
+	/// Hashes where everything in the world is, two runs that played out alike give the same
+	pub fn state_digest(&self) -> u64 {
+		self.world.state_digest()
+	}
+
+	/// Saves the world where the next `--resume` will find it; failures are logged, never fatal,
+	/// since this runs on the way out.
+	pub fn autosave(&self) {
+		let path = path::Path::new(options::AUTOSAVE_FILE);
+		match self.world.save(path) {
+			Ok(()) => info!("Saved world to {}", path.display()),
+			Err(e) => error!("Failed to save world to {}: {}", path.display(), e),
+		}
+	}
+
+	/// Replaces the world with a saved one, systems still have to be initialised.
+	/// Returns false, leaving the world untouched, if there is nothing usable at `path`.
+	fn resume(&mut self, path: &path::Path) -> bool {
+		match world::World::load(path) {
+			Ok(world) => {
+				self.world = world;
+				self.snapshot.clear();
+				info!("Resumed world from {}", path.display());
+				true
+			}
+			Err(e) => {
+				warn!("Not resuming from {}: {}", path.display(), e);
+				false
+			}
+		}
+	}
+
+	/// Captures every `every_n`th rendered frame into `dir` until `stop_recording`
+	pub fn start_recording(&mut self, dir: &path::Path, every_n: u32) {
+		self.stop_recording();
+		match capture::Recorder::start(dir, every_n) {
+			Ok(recorder) => {
+				info!("Recording to {}", dir.display());
+				self.recorder = Some(recorder);
+			}
+			Err(e) => error!("Failed to start recording to {}: {}", dir.display(), e),
+		}
+	}
+
+	pub fn stop_recording(&mut self) {
+		if let Some(mut recorder) = self.recorder.take() {
+			let captured = recorder.captured();
+			match recorder.stop() {
+				Ok(written) if written == captured => info!("Recorded {} frames", written),
+				Ok(written) => error!("Recorded {} frames out of {}", written, captured),
+				Err(e) => error!("Recording failed after {} frames: {}", captured, e),
+			}
+		}
+	}
+
+	pub fn profiler(&self) -> &Profiler {
+		&self.profiler
+	}
+
+	pub fn recorder_mut(&mut self) -> Option<&mut capture::Recorder> {
+		self.recorder.as_mut()
+	}
+
+	fn toggle_recording(&mut self) {
+		if self.recorder.is_some() {
+			self.stop_recording();
+		} else {
+			let now: DateTime<UTC> = UTC::now();
+			let dir = now.format("recordings/%Y%m%d_%H%M%S").to_string();
+			self.start_recording(path::Path::new(&dir), 1);
+		}
 	}
 
 	fn register_all(&mut self) {
 		for id in self.world.registered().into_iter() {
-			if let Some(found) = self.world.agent_mut(*id) {
-				self.systems.physics.register(found);
+			if let Some(found) = self.world.agent(*id) {
+				self.systems.for_each(&|s| s.register(found));
 			}
 		}
 	}
@@ -260,6 +884,16 @@ impl App {
 			Event::CamReset => {
 				self.camera.reset();
 			}
+			Event::ToggleProjection => self.camera.toggle_projection(),
+			Event::Zoom(lines) => self.camera.zoom_by(lines),
+			Event::FollowSelected => self.follow_selected(),
+			Event::ToggleOverview => {
+				let mode = match self.camera.mode() {
+					CameraMode::Overview => CameraMode::Free,
+					_ => CameraMode::Overview,
+				};
+				self.camera.set_mode(mode);
+			}
 			Event::NextLight => {
 				self.lights.next();
 			}
@@ -272,8 +906,54 @@ impl App {
 			Event::PrevBackground => {
 				self.backgrounds.prev();
 			}
+			Event::NextLightModel => {
+				self.light_models.next();
+			}
+			Event::PrevLightModel => {
+				self.light_models.prev();
+			}
+			Event::ToggleTwoSidedLighting => self.two_sided_lighting = !self.two_sided_lighting,
+			Event::NextAmbient => {
+				self.ambients.next();
+			}
+			Event::PrevAmbient => {
+				self.ambients.prev();
+			}
+			Event::NextExposure => {
+				self.exposures.next();
+			}
+			Event::PrevExposure => {
+				self.exposures.prev();
+			}
+			Event::ToggleBloom => self.bloom = !self.bloom,
+			Event::NextBloomIntensity => {
+				self.bloom_intensities.next();
+			}
+			Event::NextChargeRamp => {
+				self.charge_ramp = self.charge_ramps.next().and_then(ColorRamp::named);
+			}
+			Event::TogglePause => self.sim_clock.toggle_pause(),
+			Event::StepTick => self.sim_clock.step_once(),
+			Event::ToggleAi => self.systems.toggle_ai(),
+			Event::SpeedUp => self.sim_clock.speed_up(),
+			Event::SlowDown => self.sim_clock.slow_down(),
+			Event::ToggleRecording => self.toggle_recording(),
 			Event::ToggleDebug => self.debug_flags.toggle(DEBUG_TARGETS),
+			Event::ToggleProfiler => {
+				let enabled = self.profiler.is_enabled();
+				self.profiler.set_enabled(!enabled);
+			}
+			Event::ToggleMinimap => self.minimap = !self.minimap,
+			Event::CenterCamera(position) => self.camera.center_on(position),
+			Event::NextSensorOverlay => {
+				self.sensor_overlay = match self.sensor_overlay {
+					SensorOverlay::Off => SensorOverlay::Selected,
+					SensorOverlay::Selected => SensorOverlay::All,
+					SensorOverlay::All => SensorOverlay::Off,
+				}
+			}
 			Event::Reload => {}
+			Event::ResetWorld => self.reset(None),
 
 			Event::AppQuit => self.quit(),
 
@@ -284,31 +964,58 @@ impl App {
 				}
 			}
 			Event::BeginDrag(_, _) => {
-				self.camera.zero();
+				self.camera.begin_pan();
 			}
 			Event::Drag(start, end) => {
-				self.camera.set_relative(start - end);
+				self.camera.pan(start - end);
 			}
 			Event::EndDrag(start, end, vel) => {
-				self.camera.set_relative(start - end);
-				self.camera.velocity(vel);
+				self.camera.end_pan(start - end, vel);
 			}
 			Event::SelectMinion(pos, id) => self.select_minion(id),
 			Event::DeselectAll => self.deselect_all(),
+			Event::ShatterSelected => self.shatter_selected(),
+			Event::CloneSelected => self.clone_selected(),
+			Event::DiffSelected => {
+				self.diff_selected();
+			}
+			Event::FeedSelected => self.feed_selected(),
 			Event::NewMinion(pos) => self.new_minion(pos),
 			Event::RandomizeMinion(pos) => self.randomize_minion(pos),
+			Event::NewResource(pos) => self.new_resource(pos),
+			Event::NewEmitter(pos) => {
+				self.world.add_emitter(pos);
+			}
+			Event::GrabAgent(pos, id) => {
+				self.world.grab(id, pos);
+			}
+			Event::MoveGrabbed(pos) => self.world.move_grabbed(pos),
+			Event::Fling(velocity) => {
+				self.world.fling(velocity);
+			}
 		}
+		self.record_input(e);
 	}
 
 	pub fn quit(&mut self) {
 		self.is_running = false;
 	}
 
+	/// How many of each kind of agent are in the world, and how many mouths the minions feed with
+	pub fn census(&self) -> String {
+		format!("Minions: {} Mouths: {} Spores: {} Resources: {}",
+		        self.world.agents(agent::AgentType::Minion).len(),
+		        self.world.segments_with(agent::AgentType::Minion, segment::MOUTH).count(),
+		        self.world.agents(agent::AgentType::Spore).len(),
+		        self.world.agents(agent::AgentType::Resource).len())
+	}
+
 	pub fn is_running(&self) -> bool {
 		self.is_running
 	}
 
 	pub fn on_input_event(&mut self, e: &input::Event) {
+		self.sequences.event(e);
 		self.input_state.event(e);
 	}
 
@@ -338,22 +1045,85 @@ impl App {
 			F5 -> Reload,
 			N0 -> CamReset,
 			Home -> CamReset,
+			P -> ToggleProjection,
 			KpHome -> CamReset,
 			F6 -> DumpToFile,
+			F7 -> DiffSelected,
+			F8 -> ToggleRecording,
+			F3 -> ToggleProfiler,
+			R -> ResetWorld,
 			D -> ToggleDebug,
+			O -> NextSensorOverlay,
+			W -> ToggleMinimap,
 			Z -> DeselectAll,
+			X -> ShatterSelected,
+			C -> CloneSelected,
+			F -> FollowSelected,
+			Tab -> ToggleOverview,
 			L -> NextLight,
 			B -> NextBackground,
 			K -> PrevLight,
 			V -> PrevBackground,
+			M -> NextLightModel,
+			N -> PrevLightModel,
+			T -> ToggleTwoSidedLighting,
+			H -> NextAmbient,
+			G -> PrevAmbient,
+			U -> NextExposure,
+			Y -> PrevExposure,
+			J -> ToggleBloom,
+			I -> NextBloomIntensity,
+			A -> ToggleAi,
+			E -> NextChargeRamp,
 			Esc -> AppQuit
 		];
 
+		// on top of whatever the keys do on their own
+		if self.sequences.matches(FEED_SEQUENCE, ::std::time::Duration::from_millis(SEQUENCE_TIMEOUT_MS)) {
+			// or it would match again on every frame until the next press
+			self.sequences.clear();
+			events.push(Event::FeedSelected);
+		}
+
+		for &event in &[Event::TogglePause, Event::StepTick, Event::SpeedUp, Event::SlowDown] {
+			if self.input_state.action_once(&self.sim_bindings, event) {
+				events.push(event);
+			}
+		}
+
+		if self.input_state.any_ctrl_pressed() && self.input_state.key_once(input::Key::Q) {
+			events.push(Event::AppQuit);
+		}
+
 		let mouse_window_pos = self.input_state.mouse_position();
 		let mouse_view_pos = self.to_view(&mouse_window_pos);
-		let mouse_world_pos = self.to_world(&mouse_view_pos);
+		let mouse_world_pos = self.camera.camera().unproject(mouse_window_pos, self.viewport.size());
+
+		// clicks on the minimap move the camera there rather than reaching the world under it
+		let on_minimap = if self.minimap {
+			self.minimap_renderer().unproject(mouse_window_pos, self.viewport.size())
+		} else {
+			None
+		};
+		if let Some(position) = on_minimap {
+			if self.input_state.key_once(input::Key::MouseLeft) {
+				events.push(Event::CenterCamera(position));
+			}
+		}
 
-		let picked_id = if self.input_state.key_once(input::Key::MouseLeft) {
+		// modified clicks spawn where they land, rather than picking or panning
+		let mut spawned = false;
+		if on_minimap.is_none() {
+			for &(spawn, event) in &[(Spawn::Minion, Event::NewMinion(mouse_world_pos)),
+			                         (Spawn::Resource, Event::NewResource(mouse_world_pos))] {
+				if self.input_state.action_once(&self.spawn_bindings, spawn) {
+					events.push(event);
+					spawned = true;
+				}
+			}
+		}
+
+		let picked_id = if on_minimap.is_none() && self.input_state.key_once(input::Key::MouseLeft) {
 			self.pick_minion(mouse_world_pos)
 		} else {
 			None
@@ -362,23 +1132,63 @@ impl App {
 		if self.input_state.key_once(input::Key::MouseRight) {
 			if self.input_state.any_ctrl_pressed() {
 				events.push(Event::RandomizeMinion(mouse_world_pos));
+			} else if self.input_state.any_alt_pressed() {
+				events.push(Event::NewEmitter(mouse_world_pos));
 			} else {
 				events.push(Event::NewMinion(mouse_world_pos));
 			}
 		}
 
+		let scroll = self.input_state.take_scroll();
+		if scroll != 0. {
+			events.push(Event::Zoom(scroll));
+		}
+
 		if let Some(picked) = picked_id {
 			events.push(Event::SelectMinion(mouse_world_pos, picked));
-		} else {
-			match self.input_state.dragging(input::Key::MouseLeft, mouse_view_pos) {
-				input::Dragging::Begin(_, from) => {
-					let from = self.to_world(&from);
-					events.push(Event::BeginDrag(from, from));
+		} else if on_minimap.is_none() && !spawned {
+			let dragging = match self.input_state.dragging(input::Key::MouseLeft, mouse_view_pos) {
+				input::Dragging::Nothing => self.input_state.dragging(input::Key::MouseMiddle, mouse_view_pos),
+				dragging => dragging,
+			};
+			// a left drag starting on an agent carries it along once past the slop, so that clicks only select;
+			// any other drag pans
+			let grabbing = self.world.grabbed().is_some();
+			use cgmath::EuclideanVector;
+			match dragging {
+				input::Dragging::Begin(key, from) => {
+					self.pressed = if key == input::Key::MouseLeft { self.pick_minion(mouse_world_pos) } else { None };
+					if self.pressed.is_none() {
+						let from = self.to_world(&from);
+						events.push(Event::BeginDrag(from, from));
+					}
+				}
+				input::Dragging::Dragging(..) if grabbing => {
+					events.push(Event::MoveGrabbed(mouse_world_pos));
+				}
+				input::Dragging::Dragging(_, from, to) if self.pressed.is_some() => {
+					if (to - from).length() >= CLICK_SLOP {
+						let id = self.pressed.unwrap();
+						events.push(Event::GrabAgent(mouse_world_pos, id));
+					}
 				}
 				input::Dragging::Dragging(_, from, to) => {
 					events.push(Event::Drag(self.to_world(&from), self.to_world(&to)));
 				}
-				input::Dragging::End(_, from, to, prev) => {
+				input::Dragging::End(..) if grabbing => {
+					self.pressed = None;
+					let visible = self.camera.camera().visible_area();
+					let pixel = (visible.max.x - visible.min.x) / self.viewport.size().width;
+					events.push(Event::Fling(self.input_state.mouse_velocity(dt, pixel)));
+				}
+				input::Dragging::End(..) if self.pressed.is_some() => {
+					self.pressed = None;
+				}
+				input::Dragging::End(key, from, to, prev) => {
+					// a left click that went nowhere landed on empty space
+					if key == input::Key::MouseLeft && (to - from).length() < CLICK_SLOP {
+						events.push(Event::DeselectAll);
+					}
 					let mouse_vel = (self.to_view(&prev) - to) / dt;
 					events.push(Event::EndDrag(self.to_world(&from), self.to_world(&to), mouse_vel));
 				}
@@ -388,10 +1198,21 @@ impl App {
 
 
 		for e in events {
+			// a replay feeds the world its own inputs, the user's would make it diverge
+			if self.replay.is_some() && replay::is_recorded(&e) {
+				continue;
+			}
 			self.on_app_event(e)
 		}
 	}
 
+	/// Keeps the view within the extent inset by the camera margin, and picking in step with the zoom
+	fn clamp_camera(&mut self) {
+		let bounds = self.world.extent.inset(self.world.config().camera_margin);
+		self.camera.keep_inside(bounds);
+		self.viewport.scale = self.camera.scale();
+	}
+
 	fn to_view(&self, pos: &Position) -> Position {
 		self.viewport.to_world(pos)
 	}
@@ -402,45 +1223,84 @@ impl App {
 
 	pub fn on_resize(&mut self, width: u32, height: u32) {
 		self.viewport = Viewport::rect(width, height, self.viewport.scale);
+		self.camera.set_ratio(self.viewport.ratio);
 	}
 
-	fn from_transform(transform: &Transform) -> Matrix4<f32> {
-		use cgmath::Rotation3;
-		let position = transform.position;
-		let angle = transform.angle;
-		let rot = Matrix4::from(cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::rad(angle)));
-		let trans = Matrix4::from_translation(cgmath::Vector3::new(position.x, position.y, 0.0));
+	fn from_position(position: &Position) -> Matrix4<f32> {
+		Matrix4::from_translation(cgmath::Vector3::new(position.x, position.y, 0.0))
+	}
 
-		trans * rot
+	/// Scales every mesh at draw time, leaving physics and picking alone
+	pub fn set_render_scale(&mut self, scale: f32) {
+		self.render_scale = scale;
 	}
 
-	fn from_position(position: &Position) -> Matrix4<f32> {
-		Matrix4::from_translation(cgmath::Vector3::new(position.x, position.y, 0.0))
+	/// Scales the meshes of one agent type on top of the global render scale, e.g. to make resources stand out
+	pub fn set_render_scale_for(&mut self, agent_type: agent::AgentType, scale: f32) {
+		self.render_scale_overrides.insert(agent_type, scale);
+	}
+
+	fn render_scale_of(&self, agent_type: agent::AgentType) -> f32 {
+		self.render_scale * self.render_scale_overrides.get(&agent_type).cloned().unwrap_or(1.)
+	}
+
+	/// Draws frames `alpha` of the way from the previous simulation step to the current one,
+	/// 1 shows the current step as it is
+	pub fn set_render_alpha(&mut self, alpha: f32) {
+		self.render_alpha = alpha.max(0.).min(1.);
 	}
 
 	fn render_minions(&self, renderer: &mut render::Draw) {
+		let view = self.camera.camera().visible_area();
 		for (_, swarm) in self.world.swarms().iter() {
-			for (_, agent) in swarm.agents().iter() {
+			let render_scale = self.render_scale_of(swarm.type_of());
+			// shots and props don't age, they are drawn at full size
+			let growth_time = if swarm.type_of().grows() { self.world.config().growth_time } else { 0. };
+			// off screen agents are never sent to the GPU; the margin covers meshes drawn larger than their bodies
+			let culled = view.inset(-CULL_MARGIN * render_scale.max(1.));
+			for agent in swarm.agents_in_rect(culled) {
+				let id = agent.id();
 				let energy_left = agent.state.energy_ratio();
 				let age = agent.state.lifecycle().seconds();
+				let fade = agent.state.ttl_ratio();
 				for segment in agent.segments() {
-					let body_transform = Self::from_transform(&segment.transform());
+					let key = agent::Key::with_segment(id, segment.index);
+					let body_transform = match self.snapshot.transform_of(key, self.render_alpha) {
+						Some(transform) => transform.to_matrix(),
+						None => segment.transform().to_matrix(),
+					};
 
 					let mesh = &segment.mesh();
-					let fixture_scale = Matrix4::from_scale(mesh.shape.radius());
+					let shape = segment.grown_shape(segment.state.growth(growth_time));
+					let fixture_scale = Matrix4::from_scale(shape.radius() * render_scale);
 					let transform = body_transform * fixture_scale;
 
-					let appearance = render::Appearance::new(segment.color(), [energy_left, age, 0., 0.]);
+					let mut color = segment.color_with(self.charge_ramp.as_ref());
+					color[3] *= fade;
+					let appearance = render::Appearance::new(color, [energy_left, age, 0., 0.]);
 
-					match mesh.shape {
+					match shape {
 						obj::Shape::Ball { .. } => {
 							renderer.draw_ball(&transform, &appearance);
 						}
 						obj::Shape::Star { .. } => {
-							renderer.draw_star(&transform, &mesh.vertices[..], &appearance);
+							renderer.draw_star(&transform,
+							                   mesh.key,
+							                   &mesh.vertices[..],
+							                   &mesh.normals[..],
+							                   &mesh.indices[..],
+							                   mesh.depth,
+							                   &appearance);
 						}
-						obj::Shape::Poly { .. } => {
-							renderer.draw_star(&transform, &mesh.vertices[..], &appearance);
+						obj::Shape::Poly { .. } |
+						obj::Shape::Polygon { .. } => {
+							renderer.draw_star(&transform,
+							                   mesh.key,
+							                   &mesh.vertices[..],
+							                   &mesh.normals[..],
+							                   &mesh.indices[..],
+							                   mesh.depth,
+							                   &appearance);
 						}
 						obj::Shape::Box { ratio, .. } => {
 							renderer.draw_quad(&transform, ratio, &appearance);
@@ -461,9 +1321,9 @@ impl App {
 		               extent.max,
 		               Position::new(extent.max.x, extent.min.y),
 		               extent.min];
-		renderer.draw_lines(&Matrix4::identity(),
-		                    points,
-		                    &render::Appearance::rgba(self.lights.get()));
+		let color = self.lights.get();
+		let outline = points.windows(2).map(|p| render::Line::new(p[0], p[1], color, 2.)).collect::<Vec<_>>();
+		renderer.draw_thick_lines(render::LineSpace::World, &outline);
 		renderer.draw_quad(&Matrix4::from_scale(extent.max.x - extent.min.x),
 		                   1.,
 		                   &render::Appearance::rgba(self.backgrounds.get()));
@@ -477,15 +1337,19 @@ impl App {
 		if self.debug_flags.contains(DEBUG_TARGETS) {
 			use cgmath::*;
 			for (_, agent) in self.world.agents(world::agent::AgentType::Minion).iter() {
-				if agent.state.selected() {
-					let sensor = agent.first_segment(segment::HEAD).unwrap();
+				if !agent.state.selected() {
+					continue;
+				}
+				if let Some(sensor) = agent.first_segment(segment::HEAD) {
 					let p0 = sensor.transform.position;
 					let a0 = sensor.transform.angle;
-					let radar_range = sensor.mesh.shape.radius() * 10.;
+					let radar_range = agent.state.search_range(sensor.mesh.shape.radius() * 10.);
 					let p1 = *agent.state.target_position();
-					renderer.draw_debug_lines(&Matrix4::identity(),
-					                          &[p0, p1],
-					                          &render::Appearance::rgba([1., 1., 0., 1.]));
+					let target_color = match agent.state.target() {
+						&Some(id) => id.type_of().debug_color(),
+						&None => [1., 1., 0., 1.],
+					};
+					renderer.draw_debug_lines(&Matrix4::identity(), &[p0, p1], &render::Appearance::rgba(target_color));
 
 					let t0 = p1 - p0;
 					let t = t0.normalize_to(t0.length().min(radar_range));
@@ -532,26 +1396,189 @@ impl App {
 		}
 	}
 
+	/// Outlines every segment of the selected agent twice, a bright line over a dark one,
+	/// so that it stands out against any albedo and any background
+	fn render_selection(&self, renderer: &mut render::Draw) {
+		let agent = match self.selected.and_then(|id| self.world.agent(id)) {
+			Some(agent) => agent,
+			None => return,
+		};
+		let render_scale = self.render_scale_of(agent.type_of());
+		let mut halo = Vec::new();
+		let mut outline = Vec::new();
+		for segment in agent.segments() {
+			let key = agent::Key::with_segment(agent.id(), segment.index);
+			let transform = self.snapshot
+				.transform_of(key, self.render_alpha)
+				.unwrap_or_else(|| segment.transform().clone());
+			let radius = segment.mesh.shape.radius() * render_scale;
+			let rim = segment.mesh
+				.vertices
+				.iter()
+				.map(|&v| transform.transform_point(v * radius))
+				.collect::<Vec<_>>();
+			for i in 0..rim.len() {
+				let (p0, p1) = (rim[i], rim[(i + 1) % rim.len()]);
+				halo.push(render::Line::new(p0, p1, SELECTION_HALO, 4.));
+				outline.push(render::Line::new(p0, p1, SELECTION_OUTLINE, 2.));
+			}
+		}
+		halo.extend(outline);
+		renderer.draw_thick_lines(render::LineSpace::World, &halo);
+	}
+
+	/// The outline of a sensor's field of view `fov` wide, in the sensor's frame and out to a unit range: from the
+	/// sensor round the rim and back, then out along the heading
+	fn sensor_wedge(fov: f32) -> Vec<Position> {
+		const SIDES: usize = 24;
+		let sides = ::std::cmp::max(1, (SIDES as f32 * fov / (2. * consts::PI)).ceil() as usize);
+		let mut wedge = vec![Position::new(0., 0.)];
+		wedge.extend((0..sides + 1).map(|i| {
+			let a = (i as f32 / sides as f32 - 0.5) * fov;
+			// the sensor looks down its own y axis
+			Position::new(a.sin(), -a.cos())
+		}));
+		wedge.push(Position::new(0., 0.));
+		wedge.push(-Position::unit_y());
+		wedge
+	}
+
+	/// Outlines the wedge each sensor senses in, out to its radar range and live with the sensor's heading, in the
+	/// debug color of its target's type while locked on one. Every wedge is the same mesh, drawn all in one go
+	fn render_sensors(&self, renderer: &mut render::Draw) {
+		let wedge = Self::sensor_wedge(SENSOR_FOV);
+		for (_, agent) in self.world.agents(world::agent::AgentType::Minion).iter() {
+			if self.sensor_overlay == SensorOverlay::Selected && !agent.state.selected() {
+				continue;
+			}
+			if let Some(sensor) = agent.first_segment(segment::SENSOR) {
+				let radar_range = agent.state.search_range(sensor.mesh.shape.radius() * 10.);
+				let color = match agent.state.target() {
+					&Some(id) => {
+						let c = id.type_of().debug_color();
+						[c[0], c[1], c[2], 0.4]
+					}
+					&None => [0.5, 0.5, 0.5, 0.25],
+				};
+				let transform = sensor.transform.to_matrix() * Matrix4::from_scale(radar_range);
+				renderer.draw_debug_lines(&transform, &wedge, &render::Appearance::rgba(color));
+			}
+		}
+	}
+
+	/// Text over the finished frame: the stats in the top left corner, the energy and personality of the
+	/// selected minions floating over them, and the inspector of the last one picked in the top right corner
+	pub fn render_overlay(&self, renderer: &mut render::Draw, update: &Update, draw_calls: usize) {
+		let mut hud = render::HudRenderer::new();
+		let corner = render::Anchor::Screen(10, 10);
+		let speed = update.sim_speed.map_or("paused".to_string(), |s| format!("{}x", s));
+		hud.line(corner,
+		         &format!("F: {} E: {:.3} FT: {:.2} SFT: {:.2} FPS: {:.1} P: {} E: {} D: {} S: {}",
+		                  update.frame_count,
+		                  update.frame_elapsed,
+		                  update.frame_time * 1000.0,
+		                  update.frame_time_smooth * 1000.0,
+		                  update.fps,
+		                  update.population,
+		                  update.extinctions,
+		                  draw_calls,
+		                  speed));
+		hud.line(corner, &self.census());
+		for (_, agent) in self.world.agents(agent::AgentType::Minion).iter().filter(|&(_, a)| a.state.selected()) {
+			let label = render::Anchor::World(agent.transform().position);
+			let brain = agent.brain();
+			hud.line(label,
+			         &format!("{} E: {:.1} ({:.0}%)",
+			                  agent.id(),
+			                  agent.state.energy(),
+			                  agent.state.energy_ratio() * 100.));
+			hud.line(label,
+			         &format!("H: {:.2} Ha: {:.2} P: {:.2} F: {:.2} R: {:.2} T: {:.2}",
+			                  brain.hunger,
+			                  brain.haste,
+			                  brain.prudence,
+			                  brain.fear,
+			                  brain.rest,
+			                  brain.thrust));
+		}
+		if self.profiler.is_enabled() {
+			if let (Some(mean), Some(variance)) = (self.frame_times.mean(), self.frame_times.variance()) {
+				hud.line(corner,
+				         &format!("frame: mean {:.2} ms sd {:.2} ms over {} frames",
+				                  mean * 1000.,
+				                  variance.sqrt() * 1000.,
+				                  self.frame_times.len()));
+			}
+		}
+		for timing in self.profiler.timings() {
+			hud.line(corner,
+			         &format!("{}: p50 {:.2} ms p95 {:.2} ms",
+			                  timing.name,
+			                  timing.p50 * 1000.,
+			                  timing.p95 * 1000.));
+		}
+		if let Some(lines) = self.inspector() {
+			let inspector = render::Anchor::Screen(self.viewport.width as i32 - INSPECTOR_WIDTH, 10);
+			for line in lines {
+				hud.line(inspector, &line);
+			}
+		}
+		self.floating_text.render(&mut hud);
+		hud.draw(renderer, &self.camera.camera(), self.viewport.size());
+	}
+
+	fn minimap_renderer(&self) -> render::MinimapRenderer {
+		render::MinimapRenderer::new(self.world.extent, MINIMAP_SIZE)
+	}
+
+	/// Every agent as a dot in the debug color of its type, and the part of the world in view
+	fn render_minimap(&self, renderer: &mut render::Draw) {
+		let mut dots = Vec::new();
+		for agent_type in agent::AgentType::all() {
+			let color = agent_type.debug_color();
+			for (_, agent) in self.world.agents(*agent_type).iter().filter(|&(_, a)| a.state.is_alive()) {
+				dots.push((agent.transform().position, color));
+			}
+		}
+		let view = self.camera.camera().visible_area();
+		self.minimap_renderer().draw(renderer, self.viewport.size(), &dots, view);
+	}
+
 	pub fn render(&self, renderer: &mut render::Draw) {
 		self.render_minions(renderer);
 		self.render_extent(renderer);
 		self.render_hud(renderer);
+		self.render_selection(renderer);
+		if self.sensor_overlay != SensorOverlay::Off {
+			self.render_sensors(renderer);
+		}
+		if self.minimap {
+			self.render_minimap(renderer);
+		}
 	}
 
+	/// Every emitter is a light, laid out in the light buffer by its id so the same world always lights the same way
 	pub fn environment(&self) -> Environment {
 		Environment {
 			light_color: self.lights.get(),
+			light_model: self.light_models.get(),
+			two_sided_lighting: self.two_sided_lighting,
+			ambient_color: self.ambients.get().0,
+			directional_light: self.ambients.get().1,
+			exposure: self.exposures.get(),
+			bloom: if self.bloom { Some(self.bloom_intensities.get()) } else { None },
 			background_color: self.backgrounds.get(),
-			light_positions: self.world
+			emitters: self.world
 				.emitters()
 				.iter()
-				.map(|e| e.transform().position)
+				.map(|e| (e.id(), e.transform().position))
 				.collect::<Vec<_>>()
 				.into_boxed_slice(),
 		}
 	}
 
 	pub fn init(&mut self) {
+		self.world.populate(&self.world_init);
 		self.init_systems();
 	}
 
@@ -567,29 +1594,76 @@ impl App {
 	}
 
 	fn update_systems(&mut self, dt: f32) {
-		self.systems.to_world(&mut self.world,
-		                      &|s, mut world| s.update_world(&mut world, dt));
+		self.systems.update(&mut self.world, dt, &self.profiler);
+	}
+
+	/// Runs the world on by one `SIM_STEP`
+	fn tick(&mut self) {
+		self.play_back();
+		self.cleanup();
+		self.update_systems(SIM_STEP);
+		self.register_all();
+		self.world.reindex();
+		self.world.events_mut().drain(&mut [&mut self.event_log as &mut events::Subscriber,
+		                                     &mut self.floating_text as &mut events::Subscriber]);
+		self.floating_text.update(SIM_STEP);
+		if let Some(ref mut logger) = self.genome_logger {
+			logger.tick(&self.world);
+		}
+		self.snapshot.advance(&self.world);
+		self.tick_count += 1;
+		if self.replay.as_ref().map_or(false, |player| player.is_finished(self.tick_count)) {
+			self.finish_replay();
+		}
+		if self.tick_limit.map_or(false, |limit| self.tick_count >= limit) {
+			self.quit();
+		}
 	}
 
 	pub fn update(&mut self) -> Update {
 		let frame_time = self.frame_start.seconds();
+		self.frame_start.reset();
+		self.step(frame_time)
+	}
+
+	/// Advances by a frame of `frame_time` seconds whatever the wall clock says: input and camera follow
+	/// the frame, the world runs as many `SIM_STEP` ticks as fit in the time owed to it
+	pub fn step(&mut self, frame_time: f32) -> Update {
 		let frame_time_smooth = self.frame_smooth.smooth(frame_time);
 
 		self.frame_elapsed += frame_time;
-		self.frame_start.reset();
+		self.profiler.record("frame", frame_time);
+		self.frame_times.push(frame_time);
 
-		self.cleanup();
+		if let Some(config) = self.config_watcher.as_mut().and_then(|w| w.poll()) {
+			self.world.set_config(config);
+		}
 
-		self.camera.update(frame_time_smooth);
+		let target = match self.camera.mode() {
+			CameraMode::Follow(id) => self.world.agent(id).map(|agent| agent.transform().position),
+			_ => None,
+		};
+		self.camera.update(frame_time_smooth, target);
 
 		self.update_input(frame_time_smooth);
-		self.update_systems(frame_time_smooth);
-		self.register_all();
+		self.clamp_camera();
+
+		// paused, the world stands still while the camera and input carry on
+		for _ in 0..self.sim_clock.advance(frame_time) {
+			if !self.is_running {
+				break;
+			}
+			self.tick();
+		}
+		let alpha = self.sim_clock.alpha();
+		self.set_render_alpha(alpha);
 		self.frame_count += 1;
 
 		Update {
 			wall_clock_elapsed: self.wall_clock_start.seconds(),
 			frame_count: self.frame_count,
+			tick_count: self.tick_count,
+			sim_speed: if self.sim_clock.is_paused() { None } else { Some(self.sim_clock.speed()) },
 			frame_elapsed: self.frame_elapsed,
 			frame_time: frame_time,
 			frame_time_smooth: frame_time_smooth,
@@ -599,3 +1673,353 @@ impl App {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::fs;
+	use std::io;
+	use core::geometry::Position;
+	use core::resource::ResourceLoader;
+	use backend::world;
+
+	struct NoResources;
+
+	impl ResourceLoader<u8> for NoResources {
+		fn load(&self, key: &str) -> io::Result<Box<[u8]>> {
+			Err(io::Error::new(io::ErrorKind::NotFound, key))
+		}
+	}
+
+	#[test]
+	fn reset_reproduces_fresh_start() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let fresh = world::World::new(world::World::load_gene_pool(&NoResources, "none.csv"), 42).digest();
+		app.world.new_minion(Position::new(0., 0.), None);
+		app.reset(Some(42));
+		assert_eq!(42, app.world.seed());
+		assert_eq!(fresh, app.world.digest());
+		// changing any one input changes the digest: the seed, or a single agent more
+		let other_seed = world::World::new(world::World::load_gene_pool(&NoResources, "none.csv"), 43).digest();
+		assert!(fresh != other_seed);
+		app.world.new_minion(Position::new(0., 0.), None);
+		assert!(fresh != app.world.digest());
+	}
+
+	#[test]
+	fn render_scale_leaves_picking_alone() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let center = Position::new(0., 0.);
+		let id = app.world.new_resource(&Transform::from_position(center), None);
+		app.register_all();
+		let radius = app.world.agent(id).unwrap().segments()[0].mesh.shape.radius();
+		let outside = Position::new(radius * 1.5, 0.);
+		for &scale in &[1., 3.] {
+			app.set_render_scale(scale);
+			app.set_render_scale_for(agent::AgentType::Resource, 1.5);
+			assert_eq!(Some(id), app.pick_minion(center));
+			assert_eq!(None, app.pick_minion(outside));
+			assert_eq!(radius, app.world.agent(id).unwrap().segments()[0].mesh.shape.radius());
+		}
+	}
+
+	#[test]
+	fn render_scales_come_from_the_options() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let options = options::Options {
+			render_scale: 0.5,
+			resource_scale: Some(3.),
+			..options::Options::default()
+		};
+		app.start(&options, SimConfig::default(), None);
+		assert_eq!(0.5, app.render_scale_of(agent::AgentType::Minion));
+		assert_eq!(1.5, app.render_scale_of(agent::AgentType::Resource));
+	}
+
+	#[test]
+	fn ticks_follow_simulated_time_not_frames() {
+		for &(frames, frame_time) in &[(30, SIM_STEP * 2.), (120, SIM_STEP / 2.)] {
+			let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+			app.reset(Some(7));
+			let mut last = None;
+			for _ in 0..frames {
+				last = Some(app.step(frame_time));
+			}
+			let r = last.unwrap();
+			assert_eq!(frames, r.frame_count);
+			assert_eq!(60, r.tick_count);
+		}
+		// half a tick owed is drawn half way between the last two ticks
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		assert_eq!(0, app.step(SIM_STEP / 2.).tick_count);
+		assert_eq!(0.5, app.render_alpha);
+	}
+
+	#[test]
+	fn pausing_freezes_lifecycles_and_emitters() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		app.reset(Some(7));
+		let id = app.world.new_minion(Position::new(0., 0.), None);
+		app.register_all();
+		app.step(SIM_STEP);
+		let left = app.world.agent(id).unwrap().state.lifecycle().left();
+		let resources = app.world.agents(agent::AgentType::Resource).len();
+		app.on_app_event(Event::TogglePause);
+		// long enough for the minion's lifecycle and many emitter periods, had the time been running
+		for _ in 0..10 {
+			app.step(1.);
+		}
+		assert_eq!(left, app.world.agent(id).unwrap().state.lifecycle().left());
+		assert_eq!(resources, app.world.agents(agent::AgentType::Resource).len());
+		app.on_app_event(Event::TogglePause);
+		app.step(SIM_STEP);
+		assert!(app.world.agent(id).unwrap().state.lifecycle().left() < left);
+	}
+
+	#[test]
+	fn headless_runs_simulate_the_same_world_as_real_time_ones() {
+		// headless runs step a tick a frame, real time ones as many as the frame took: past a minion's
+		// lifecycle and a good many emitter periods, they all still end alike
+		let run = |frames: u32, frame_time: f32| {
+			let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+			app.reset(Some(7));
+			for _ in 0..frames {
+				app.step(frame_time);
+			}
+			assert_eq!(360, app.tick_count);
+			app.state_digest()
+		};
+		let headless = run(360, SIM_STEP);
+		assert_eq!(headless, run(180, SIM_STEP * 2.));
+		assert_eq!(headless, run(720, SIM_STEP / 2.));
+	}
+
+	#[test]
+	fn replays_end_where_their_recording_did() {
+		let file = env::temp_dir().join("rust-oids-app-replay-test.txt");
+		let run = |options: &options::Options, player: Option<replay::Player>, events: &[(u32, Event)]| {
+			let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+			app.start(options, SimConfig::default(), player);
+			while app.is_running() && app.tick_count < 90 {
+				for &(_, e) in events.iter().filter(|&&(tick, _)| tick == app.tick_count) {
+					app.on_app_event(e);
+				}
+				app.step(SIM_STEP);
+			}
+			app.stop_input_recording();
+			(app.tick_count, app.state_digest(), app.replay.is_none())
+		};
+		let events = [(10, Event::NewMinion(Position::new(3., 4.))),
+		              (20, Event::RandomizeMinion(Position::new(-5., 1.))),
+		              (30, Event::ResetWorld),
+		              (40, Event::NewMinion(Position::new(0., -2.))),
+		              (50, Event::CamUp)];
+		let recording = options::Options {
+			seed: Some(5),
+			minions: 2,
+			resources: 4,
+			record: Some(file.to_string_lossy().into_owned()),
+			..options::Options::default()
+		};
+		let recorded = run(&recording, None, &events);
+		let replaying = options::Options {
+			headless: true,
+			..options::Options::default()
+		};
+		let player = replay::Player::load(&file).unwrap();
+		assert_eq!(5, player.start().seed);
+		assert_eq!(recorded, run(&replaying, Some(player), &[]));
+		// the same seed without the inputs plays out differently
+		let unrecorded = options::Options { record: None, ..recording };
+		assert!(recorded.1 != run(&unrecorded, None, &[]).1);
+		fs::remove_file(&file).unwrap();
+	}
+
+	#[test]
+	fn seeded_runs_play_out_alike() {
+		let run = |seed: u64| {
+			let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+			let options = options::Options { seed: Some(seed), ..options::Options::default() };
+			app.start(&options, SimConfig::default(), None);
+			// long enough for every emitter to spray a few resources, some of them at random
+			while app.tick_count < 150 {
+				app.step(SIM_STEP);
+			}
+			app.state_digest()
+		};
+		assert_eq!(run(5), run(5));
+		assert!(run(5) != run(6));
+	}
+
+	#[test]
+	fn typing_the_feed_sequence_fills_up_the_selected_minions() {
+		use frontend::input::State;
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let selected = app.world.new_minion(Position::new(0., 0.), None);
+		let other = app.world.new_minion(Position::new(10., 0.), None);
+		app.world.agent_mut(selected).unwrap().state.select();
+		let type_keys = |app: &mut App, keys: &[input::Key]| {
+			for &key in keys {
+				app.on_input_event(&input::Event::Key(State::Down, key));
+				app.on_input_event(&input::Event::Key(State::Up, key));
+			}
+			app.update_input(SIM_STEP);
+		};
+		let ratio = |app: &App, id| app.world.agent(id).unwrap().state.energy_ratio();
+		type_keys(&mut app, &FEED_SEQUENCE[1..]);
+		assert!(ratio(&app, selected) < 1.);
+		type_keys(&mut app, FEED_SEQUENCE);
+		assert_eq!(1., ratio(&app, selected));
+		assert!(ratio(&app, other) < 1.);
+	}
+
+	#[test]
+	fn selected_minions_are_diffed_against_the_first() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let first = app.world.new_minion(Position::new(0., 0.), None);
+		let copy = app.world.clone_agent(first, Position::new(10., 0.)).unwrap();
+		app.world.agent_mut(first).unwrap().state.select();
+		assert!(app.diff_selected().is_empty());
+		app.world.agent_mut(copy).unwrap().state.select();
+		app.world.agent_mut(copy).unwrap().brain_mut().fear += 0.25;
+		let diffs = app.diff_selected();
+		assert_eq!(1, diffs.len());
+		assert_eq!(vec!["fear".to_string()], diffs[0].brain.iter().map(|d| d.0.clone()).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn long_frames_drop_what_they_cannot_catch_up() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		assert_eq!(MAX_TICKS_PER_FRAME, app.step(10.).tick_count);
+		assert!(app.sim_clock.alpha() < 1.);
+		assert_eq!(MAX_TICKS_PER_FRAME + 1, app.step(SIM_STEP).tick_count);
+	}
+
+	#[test]
+	fn modified_clicks_spawn_under_the_mouse() {
+		use cgmath::EuclideanVector;
+		use frontend::input::{Key, State};
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let window = Position::new(20., 40.);
+		let under_mouse = app.camera.camera().unproject(window, app.viewport.size());
+		let click = |app: &mut App, modifier: Key| {
+			for e in &[input::Event::Mouse(window),
+			           input::Event::Key(State::Down, modifier),
+			           input::Event::Key(State::Down, Key::MouseLeft)] {
+				app.on_input_event(e);
+			}
+			app.update_input(SIM_STEP);
+			for &key in &[modifier, Key::MouseLeft] {
+				app.on_input_event(&input::Event::Key(State::Up, key));
+			}
+		};
+		let count = |app: &App, t: agent::AgentType| app.world.agents(t).len();
+		let (minions, resources) = (count(&app, agent::AgentType::Minion), count(&app, agent::AgentType::Resource));
+
+		click(&mut app, Key::LShift);
+		assert_eq!(minions + 1, count(&app, agent::AgentType::Minion));
+		assert_eq!(resources, count(&app, agent::AgentType::Resource));
+		let minion = app.world.agents(agent::AgentType::Minion).values().last().unwrap().transform().position;
+		assert!((minion - under_mouse).length() < 1e-3);
+
+		click(&mut app, Key::LAlt);
+		assert_eq!(minions + 1, count(&app, agent::AgentType::Minion));
+		assert_eq!(resources + 1, count(&app, agent::AgentType::Resource));
+
+		let outside = app.world.extent.max + Position::new(1., 1.);
+		app.on_app_event(Event::NewMinion(outside));
+		app.on_app_event(Event::NewResource(outside));
+		assert_eq!(minions + 1, count(&app, agent::AgentType::Minion));
+		assert_eq!(resources + 1, count(&app, agent::AgentType::Resource));
+	}
+
+	#[test]
+	fn picking_selects_for_the_inspector_and_clicking_away_deselects() {
+		use frontend::input::{Key, State};
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let id = app.world.new_minion(Position::new(0., 0.), None);
+		assert!(app.inspector().is_none());
+
+		app.on_app_event(Event::SelectMinion(Position::new(0., 0.), id));
+		assert_eq!(Some(id), app.selected);
+		let lines = app.inspector().unwrap();
+		assert!(lines[0].starts_with(&id.to_string()));
+		let segments = app.world.agent(id).unwrap().segments().len();
+		assert!(lines.contains(&format!("Segments: {}", segments)));
+		assert!(lines.contains(&"Target: none".to_string()));
+		let (friction, restitution) = app.world.agent(id).unwrap().segments().iter().fold((0., 0.), |(f, r), s| {
+			(f + s.material.friction / segments as f32, r + s.material.restitution / segments as f32)
+		});
+		assert!(lines.contains(&format!("Friction: {:.2} Restitution: {:.2}", friction, restitution)));
+		// picking it again lets go of it
+		app.on_app_event(Event::SelectMinion(Position::new(0., 0.), id));
+		assert_eq!(None, app.selected);
+
+		app.on_app_event(Event::SelectMinion(Position::new(0., 0.), id));
+		app.on_input_event(&input::Event::Mouse(Position::new(2., 2.)));
+		app.on_input_event(&input::Event::Key(State::Down, Key::MouseLeft));
+		app.update_input(SIM_STEP);
+		app.on_input_event(&input::Event::Key(State::Up, Key::MouseLeft));
+		app.update_input(SIM_STEP);
+		assert_eq!(None, app.selected);
+		assert!(!app.world.agent(id).unwrap().state.selected());
+		assert!(app.inspector().is_none());
+	}
+
+	#[test]
+	fn sensor_wedges_open_around_the_heading() {
+		use cgmath::{EuclideanVector, Vector};
+		let heading = -Position::unit_y();
+		let quarter = App::sensor_wedge(consts::PI / 2.);
+		let n = quarter.len();
+		assert_eq!(Position::new(0., 0.), quarter[0]);
+		assert_eq!(Position::new(0., 0.), quarter[n - 2]);
+		assert_eq!(heading, quarter[n - 1]);
+		for p in &quarter[1..n - 2] {
+			assert!((p.length() - 1.).abs() < 1e-5);
+			assert!(p.dot(heading) >= (consts::PI / 4.).cos() - 1e-5);
+		}
+		// either edge is half the field away from the heading
+		assert!((quarter[1] - Position::new(-(0.5f32).sqrt(), -(0.5f32).sqrt())).length() < 1e-5);
+		assert!((quarter[n - 3] - Position::new((0.5f32).sqrt(), -(0.5f32).sqrt())).length() < 1e-5);
+		// all the way round the rim closes behind the sensor
+		let full = App::sensor_wedge(SENSOR_FOV);
+		assert!((full[1] - Position::unit_y()).length() < 1e-5);
+		assert!((full[full.len() - 3] - Position::unit_y()).length() < 1e-5);
+	}
+
+	#[test]
+	fn every_emitter_lights_the_scene() {
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let id = app.world.add_emitter(Position::new(3., 4.));
+		let emitters = app.environment().emitters;
+		assert_eq!(app.world.emitters().len(), emitters.len());
+		assert!(emitters.contains(&(id, Position::new(3., 4.))));
+	}
+
+	#[test]
+	fn clicking_an_agent_selects_it_and_only_dragging_grabs_it() {
+		use frontend::input::{Key, State};
+		let mut app = App::new(64, 64, 100., &NoResources, "none.csv");
+		let center = Position::new(32., 32.);
+		let pos = app.camera.camera().unproject(center, app.viewport.size());
+		let id = app.world.new_minion(pos, None);
+		app.on_input_event(&input::Event::Mouse(center));
+		app.on_input_event(&input::Event::Key(State::Down, Key::MouseLeft));
+		app.update_input(SIM_STEP);
+		app.update_input(SIM_STEP);
+		app.on_input_event(&input::Event::Key(State::Up, Key::MouseLeft));
+		app.update_input(SIM_STEP);
+		assert_eq!(Some(id), app.selected);
+		assert!(app.world.grabbed().is_none());
+
+		app.on_input_event(&input::Event::Key(State::Down, Key::MouseLeft));
+		app.update_input(SIM_STEP);
+		app.update_input(SIM_STEP);
+		assert!(app.world.grabbed().is_none());
+		app.on_input_event(&input::Event::Mouse(Position::new(40., 32.)));
+		app.update_input(SIM_STEP);
+		assert_eq!(Some(id), app.world.grabbed().map(|grab| grab.id));
+	}
+}
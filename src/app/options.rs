@@ -0,0 +1,260 @@
+//! Command line options, so that runs can be scripted for experiments and CI.
+use std::fmt;
+use backend::world::WorldInit;
+
+pub const USAGE: &'static str = "Usage: rust-oids [options] [gene_pool_file.csv]
+
+Options:
+    --seed <n>          seed the world with n instead of a random seed
+    --minions <n>       start with n minions scattered over the world
+    --resources <n>     start with n resources scattered over the world
+    --enemies <n>       start with n predators hunting the minions
+    --headless          run the simulation without opening a window
+    --ticks <n>         quit after n simulation ticks, saving the world
+    --until-population <n> quit once there are n minions or more
+    --until-extinct     quit on the first extinction instead of restarting the world
+    --config <file>     read the simulation parameters from a JSON file
+    --load <save>       start from a saved world instead of a fresh one
+    --resume            same as --load autosave.json
+    --record <file>     record the seed and every input reaching the world, to replay the run later
+    --replay <file>     play a recorded run back, from its own seed, and check it ends the same
+    --genome-log <file> append the personality of every minion to a CSV file once a second
+    --width <pixels>    window width, 1024 by default
+    --height <pixels>   window height, 1024 by default
+    --fullscreen        fill the primary monitor at its own resolution
+    --no-bloom          start without the glow around bright segments, for slower GPUs
+    --render-scale <x>  draw every agent x times its size, leaving its body as it is
+    --resource-scale <x> draw the resources x times larger on top of the render scale
+    --help              print this message";
+
+/// Written on every clean shutdown, picked up again by `--resume`
+pub const AUTOSAVE_FILE: &'static str = "autosave.json";
+
+/// Smaller windows are grown to this, below it the HUD no longer fits
+pub const MIN_WIDTH: u32 = 320;
+pub const MIN_HEIGHT: u32 = 240;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+	pub gene_pool: String,
+	pub seed: Option<u64>,
+	/// initial population, on top of the minions hatched from the gene pool
+	pub minions: usize,
+	pub resources: usize,
+	pub enemies: usize,
+	pub headless: bool,
+	pub ticks: Option<u32>,
+	/// the other ways a run can end, checked after every tick
+	pub until_population: Option<usize>,
+	pub until_extinct: bool,
+	pub config: Option<String>,
+	pub load: Option<String>,
+	pub record: Option<String>,
+	pub replay: Option<String>,
+	pub genome_log: Option<String>,
+	pub width: u32,
+	pub height: u32,
+	pub fullscreen: bool,
+	pub no_bloom: bool,
+	/// cosmetic, the bodies keep their size
+	pub render_scale: f32,
+	pub resource_scale: Option<f32>,
+}
+
+/// What the window gets created with
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+	pub title: String,
+	pub width: u32,
+	pub height: u32,
+	/// when set, the size is the primary monitor's and `width` and `height` are ignored
+	pub fullscreen: bool,
+}
+
+impl Options {
+	/// What the first world is populated with, and every world restarted after it
+	pub fn world_init(&self) -> WorldInit {
+		WorldInit {
+			minions: self.minions,
+			resources: self.resources,
+			enemies: self.enemies,
+			..WorldInit::default()
+		}
+	}
+
+	pub fn window(&self) -> WindowConfig {
+		WindowConfig {
+			title: "Box2d + GFX".to_string(),
+			width: ::std::cmp::max(self.width, MIN_WIDTH),
+			height: ::std::cmp::max(self.height, MIN_HEIGHT),
+			fullscreen: self.fullscreen,
+		}
+	}
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			gene_pool: "minion_gene_pool.csv".to_string(),
+			seed: None,
+			minions: WorldInit::default().minions,
+			resources: WorldInit::default().resources,
+			enemies: WorldInit::default().enemies,
+			headless: false,
+			ticks: None,
+			until_population: None,
+			until_extinct: false,
+			config: None,
+			load: None,
+			record: None,
+			replay: None,
+			genome_log: None,
+			width: 1024,
+			height: 1024,
+			fullscreen: false,
+			no_bloom: false,
+			render_scale: 1.,
+			resource_scale: None,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	Help,
+	Unknown(String),
+	MissingValue(String),
+	Invalid(String, String),
+	Unexpected(String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			&Error::Help => write!(f, "{}", USAGE),
+			&Error::Unknown(ref arg) => write!(f, "unknown option {}\n\n{}", arg, USAGE),
+			&Error::MissingValue(ref arg) => write!(f, "{} needs a value\n\n{}", arg, USAGE),
+			&Error::Invalid(ref arg, ref value) => write!(f, "invalid value {} for {}\n\n{}", value, arg, USAGE),
+			&Error::Unexpected(ref arg) => write!(f, "unexpected argument {}\n\n{}", arg, USAGE),
+		}
+	}
+}
+
+/// Parses the arguments following the program name
+pub fn parse(args: &[String]) -> Result<Options, Error> {
+	let mut options = Options::default();
+	let mut gene_pool = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		macro_rules! value {
+			() => (try!(args.next().ok_or(Error::MissingValue(arg.clone()))))
+		}
+		macro_rules! number {
+			() => ({
+				let value = value!();
+				try!(value.parse().map_err(|_| Error::Invalid(arg.clone(), value.clone())))
+			})
+		}
+		match arg.as_str() {
+			"--help" | "-h" => return Err(Error::Help),
+			"--seed" => options.seed = Some(number!()),
+			"--minions" => options.minions = number!(),
+			"--resources" => options.resources = number!(),
+			"--enemies" => options.enemies = number!(),
+			"--headless" => options.headless = true,
+			"--ticks" => options.ticks = Some(number!()),
+			"--until-population" => options.until_population = Some(number!()),
+			"--until-extinct" => options.until_extinct = true,
+			"--config" => options.config = Some(value!().clone()),
+			"--load" => options.load = Some(value!().clone()),
+			"--resume" => options.load = Some(AUTOSAVE_FILE.to_string()),
+			"--record" => options.record = Some(value!().clone()),
+			"--replay" => options.replay = Some(value!().clone()),
+			"--genome-log" => options.genome_log = Some(value!().clone()),
+			"--width" => options.width = number!(),
+			"--height" => options.height = number!(),
+			"--fullscreen" => options.fullscreen = true,
+			"--no-bloom" => options.no_bloom = true,
+			"--render-scale" => options.render_scale = number!(),
+			"--resource-scale" => options.resource_scale = Some(number!()),
+			s if s.starts_with("-") => return Err(Error::Unknown(arg.clone())),
+			_ if gene_pool.is_none() => gene_pool = Some(arg.clone()),
+			_ => return Err(Error::Unexpected(arg.clone())),
+		}
+	}
+	if let Some(gene_pool) = gene_pool {
+		options.gene_pool = gene_pool;
+	}
+	Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::world::WorldInit;
+
+	fn args(line: &str) -> Vec<String> {
+		line.split_whitespace().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn parse_representative_args() {
+		let options = parse(&args("--seed 42 --headless --ticks 600 --config sim.json --width 640 --height 480 \
+		                           pool.csv --resume --genome-log traits.csv --no-bloom --minions 12 --resources 300 \
+		                           --until-population 50 --until-extinct --record run.txt --replay old.txt \
+		                           --render-scale 0.5 --resource-scale 1.5"))
+			.unwrap();
+		assert_eq!(Options {
+			           gene_pool: "pool.csv".to_string(),
+			           seed: Some(42),
+			           minions: 12,
+			           resources: 300,
+			           enemies: 0,
+			           headless: true,
+			           ticks: Some(600),
+			           until_population: Some(50),
+			           until_extinct: true,
+			           config: Some("sim.json".to_string()),
+			           load: Some(AUTOSAVE_FILE.to_string()),
+			           record: Some("run.txt".to_string()),
+			           replay: Some("old.txt".to_string()),
+			           genome_log: Some("traits.csv".to_string()),
+			           width: 640,
+			           height: 480,
+			           fullscreen: false,
+			           no_bloom: true,
+			           render_scale: 0.5,
+			           resource_scale: Some(1.5),
+		           },
+		           options);
+	}
+
+	#[test]
+	fn parsed_size_reaches_the_window() {
+		let window = parse(&args("--width 1920 --height 1080 --fullscreen")).unwrap().window();
+		assert_eq!((1920, 1080, true), (window.width, window.height, window.fullscreen));
+		let window = parse(&args("--width 10 --height 0")).unwrap().window();
+		assert_eq!((MIN_WIDTH, MIN_HEIGHT, false), (window.width, window.height, window.fullscreen));
+	}
+
+	#[test]
+	fn parsed_population_reaches_the_world() {
+		let init = parse(&args("--resources 40 --minions 3 --enemies 2")).unwrap().world_init();
+		assert_eq!((3, 40, 2, WorldInit::default().obstacles),
+		           (init.minions, init.resources, init.enemies, init.obstacles));
+		let init = parse(&[]).unwrap().world_init();
+		assert_eq!((0, 0, 0), (init.minions, init.resources, init.enemies));
+	}
+
+	#[test]
+	fn parse_rejects_bad_args() {
+		assert_eq!(Ok(Options::default()), parse(&[]));
+		assert_eq!(Err(Error::Unknown("--sed".to_string())), parse(&args("--sed 42")));
+		assert_eq!(Err(Error::MissingValue("--ticks".to_string())), parse(&args("--ticks")));
+		assert_eq!(Err(Error::Invalid("--width".to_string(), "wide".to_string())),
+		           parse(&args("--width wide")));
+		assert_eq!(Err(Error::Invalid("--minions".to_string(), "-3".to_string())),
+		           parse(&args("--minions -3")));
+		assert_eq!(Err(Error::Unexpected("b.csv".to_string())), parse(&args("a.csv b.csv")));
+	}
+}
@@ -0,0 +1,363 @@
+//! Input recordings, to replay a run exactly.
+//! Starting from the same seed, a world fed the same inputs at the same ticks plays out the same way, so a recording
+//! only keeps the seed and the inputs that reach the world, one JSON line each after a `#rust-oids-replay <version>`
+//! header. The last line has the state digest the run ended on, for the replay to check it got there too.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path;
+use std::result;
+use std::collections::{BTreeMap, VecDeque};
+use serialize::json::Json;
+use core::geometry::Position;
+use app::Event;
+
+pub type Version = u32;
+
+pub const CURRENT_VERSION: Version = 1;
+
+const MAGIC: &'static str = "#rust-oids-replay";
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Format(String),
+	Unsupported(Version),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			&Error::Io(ref e) => write!(f, "I/O error: {}", e),
+			&Error::Format(ref e) => write!(f, "malformed recording: {}", e),
+			&Error::Unsupported(version) => {
+				write!(f,
+				       "recording version {} is not supported (current is {})",
+				       version,
+				       CURRENT_VERSION)
+			}
+		}
+	}
+}
+
+/// What a recording knows of the world it started from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Start {
+	pub seed: u64,
+	pub minions: usize,
+	pub resources: usize,
+	pub enemies: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+	Event(Event),
+	/// the world was reset, and got this seed
+	Reset(u64),
+}
+
+/// Whether an event changes the world, and so has to be recorded; the camera and the looks are left out
+pub fn is_recorded(e: &Event) -> bool {
+	match *e {
+		Event::NewMinion(_) |
+		Event::RandomizeMinion(_) |
+		Event::NewResource(_) |
+		Event::NewEmitter(_) |
+		Event::SelectMinion(_, _) |
+		Event::DeselectAll |
+		Event::ShatterSelected |
+		Event::CloneSelected |
+		Event::FeedSelected |
+		Event::ToggleAi |
+		Event::GrabAgent(_, _) |
+		Event::MoveGrabbed(_) |
+		Event::Fling(_) |
+		Event::ResetWorld => true,
+		_ => false,
+	}
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+	Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<BTreeMap<_, _>>())
+}
+
+fn missing(field: &str) -> Error {
+	Error::Format(format!("missing or invalid field \"{}\"", field))
+}
+
+/// `None` for events with nothing to replay, like a bare `ResetWorld` which is recorded as the `Reset` it led to
+fn encode(tick: u32, input: &Input) -> Option<Json> {
+	let at = |name: &str, p: Position| {
+		vec![("tick", Json::U64(tick as u64)),
+		     ("input", Json::String(name.to_string())),
+		     ("x", Json::F64(p.x as f64)),
+		     ("y", Json::F64(p.y as f64))]
+	};
+	let named = |name: &str| vec![("tick", Json::U64(tick as u64)), ("input", Json::String(name.to_string()))];
+	let fields = match *input {
+		Input::Event(Event::NewMinion(p)) => at("NewMinion", p),
+		Input::Event(Event::RandomizeMinion(p)) => at("RandomizeMinion", p),
+		Input::Event(Event::NewResource(p)) => at("NewResource", p),
+		Input::Event(Event::NewEmitter(p)) => at("NewEmitter", p),
+		Input::Event(Event::SelectMinion(p, id)) => {
+			let mut fields = at("SelectMinion", p);
+			fields.push(("id", Json::U64(id as u64)));
+			fields
+		}
+		Input::Event(Event::GrabAgent(p, id)) => {
+			let mut fields = at("GrabAgent", p);
+			fields.push(("id", Json::U64(id as u64)));
+			fields
+		}
+		Input::Event(Event::MoveGrabbed(p)) => at("MoveGrabbed", p),
+		Input::Event(Event::Fling(v)) => at("Fling", v),
+		Input::Event(Event::DeselectAll) => named("DeselectAll"),
+		Input::Event(Event::ShatterSelected) => named("ShatterSelected"),
+		Input::Event(Event::CloneSelected) => named("CloneSelected"),
+		Input::Event(Event::FeedSelected) => named("FeedSelected"),
+		Input::Event(Event::ToggleAi) => named("ToggleAi"),
+		Input::Event(_) => return None,
+		Input::Reset(seed) => {
+			let mut fields = named("Reset");
+			fields.push(("seed", Json::U64(seed)));
+			fields
+		}
+	};
+	Some(object(fields))
+}
+
+fn decode(json: &Json) -> Result<(u32, Input)> {
+	let u64_field = |name: &str| json.find(name).and_then(|v| v.as_u64()).ok_or(missing(name));
+	let f32_field = |name: &str| json.find(name).and_then(|v| v.as_f64()).map(|v| v as f32).ok_or(missing(name));
+	let position = || -> Result<Position> { Ok(Position::new(try!(f32_field("x")), try!(f32_field("y")))) };
+	let tick = try!(u64_field("tick")) as u32;
+	let name = try!(json.find("input").and_then(|v| v.as_string()).ok_or(missing("input")));
+	let input = match name {
+		"NewMinion" => Input::Event(Event::NewMinion(try!(position()))),
+		"RandomizeMinion" => Input::Event(Event::RandomizeMinion(try!(position()))),
+		"NewResource" => Input::Event(Event::NewResource(try!(position()))),
+		"NewEmitter" => Input::Event(Event::NewEmitter(try!(position()))),
+		"SelectMinion" => Input::Event(Event::SelectMinion(try!(position()), try!(u64_field("id")) as usize)),
+		"GrabAgent" => Input::Event(Event::GrabAgent(try!(position()), try!(u64_field("id")) as usize)),
+		"MoveGrabbed" => Input::Event(Event::MoveGrabbed(try!(position()))),
+		"Fling" => Input::Event(Event::Fling(try!(position()))),
+		"DeselectAll" => Input::Event(Event::DeselectAll),
+		"ShatterSelected" => Input::Event(Event::ShatterSelected),
+		"CloneSelected" => Input::Event(Event::CloneSelected),
+		"FeedSelected" => Input::Event(Event::FeedSelected),
+		"ToggleAi" => Input::Event(Event::ToggleAi),
+		"Reset" => Input::Reset(try!(u64_field("seed"))),
+		other => return Err(Error::Format(format!("unknown input {}", other))),
+	};
+	Ok((tick, input))
+}
+
+/// Writes a recording as the run goes, so that a crash loses nothing but the final digest
+pub struct Recorder {
+	out: io::BufWriter<fs::File>,
+	inputs: usize,
+}
+
+impl Recorder {
+	pub fn start(path: &path::Path, start: &Start) -> io::Result<Recorder> {
+		let mut out = io::BufWriter::new(try!(fs::File::create(path)));
+		try!(write!(out, "{} {}\n", MAGIC, CURRENT_VERSION));
+		try!(writeln!(out,
+		              "{}",
+		              object(vec![("seed", Json::U64(start.seed)),
+		                          ("minions", Json::U64(start.minions as u64)),
+		                          ("resources", Json::U64(start.resources as u64)),
+		                          ("enemies", Json::U64(start.enemies as u64))])));
+		try!(out.flush());
+		Ok(Recorder {
+			out: out,
+			inputs: 0,
+		})
+	}
+
+	/// Keeps an input that reached the world before `tick` ran
+	pub fn record(&mut self, tick: u32, input: &Input) -> io::Result<()> {
+		match encode(tick, input) {
+			Some(json) => {
+				self.inputs += 1;
+				try!(writeln!(self.out, "{}", json));
+				self.out.flush()
+			}
+			None => Ok(()),
+		}
+	}
+
+	/// Closes the recording on the world's state digest after `tick` ticks, returning how many inputs it holds
+	pub fn stop(mut self, tick: u32, digest: u64) -> io::Result<usize> {
+		try!(writeln!(self.out,
+		              "{}",
+		              object(vec![("end", Json::U64(tick as u64)), ("digest", Json::U64(digest))])));
+		try!(self.out.flush());
+		Ok(self.inputs)
+	}
+}
+
+/// Where and how a recorded run ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct End {
+	pub tick: u32,
+	pub digest: u64,
+}
+
+pub struct Player {
+	start: Start,
+	inputs: VecDeque<(u32, Input)>,
+	end: Option<End>,
+}
+
+impl Player {
+	pub fn load(path: &path::Path) -> Result<Player> {
+		Self::read(io::BufReader::new(try!(fs::File::open(path))))
+	}
+
+	fn read<R: BufRead>(reader: R) -> Result<Player> {
+		let mut lines = reader.lines();
+		let header = try!(lines.next().unwrap_or_else(|| Ok(String::new())));
+		if !header.starts_with(MAGIC) {
+			return Err(Error::Format("not a recording".to_string()));
+		}
+		let version = try!(header[MAGIC.len()..]
+			.trim()
+			.parse::<Version>()
+			.map_err(|e| Error::Format(e.to_string())));
+		if version != CURRENT_VERSION {
+			return Err(Error::Unsupported(version));
+		}
+		let mut start = None;
+		let mut inputs = VecDeque::new();
+		let mut end = None;
+		for line in lines {
+			let line = try!(line);
+			let json = try!(Json::from_str(&line).map_err(|e| Error::Format(e.to_string())));
+			let u64_field = |name: &str| json.find(name).and_then(|v| v.as_u64()).ok_or(missing(name));
+			if start.is_none() {
+				start = Some(Start {
+					seed: try!(u64_field("seed")),
+					minions: try!(u64_field("minions")) as usize,
+					resources: try!(u64_field("resources")) as usize,
+					// recorded before there were enemies
+					enemies: json.find("enemies").and_then(|v| v.as_u64()).unwrap_or(0) as usize,
+				});
+			} else if json.find("end").is_some() {
+				end = Some(End {
+					tick: try!(u64_field("end")) as u32,
+					digest: try!(u64_field("digest")),
+				});
+			} else {
+				inputs.push_back(try!(decode(&json)));
+			}
+		}
+		match start {
+			Some(start) => {
+				Ok(Player {
+					start: start,
+					inputs: inputs,
+					end: end,
+				})
+			}
+			None => Err(missing("seed")),
+		}
+	}
+
+	pub fn start(&self) -> &Start {
+		&self.start
+	}
+
+	/// `None` if the recording was cut short
+	pub fn end(&self) -> Option<End> {
+		self.end
+	}
+
+	/// The inputs to feed the world before `tick` runs, in the order they were recorded
+	pub fn take(&mut self, tick: u32) -> Vec<Input> {
+		let mut due = Vec::new();
+		while self.inputs.front().map_or(false, |&(t, _)| t <= tick) {
+			due.extend(self.inputs.pop_front().map(|(_, input)| input));
+		}
+		due
+	}
+
+	/// Done once the recorded run's last tick has been played back
+	pub fn is_finished(&self, tick: u32) -> bool {
+		self.end.map_or(false, |end| tick >= end.tick)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::fs;
+	use std::io;
+	use core::geometry::Position;
+	use app::Event;
+
+	#[test]
+	fn recordings_play_back_what_they_recorded() {
+		let file = env::temp_dir().join("rust-oids-replay-test.txt");
+		let start = Start {
+			seed: 42,
+			minions: 3,
+			resources: 10,
+			enemies: 1,
+		};
+		let inputs = vec![(0, Input::Event(Event::NewMinion(Position::new(1.5, -0.1)))),
+		                  (0, Input::Event(Event::SelectMinion(Position::new(0.3, 7.), 12))),
+		                  (5, Input::Event(Event::CloneSelected)),
+		                  (7, Input::Event(Event::NewEmitter(Position::new(-2., 3.)))),
+		                  (8, Input::Event(Event::Fling(Position::new(12., -0.5)))),
+		                  (9, Input::Reset(1 << 40)),
+		                  (9, Input::Event(Event::ToggleAi))];
+		let mut recorder = Recorder::start(&file, &start).unwrap();
+		for &(tick, ref input) in &inputs {
+			recorder.record(tick, input).unwrap();
+		}
+		assert_eq!(7, recorder.stop(20, 1234).unwrap());
+
+		let mut player = Player::load(&file).unwrap();
+		assert_eq!(&start, player.start());
+		assert_eq!(Some(End {
+			           tick: 20,
+			           digest: 1234,
+		           }),
+		           player.end());
+		assert_eq!(vec![inputs[0].1, inputs[1].1], player.take(0));
+		assert!(player.take(4).is_empty());
+		assert_eq!(inputs[2..].iter().map(|&(_, input)| input).collect::<Vec<_>>(), player.take(12));
+		assert!(!player.is_finished(19));
+		assert!(player.is_finished(20));
+		fs::remove_file(&file).unwrap();
+	}
+
+	#[test]
+	fn foreign_recordings_are_refused() {
+		let read = |text: &str| Player::read(io::Cursor::new(text.as_bytes().to_vec()));
+		let start = "{\"seed\":1,\"minions\":0,\"resources\":0}\n";
+		match read(&format!("#rust-oids-replay 2\n{}", start)) {
+			Err(Error::Unsupported(2)) => {}
+			_ => panic!("a newer recording was accepted"),
+		}
+		match read("#rust-oids 3\n{}\n") {
+			Err(Error::Format(_)) => {}
+			_ => panic!("a save was taken for a recording"),
+		}
+		match read(&format!("#rust-oids-replay 1\n{}{{\"tick\":0,\"input\":\"Fly\"}}\n", start)) {
+			Err(Error::Format(_)) => {}
+			_ => panic!("an unknown input was accepted"),
+		}
+	}
+}
@@ -0,0 +1,407 @@
+//! Simulation tuning parameters, pulled by the systems from the world every frame.
+//! They can be read from a JSON file, where any missing field keeps its default,
+//! and a `ConfigWatcher` picks up edits to that file while the simulation runs.
+use std::io;
+use std::io::Read;
+use std::fs;
+use std::path;
+use std::time;
+use serialize::json::Json;
+use core::clock::*;
+use backend::spatial;
+use backend::world::agent::{BrainSpec, N_INPUTS, N_WEIGHTS};
+
+#[derive(Clone, Debug)]
+pub struct FenceConfig {
+	/// collide with a hard wall along the extent
+	pub rigid: bool,
+	/// distance from the extent at which the repulsion kicks in, zero disables it
+	pub onset: f32,
+	/// acceleration pushing back at the extent
+	pub strength: f32,
+	/// shape of the force profile over the onset distance, 1 is linear
+	pub exponent: f32,
+}
+
+impl Default for FenceConfig {
+	fn default() -> Self {
+		FenceConfig {
+			rigid: true,
+			onset: 8.,
+			strength: 40.,
+			exponent: 2.,
+		}
+	}
+}
+
+impl FenceConfig {
+	/// Repulsive acceleration at `distance` from the extent
+	pub fn repulsion(&self, distance: f32) -> f32 {
+		if self.onset <= 0. || distance >= self.onset {
+			0.
+		} else {
+			self.strength * (1. - distance.max(0.) / self.onset).powf(self.exponent)
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct BoredomConfig {
+	/// growth per second of an unfed minion's search range, relative to its sensor range
+	pub rate: f32,
+	/// cap on the growth, the search range never exceeds `1 + max` times the sensor range
+	pub max: f32,
+}
+
+impl Default for BoredomConfig {
+	fn default() -> Self {
+		BoredomConfig {
+			rate: 0.05,
+			max: 4.,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct EnergyConfig {
+	/// energy per second a moving actuator burns, per unit of its area
+	pub drain_rate: f32,
+	/// energy per second a segment gains while touching a resource
+	pub refill_rate: f32,
+}
+
+impl Default for EnergyConfig {
+	fn default() -> Self {
+		EnergyConfig {
+			drain_rate: 2.,
+			refill_rate: 5.,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ReproductionConfig {
+	/// share of its most energy a minion needs to bud, above 1 it never does
+	pub threshold: f32,
+	/// share of its most energy a minion gives up for each child
+	pub cost: f32,
+	/// how far behind the parent's tail the child is placed
+	pub spacing: f32,
+	/// how far a child's personality strays from its parent's, see `Brain::mutate`
+	pub mutation_rate: f32,
+}
+
+impl Default for ReproductionConfig {
+	fn default() -> Self {
+		ReproductionConfig {
+			threshold: 0.9,
+			cost: 0.5,
+			spacing: 2.,
+			mutation_rate: 0.05,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ProjectileConfig {
+	/// charge a shot takes from the weapon segment, which has to hold at least as much to fire
+	pub charge: f32,
+	/// speed a projectile leaves the weapon at, along its facing
+	pub speed: f32,
+	/// seconds a projectile flies before it's gone, if it hit nothing
+	pub lifetime: f32,
+	/// farthest a target may be for a shot at it
+	pub range: f32,
+}
+
+impl Default for ProjectileConfig {
+	fn default() -> Self {
+		ProjectileConfig {
+			charge: 0.2,
+			speed: 40.,
+			lifetime: 1.5,
+			range: 30.,
+		}
+	}
+}
+
+/// What segments do once their charge reaches its target, before starting over from their recharge level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChargeConfig {
+	/// seconds the charge holds at the target first
+	pub refractory: f32,
+	/// seconds it then takes to get back to the recharge level, zero snaps back at once
+	pub ramp: f32,
+}
+
+impl Default for ChargeConfig {
+	fn default() -> Self {
+		ChargeConfig {
+			refractory: 0.,
+			ramp: 0.,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+	pub fence: FenceConfig,
+	pub boredom: BoredomConfig,
+	pub energy: EnergyConfig,
+	pub reproduction: ReproductionConfig,
+	pub projectile: ProjectileConfig,
+	/// applies to agents spawned from then on
+	pub charge: ChargeConfig,
+	/// inset from the extent that spawners keep clear, so nothing is born against the fence
+	pub spawn_margin: f32,
+	/// inset from the extent beyond which the camera won't show
+	pub camera_margin: f32,
+	/// scales the force of the minions' actuators
+	pub power_boost: f32,
+	/// scales the energy the minions burn to keep their segments charged
+	pub metabolic_rate: f32,
+	/// share of an agent's total charge its actuators may draw in a tick, more demand gets scaled down
+	pub effort_budget: f32,
+	/// deceleration of a braking segment, as a fraction of its speed per second
+	pub brake_coefficient: f32,
+	/// share of the forward force thrusters can also push backwards with, zero keeps them forward only
+	pub reverse_thrust: f32,
+	/// seconds segments take to grow to their full size once born, drawn smaller until then, zero draws them
+	/// full size from the start; the bodies are full size either way
+	pub growth_time: f32,
+	/// most segments an agent may develop, whatever its genome, applies to agents spawned from then on
+	pub max_segments: usize,
+	/// seconds the remains of a shattered agent drift for, fading out, before they are gone
+	pub remains_ttl: f32,
+	/// ticks a segment goes on reporting what it last touched, counting the tick of the contact
+	pub touch_memory: u32,
+	/// how the AI looks up the targets around a minion, `grid` or `quadtree` for crowded worlds;
+	/// the physics keeps the broadphase of Box2D
+	pub broadphase: spatial::Kind,
+	/// shape of the network minions and enemies are born with, drawn at random rather than inherited;
+	/// `None` keeps the one their genome encodes. Applies to agents spawned from then on
+	pub brain: Option<BrainSpec>,
+}
+
+impl Default for SimConfig {
+	fn default() -> Self {
+		SimConfig {
+			fence: FenceConfig::default(),
+			boredom: BoredomConfig::default(),
+			energy: EnergyConfig::default(),
+			reproduction: ReproductionConfig::default(),
+			projectile: ProjectileConfig::default(),
+			charge: ChargeConfig::default(),
+			spawn_margin: 4.,
+			camera_margin: 0.,
+			power_boost: 100.,
+			metabolic_rate: 1.,
+			effort_budget: 0.5,
+			brake_coefficient: 4.,
+			reverse_thrust: 0.,
+			growth_time: 1.,
+			max_segments: 32,
+			remains_ttl: 10.,
+			touch_memory: 1,
+			broadphase: spatial::Kind::Grid,
+			brain: None,
+		}
+	}
+}
+
+impl SimConfig {
+	pub fn load(path: &path::Path) -> io::Result<SimConfig> {
+		let mut text = String::new();
+		try!(try!(fs::File::open(path)).read_to_string(&mut text));
+		let json = try!(Json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+		Ok(Self::from_json(&json))
+	}
+
+	pub fn from_json(json: &Json) -> SimConfig {
+		fn float(json: &Json, name: &str, default: f32) -> f32 {
+			json.find(name).and_then(|v| v.as_f64()).map_or(default, |v| v as f32)
+		}
+		let mut config = SimConfig::default();
+		if let Some(fence) = json.find("fence") {
+			config.fence = FenceConfig {
+				rigid: fence.find("rigid").and_then(|v| v.as_boolean()).unwrap_or(config.fence.rigid),
+				onset: float(fence, "onset", config.fence.onset),
+				strength: float(fence, "strength", config.fence.strength),
+				exponent: float(fence, "exponent", config.fence.exponent),
+			};
+		}
+		if let Some(boredom) = json.find("boredom") {
+			config.boredom = BoredomConfig {
+				rate: float(boredom, "rate", config.boredom.rate),
+				max: float(boredom, "max", config.boredom.max),
+			};
+		}
+		if let Some(energy) = json.find("energy") {
+			config.energy = EnergyConfig {
+				drain_rate: float(energy, "drain_rate", config.energy.drain_rate),
+				refill_rate: float(energy, "refill_rate", config.energy.refill_rate),
+			};
+		}
+		if let Some(reproduction) = json.find("reproduction") {
+			config.reproduction = ReproductionConfig {
+				threshold: float(reproduction, "threshold", config.reproduction.threshold),
+				cost: float(reproduction, "cost", config.reproduction.cost),
+				spacing: float(reproduction, "spacing", config.reproduction.spacing),
+				mutation_rate: float(reproduction, "mutation_rate", config.reproduction.mutation_rate),
+			};
+		}
+		if let Some(projectile) = json.find("projectile") {
+			config.projectile = ProjectileConfig {
+				charge: float(projectile, "charge", config.projectile.charge),
+				speed: float(projectile, "speed", config.projectile.speed),
+				lifetime: float(projectile, "lifetime", config.projectile.lifetime),
+				range: float(projectile, "range", config.projectile.range),
+			};
+		}
+		if let Some(charge) = json.find("charge") {
+			config.charge = ChargeConfig {
+				refractory: float(charge, "refractory", config.charge.refractory),
+				ramp: float(charge, "ramp", config.charge.ramp),
+			};
+		}
+		config.spawn_margin = float(json, "spawn_margin", config.spawn_margin);
+		config.camera_margin = float(json, "camera_margin", config.camera_margin);
+		config.power_boost = float(json, "power_boost", config.power_boost);
+		config.metabolic_rate = float(json, "metabolic_rate", config.metabolic_rate);
+		config.effort_budget = float(json, "effort_budget", config.effort_budget);
+		config.remains_ttl = float(json, "remains_ttl", config.remains_ttl);
+		config.brake_coefficient = float(json, "brake_coefficient", config.brake_coefficient);
+		config.reverse_thrust = float(json, "reverse_thrust", config.reverse_thrust);
+		config.growth_time = float(json, "growth_time", config.growth_time);
+		if let Some(max_segments) = json.find("max_segments").and_then(|v| v.as_u64()) {
+			config.max_segments = max_segments as usize;
+		}
+		if let Some(touch_memory) = json.find("touch_memory").and_then(|v| v.as_u64()) {
+			config.touch_memory = touch_memory as u32;
+		}
+		if let Some(broadphase) = json.find("broadphase").and_then(|v| v.as_string()) {
+			match spatial::Kind::from_name(broadphase) {
+				Some(kind) => config.broadphase = kind,
+				None => warn!("Unknown broadphase {}, keeping {:?}", broadphase, config.broadphase),
+			}
+		}
+		if let Some(brain) = json.find("brain") {
+			let width = |name: &str| brain.find(name).and_then(|v| v.as_u64()).map(|v| v as usize);
+			let hidden = brain.find("hidden").and_then(|v| v.as_array()).map_or(Vec::new(), |hidden| {
+				hidden.iter().filter_map(|v| v.as_u64()).map(|v| v as usize).collect()
+			});
+			let spec = BrainSpec {
+				inputs: width("inputs").unwrap_or(N_INPUTS),
+				hidden: hidden,
+				outputs: width("outputs").unwrap_or(N_WEIGHTS),
+			};
+			if spec.is_valid() {
+				config.brain = Some(spec);
+			} else {
+				warn!("Brain {:?} has an empty layer, keeping the genome's", spec);
+			}
+		}
+		config
+	}
+}
+
+/// Seconds between two looks at the file's modification time
+const POLL_INTERVAL: f32 = 0.5;
+
+/// Reloads a config file whenever its modification time changes.
+/// The systems pull the config from the world every frame, so most values apply on the next one;
+/// `fence.rigid` only takes effect when the world is rebuilt.
+pub struct ConfigWatcher {
+	path: path::PathBuf,
+	modified: Option<time::SystemTime>,
+	last_poll: SystemStopwatch,
+}
+
+impl ConfigWatcher {
+	pub fn new(path: &path::Path) -> Self {
+		ConfigWatcher {
+			path: path.to_owned(),
+			modified: Self::modified(path),
+			last_poll: SystemStopwatch::new(),
+		}
+	}
+
+	fn modified(path: &path::Path) -> Option<time::SystemTime> {
+		fs::metadata(path).and_then(|m| m.modified()).ok()
+	}
+
+	/// Called every frame, returns the new config once the file has changed and parses.
+	/// A broken file is logged and skipped, so the last good config stays in place.
+	pub fn poll(&mut self) -> Option<SimConfig> {
+		if self.last_poll.seconds() < POLL_INTERVAL {
+			return None;
+		}
+		self.last_poll.reset();
+		self.reload_if_changed()
+	}
+
+	fn reload_if_changed(&mut self) -> Option<SimConfig> {
+		let modified = Self::modified(&self.path);
+		if modified.is_none() || modified == self.modified {
+			return None;
+		}
+		self.modified = modified;
+		match SimConfig::load(&self.path) {
+			Ok(config) => {
+				info!("Reloaded config from {}", self.path.display());
+				Some(config)
+			}
+			Err(e) => {
+				error!("Keeping the current config, {} is invalid: {}", self.path.display(), e);
+				None
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serialize::json::Json;
+
+	#[test]
+	fn watcher_picks_up_changes() {
+		use std::env;
+		use std::io::Write;
+		let path = env::temp_dir().join("rust-oids-config-watch-test.json");
+		fs::File::create(&path).unwrap().write_all(br#"{"power_boost": 50}"#).unwrap();
+		let mut watcher = ConfigWatcher::new(&path);
+		assert!(watcher.reload_if_changed().is_none());
+
+		fs::File::create(&path).unwrap().write_all(br#"{"power_boost": 75}"#).unwrap();
+		// file times can be too coarse to tell two quick writes apart
+		watcher.modified = None;
+		assert_eq!(75., watcher.reload_if_changed().unwrap().power_boost);
+
+		fs::File::create(&path).unwrap().write_all(b"{\"power_boost\": ").unwrap();
+		watcher.modified = None;
+		assert!(watcher.reload_if_changed().is_none());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn missing_fields_keep_defaults() {
+		let config = SimConfig::from_json(&Json::from_str(r#"{"fence": {"rigid": false, "onset": 4}}"#).unwrap());
+		assert!(!config.fence.rigid);
+		assert_eq!(4., config.fence.onset);
+		assert_eq!(FenceConfig::default().strength, config.fence.strength);
+		assert_eq!(spatial::Kind::Grid, config.broadphase);
+		assert_eq!(ChargeConfig::default(), config.charge);
+		let config = SimConfig::from_json(&Json::from_str(r#"{"charge": {"ramp": 0.25}}"#).unwrap());
+		assert_eq!(0.25, config.charge.ramp);
+		assert_eq!(0., config.charge.refractory);
+		let config = SimConfig::from_json(&Json::from_str(r#"{"broadphase": "quadtree"}"#).unwrap());
+		assert_eq!(spatial::Kind::QuadTree, config.broadphase);
+		assert!(config.brain.is_none());
+		let config = SimConfig::from_json(&Json::from_str(r#"{"brain": {"hidden": [8, 6]}}"#).unwrap());
+		assert_eq!(Some(vec![8, 6]), config.brain.as_ref().map(|spec| spec.hidden.clone()));
+		assert_eq!(Some(N_INPUTS), config.brain.as_ref().map(|spec| spec.inputs));
+		let config = SimConfig::from_json(&Json::from_str(r#"{"brain": {"hidden": [8, 0]}}"#).unwrap());
+		assert!(config.brain.is_none());
+	}
+}
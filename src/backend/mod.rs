@@ -1,3 +1,5 @@
 pub mod obj;
+pub mod config;
 pub mod world;
 pub mod systems;
+pub mod spatial;
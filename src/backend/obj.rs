@@ -1,6 +1,11 @@
+use std::f32;
+use std::mem;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
 use std::f32::consts::*;
 use core::geometry::*;
 use core::color;
+use cgmath::EuclideanVector;
 
 pub type Rgba = color::Rgba<f32>;
 
@@ -8,9 +13,18 @@ pub type Id = usize;
 pub type SegmentIndex = u8;
 pub type BoneIndex = u8;
 pub type AttachmentIndex = u8;
+/// Outside the physics a body goes by the id of its agent, the physics system keys each segment's body by its
+/// `agent::Key`
 pub type PhysicsHandle = Id;
 
-#[derive(Clone)]
+/// smallest radius and arm ratio of a star
+const MIN_STAR_RATIO: f32 = 0.01;
+/// most arms of a star, so that its vertices can be counted in a u8
+const MAX_STAR_ARMS: u8 = 127;
+/// thickest extrusion of a mesh, as a fraction of its radius
+const MAX_EXTRUSION: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Shape {
 	Ball { radius: f32 },
 	Box { radius: f32, ratio: f32 },
@@ -26,6 +40,11 @@ pub enum Shape {
 		angle1: f32,
 		angle2: f32,
 	},
+	/// any convex polygon, its points clockwise around the centroid and at most 1 from it
+	Polygon {
+		radius: f32,
+		points: Box<[Position]>,
+	},
 }
 
 impl Shape {
@@ -36,6 +55,7 @@ impl Shape {
 			&Shape::Star { radius, .. } => radius,
 			&Shape::Poly { radius, .. } => radius,
 			&Shape::Triangle { radius, .. } => radius,
+			&Shape::Polygon { radius, .. } => radius,
 		}
 	}
 
@@ -46,6 +66,7 @@ impl Shape {
 			&Shape::Poly { n, .. } => n.abs() as usize * 2,
 			&Shape::Star { n, .. } => n as usize * 2,
 			&Shape::Triangle { .. } => 3,
+			&Shape::Polygon { ref points, .. } => points.len(),
 		}
 	}
 
@@ -56,12 +77,105 @@ impl Shape {
 			&Shape::Poly { .. } => true,
 			&Shape::Star { .. } => false,
 			&Shape::Triangle { .. } => true,
+			&Shape::Polygon { .. } => true,
 		}
 	}
 
 	pub fn mid(&self) -> isize {
 		self.length() as isize / 2
 	}
+
+	/// Area in world units, the right-handed outline of the shape being as large as the left-handed one
+	pub fn area(&self) -> f32 {
+		match self {
+			&Shape::Ball { radius } => PI * radius * radius,
+			&Shape::Box { radius, ratio } => 4. * ratio * radius * radius,
+			_ => signed_area2(&self.vertices(Winding::CW)).abs() * 0.5 * self.radius() * self.radius(),
+		}
+	}
+
+	/// The same outline at another radius
+	pub fn with_radius(&self, radius: f32) -> Shape {
+		match self.clone() {
+			Shape::Ball { .. } => Shape::Ball { radius: radius },
+			Shape::Box { ratio, .. } => Shape::Box { radius: radius, ratio: ratio },
+			Shape::Star { n, ratio1, ratio2, .. } => {
+				Shape::Star {
+					radius: radius,
+					n: n,
+					ratio1: ratio1,
+					ratio2: ratio2,
+				}
+			}
+			Shape::Poly { n, .. } => Shape::Poly { radius: radius, n: n },
+			Shape::Triangle { angle1, angle2, .. } => {
+				Shape::Triangle {
+					radius: radius,
+					angle1: angle1,
+					angle2: angle2,
+				}
+			}
+			Shape::Polygon { points, .. } => Shape::Polygon { radius: radius, points: points },
+		}
+	}
+
+	/// `t` of the way from this shape to `other`, blending every parameter of shapes of the same kind; stars,
+	/// polys and polygons also need as many arms, sides or points. Anything else takes the outline of `other`,
+	/// only scaled by the blended radius.
+	pub fn lerp(&self, other: &Shape, t: f32) -> Shape {
+		let mix = |a: f32, b: f32| a * (1. - t) + b * t;
+		match (self, other) {
+			(&Shape::Ball { radius: r0 }, &Shape::Ball { radius: r1 }) => Shape::new_ball(mix(r0, r1)),
+			(&Shape::Box { radius: r0, ratio: q0 }, &Shape::Box { radius: r1, ratio: q1 }) => {
+				Shape::new_box(mix(r0, r1), mix(q0, q1))
+			}
+			(&Shape::Star { radius: r0, n: n0, ratio1: a0, ratio2: b0 },
+			 &Shape::Star { radius: r1, n: n1, ratio1: a1, ratio2: b1 }) if n0 == n1 => {
+				Shape::Star {
+					radius: mix(r0, r1),
+					n: n1,
+					ratio1: mix(a0, a1),
+					ratio2: mix(b0, b1),
+				}
+			}
+			(&Shape::Poly { radius: r0, n: n0 }, &Shape::Poly { radius: r1, n: n1 }) if n0 == n1 => {
+				Shape::Poly {
+					radius: mix(r0, r1),
+					n: n1,
+				}
+			}
+			(&Shape::Triangle { radius: r0, angle1: a0, angle2: b0 },
+			 &Shape::Triangle { radius: r1, angle1: a1, angle2: b1 }) => {
+				Shape::Triangle {
+					radius: mix(r0, r1),
+					angle1: mix(a0, a1),
+					angle2: mix(b0, b1),
+				}
+			}
+			(&Shape::Polygon { radius: r0, points: ref p0 }, &Shape::Polygon { radius: r1, points: ref p1 })
+				if p0.len() == p1.len() => {
+				Shape::Polygon {
+					radius: mix(r0, r1),
+					points: p0.iter()
+						.zip(p1.iter())
+						.map(|(a, b)| Position::new(mix(a.x, b.x), mix(a.y, b.y)))
+						.collect::<Vec<_>>()
+						.into_boxed_slice(),
+				}
+			}
+			_ => other.with_radius(mix(self.radius(), other.radius())),
+		}
+	}
+
+	/// Centre of mass relative to the segment's origin, for the right-handed outline; the left-handed one
+	/// mirrors it in x
+	pub fn centroid(&self) -> Position {
+		match self {
+			&Shape::Ball { .. } |
+			&Shape::Box { .. } => origin(),
+			_ => polygon_centroid(&self.vertices(Winding::CW)) * self.radius(),
+		}
+	}
 }
 
 #[derive(Clone, Copy)]
@@ -89,16 +203,15 @@ impl Shape {
 		}
 	}
 
+	/// Parameters out of range are clamped rather than rejected, genomes can spell out anything: a star has
+	/// two to `MAX_STAR_ARMS` arms, and each pair of arms is no longer than the one before, however precise the ratios.
 	pub fn new_star(n: u8, radius: f32, ratio1: f32, ratio2: f32) -> Self {
-		assert!(n > 1);
-		assert!(radius > 0.);
-		assert!(ratio1 > 0.);
-		assert!(ratio2 > 0.);
-		assert!(ratio1 * ratio2 <= 1.);
-
+		// max and min also turn NaN into the bound
+		let ratio1 = ratio1.max(MIN_STAR_RATIO).min(1. / MIN_STAR_RATIO);
+		let ratio2 = ratio2.max(MIN_STAR_RATIO).min(1. / ratio1);
 		Shape::Star {
-			radius: radius,
-			n: n,
+			radius: radius.max(MIN_STAR_RATIO).min(f32::MAX),
+			n: ::std::cmp::min(::std::cmp::max(n, 2), MAX_STAR_ARMS),
 			ratio1: ratio1,
 			ratio2: ratio2,
 		}
@@ -121,6 +234,35 @@ impl Shape {
 		}
 	}
 
+	/// A polygon through `points`, which the physics needs to be convex: a concave outline is replaced by its
+	/// convex hull. The shape is centred on the centroid, its radius the distance to the farthest point, and
+	/// its first vertex the topmost one, where anything attached at 0 joins it.
+	/// `None` when the points don't enclose any area, for the caller to reject.
+	#[allow(dead_code)]
+	pub fn new_polygon(points: Vec<Position>) -> Option<Self> {
+		let mut points = if PolygonType::classify(&points).is_convex() {
+			points
+		} else {
+			warn!("concave polygon of {} points replaced by its convex hull", points.len());
+			convex_hull(&points)
+		};
+		if points.len() < 3 || signed_area2(&points) == 0. {
+			return None;
+		}
+		// clockwise, as the other shapes wind
+		if signed_area2(&points) > 0. {
+			points.reverse();
+		}
+		let n = points.len();
+		let centroid = polygon_centroid(&points);
+		let radius = points.iter().fold(0., |r, p| f32::max(r, (*p - centroid).length()));
+		let top = (0..n).fold(0, |top, i| if points[i].y > points[top].y { i } else { top });
+		Some(Shape::Polygon {
+			radius: radius,
+			points: (0..n).map(|i| (points[(top + i) % n] - centroid) / radius).collect::<Vec<_>>().into_boxed_slice(),
+		})
+	}
+
 	pub fn vertices(&self, winding: Winding) -> Box<[Position]> {
 		let xunit = winding.xunit();
 		match self {
@@ -160,20 +302,32 @@ impl Shape {
 				&Shape::Star { n, ratio1, ratio2, .. } => {
 					let mut damp = 1.;
 					let ratio = &[ratio1, ratio2];
-					(0..(2 * n))
+					let star = (0..(2 * n))
 						.map(|i| {
 							let p = i as f32 * (PI / n as f32);
 							let r = f32::max(damp, 0.01); // zero is bad!
 							damp *= ratio[i as usize % 2];
 							Position::new(xunit * r * f32::sin(p), r * f32::cos(p))
 						})
-						.collect()
+						.collect::<Vec<_>>();
+					if star.iter().all(|v| v.x.is_finite() && v.y.is_finite()) {
+						star
+					} else {
+						// a regular polygon with as many vertices, which attachments can still refer to
+						(0..(2 * n))
+							.map(|i| {
+								let p = i as f32 * (PI / n as f32);
+								Position::new(xunit * f32::sin(p), f32::cos(p))
+							})
+							.collect()
+					}
 				}
 				&Shape::Triangle { angle1, angle2, .. } => {
 					vec![Position::new(0., 1.),
 					     Position::new(xunit * f32::sin(angle1), f32::cos(angle1)),
 					     Position::new(xunit * f32::sin(angle2), f32::cos(angle2))]
 				}
+				&Shape::Polygon { ref points, .. } => points.iter().map(|p| Position::new(xunit * p.x, p.y)).collect(),
 			}
 			.into_boxed_slice()
 	}
@@ -187,11 +341,167 @@ bitflags! {
 	}
 }
 
+/// Splits the polygon with vertices `v` into triangles by ear clipping, so that concave outlines such as stars
+/// are filled without overlaps. Triangles are listed as index triples into `v`, n - 2 of them for n vertices.
+pub fn triangulate(v: &[Position]) -> Box<[u16]> {
+	let n = v.len();
+	if n < 3 {
+		return Box::new([]);
+	}
+	// convex vertices turn the same way the whole outline does
+	let convex = if signed_area2(v) >= 0. { VertexType::Plus } else { VertexType::Minus };
+	let mut remaining = (0..n).collect::<Vec<_>>();
+	let mut indices = Vec::with_capacity((n - 2) * 3);
+	while remaining.len() > 3 {
+		let (ear, (a, b, c)) = {
+			let m = remaining.len();
+			let corners = |k: usize| (remaining[(k + m - 1) % m], remaining[k], remaining[(k + 1) % m]);
+			let turn = |k: usize| {
+				let (a, b, c) = corners(k);
+				PolygonType::classify_vertex(&v[a], &v[b], &v[c])
+			};
+			let is_ear = |k: usize| {
+				let (a, b, c) = corners(k);
+				turn(k) == convex &&
+				remaining.iter().all(|&i| i == a || i == b || i == c || !triangle_contains(v[a], v[b], v[c], v[i]))
+			};
+			// a flat vertex makes a triangle of no area, only clipped when there's no ear left
+			let ear = (0..m)
+				.find(|&k| is_ear(k))
+				.or_else(|| (0..m).find(|&k| turn(k) == VertexType::Flat))
+				.unwrap_or(0);
+			(ear, corners(ear))
+		};
+		indices.push(a as u16);
+		indices.push(b as u16);
+		indices.push(c as u16);
+		remaining.remove(ear);
+	}
+	indices.push(remaining[0] as u16);
+	indices.push(remaining[1] as u16);
+	indices.push(remaining[2] as u16);
+	indices.into_boxed_slice()
+}
+
+/// Whether `p` is inside or on the edge of the triangle `a`, `b`, `c`, whichever way it winds
+fn triangle_contains(a: Position, b: Position, c: Position, p: Position) -> bool {
+	let d1 = (b - a).perp_dot(p - a);
+	let d2 = (c - b).perp_dot(p - b);
+	let d3 = (a - c).perp_dot(p - c);
+	!((d1 < 0. || d2 < 0. || d3 < 0.) && (d1 > 0. || d2 > 0. || d3 > 0.))
+}
+
+/// The outline of two counterclockwise pieces sharing an edge, `None` if they share none
+fn join(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+	let (na, nb) = (a.len(), b.len());
+	for k in 0..na {
+		let (x, y) = (a[k], a[(k + 1) % na]);
+		for m in 0..nb {
+			// b runs the other way along the edge they share
+			if b[m] == y && b[(m + 1) % nb] == x {
+				let mut joined = (1..na + 1).map(|i| a[(k + i) % na]).collect::<Vec<_>>();
+				joined.extend((2..nb).map(|i| b[(m + i) % nb]));
+				return Some(joined);
+			}
+		}
+	}
+	None
+}
+
+/// Splits the simple polygon with vertices `v` into convex pieces of at most `max_vertices` each, counterclockwise.
+/// A convex polygon that is small enough comes back whole; anything else is cut into triangles, which are then
+/// merged with their neighbours for as long as the pieces stay convex (Hertel-Mehlhorn).
+pub fn convex_decomposition(v: &[Position], max_vertices: usize) -> Vec<Vec<Position>> {
+	let mut ccw = v.to_vec();
+	if signed_area2(&ccw) < 0. {
+		ccw.reverse();
+	}
+	if ccw.len() <= max_vertices && PolygonType::classify(&ccw).is_convex() {
+		return vec![ccw];
+	}
+	let points = |piece: &[usize]| piece.iter().map(|&i| ccw[i]).collect::<Vec<_>>();
+	// flat triangles cover nothing and would only hold up the merging
+	let mut pieces = triangulate(&ccw)
+		.chunks(3)
+		.map(|t| t.iter().map(|&i| i as usize).collect::<Vec<_>>())
+		.filter(|t| signed_area2(&points(t)) > 0.)
+		.collect::<Vec<_>>();
+	loop {
+		let mut merged = None;
+		'search: for i in 0..pieces.len() {
+			for j in i + 1..pieces.len() {
+				if let Some(joined) = join(&pieces[i], &pieces[j]) {
+					if joined.len() <= max_vertices && PolygonType::classify(&points(&joined)).is_convex() {
+						merged = Some((i, j, joined));
+						break 'search;
+					}
+				}
+			}
+		}
+		match merged {
+			Some((i, j, joined)) => {
+				pieces[i] = joined;
+				pieces.swap_remove(j);
+			}
+			None => break,
+		}
+	}
+	pieces.iter().map(|piece| points(piece)).collect()
+}
+
+/// Outward unit normals of the polygon with vertices `v`, one per vertex, bisecting the normals of the two edges
+/// meeting there so that the rim is beveled rather than faceted.
+pub fn rim_normals(v: &[Position]) -> Box<[Position]> {
+	let n = v.len();
+	// the outward side of an edge depends on the way the outline turns
+	let outward = if signed_area2(v) >= 0. { 1. } else { -1. };
+	let edge_normal = |a: Position, b: Position| {
+		let e = b - a;
+		if e.length2() > 0. { Position::new(e.y, -e.x).normalize() * outward } else { Position::new(0., 0.) }
+	};
+	(0..n)
+		.map(|i| {
+			let (prev, this, next) = (v[(i + n - 1) % n], v[i], v[(i + 1) % n]);
+			let bisector = edge_normal(prev, this) + edge_normal(this, next);
+			if bisector.length2() > 1e-12 {
+				bisector.normalize()
+			} else if this.length2() > 0. {
+				// a spike folding back on itself, point it away from the centre
+				this.normalize()
+			} else {
+				Position::unit_y()
+			}
+		})
+		.collect::<Vec<_>>()
+		.into_boxed_slice()
+}
+
 #[derive(Clone)]
 pub struct Mesh {
 	flags: MeshFlags,
 	pub shape: Shape,
 	pub vertices: Box<[Position]>,
+	/// triangles filling the outline, as index triples into `vertices`
+	pub indices: Box<[u16]>,
+	/// outward unit normals of the rim, one per vertex
+	pub normals: Box<[Position]>,
+	/// how far the outline is raised off its rim when drawn, as a fraction of the radius; flat when 0
+	pub depth: f32,
+	/// what the mesh is told apart by when drawn, equal for meshes of the same outline and depth
+	pub key: u64,
+}
+
+/// Hashes the bits of the outline, its triangles and its depth; done once, when the mesh is built
+fn mesh_key(vertices: &[Position], indices: &[u16], depth: f32) -> u64 {
+	let bits = |x: f32| unsafe { mem::transmute::<f32, u32>(x) };
+	let mut hasher = DefaultHasher::new();
+	for v in vertices {
+		bits(v.x).hash(&mut hasher);
+		bits(v.y).hash(&mut hasher);
+	}
+	indices.hash(&mut hasher);
+	bits(depth).hash(&mut hasher);
+	hasher.finish()
 }
 
 impl Mesh {
@@ -208,13 +518,24 @@ impl Mesh {
 			let classifier = PolygonType::classify(vertices.as_ref());
 			if classifier.is_convex() { CONVEX } else { MeshFlags::empty() }
 		};
+		let indices = triangulate(&vertices);
 		Mesh {
 			shape: shape,
 			flags: winding_flags | shape_flags,
+			key: mesh_key(&vertices, &indices, 0.),
+			normals: rim_normals(&vertices),
+			indices: indices,
 			vertices: vertices,
+			depth: 0.,
 		}
 	}
 
+	/// Gives the mesh some thickness, a beveled rim of `depth` rising to a raised cap
+	pub fn with_extrusion(self, depth: f32) -> Self {
+		let depth = depth.max(0.).min(MAX_EXTRUSION);
+		Mesh { key: mesh_key(&self.vertices, &self.indices, depth), depth: depth, ..self }
+	}
+
 	#[inline]
 	#[allow(dead_code)]
 	pub fn is_convex(&self) -> bool {
@@ -286,3 +607,243 @@ pub trait Geometry {
 pub trait Drawable: Geometry {
 	fn color(&self) -> Rgba;
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::EuclideanVector;
+	use core::geometry::*;
+
+	#[test]
+	fn polygons_are_centred_and_scaled() {
+		let square = Shape::new_polygon(vec![Position::new(1., 1.),
+		                                     Position::new(3., 1.),
+		                                     Position::new(3., 3.),
+		                                     Position::new(1., 3.)]).unwrap();
+		assert!((square.radius() - 2f32.sqrt()).abs() < 1e-5);
+		assert_eq!(4, square.length());
+		let vertices = square.vertices(Winding::CW);
+		for v in vertices.iter() {
+			assert!((v.length() - 1.).abs() < 1e-5);
+		}
+		// topmost first, then clockwise
+		assert!(vertices.iter().all(|v| v.y <= vertices[0].y));
+		assert!(signed_area2(&vertices) < 0.);
+		assert!(signed_area2(&square.vertices(Winding::CCW)) > 0.);
+		assert!(Mesh::from_shape(square, Winding::CW).is_convex());
+	}
+
+	#[test]
+	fn area_matches_closed_forms() {
+		assert!((Shape::new_ball(2.).area() - 4. * PI).abs() < 1e-5);
+		assert_eq!(1., Shape::new_box(0.5, 1.).area());
+		assert_eq!(origin(), Shape::new_box(0.5, 3.).centroid());
+		// a regular polygon tends to its circumcircle
+		let many = Shape::new_poly(100, 1.);
+		assert!((many.area() - PI).abs() < 0.01);
+		assert!(many.centroid().length() < 1e-4);
+		// a square polygon, as large as the box of the same size
+		let square = Shape::new_polygon(vec![Position::new(1., 1.),
+		                                     Position::new(3., 1.),
+		                                     Position::new(3., 3.),
+		                                     Position::new(1., 3.)]).unwrap();
+		assert!((square.area() - 4.).abs() < 1e-5);
+		assert!(square.centroid().length() < 1e-5);
+		// a star's arms add less than its core circle
+		let star = Shape::new_star(5, 1., 0.5, 1.);
+		assert!(star.area() > 0. && star.area() < PI);
+	}
+
+	#[test]
+	fn stars_have_finite_vertices_whatever_the_parameters() {
+		let values = [-1., 0., 1e-30, 0.01, 0.5, 0.999, 1., 1.0001, 2., 1e30, f32::INFINITY, f32::NAN];
+		for &n in &[0u8, 1, 2, 3, 8, 255] {
+			for &radius in &values {
+				for &ratio1 in &values {
+					for &ratio2 in &values {
+						let star = Shape::new_star(n, radius, ratio1, ratio2);
+						assert!(star.radius().is_finite() && star.radius() > 0.);
+						for winding in &[Winding::CW, Winding::CCW] {
+							let vertices = star.vertices(*winding);
+							assert_eq!(star.length(), vertices.len());
+							assert!(vertices.iter().all(|v| v.x.is_finite() && v.y.is_finite()),
+							        "{:?} has a vertex that isn't finite",
+							        star);
+						}
+					}
+				}
+			}
+		}
+		// the ratios of a regular polygon drawn as a star multiply to 1 give or take an ulp
+		let ratio1 = f32::cos(PI / 11.);
+		match Shape::new_star(11, 1., 1. / ratio1, ratio1) {
+			Shape::Star { ratio1, ratio2, .. } => assert!(ratio1 * ratio2 <= 1.0001),
+			_ => unreachable!(),
+		}
+	}
+
+	fn triangle_area(v: &[Position], t: &[u16]) -> f32 {
+		signed_area2(&[v[t[0] as usize], v[t[1] as usize], v[t[2] as usize]]).abs() * 0.5
+	}
+
+	#[test]
+	fn triangulation_covers_the_outline_without_overlaps() {
+		for shape in &[Shape::new_ball(1.),
+		               Shape::new_star(5, 1., 0.5, 1.),
+		               Shape::new_star(8, 1., 0.7, 1.2),
+		               Shape::new_triangle(1., PI * 0.6, PI * 1.3),
+		               Shape::new_polygon(vec![Position::new(0., 2.),
+		                                       Position::new(2., 0.),
+		                                       Position::new(1., -2.),
+		                                       Position::new(-1., -2.),
+		                                       Position::new(-2., 0.)]).unwrap()] {
+			for winding in &[Winding::CW, Winding::CCW] {
+				let mesh = Mesh::from_shape(shape.clone(), *winding);
+				let n = mesh.vertices.len();
+				assert_eq!((n - 2) * 3, mesh.indices.len());
+				let mut covered = 0.;
+				for triangle in mesh.indices.chunks(3) {
+					let area = triangle_area(&mesh.vertices, triangle);
+					assert!(area > 1e-6, "{:?} has a triangle of no area", shape);
+					covered += area;
+				}
+				// triangles that overlapped or came out of the outline would add up to more
+				let outline = signed_area2(&mesh.vertices).abs() * 0.5;
+				assert!((covered - outline).abs() < 1e-4, "{} for {} in {:?}", covered, outline, shape);
+			}
+		}
+	}
+
+	#[test]
+	fn rim_normals_point_outwards() {
+		for shape in &[Shape::new_ball(1.),
+		               Shape::new_box(1., 0.5),
+		               Shape::new_star(5, 1., 0.5, 1.),
+		               Shape::new_triangle(1., PI * 0.6, PI * 1.3)] {
+			for winding in &[Winding::CW, Winding::CCW] {
+				let mesh = Mesh::from_shape(shape.clone(), *winding);
+				assert_eq!(mesh.vertices.len(), mesh.normals.len());
+				let n = mesh.vertices.len();
+				for (i, normal) in mesh.normals.iter().enumerate() {
+					assert!((normal.length() - 1.).abs() < 1e-5);
+					// a hair along the normal leaves the outline, a hair against it goes in
+					let (prev, this, next) = (mesh.vertices[(i + n - 1) % n],
+					                          mesh.vertices[i],
+					                          mesh.vertices[(i + 1) % n]);
+					let turn = (this - prev).perp_dot(next - this) * signed_area2(&mesh.vertices);
+					if turn > 1e-6 {
+						assert!(!polygon_contains(&mesh.vertices, this + *normal * 1e-3), "{:?} at {}", shape, i);
+						assert!(polygon_contains(&mesh.vertices, this - *normal * 1e-3), "{:?} at {}", shape, i);
+					}
+				}
+			}
+		}
+		assert_eq!(0., Mesh::from_shape(Shape::new_ball(1.), Winding::CW).depth);
+		assert_eq!(MAX_EXTRUSION, Mesh::from_shape(Shape::new_ball(1.), Winding::CW).with_extrusion(10.).depth);
+	}
+
+	#[test]
+	fn meshes_are_keyed_by_outline_and_depth() {
+		let key = |shape: Shape, depth: f32| Mesh::from_shape(shape, Winding::CW).with_extrusion(depth).key;
+		// outlines are scaled to a unit radius, so stars of any size share a mesh
+		assert_eq!(key(Shape::new_star(5, 1., 0.5, 1.), 0.1), key(Shape::new_star(5, 3., 0.5, 1.), 0.1));
+		assert!(key(Shape::new_star(5, 1., 0.5, 1.), 0.1) != key(Shape::new_star(5, 1., 0.5, 1.), 0.2));
+		assert!(key(Shape::new_star(5, 1., 0.5, 1.), 0.1) != key(Shape::new_star(6, 1., 0.5, 1.), 0.1));
+		assert!(Mesh::from_shape(Shape::new_poly(6, 1.), Winding::CW).key !=
+		        Mesh::from_shape(Shape::new_poly(6, 1.), Winding::CCW).key);
+	}
+
+	#[test]
+	fn lerp_ends_on_either_shape() {
+		let square = Shape::new_polygon(vec![Position::new(1., 1.),
+		                                     Position::new(3., 1.),
+		                                     Position::new(3., 3.),
+		                                     Position::new(1., 3.)]).unwrap();
+		let diamond = Shape::new_polygon(vec![Position::new(0., 3.),
+		                                      Position::new(2., 0.),
+		                                      Position::new(0., -3.),
+		                                      Position::new(-2., 0.)]).unwrap();
+		let pairs = [(Shape::new_ball(0.5), Shape::new_ball(2.)),
+		             (Shape::new_box(0.5, 0.2), Shape::new_box(2., 1.5)),
+		             (Shape::new_star(5, 0.5, 0.3, 1.1), Shape::new_star(5, 2., 0.8, 0.9)),
+		             (Shape::new_poly(6, 0.5), Shape::new_poly(6, 2.)),
+		             (Shape::new_triangle(0.5, PI * 0.6, PI * 1.3), Shape::new_triangle(2., PI * 0.7, PI * 1.4)),
+		             (square, diamond)];
+		for &(ref a, ref b) in &pairs {
+			assert_eq!(*a, a.lerp(b, 0.));
+			assert_eq!(*b, a.lerp(b, 1.));
+			assert!((a.lerp(b, 0.5).radius() - (a.radius() + b.radius()) * 0.5).abs() < 1e-6);
+		}
+		match Shape::new_box(1., 0.2).lerp(&Shape::new_box(1., 0.6), 0.5) {
+			Shape::Box { ratio, .. } => assert!((ratio - 0.4).abs() < 1e-6),
+			_ => unreachable!(),
+		}
+		// other kinds keep the outline they're heading for and only scale
+		let ball = Shape::new_ball(1.);
+		let star = Shape::new_star(5, 3., 0.5, 1.);
+		assert_eq!(Shape::new_star(5, 2., 0.5, 1.), ball.lerp(&star, 0.5));
+		assert_eq!(star, ball.lerp(&star, 1.));
+		assert_eq!(Shape::new_star(5, 1., 0.5, 1.), ball.lerp(&star, 0.));
+		assert_eq!(Shape::new_poly(7, 1.), Shape::new_poly(5, 1.).lerp(&Shape::new_poly(7, 3.), 0.));
+	}
+
+	#[test]
+	fn concave_polygons_become_their_hull() {
+		// an arrowhead, its notch at the origin
+		let arrow = Shape::new_polygon(vec![Position::new(0., 2.),
+		                                    Position::new(2., -2.),
+		                                    Position::new(0., 0.),
+		                                    Position::new(-2., -2.)]).unwrap();
+		assert_eq!(3, arrow.length());
+		assert!(PolygonType::classify(&arrow.vertices(Winding::CW)).is_convex());
+	}
+
+	#[test]
+	fn polygons_without_an_area_are_rejected() {
+		assert!(Shape::new_polygon(vec![]).is_none());
+		assert!(Shape::new_polygon(vec![Position::new(0., 0.), Position::new(1., 1.)]).is_none());
+		let collinear = vec![Position::new(0., 0.), Position::new(1., 1.), Position::new(2., 2.)];
+		assert!(Shape::new_polygon(collinear).is_none());
+	}
+
+	fn star(n: usize, inner: f32) -> Vec<Position> {
+		(0..n * 2)
+			.map(|i| {
+				let r = if i % 2 == 0 { 1. } else { inner };
+				let (s, c) = (PI * i as f32 / n as f32).sin_cos();
+				Position::new(c * r, s * r)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn convex_pieces_cover_the_polygon() {
+		for &(n, inner) in &[(3, 0.3), (5, 0.5), (7, 0.2), (8, 0.9)] {
+			let mut outline = star(n, inner);
+			// clockwise outlines are taken as well
+			if n % 2 == 1 {
+				outline.reverse();
+			}
+			let pieces = convex_decomposition(&outline, 8);
+			assert!(pieces.len() > 1);
+			for piece in &pieces {
+				assert!(piece.len() <= 8);
+				assert!(PolygonType::classify(piece).is_convex());
+				assert!(signed_area2(piece) > 0.);
+			}
+			let area = pieces.iter().fold(0., |a, piece| a + signed_area2(piece)) * 0.5;
+			assert!((area - signed_area2(&outline).abs() * 0.5).abs() < 1e-4);
+			// the pieces don't overlap, so a point of the star is in just one of them
+			for i in 0..20 {
+				for j in 0..20 {
+					// off any diagonal the pieces could be cut along
+					let p = Position::new(i as f32 / 10. - 0.9537, j as f32 / 10. - 0.9413);
+					let holding = pieces.iter().filter(|piece| polygon_contains(piece, p)).count();
+					assert_eq!(if polygon_contains(&outline, p) { 1 } else { 0 }, holding);
+				}
+			}
+		}
+		let square = [Position::new(0., 0.), Position::new(1., 0.), Position::new(1., 1.), Position::new(0., 1.)];
+		assert_eq!(vec![square.to_vec()], convex_decomposition(&square, 8));
+	}
+}
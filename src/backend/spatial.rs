@@ -0,0 +1,379 @@
+//! Broadphases over the world, so that finding what's near a point only looks at a few agents
+//! instead of all of them. `SpatialHash` is a uniform grid, cheap to fill but slow where the agents
+//! crowd into a few cells; `QuadTree` subdivides where they crowd instead.
+//! Points outside the extent are kept by both, along its edge.
+use cgmath::EuclideanVector;
+use core::geometry::*;
+use backend::obj::Id;
+
+/// Lookups of points by where they are
+pub trait Broadphase: Send + Sync {
+	fn insert(&mut self, id: Id, position: Position);
+
+	fn clear(&mut self);
+
+	/// Whatever was inserted inside `rect`, edges included, with its position
+	fn query_rect(&self, rect: Rect) -> Vec<(Id, Position)>;
+
+	/// Whatever was inserted no further than `radius` from `center`, with its position
+	fn query_radius(&self, center: Position, radius: f32) -> Vec<(Id, Position)> {
+		let radius2 = radius * radius;
+		let mut found = self.query_rect(Rect::new(center.x - radius,
+		                                          center.y - radius,
+		                                          center.x + radius,
+		                                          center.y + radius));
+		found.retain(|&(_, p)| (p - center).length2() <= radius2);
+		found
+	}
+
+	/// What was inserted closest to `p`, `None` when nothing was
+	fn nearest(&self, p: Position) -> Option<(Id, Position)>;
+}
+
+/// Which broadphase to build, picked by name in the config
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+	Grid,
+	QuadTree,
+}
+
+impl Kind {
+	pub fn from_name(name: &str) -> Option<Kind> {
+		match name {
+			"grid" => Some(Kind::Grid),
+			"quadtree" => Some(Kind::QuadTree),
+			_ => None,
+		}
+	}
+
+	/// A broadphase over `extent`, down to `resolution` cells along its longer side:
+	/// the grid has that many, the quadtree stops subdividing once its quads are that small
+	pub fn build(self, extent: Rect, resolution: usize) -> Box<Broadphase> {
+		match self {
+			Kind::Grid => Box::new(SpatialHash::new(extent, resolution)),
+			Kind::QuadTree => {
+				let depth = (::std::cmp::max(resolution, 1) as f32).log2().ceil() as usize;
+				Box::new(QuadTree::new(extent, depth))
+			}
+		}
+	}
+}
+
+fn closest<I>(p: Position, points: I) -> Option<(Id, Position)>
+	where I: Iterator<Item = (Id, Position)> {
+	points.fold(None, |n, t| match n {
+		Some(n) if (n.1 - p).length2() <= (t.1 - p).length2() => Some(n),
+		_ => Some(t),
+	})
+}
+
+pub struct SpatialHash {
+	origin: Position,
+	cell_size: f32,
+	columns: usize,
+	rows: usize,
+	cells: Vec<Vec<(Id, Position)>>,
+}
+
+impl SpatialHash {
+	/// A grid covering `extent` with `resolution` cells along its longer side
+	pub fn new(extent: Rect, resolution: usize) -> Self {
+		let width = extent.max.x - extent.min.x;
+		let height = extent.max.y - extent.min.y;
+		let cell_size = (f32::max(width, height) / ::std::cmp::max(resolution, 1) as f32).max(1e-3);
+		let columns = ::std::cmp::max((width / cell_size).ceil() as usize, 1);
+		let rows = ::std::cmp::max((height / cell_size).ceil() as usize, 1);
+		SpatialHash {
+			origin: extent.min,
+			cell_size: cell_size,
+			columns: columns,
+			rows: rows,
+			cells: vec![Vec::new(); columns * rows],
+		}
+	}
+
+	fn column(&self, x: f32) -> usize {
+		::std::cmp::min(((x - self.origin.x) / self.cell_size).floor().max(0.) as usize, self.columns - 1)
+	}
+
+	fn row(&self, y: f32) -> usize {
+		::std::cmp::min(((y - self.origin.y) / self.cell_size).floor().max(0.) as usize, self.rows - 1)
+	}
+
+	/// Whether a square of side `2 * radius` around `center` spans every cell
+	fn covers(&self, center: Position, radius: f32) -> bool {
+		self.row(center.y - radius) == 0 && self.row(center.y + radius) == self.rows - 1 &&
+		self.column(center.x - radius) == 0 && self.column(center.x + radius) == self.columns - 1
+	}
+}
+
+impl Broadphase for SpatialHash {
+	fn insert(&mut self, id: Id, position: Position) {
+		let index = self.row(position.y) * self.columns + self.column(position.x);
+		self.cells[index].push((id, position));
+	}
+
+	fn clear(&mut self) {
+		for cell in &mut self.cells {
+			cell.clear();
+		}
+	}
+
+	fn query_rect(&self, rect: Rect) -> Vec<(Id, Position)> {
+		let mut found = Vec::new();
+		for row in self.row(rect.min.y)..self.row(rect.max.y) + 1 {
+			for column in self.column(rect.min.x)..self.column(rect.max.x) + 1 {
+				for &(id, p) in &self.cells[row * self.columns + column] {
+					if rect.contains(p) {
+						found.push((id, p));
+					}
+				}
+			}
+		}
+		found
+	}
+
+	fn nearest(&self, p: Position) -> Option<(Id, Position)> {
+		// widen the search until it finds something: nothing it missed can be any closer
+		let mut radius = self.cell_size;
+		loop {
+			let found = self.query_radius(p, radius);
+			if !found.is_empty() {
+				return closest(p, found.into_iter());
+			} else if self.covers(p, radius) {
+				// whatever is left lies outside the extent, further than the search got
+				return closest(p, self.cells.iter().flat_map(|cell| cell.iter().cloned()));
+			}
+			radius *= 2.;
+		}
+	}
+}
+
+/// Most points a quad holds before it is split
+const QUAD_CAPACITY: usize = 8;
+
+struct Quad {
+	/// the part of the extent the quad splits into four
+	region: Rect,
+	/// tight around the points under the quad, which may stray outside its region at the edge of the extent
+	bounds: Option<Rect>,
+	points: Vec<(Id, Position)>,
+	/// index of the first of its four children, which follow each other
+	children: Option<usize>,
+}
+
+impl Quad {
+	fn new(region: Rect) -> Quad {
+		Quad {
+			region: region,
+			bounds: None,
+			points: Vec::new(),
+			children: None,
+		}
+	}
+
+	/// Which of the four children `p` goes into, left to right then bottom to top
+	fn quadrant(&self, p: Position) -> usize {
+		let center = (self.region.min + self.region.max) * 0.5;
+		(if p.x >= center.x { 1 } else { 0 }) + (if p.y >= center.y { 2 } else { 0 })
+	}
+
+	fn grow(&mut self, p: Position) {
+		self.bounds = Some(match self.bounds {
+			Some(bounds) => bounds.grow(p),
+			None => Rect::new(p.x, p.y, p.x, p.y),
+		});
+	}
+}
+
+/// A quadtree over the extent, splitting a quad once it holds more than a few points.
+/// Quads at `max_depth` are never split, so that piles of points in the same place can't make it grow unbounded.
+pub struct QuadTree {
+	extent: Rect,
+	max_depth: usize,
+	quads: Vec<Quad>,
+}
+
+impl QuadTree {
+	pub fn new(extent: Rect, max_depth: usize) -> Self {
+		QuadTree {
+			extent: extent,
+			max_depth: max_depth,
+			quads: vec![Quad::new(extent)],
+		}
+	}
+
+	/// How deep the deepest quad is, the root being at zero
+	#[cfg(test)]
+	pub fn depth(&self) -> usize {
+		fn depth_of(quads: &[Quad], i: usize) -> usize {
+			quads[i].children.map_or(0, |first| 1 + (first..first + 4).map(|c| depth_of(quads, c)).max().unwrap_or(0))
+		}
+		depth_of(&self.quads, 0)
+	}
+
+	fn split(&mut self, i: usize) {
+		let first = self.quads.len();
+		let Rect { min, max } = self.quads[i].region;
+		let center = (min + max) * 0.5;
+		self.quads.push(Quad::new(Rect::new(min.x, min.y, center.x, center.y)));
+		self.quads.push(Quad::new(Rect::new(center.x, min.y, max.x, center.y)));
+		self.quads.push(Quad::new(Rect::new(min.x, center.y, center.x, max.y)));
+		self.quads.push(Quad::new(Rect::new(center.x, center.y, max.x, max.y)));
+		let points = ::std::mem::replace(&mut self.quads[i].points, Vec::new());
+		for (id, p) in points {
+			let child = first + self.quads[i].quadrant(p);
+			self.quads[child].grow(p);
+			self.quads[child].points.push((id, p));
+		}
+		self.quads[i].children = Some(first);
+	}
+}
+
+impl Broadphase for QuadTree {
+	fn insert(&mut self, id: Id, position: Position) {
+		let mut i = 0;
+		let mut depth = 0;
+		loop {
+			self.quads[i].grow(position);
+			match self.quads[i].children {
+				Some(first) => {
+					i = first + self.quads[i].quadrant(position);
+					depth += 1;
+				}
+				None => {
+					self.quads[i].points.push((id, position));
+					if self.quads[i].points.len() > QUAD_CAPACITY && depth < self.max_depth {
+						self.split(i);
+					}
+					return;
+				}
+			}
+		}
+	}
+
+	fn clear(&mut self) {
+		self.quads = vec![Quad::new(self.extent)];
+	}
+
+	fn query_rect(&self, rect: Rect) -> Vec<(Id, Position)> {
+		let mut found = Vec::new();
+		let mut open = vec![0];
+		while let Some(i) = open.pop() {
+			let quad = &self.quads[i];
+			if quad.bounds.map_or(false, |bounds| bounds.intersects(&rect)) {
+				match quad.children {
+					Some(first) => open.extend(first..first + 4),
+					None => found.extend(quad.points.iter().cloned().filter(|&(_, p)| rect.contains(p))),
+				}
+			}
+		}
+		found
+	}
+
+	fn nearest(&self, p: Position) -> Option<(Id, Position)> {
+		let mut best: Option<(Id, Position)> = None;
+		let mut open = vec![0];
+		while let Some(i) = open.pop() {
+			let quad = &self.quads[i];
+			let reach = match quad.bounds {
+				Some(bounds) => (bounds.clamp(p) - p).length2(),
+				None => continue,
+			};
+			if best.map_or(false, |b| (b.1 - p).length2() < reach) {
+				continue;
+			}
+			match quad.children {
+				Some(first) => open.extend(first..first + 4),
+				None => best = closest(p, best.into_iter().chain(quad.points.iter().cloned())),
+			}
+		}
+		best
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand;
+	use rand::{Rng, SeedableRng};
+	use cgmath::EuclideanVector;
+	use core::geometry::*;
+
+	fn random_position<R: Rng>(rng: &mut R, range: f32) -> Position {
+		Position::new(rng.gen_range(-range, range), rng.gen_range(-range, range))
+	}
+
+	/// Checks every query of `broadphase` against a plain walk through `points`
+	fn check_against_brute_force<R: Rng>(rng: &mut R, broadphase: &mut Broadphase, points: &[Position]) {
+		for (id, &p) in points.iter().enumerate() {
+			broadphase.insert(id, p);
+		}
+		for _ in 0..100 {
+			let center = random_position(rng, 100.);
+			let radius = rng.gen_range(0., 50.);
+			let mut found = broadphase.query_radius(center, radius).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+			found.sort();
+			let expected = points.iter()
+				.enumerate()
+				.filter(|&(_, &p)| (p - center).length2() <= radius * radius)
+				.map(|(id, _)| id)
+				.collect::<Vec<_>>();
+			assert_eq!(expected, found);
+
+			let corner = center + Position::new(rng.gen_range(0., 50.), rng.gen_range(0., 50.));
+			let rect = Rect::new(center.x, center.y, corner.x, corner.y);
+			let mut found = broadphase.query_rect(rect).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+			found.sort();
+			let expected = points.iter()
+				.enumerate()
+				.filter(|&(_, &p)| rect.contains(p))
+				.map(|(id, _)| id)
+				.collect::<Vec<_>>();
+			assert_eq!(expected, found);
+
+			// ties may go either way, the distance may not
+			let nearest = points.iter().map(|&p| (p - center).length2()).fold(None, |n: Option<f32>, d| {
+				Some(n.map_or(d, |n| n.min(d)))
+			});
+			assert_eq!(nearest, broadphase.nearest(center).map(|(_, p)| (p - center).length2()));
+		}
+		broadphase.clear();
+		assert!(broadphase.query_radius(Position::new(0., 0.), 200.).is_empty());
+		assert!(broadphase.nearest(Position::new(0., 0.)).is_none());
+	}
+
+	#[test]
+	fn queries_find_what_brute_force_does() {
+		let mut rng = rand::XorShiftRng::from_seed([1, 2, 3, 4]);
+		let extent = Rect::new(-80., -80., 80., 80.);
+		for &kind in &[Kind::Grid, Kind::QuadTree] {
+			for &resolution in &[1, 7, 32, 200] {
+				// some points fall outside the extent
+				let scattered = (0..500).map(|_| random_position(&mut rng, 100.)).collect::<Vec<_>>();
+				check_against_brute_force(&mut rng, &mut *kind.build(extent, resolution), &scattered);
+				// and most crowd into a couple of spots
+				let crowded = (0..500)
+					.map(|i| {
+						let spot = if i % 2 == 0 { Position::new(-40., 30.) } else { Position::new(75., -75.) };
+						spot + random_position(&mut rng, 1.)
+					})
+					.chain((0..20).map(|_| random_position(&mut rng, 100.)))
+					.collect::<Vec<_>>();
+				check_against_brute_force(&mut rng, &mut *kind.build(extent, resolution), &crowded);
+			}
+		}
+	}
+
+	#[test]
+	fn quadtrees_stop_splitting_at_their_depth_cap() {
+		let mut tree = QuadTree::new(Rect::new(-80., -80., 80., 80.), 5);
+		for id in 0..1000 {
+			tree.insert(id, Position::new(12.5, -3.));
+		}
+		assert_eq!(5, tree.depth());
+		assert_eq!(1 + 5 * 4, tree.quads.len());
+		assert_eq!(1000, tree.query_radius(Position::new(12.5, -3.), 0.).len());
+		assert_eq!(Position::new(12.5, -3.), tree.nearest(Position::new(-70., 70.)).unwrap().1);
+	}
+}
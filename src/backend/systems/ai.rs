@@ -1,42 +1,163 @@
 use super::*;
 use std::f32::consts;
 use std::collections::HashMap;
+use backend::config::SimConfig;
 use backend::obj;
 use backend::obj::Identified;
 use backend::obj::Transformable;
+use backend::spatial;
+use backend::spatial::Broadphase;
 use backend::world;
 use backend::world::agent;
 use backend::world::agent::Personality;
 use backend::world::agent::TypedAgent;
+use backend::world::contact;
+use backend::world::contact::ContactPolicy;
 use backend::world::segment;
 use backend::world::segment::Intent;
 use cgmath::*;
-use core::geometry::Position;
+use core::geometry::{Position, Rect};
 use itertools::Itertools;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 type IdPositionMap = HashMap<obj::Id, Position>;
 
+/// cells along the longer side of the broadphase the targets are looked up in
+const TARGET_GRID_RESOLUTION: usize = 32;
+
+/// What an agent goes after, what it does once it gets there and what it had better keep away from
+#[derive(Clone, Copy, Debug)]
+struct Role {
+	prey: agent::AgentType,
+	/// hunters keep pushing into their prey, grazers stop to feed
+	hunter: bool,
+	threat: agent::AgentType,
+}
+
+const GRAZER: Role = Role {
+	prey: agent::AgentType::Resource,
+	hunter: false,
+	threat: agent::AgentType::Enemy,
+};
+
+const HUNTER: Role = Role {
+	prey: agent::AgentType::Minion,
+	hunter: true,
+	threat: agent::AgentType::FriendlyBullet,
+};
+
+/// The active agents of one type, where they are and how to find the nearest ones
+struct Targets {
+	positions: IdPositionMap,
+	grid: Box<Broadphase>,
+}
+
+impl Targets {
+	fn new(world: &world::World, agent_type: agent::AgentType) -> Self {
+		let positions = world.agents(agent_type)
+			.iter()
+			.filter(|&(_, ref v)| v.state.is_active())
+			.map(|(_, v)| (v.id(), v.transform().position))
+			.collect::<HashMap<_, _>>();
+		let mut grid = world.config().broadphase.build(world.extent, TARGET_GRID_RESOLUTION);
+		for (&id, &position) in &positions {
+			grid.insert(id, position);
+		}
+		Targets {
+			positions: positions,
+			grid: grid,
+		}
+	}
+}
+
+impl Default for Targets {
+	fn default() -> Self {
+		Targets {
+			positions: HashMap::new(),
+			grid: spatial::Kind::Grid.build(Rect::new(-1., -1., 1., 1.), 1),
+		}
+	}
+}
+
+/// What an agent finds around it: what it goes after, what it keeps away from, the beacons it heads for
+/// when there's nothing to go after and the fence
+struct Surroundings<'a> {
+	targets: &'a Targets,
+	threats: &'a Targets,
+	beacons: &'a [Position],
+	fence: Rect,
+}
+
+/// How the brain's decisions turn into actuator forces, pulled from the config every frame
+#[derive(Clone, Copy, Debug)]
+struct Drive {
+	power_boost: f32,
+	effort_budget: f32,
+	reverse_thrust: f32,
+}
+
+impl<'a> From<&'a SimConfig> for Drive {
+	fn from(config: &'a SimConfig) -> Self {
+		Drive {
+			power_boost: config.power_boost,
+			effort_budget: config.effort_budget,
+			reverse_thrust: config.reverse_thrust,
+		}
+	}
+}
+
 pub struct AiSystem {
 	beacons: Box<[Position]>,
-	targets: IdPositionMap,
+	/// the resources the minions graze on
+	food: Targets,
+	/// the minions the enemies hunt
+	prey: Targets,
+	/// the enemies the minions keep away from
+	hunters: Targets,
+	/// the shots the minions fire at the enemies
+	shots: Targets,
+	fence: Rect,
+	brain_config: agent::BrainConfig,
+	drive: Drive,
+	contacts: ContactPolicy,
 }
 
 impl Updateable for AiSystem {}
 
 impl System for AiSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::BODIES | schedule::METABOLISM | schedule::EMITTERS,
+		                   schedule::INTENTS)
+	}
+
 	fn from_world(&mut self, world: &world::World) {
+		self.drive = Drive::from(world.config());
+		self.contacts = world.contact_policy().clone();
 		self.beacons = world.emitters().iter().map(|e| e.transform().position).collect::<Vec<_>>().into_boxed_slice();
-		self.targets = world.agents(agent::AgentType::Resource)
-			.iter()
-			.filter(|&(_, ref v)| v.state.is_active())
-			.map(|(_, v)| (v.id(), v.transform().position))
-			.collect::<HashMap<_, _>>();
+		self.food = Targets::new(world, GRAZER.prey);
+		self.prey = Targets::new(world, HUNTER.prey);
+		self.hunters = Targets::new(world, GRAZER.threat);
+		self.shots = Targets::new(world, HUNTER.threat);
+		self.fence = world.extent;
 	}
 
 	fn to_world(&self, world: &mut world::World) {
-		Self::update_minions(&self.targets,
-		                     &self.beacons,
-		                     &mut world.agents_mut(agent::AgentType::Minion));
+		for &(agent_type, role, targets, threats) in &[(agent::AgentType::Minion, GRAZER, &self.food, &self.hunters),
+		                                               (agent::AgentType::Enemy, HUNTER, &self.prey, &self.shots)] {
+			let surroundings = Surroundings {
+				targets: targets,
+				threats: threats,
+				beacons: &self.beacons,
+				fence: self.fence,
+			};
+			Self::update_agents(&self.brain_config,
+			                    &self.drive,
+			                    &self.contacts,
+			                    role,
+			                    &surroundings,
+			                    &mut world.agents_mut(agent_type));
+		}
 	}
 }
 
@@ -44,13 +165,73 @@ impl Default for AiSystem {
 	fn default() -> Self {
 		AiSystem {
 			beacons: Box::new([]),
-			targets: HashMap::new(),
+			food: Targets::default(),
+			prey: Targets::default(),
+			hunters: Targets::default(),
+			shots: Targets::default(),
+			fence: Rect::new(-1., -1., 1., 1.),
+			brain_config: agent::BrainConfig::default(),
+			drive: Drive::from(&SimConfig::default()),
+			contacts: ContactPolicy::default(),
 		}
 	}
 }
 
 impl AiSystem {
-	fn update_minions(targets: &IdPositionMap, beacons: &[Position], minions: &mut agent::AgentMap) {
+	#[cfg(not(feature = "parallel"))]
+	fn update_agents(config: &agent::BrainConfig, drive: &Drive, contacts: &ContactPolicy, role: Role,
+	                 surroundings: &Surroundings, agents: &mut agent::AgentMap) {
+		for (_, agent) in agents.iter_mut() {
+			Self::update_agent(config, drive, contacts, role, surroundings, agent);
+		}
+	}
+
+	#[cfg(feature = "parallel")]
+	fn update_agents(config: &agent::BrainConfig, drive: &Drive, contacts: &ContactPolicy, role: Role,
+	                 surroundings: &Surroundings, agents: &mut agent::AgentMap) {
+		// the surroundings are read only and each agent only writes its own segments
+		let mut agents = agents.values_mut().collect::<Vec<_>>();
+		agents.par_iter_mut()
+			.for_each(|agent| Self::update_agent(config, drive, contacts, role, surroundings, agent));
+	}
+
+	/// What the sensor feeds the brain, in the sensor's frame: the bend of the neck, where the target is, how
+	/// close the fence is and how close and where the nearest threat is. Closeness goes from 1 right at the
+	/// thing down to 0 at the edge of the radar range, past which nothing is sensed
+	fn senses(sensor: &segment::Segment, core_angle: f32, radar_range: f32, target_position: Position,
+	          surroundings: &Surroundings)
+	          -> agent::InputVector<f32> {
+		let p0 = sensor.transform.position;
+		let closeness = |d: f32| if radar_range > 0. { (1. - d / radar_range).max(0.) } else { 0. };
+		// transform the target's world position into the head's frame
+		let t0 = target_position - p0;
+		let t = t0.normalize_to(t0.length().min(radar_range));
+		// direction in which the head is pointing, normalized
+		let s = Matrix2::from_angle(rad(sensor.transform.angle)) * (-Position::unit_y());
+		// some proprioception, feeding back the angle betweent the neck and the first torso
+		let neck_angle = consts::PI + sensor.transform.angle - core_angle;
+		let fence = &surroundings.fence;
+		let wall = (p0.x - fence.min.x).min(fence.max.x - p0.x).min(p0.y - fence.min.y).min(fence.max.y - p0.y);
+		let threat = surroundings.threats
+			.grid
+			.query_radius(p0, radar_range)
+			.into_iter()
+			.map(|(_, p)| p - p0)
+			.fold1(|n, d| if n.length2() <= d.length2() { n } else { d });
+		let (near, along, across) = match threat {
+			Some(d) if d.length2() > 0. => {
+				let u = d.normalize();
+				(closeness(d.length()), u.dot(s), u.perp_dot(s))
+			}
+			Some(_) => (1., 0., 0.),
+			None => (0., 0., 0.),
+		};
+		[neck_angle, t.dot(s), t.perp_dot(s), closeness(wall.max(0.)), near, along, across]
+	}
+
+	/// Steers the agent towards the nearest of its targets, or the nearest beacon when none is in range
+	fn update_agent(config: &agent::BrainConfig, drive: &Drive, contacts: &ContactPolicy, role: Role,
+	                surroundings: &Surroundings, agent: &mut agent::Agent) {
 
 		fn nearest_beacon<'a>(beacons: &'a [Position], p: &'a Position) -> &'a Position {
 			beacons.iter()
@@ -58,80 +239,277 @@ impl AiSystem {
 				.unwrap_or(p)
 		}
 
-		for (_, agent) in minions.iter_mut() {
-			let brain = agent.brain().clone();
-			let core = agent.first_segment(segment::CORE);
-			let head = agent.first_segment(segment::SENSOR);
-			if let Some(sensor) = head {
-				let p0 = sensor.transform.position;
-				let radar_range = sensor.mesh.shape.radius() * 10.;
-				let current_target = agent.state.target().clone();
-				let current_target_position = agent.state.target_position().clone();
-				// if our original target is dead then we need to find another one
-				let new_target: Option<(obj::Id, Position)> = match current_target {
-					None => {
-						targets.iter()
-							.find(|&(_, &p)| (p - p0).length() < radar_range)
-							.map(|(&id, &position)| (id, position))
-					}
-					Some(id) => targets.get(&id).map(|&position| (id, position)),
-				};
-				// and failing that again, we target
-				match new_target {
-					None => agent.state.retarget(None, *nearest_beacon(beacons, &current_target_position)),
-					Some((id, position)) => agent.state.retarget(Some(id), position),
-				};
-				// find where our target is in the world
-				let target_position = agent.state.target_position().clone();
-				// and transform the world position into the head's frame
-				let t0 = target_position - sensor.transform.position;
-				let t = t0.normalize_to(t0.length().min(radar_range));
-				// direction in which the head is pointing, normalized
-				let s = Matrix2::from_angle(rad(sensor.transform.angle)) * (-Position::unit_y());
-				// some proprioception, feeding back the angle betweent the neck and the first torso
-				let neck_angle = consts::PI + sensor.transform.angle -
-				                 core.map(|t| t.transform.angle).unwrap_or(sensor.transform.angle);
-				// we pass the relative position of the target decomposed in our frame of reference to the neural network
-				// expecting four components we can use as thresholds
-				let r = agent.brain().response(&[neck_angle, t.dot(s), t.perp_dot(s), 0.]);
-				const POWER_BOOST: f32 = 100.;
-
-				let segments = &mut agent.segments_mut();
-				for segment in segments.iter_mut() {
-					let flags = &segment.flags;
-					if flags.contains(segment::ACTUATOR) {
-						let power = segment.state.get_charge() * segment.mesh.shape.radius().powi(2) * POWER_BOOST;
-						let f = Matrix2::from_angle(rad(segment.transform.angle)) * Position::unit_y() * power;
-						let intent =
-							if let Some(refs) = segment.state.last_touched {
-								match refs.id().type_of() {
-									agent::AgentType::Resource => Intent::Idle,
-									_ => {
-										let fear: f32 = brain.fear();
-										Intent::RunAway(f * fear)
-									}
-								}
-							} else if flags.contains(segment::RUDDER | segment::LEFT) && r[0] > brain.hunger() {
+		let brain = agent.brain().clone();
+		let agent_type = agent.id().type_of();
+		let core = agent.first_segment(segment::CORE);
+		let head = agent.first_segment(segment::SENSOR);
+		if let Some(sensor) = head {
+			let p0 = sensor.transform.position;
+			let radar_range = agent.state.search_range(sensor.mesh.shape.radius() * 10.);
+			let current_target = agent.state.target().clone();
+			let current_target_position = agent.state.target_position().clone();
+			// if our original target is dead then we need to find another one
+			let targets = surroundings.targets;
+			let new_target: Option<(obj::Id, Position)> = match current_target {
+				None => {
+					targets.grid
+						.query_radius(p0, radar_range)
+						.into_iter()
+						.fold1(|n, t| if (n.1 - p0).length2() <= (t.1 - p0).length2() { n } else { t })
+				}
+				Some(id) => targets.positions.get(&id).map(|&position| (id, position)),
+			};
+			// and failing that again, we target
+			match new_target {
+				None => agent.state.retarget(None, *nearest_beacon(surroundings.beacons, &current_target_position)),
+				Some((id, position)) => agent.state.retarget(Some(id), position),
+			};
+			// find where our target is in the world
+			let target_position = agent.state.target_position().clone();
+			let core_angle = core.map(|t| t.transform.angle).unwrap_or(sensor.transform.angle);
+			// we pass what the sensor makes of the surroundings to the neural network,
+			// expecting four components we can use as thresholds
+			let input = Self::senses(&sensor, core_angle, radar_range, target_position, surroundings);
+			let r = agent.brain().response(config, &input);
+
+			let segments = &mut agent.segments_mut();
+			for segment in segments.iter_mut() {
+				let flags = &segment.flags;
+				if flags.contains(segment::ACTUATOR) {
+					let power = segment.state.get_charge() * segment.mesh.shape.radius().powi(2) * drive.power_boost;
+					let f = Matrix2::from_angle(rad(segment.transform.angle)) * Position::unit_y() * power;
+					let touched = match segment.state.last_touched.map(|refs| refs.id().type_of()) {
+						// hunters hold on to their prey rather than recoil from it
+						Some(other) if role.hunter && other == role.prey => None,
+						touched => touched,
+					};
+					let intent = match touched.map(|other| contacts.effect(agent_type, other)) {
+						Some(contact::Effect::Eat) => Intent::Idle,
+						Some(contact::Effect::Bounce) |
+						Some(contact::Effect::Damage) => {
+							// the harder the hit, the stronger the urge to get away
+							let fear: f32 = brain.fear() * (1. + segment.state.last_impulse);
+							Intent::RunAway(f * fear)
+						}
+						_ => {
+							if flags.contains(segment::RUDDER | segment::LEFT) && r[0] > brain.hunger() {
 								Intent::Move(-f)
 							} else if flags.contains(segment::RUDDER | segment::RIGHT) && r[1] > brain.hunger() {
 								Intent::Move(-f)
 							} else if flags.contains(segment::THRUSTER) && r[2] > brain.haste() {
 								Intent::Move(f)
+							} else if flags.contains(segment::THRUSTER) && drive.reverse_thrust > 0. &&
+							          r[2] < -brain.haste() {
+								Intent::Move(-f * drive.reverse_thrust)
 							} else if flags.contains(segment::BRAKE) && r[3] > brain.prudence() {
 								Intent::Brake(-f)
 							} else {
 								Intent::Idle
-							};
-						match intent {
-							Intent::Idle => segment.state.set_target_charge(brain.rest()),
-							Intent::Move(_) => segment.state.set_target_charge(brain.thrust()),
-							Intent::Brake(_) => segment.state.set_target_charge(brain.thrust()),
-							Intent::RunAway(_) => segment.state.set_charge(brain.thrust()),
+							}
 						}
-						segment.state.intent = intent;
+					};
+					match intent {
+						Intent::Idle => segment.state.set_target_charge(brain.rest()),
+						Intent::Move(_) => segment.state.set_target_charge(brain.thrust()),
+						Intent::Brake(_) => segment.state.set_target_charge(brain.thrust()),
+						Intent::RunAway(_) => segment.state.set_charge(brain.thrust()),
 					}
+					segment.state.intent = intent;
 				}
 			}
+			Self::apply_effort_budget(segments, drive.effort_budget);
+		}
+	}
+
+	/// Scales down the actuators' intents, all by the same ratio, so that together they draw no more than
+	/// `budget` times the charge of the whole agent
+	fn apply_effort_budget(segments: &mut [segment::Segment], budget: f32) {
+		let total = segments.iter().fold(0., |a, s| a + s.state.get_charge());
+		let demand = segments.iter()
+			.filter(|s| s.flags.contains(segment::ACTUATOR))
+			.filter(|s| match s.state.intent {
+				Intent::Idle => false,
+				_ => true,
+			})
+			.fold(0., |a, s| a + s.state.get_charge());
+		let allowed = total * budget;
+		if demand > allowed && demand > 0. {
+			let scale = allowed / demand;
+			for segment in segments.iter_mut() {
+				segment.state.intent = match segment.state.intent {
+					Intent::Move(f) => Intent::Move(f * scale),
+					Intent::Brake(f) => Intent::Brake(f * scale),
+					Intent::RunAway(f) => Intent::RunAway(f * scale),
+					Intent::Idle => Intent::Idle,
+				};
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::world::gen;
+	use backend::world::phen;
+	use backend::world::phen::Phenotype;
+	use core::geometry::Transform;
+
+	fn force(intent: &Intent) -> f32 {
+		match intent {
+			&Intent::Move(f) | &Intent::Brake(f) | &Intent::RunAway(f) => f.length(),
+			&Intent::Idle => 0.,
+		}
+	}
+
+	#[cfg(feature = "bench")]
+	#[bench]
+	fn thinking_for_5000_minions(b: &mut ::test::Bencher) {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		for i in 0..5000 {
+			world.new_minion(Position::new((i % 100) as f32 * 10., (i / 100) as f32 * 10.), None);
+		}
+		world.add_emitter(Position::new(500., 250.));
+		let mut system = AiSystem::default();
+		system.from_world(&world);
+		b.iter(|| system.to_world(&mut world));
+	}
+
+	#[test]
+	fn the_minions_head_for_the_emitters_added_since() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let beacon = world.add_emitter(Position::new(30., -12.));
+		let mut system = AiSystem::default();
+		system.from_world(&world);
+		assert_eq!(world.emitters().len(), system.beacons.len());
+		assert!(system.beacons.contains(&Position::new(30., -12.)));
+		world.move_emitter(beacon, Position::new(-4., 4.));
+		system.from_world(&world);
+		assert!(system.beacons.contains(&Position::new(-4., 4.)));
+		assert!(!system.beacons.contains(&Position::new(30., -12.)));
+		world.remove_emitter(beacon);
+		system.from_world(&world);
+		assert!(!system.beacons.contains(&Position::new(-4., 4.)));
+	}
+
+	#[test]
+	fn enemies_hunt_the_nearest_minion() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let enemy = world.new_enemy(Position::new(0., 0.), None);
+		let (head, radar) = {
+			let sensor = world.agent(enemy).unwrap().first_segment(segment::SENSOR).unwrap();
+			(sensor.transform.position, sensor.mesh.shape.radius() * 10.)
+		};
+		let far = world.new_minion(head + Position::new(0., radar * 0.6), None);
+		let near = world.new_minion(head + Position::new(radar * 0.3, 0.), None);
+		let mut system = AiSystem::default();
+		system.update_world(&mut world, 1. / 60.);
+		assert_eq!(&Some(near), world.agent(enemy).unwrap().state.target());
+		// minions go after resources, never after enemies or each other
+		for &id in &[near, far] {
+			assert_eq!(&None, world.agent(id).unwrap().state.target());
+		}
+	}
+
+	#[test]
+	fn minions_flee_enemies_and_enemies_press_on() {
+		let mut genome = gen::GenePool::for_tests().next();
+		let develop = |genome: &mut gen::Genome, id| {
+			let mut agent = phen::Minion::develop(genome, id, &Transform::default(), None, 1., usize::max_value());
+			for segment in agent.segments_mut().iter_mut() {
+				segment.state.set_charge(1.);
+			}
+			agent
+		};
+		let minion_id = 1 << 8 | agent::AgentType::Minion as usize;
+		let enemy_id = 1 << 8 | agent::AgentType::Enemy as usize;
+		let mut minion = develop(&mut genome, minion_id);
+		let mut enemy = develop(&mut genome, enemy_id);
+		// even where bumping into a minion would make anything else recoil
+		let mut contacts = ContactPolicy::default();
+		contacts.set(agent::AgentType::Enemy, agent::AgentType::Minion, contact::Effect::Bounce);
+		let touch = |agent: &mut agent::Agent, role: Role, touched: obj::Id| {
+			for segment in agent.segments_mut().iter_mut() {
+				segment.state.last_touched = Some(agent::Key::with_id(touched));
+			}
+			let nothing = Targets::default();
+			AiSystem::update_agent(&agent::BrainConfig::default(),
+			                       &AiSystem::default().drive,
+			                       &contacts,
+			                       role,
+			                       &Surroundings {
+				                       targets: &nothing,
+				                       threats: &nothing,
+				                       beacons: &[],
+				                       fence: Rect::new(-100., -100., 100., 100.),
+			                       },
+			                       agent);
+			agent.segments_with(segment::ACTUATOR)
+				.filter(|s| match s.state.intent {
+					Intent::RunAway(_) => true,
+					_ => false,
+				})
+				.count()
+		};
+		let actuators = minion.segments_with(segment::ACTUATOR).count();
+		assert!(actuators > 0);
+		assert_eq!(actuators, touch(&mut minion, GRAZER, enemy_id));
+		assert_eq!(0, touch(&mut enemy, HUNTER, minion_id));
+	}
+
+	#[test]
+	fn senses_fill_the_brain_input() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let minion = world.new_minion(Position::new(0., 0.), None);
+		let (sensor, radar) = {
+			let sensor = world.agent(minion).unwrap().first_segment(segment::SENSOR).unwrap();
+			let radar = sensor.mesh.shape.radius() * 10.;
+			(sensor, radar)
+		};
+		let p0 = sensor.transform.position;
+		let nothing = Targets::default();
+		let far_fence = Rect::new(p0.x - radar * 2., p0.y - radar * 2., p0.x + radar * 2., p0.y + radar * 2.);
+		let senses = |threats: &Targets, fence: Rect| {
+			let surroundings = Surroundings {
+				targets: &nothing,
+				threats: threats,
+				beacons: &[],
+				fence: fence,
+			};
+			AiSystem::senses(&sensor, sensor.transform.angle, radar, p0 + Position::unit_x(), &surroundings)
+		};
+		assert_eq!(world.agent(minion).unwrap().brain().spec().inputs, senses(&nothing, far_fence).len());
+		// nothing around
+		assert_eq!([0., 0., 0., 0.], senses(&nothing, far_fence)[3..]);
+
+		let near_fence = Rect::new(p0.x - radar * 0.25, p0.y - radar * 2., p0.x + radar * 2., p0.y + radar * 2.);
+		assert!((senses(&nothing, near_fence)[3] - 0.75).abs() < 1e-4);
+
+		world.new_enemy(p0 + Position::new(0., radar * 0.5), None);
+		let hunters = Targets::new(&world, GRAZER.threat);
+		let sensed = senses(&hunters, far_fence);
+		assert!(sensed[4] > 0. && sensed[4] <= 1.);
+		assert!((sensed[5] * sensed[5] + sensed[6] * sensed[6] - 1.).abs() < 1e-4);
+	}
+
+	#[test]
+	fn full_thrust_is_scaled_to_the_budget() {
+		let mut genome = gen::GenePool::for_tests().next();
+		let mut agent = phen::Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., usize::max_value());
+		let segments = agent.segments_mut();
+		let mut actuators = 0;
+		for segment in segments.iter_mut() {
+			segment.state.set_charge(1.);
+			if segment.flags.contains(segment::ACTUATOR) {
+				segment.state.intent = Intent::Move(Position::unit_y() * 10.);
+				actuators += 1;
+			}
+		}
+		assert!(actuators > 0);
+		let budget = 0.5 * actuators as f32 / segments.len() as f32;
+		AiSystem::apply_effort_budget(segments, budget);
+		for segment in segments.iter().filter(|s| s.flags.contains(segment::ACTUATOR)) {
+			assert!((force(&segment.state.intent) - 5.).abs() < 1e-4);
 		}
 	}
 }
@@ -8,17 +8,35 @@ use backend::obj::Identified;
 use backend::world;
 use backend::world::gen;
 use backend::world::agent;
+use backend::world::agent::TypedAgent;
+use backend::world::contact;
+use backend::world::contact::ContactPolicy;
 use backend::world::segment;
+use backend::world::events::WorldEvent;
 use backend::world::WorldState;
+use backend::config::BoredomConfig;
 use serialize::base64::{self, ToBase64};
 
 type StateMap = HashMap<obj::Id, agent::State>;
 type GeneMap = HashMap<obj::Id, gen::Dna>;
 
+/// A share of a resource going down a minion's mouth
+#[derive(Clone)]
+struct Meal {
+	resource: obj::Id,
+	eater: obj::Id,
+	energy: f32,
+	position: geometry::Position,
+}
+
 pub struct AlifeSystem {
 	dt: f32,
+	boredom: BoredomConfig,
+	metabolic_rate: f32,
+	contacts: ContactPolicy,
 	source: Box<[world::Emitter]>,
 	eaten: StateMap,
+	meals: Vec<Meal>,
 	touched: GeneMap,
 }
 
@@ -29,10 +47,21 @@ impl Updateable for AlifeSystem {
 }
 
 impl System for AlifeSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::BODIES | schedule::METABOLISM | schedule::EMITTERS,
+		                   schedule::AGENTS | schedule::METABOLISM)
+	}
+
 	fn from_world(&mut self, world: &world::World) {
+		self.boredom = world.config().boredom.clone();
+		self.metabolic_rate = world.config().metabolic_rate;
+		self.contacts = world.contact_policy().clone();
 		self.source = world.emitters().to_vec().into_boxed_slice();
-		self.eaten = Self::find_eaten_resources(&world.agents(agent::AgentType::Minion),
-		                                        &world.agents(agent::AgentType::Resource));
+		let (eaten, meals) = Self::find_eaten_resources(&self.contacts,
+		                                                &world.agents(agent::AgentType::Minion),
+		                                                &world.agents(agent::AgentType::Resource));
+		self.eaten = eaten;
+		self.meals = meals;
 		self.touched = Self::find_touched_spores(&world.agents(agent::AgentType::Minion),
 		                                         &world.agents(agent::AgentType::Spore));
 	}
@@ -41,11 +70,23 @@ impl System for AlifeSystem {
 		Self::update_resources(self.dt,
 		                       &mut world.agents_mut(agent::AgentType::Resource),
 		                       &self.eaten);
+		for meal in &self.meals {
+			world.events_mut().push(WorldEvent::Eaten {
+				resource: meal.resource,
+				eater: meal.eater,
+				energy: meal.energy,
+				position: meal.position,
+			});
+		}
 
 		let (spores, corpses) = Self::update_minions(self.dt,
+		                                             &self.boredom,
+		                                             self.metabolic_rate,
+		                                             &self.contacts,
 		                                             &world.extent.clone(),
 		                                             &mut world.agents_mut(agent::AgentType::Minion),
-		                                             &self.eaten);
+		                                             &self.meals);
+		Self::update_enemies(self.dt, &self.contacts, &mut world.agents_mut(agent::AgentType::Enemy));
 		let hatch = Self::update_spores(self.dt,
 		                                &mut world.agents_mut(agent::AgentType::Spore),
 		                                &self.touched);
@@ -53,8 +94,9 @@ impl System for AlifeSystem {
 		for &(ref transform, ref dna) in spores.into_iter() {
 			world.new_spore(transform, dna);
 		}
-		for &(ref transform, ref dna) in hatch.into_iter() {
-			world.hatch_spore(transform, dna);
+		for &(ref transform, ref dna, ref foreign_dna) in hatch.into_iter() {
+			let dna = Self::crossover(world.swarm_mut(&agent::AgentType::Minion).rng(), dna, foreign_dna);
+			world.hatch_spore(transform, &dna);
 		}
 		for &(ref transform, ref dna) in corpses.into_iter() {
 			world.decay_to_resource(transform, dna);
@@ -66,26 +108,52 @@ impl Default for AlifeSystem {
 	fn default() -> Self {
 		AlifeSystem {
 			dt: 1. / 60.,
+			boredom: BoredomConfig::default(),
+			metabolic_rate: 1.,
+			contacts: ContactPolicy::default(),
 			source: Box::new([]),
 			eaten: StateMap::new(),
+			meals: Vec::new(),
 			touched: GeneMap::new(),
 		}
 	}
 }
 
 impl AlifeSystem {
-	fn find_eaten_resources(minions: &agent::AgentMap, resources: &agent::AgentMap) -> StateMap {
-		let mut eaten = HashMap::new();
-		for (_, agent) in minions.iter().filter(|&(_, a)| a.state.is_active()) {
-			for segment in agent.segments.iter().filter(|&s| s.flags.contains(segment::MOUTH)) {
+	/// Finds the resources under a mouth, along with how much energy each minion gets out of them.
+	/// A resource is eaten once: when several minions have a mouth on it, it is split evenly between them.
+	fn find_eaten_resources(contacts: &ContactPolicy, minions: &agent::AgentMap, resources: &agent::AgentMap)
+	                        -> (StateMap, Vec<Meal>) {
+		let mut eaters: HashMap<obj::Id, Vec<obj::Id>> = HashMap::new();
+		let edible = contacts.effect(agent::AgentType::Minion, agent::AgentType::Resource) == contact::Effect::Eat;
+		for (&id, agent) in minions.iter().filter(|&(_, a)| edible && a.state.is_active()) {
+			for segment in agent.segments_with(segment::MOUTH) {
 				if let Some(key) = segment.state.last_touched {
-					if let Some(&agent::Agent { ref state, .. }) = resources.get(&key.id()) {
-						eaten.insert(key.id(), (*state).clone());
+					if resources.contains_key(&key.id()) {
+						let by = eaters.entry(key.id()).or_insert_with(Vec::new);
+						if !by.contains(&id) {
+							by.push(id);
+						}
 					}
 				}
 			}
 		}
-		eaten
+		let mut eaten = StateMap::new();
+		let mut meals = Vec::new();
+		for (resource_id, by) in eaters.into_iter() {
+			let resource = &resources[&resource_id];
+			let share = resource.state.energy() / by.len() as f32;
+			for eater in by {
+				meals.push(Meal {
+					resource: resource_id,
+					eater: eater,
+					energy: share,
+					position: resource.transform().position,
+				});
+			}
+			eaten.insert(resource_id, resource.state.clone());
+		}
+		(eaten, meals)
 	}
 
 	fn find_touched_spores(minions: &agent::AgentMap, spores: &agent::AgentMap) -> GeneMap {
@@ -104,34 +172,43 @@ impl AlifeSystem {
 		touched
 	}
 
-	fn update_minions(dt: f32, extent: &geometry::Rect, minions: &mut agent::AgentMap, eaten: &StateMap)
+	fn update_minions(dt: f32, boredom: &BoredomConfig, metabolic_rate: f32, contacts: &ContactPolicy,
+	                  extent: &geometry::Rect, minions: &mut agent::AgentMap, meals: &[Meal])
 	                  -> (Box<[(geometry::Transform, gen::Dna)]>, Box<[(geometry::Transform, gen::Dna)]>) {
 		let mut spawns = Vec::new();
 		let mut corpses = Vec::new();
-		for (_, agent) in minions.iter_mut() {
+		for (id, agent) in minions.iter_mut() {
 			if agent.state.is_active() {
 				if agent.state.lifecycle().is_expired() && agent.state.consume_ratio(0.75) {
 					spawns.push((agent.last_segment().transform().clone(), agent.dna().clone()));
 					agent.state.renew();
 				}
+				agent.state.grow_older(dt);
+				// feeding below resets it
+				agent.state.get_bored(dt * boredom.rate, boredom.max);
+				let meal = meals.iter().filter(|meal| meal.eater == *id).fold(0., |a, meal| a + meal.energy);
+				if meal > 0. {
+					agent.state.absorb(meal);
+				}
 				for segment in agent.segments.iter_mut() {
 					let p = segment.transform().position;
 					if p.x < extent.min.x || p.x > extent.max.x || p.y < extent.min.y || p.y > extent.max.y {
 						agent.state.die();
 					}
-					if segment.flags.contains(segment::MOUTH) {
-						if let Some(id) = segment.state.last_touched {
-							if let Some(eaten_state) = eaten.get(&id.id()) {
-								agent.state.absorb(eaten_state.energy());
+					if let Some(id) = segment.state.last_touched {
+						match contacts.effect(agent::AgentType::Minion, id.id().type_of()) {
+							contact::Effect::Damage => {
+								agent.state.consume(segment.state.last_impulse);
 							}
+							_ => {}
 						}
 					}
-					agent.state.consume(dt * segment.state.get_charge() * segment.mesh.shape.radius());
+					agent.state.consume(dt * metabolic_rate * segment.state.get_charge() * segment.mesh.shape.radius());
 					segment.state.update(dt);
 				}
 
 				if agent.state.energy() < 1. {
-					for segment in agent.segments.iter().filter(|s| s.flags.contains(segment::STORAGE)) {
+					for segment in agent.segments_with(segment::STORAGE) {
 						corpses.push((segment.transform.clone(), agent.dna().clone()));
 					}
 					agent.state.die();
@@ -145,6 +222,24 @@ impl AlifeSystem {
 		(spawns.into_boxed_slice(), corpses.into_boxed_slice())
 	}
 
+	/// Enemies don't feed or breed, they only age, recharge their segments and take damage until none is left
+	fn update_enemies(dt: f32, contacts: &ContactPolicy, enemies: &mut agent::AgentMap) {
+		for (_, agent) in enemies.iter_mut() {
+			if agent.state.is_active() {
+				agent.state.grow_older(dt);
+				for segment in agent.segments.iter_mut() {
+					if let Some(id) = segment.state.last_touched {
+						if contacts.effect(agent::AgentType::Enemy, id.id().type_of()) == contact::Effect::Damage &&
+						   !agent.state.drain(segment.state.last_impulse) {
+							agent.state.die();
+						}
+					}
+					segment.state.update(dt);
+				}
+			}
+		}
+	}
+
 	fn update_resources(dt: f32, resources: &mut agent::AgentMap, eaten: &StateMap) {
 		for (_, agent) in resources.iter_mut() {
 			if eaten.get(&agent.id()).is_some() {
@@ -153,7 +248,10 @@ impl AlifeSystem {
 				agent.state.die();
 			} else if agent.state.lifecycle().is_expired() {
 				agent.state.die();
+			} else if agent.state.has_outlived() {
+				agent.state.die();
 			} else if agent.state.is_active() {
+				agent.state.grow_older(dt);
 				for segment in agent.segments.iter_mut() {
 					segment.state.update(dt)
 				}
@@ -161,21 +259,23 @@ impl AlifeSystem {
 		}
 	}
 
-	fn crossover(dna: &gen::Dna, foreign_dna: &Option<gen::Dna>) -> gen::Dna {
+	fn crossover<R: rand::Rng>(rng: &mut R, dna: &gen::Dna, foreign_dna: &Option<gen::Dna>) -> gen::Dna {
 		match foreign_dna {
-			&Some(ref foreign) => gen::Genome::new(&foreign).crossover(&mut rand::thread_rng(), dna).dna().clone(),
+			&Some(ref foreign) => gen::Genome::new(&foreign).crossover(rng, dna).dna().clone(),
 			&None => dna.clone(),
 		}
 	}
 
+	/// Ages the spores, returning where the expired ones hatch with their dna and the dna they were fertilised with
 	fn update_spores(dt: f32, spores: &mut agent::AgentMap, touched: &GeneMap)
-	                 -> Box<[(geometry::Transform, gen::Dna)]> {
+	                 -> Box<[(geometry::Transform, gen::Dna, Option<gen::Dna>)]> {
 		let mut spawns = Vec::new();
 		for (spore_id, spore) in spores.iter_mut() {
 			if spore.state.lifecycle().is_expired() {
 				spore.state.die();
-				spawns.push((spore.transform().clone(), Self::crossover(spore.dna(), spore.state.foreign_dna())))
+				spawns.push((spore.transform().clone(), spore.dna().clone(), spore.state.foreign_dna().clone()))
 			} else if spore.state.is_active() {
+				spore.state.grow_older(dt);
 				for segment in spore.segments.iter_mut() {
 					if let Some(key) = segment.state.last_touched {
 						if let Some(touched_dna) = touched.get(&key.id()) {
@@ -195,3 +295,103 @@ impl AlifeSystem {
 		spawns.into_boxed_slice()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::*;
+	use backend::world::agent::AgentType;
+
+	#[test]
+	fn remains_fade_out_and_expire() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let minion = world.new_minion(Position::new(0., 0.), None);
+		let remains = world.shatter(minion);
+		let ttl = world.config().remains_ttl;
+		let mut system = AlifeSystem::default();
+		system.update_world(&mut world, ttl * 0.5);
+		for id in &remains {
+			let state = &world.agent(*id).unwrap().state;
+			assert!(state.is_alive());
+			assert_eq!(0.5, state.ttl_ratio());
+		}
+		system.update_world(&mut world, ttl * 0.5);
+		system.update_world(&mut world, 1. / 60.);
+		for id in &remains {
+			assert!(!world.agent(*id).unwrap().state.is_alive());
+		}
+	}
+
+	#[test]
+	fn spores_hatch_after_their_lifecycle_in_ticks() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let dna = gen::GenePool::for_tests().next().dna().clone();
+		let spore = world.new_spore(&Transform::default(), &dna);
+		let mut system = AlifeSystem::default();
+		// however long the ticks take on the wall clock, five seconds of them
+		for _ in 0..10 {
+			system.update_world(&mut world, 0.5);
+		}
+		assert!(world.agent(spore).unwrap().state.is_alive());
+		assert!(world.agents(AgentType::Minion).is_empty());
+		system.update_world(&mut world, 0.5);
+		assert!(!world.agent(spore).unwrap().state.is_alive());
+		assert_eq!(1, world.agents(AgentType::Minion).len());
+	}
+
+	#[test]
+	fn minions_sharing_a_resource_split_it() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let eaters = [world.new_minion(Position::new(-20., 0.), None), world.new_minion(Position::new(20., 0.), None)];
+		let food = world.new_resource(&Transform::default(), None);
+		let energy = world.agent(food).unwrap().state.energy();
+		assert!(energy > 0.);
+		let mut limits = Vec::new();
+		for &id in &eaters {
+			let agent = world.agent_mut(id).unwrap();
+			limits.push(agent.state.energy() / agent.state.energy_ratio());
+			agent.state.set_energy(2.);
+			// every segment is on it, but only the mouth eats
+			for segment in agent.segments_mut().iter_mut() {
+				segment.state.last_touched = Some(agent::Key::with_segment(food, 0));
+			}
+		}
+		let mut system = AlifeSystem::default();
+		// no time passes, so nothing is spent on metabolism
+		system.update_world(&mut world, 0.);
+		for (&id, &limit) in eaters.iter().zip(limits.iter()) {
+			assert_eq!(limit.min(2. + energy / 2.), world.agent(id).unwrap().state.energy());
+		}
+		assert!(!world.agent(food).unwrap().state.is_alive());
+		let meals = world.events_mut()
+			.pending()
+			.iter()
+			.filter(|&event| match *event {
+				WorldEvent::Eaten { resource, energy: share, .. } => resource == food && share == energy / 2.,
+				_ => false,
+			})
+			.count();
+		assert_eq!(2, meals);
+		world.sweep();
+		assert!(world.agent(food).is_none());
+		assert_eq!(2, world.agents(AgentType::Minion).len());
+	}
+
+	#[test]
+	fn friendly_fire_wears_enemies_down() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let enemy = world.new_enemy(Position::new(0., 0.), None);
+		let shot = agent::Key::with_id(1 << 8 | AgentType::FriendlyBullet as usize);
+		let energy = world.agent(enemy).unwrap().state.energy();
+		let contacts = ContactPolicy::default();
+		for &(impulse, alive) in &[(energy * 0.5, true), (energy, false)] {
+			{
+				let segment = &mut world.agent_mut(enemy).unwrap().segments_mut()[0];
+				segment.state.last_touched = Some(shot);
+				segment.state.last_impulse = impulse;
+			}
+			AlifeSystem::update_enemies(0., &contacts, &mut world.agents_mut(AgentType::Enemy));
+			assert_eq!(alive, world.agent(enemy).unwrap().state.is_alive());
+		}
+	}
+}
@@ -20,7 +20,11 @@ impl Updateable for AnimationSystem {
 	}
 }
 
-impl System for AnimationSystem {}
+impl System for AnimationSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::Access::empty(), schedule::Access::empty())
+	}
+}
 
 impl Default for AnimationSystem {
 	fn default() -> Self {
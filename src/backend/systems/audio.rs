@@ -4,7 +4,11 @@ pub struct AudioSystem {}
 
 impl Updateable for AudioSystem {}
 
-impl System for AudioSystem {}
+impl System for AudioSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::Access::empty(), schedule::Access::empty())
+	}
+}
 
 impl Default for AudioSystem {
 	fn default() -> Self {
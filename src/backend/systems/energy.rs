@@ -0,0 +1,136 @@
+use super::*;
+use backend::obj::Identified;
+use backend::world;
+use backend::world::agent;
+use backend::world::agent::TypedAgent;
+use backend::world::segment;
+use backend::world::segment::Intent;
+use backend::world::WorldState;
+use backend::config::EnergyConfig;
+
+/// Charges the minions for moving and feeds the ones grazing on resources, killing those that run out
+pub struct EnergySystem {
+	dt: f32,
+	config: EnergyConfig,
+}
+
+impl Updateable for EnergySystem {
+	fn update(&mut self, _: &WorldState, dt: f32) {
+		self.dt = dt;
+	}
+}
+
+impl System for EnergySystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::BODIES | schedule::INTENTS | schedule::METABOLISM,
+		                   schedule::AGENTS | schedule::METABOLISM)
+	}
+
+	fn from_world(&mut self, world: &world::World) {
+		self.config = world.config().energy.clone();
+	}
+
+	fn to_world(&self, world: &mut world::World) {
+		let mut starved = Vec::new();
+		for (_, agent) in world.agents_mut(agent::AgentType::Minion).iter_mut() {
+			if agent.state.is_active() && !Self::update_minion(self.dt, &self.config, agent) {
+				starved.push(agent.id());
+			}
+		}
+		for id in starved {
+			world.kill(agent::AgentType::Minion, id);
+		}
+	}
+}
+
+impl Default for EnergySystem {
+	fn default() -> Self {
+		EnergySystem {
+			dt: 1. / 60.,
+			config: EnergyConfig::default(),
+		}
+	}
+}
+
+impl EnergySystem {
+	/// Drains and refills the agent's energy over `dt`, false once there's none left
+	fn update_minion(dt: f32, config: &EnergyConfig, agent: &mut agent::Agent) -> bool {
+		let mut drain = 0.;
+		let mut refill = 0.;
+		for segment in agent.segments.iter() {
+			let moving = match segment.state.intent {
+				Intent::Idle => false,
+				_ => true,
+			};
+			if moving && segment.flags.contains(segment::ACTUATOR) {
+				drain += segment.mesh.shape.radius().powi(2);
+			}
+			if let Some(key) = segment.state.last_touched {
+				if key.id().type_of() == agent::AgentType::Resource {
+					refill += 1.;
+				}
+			}
+		}
+		if refill > 0. {
+			agent.state.absorb(dt * config.refill_rate * refill);
+		}
+		agent.state.drain(dt * config.drain_rate * drain)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::*;
+	use backend::config::EnergyConfig;
+	use backend::world;
+	use backend::world::gen;
+	use backend::world::phen;
+	use backend::world::phen::Phenotype;
+
+	fn minion() -> agent::Agent {
+		let mut genome = gen::GenePool::for_tests().next();
+		phen::Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., usize::max_value())
+	}
+
+	#[test]
+	fn idle_minions_outlive_thrusting_ones() {
+		let config = EnergyConfig::default();
+		let mut idle = minion();
+		let mut thrusting = minion();
+		let mut ticks = 0;
+		loop {
+			for segment in thrusting.segments_mut().iter_mut() {
+				segment.state.intent = Intent::Move(Position::unit_y());
+			}
+			let alive = EnergySystem::update_minion(1. / 60., &config, &mut thrusting);
+			assert!(EnergySystem::update_minion(1. / 60., &config, &mut idle));
+			ticks += 1;
+			if !alive {
+				break;
+			}
+			assert!(ticks < 1_000_000, "the thrusting minion never starved");
+		}
+		assert_eq!(0., thrusting.state.energy());
+		assert_eq!(minion().state.energy(), idle.state.energy());
+	}
+
+	#[test]
+	fn grazing_minions_refill() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let resource_id = world.new_resource(&Transform::default(), None);
+		let dt = 1. / 60.;
+		let graze = |config: &EnergyConfig| {
+			let mut grazing = minion();
+			let half = grazing.state.energy() * 0.5;
+			grazing.state.drain(half);
+			let hungry = grazing.state.energy();
+			grazing.segments_mut()[0].state.last_touched = Some(agent::Key::with_segment(resource_id, 0));
+			assert!(EnergySystem::update_minion(dt, config, &mut grazing));
+			grazing.state.energy() - hungry
+		};
+		let config = EnergyConfig::default();
+		assert!((dt * config.refill_rate - graze(&config)).abs() < 1e-4);
+		assert_eq!(0., graze(&EnergyConfig { refill_rate: 0., ..config }));
+	}
+}
@@ -1,9 +1,10 @@
 use super::*;
 use std::f32::consts;
-use rand;
 use rand::Rng;
 use core::clock::*;
 use core::geometry::*;
+use backend::obj;
+use backend::obj::Identified;
 use backend::obj::Transformable;
 use backend::world;
 use backend::world::agent;
@@ -14,8 +15,9 @@ pub struct GameSystem {
 }
 
 struct Emitter {
+	id: obj::Id,
 	position: Position,
-	hourglass: Hourglass<SystemStopwatch>,
+	hourglass: Hourglass<SimStopwatch>,
 	to_spawn: usize,
 	spawned: usize,
 	emission: Emission,
@@ -24,8 +26,9 @@ struct Emitter {
 }
 
 impl Emitter {
-	fn new(position: Position, rate: f32, emission: Emission) -> Self {
+	fn new(id: obj::Id, position: Position, rate: f32, emission: Emission) -> Self {
 		Emitter {
+			id: id,
 			position: position,
 			hourglass: Hourglass::new(rate),
 			to_spawn: 0,
@@ -38,11 +41,12 @@ impl Emitter {
 }
 
 impl Updateable for GameSystem {
-	fn update(&mut self, _: &world::WorldState, _: f32) {
+	fn update(&mut self, _: &world::WorldState, dt: f32) {
 		for e in &mut self.emitters {
 			e.spawned = e.to_spawn;
 		}
 		for e in &mut self.emitters {
+			e.hourglass.tick(dt);
 			if e.hourglass.is_expired() {
 				e.hourglass.flip();
 				e.to_spawn += 1;
@@ -52,24 +56,28 @@ impl Updateable for GameSystem {
 }
 
 impl System for GameSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::EMITTERS | schedule::AGENTS, schedule::AGENTS)
+	}
+
 	fn from_world(&mut self, world: &world::World) {
 		let source = world.emitters();
-		// Add missing emitters - deletion not supported
-		for i in self.emitters.len()..source.len() {
-			let s = &source[i];
-			self.emitters.push(Emitter::new(s.transform().position, s.rate(), s.emission()));
-		}
-		for (i, mut d) in self.emitters.iter_mut().enumerate() {
-			d.position = source[i].transform().position;
+		// emitters keep their timers for as long as the world has them, wherever they were moved
+		self.emitters.retain(|d| source.iter().any(|s| s.id() == d.id));
+		for s in source {
+			match self.emitters.iter().position(|d| d.id == s.id()) {
+				Some(i) => self.emitters[i].position = s.transform().position,
+				None => self.emitters.push(Emitter::new(s.id(), s.transform().position, s.rate(), s.emission())),
+			}
 		}
 	}
 
 	fn to_world(&self, world: &mut world::World) {
-		let mut rng = &mut rand::thread_rng();
 		for e in &self.emitters {
 			for i in e.spawned..e.to_spawn {
+				// drawn from the resources' own stream, so that a seed always scatters them the same way
 				let r = match e.emission {
-					Emission::Random => rng.next_f32() * 2. * consts::PI,
+					Emission::Random => world.swarm_mut(&agent::AgentType::Resource).rng().next_f32() * 2. * consts::PI,
 					Emission::CCW(angle) => angle * i as f32,
 					Emission::CW(angle) => -angle * i as f32,
 				};
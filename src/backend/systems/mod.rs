@@ -2,15 +2,25 @@ pub mod physics;
 pub mod animation;
 pub mod ai;
 pub mod alife;
+pub mod energy;
+pub mod reproduction;
+pub mod projectile;
 pub mod game;
 pub mod audio;
+pub mod schedule;
 
 pub use self::physics::PhysicsSystem;
 pub use self::animation::AnimationSystem;
 pub use self::game::GameSystem;
 pub use self::ai::AiSystem;
 pub use self::alife::AlifeSystem;
+pub use self::energy::EnergySystem;
+pub use self::reproduction::ReproductionSystem;
+pub use self::projectile::ProjectileSystem;
 pub use self::audio::AudioSystem;
+pub use self::schedule::Scheduler;
+pub use self::schedule::SystemScheduler;
+pub use self::schedule::SystemId;
 
 use backend::world;
 
@@ -18,7 +28,13 @@ pub trait Updateable {
 	fn update(&mut self, _world_state: &world::WorldState, _dt: f32) {}
 }
 
+/// Systems that are also `Send` can be run on the scheduler's thread pool alongside non-conflicting ones,
+/// the others are added with `SystemScheduler::add_local` and stay on the thread that owns them
 pub trait System: Updateable {
+	/// The world data this system reads in `from_world` and `update` and writes in `to_world`
+	fn access(&self) -> schedule::Job {
+		schedule::Job::exclusive()
+	}
 	fn init(&mut self, _: &world::World) {}
 	fn register(&mut self, _: &world::agent::Agent) {}
 	fn unregister(&mut self, _: &world::agent::Agent) {}
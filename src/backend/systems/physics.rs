@@ -1,3 +1,16 @@
+//! Simulates the segments of every agent as Box2D bodies. As an agent is registered each of its segments gets a
+//! dynamic body, with fixtures shaped after the segment's `Shape` and made of its `Material`, and a joint to the
+//! segment it is attached to: revolute for `JOINT` segments, welded otherwise. Every tick applies the segments'
+//! intents, steps the world, then writes the transforms, motion and contacts back. Thrusters push their body
+//! along the force they ask for, rudders turn it about the joint to their parent and brakes damp its motion;
+//! the AI has already scaled each intent by the segment's charge. An agent held by the mouse gets no intents
+//! applied: its bodies are given the velocity that takes it to the mouse within the tick, and the one it's
+//! flung with once it's let go.
+//!
+//! Bodies are known by the `agent::Key` of their segment, the agent id and segment index: `handles` maps the key to
+//! the `b2::BodyHandle` and every body carries the key as its user data, so either can be had from the other.
+//! Fixtures carry the key too, with the bone index of the convex piece they cover where a segment takes more
+//! than one: Box2D only collides convex polygons, so stars and large polygons are cut up with `convex_decomposition`.
 use super::*;
 use std::f32::consts;
 use std::collections::HashMap;
@@ -8,10 +21,15 @@ use wrapped2d::user_data::*;
 use wrapped2d::dynamics::world::callbacks::ContactAccess;
 use core::geometry::*;
 use cgmath::Vector;
+use cgmath::EuclideanVector;
 use backend::obj;
 use backend::obj::*;
+use backend::config::FenceConfig;
 use backend::world;
 use backend::world::agent;
+use backend::world::agent::AgentType;
+use backend::world::agent::TypedAgent;
+use backend::world::contact::ContactPolicy;
 use backend::world::segment;
 use backend::world::segment::Intent;
 
@@ -23,47 +41,101 @@ impl UserDataTypes for AgentData {
 	type FixtureData = agent::Key;
 }
 
-type ContactSet = Rc<RefCell<HashMap<agent::Key, agent::Key>>>;
+/// What a segment touched hardest on the last tick it touched anything
+#[derive(Clone, Copy, Debug)]
+struct Touch {
+	other: agent::Key,
+	/// normal impulse of the contact
+	impulse: f32,
+	/// ticks since that tick
+	age: u32,
+}
+
+/// Every segment touching, or lately touching, another agent
+type ContactSet = Rc<RefCell<HashMap<agent::Key, Touch>>>;
+
+/// What a segment's intent does to its body over a tick
+enum Actuation {
+	Force(Position),
+	Torque(f32),
+	/// linear damping on top of the body's own
+	Damping(f32),
+	Impulse(Position),
+}
 
 pub struct PhysicsSystem {
 	world: b2::World<AgentData>,
+	/// the body of every registered segment
 	handles: HashMap<agent::Key, b2::BodyHandle>,
+	/// where each attached segment's body is jointed to its parent, in the body's own frame
+	pivots: HashMap<agent::Key, b2::Vec2>,
 	touched: ContactSet,
+	extent: Rect,
+	fence: FenceConfig,
+	contacts: ContactPolicy,
+	brake_coefficient: f32,
+	touch_memory: u32,
+	grab: Option<world::Grab>,
+	/// where the segments of the agents moved in the world since the last tick now are
+	moves: Vec<(agent::Key, Transform)>,
 }
 
+/// Collision category of the fence, each agent type gets the next bit up
+const FENCE_CATEGORY: u16 = 0x0001;
+
+/// Box2D's limit on the vertices of a polygon fixture
+const MAX_POLYGON_VERTICES: usize = 8;
+
+/// The damping every segment body moves with, brakes add to it
+const LINEAR_DAMPING: f32 = 0.8;
+
 impl Updateable for PhysicsSystem {
 	fn update(&mut self, state: &world::WorldState, dt: f32) {
-		let mut forces = Vec::new();
-		let mut impulses = Vec::new();
+		let mut actuations = Vec::new();
 
 		for (h, b) in self.world.bodies() {
 			let body = b.borrow();
 			let center = (*body).world_center().clone();
 			let key = (*body).user_data();
+			if self.grab.as_ref().map_or(false, |grab| grab.id == key.agent_id) {
+				continue;
+			}
 			if let Some(segment) = state.agent(key.agent_id).and_then(|c| c.segment(key.segment_index)) {
-				match segment.state.intent {
-					Intent::Move(force) => forces.push((h, center, force)),
+				let actuation = match segment.state.intent {
+					Intent::Move(force) if segment.flags.contains(segment::RUDDER) => {
+						let pivot = self.pivots.get(key).map_or(center, |p| (*body).world_point(p));
+						let lever = PhysicsSystem::from_vec2(&center) - PhysicsSystem::from_vec2(&pivot);
+						Actuation::Torque(lever.perp_dot(force))
+					}
+					Intent::Move(force) => Actuation::Force(force),
 					Intent::Brake(force) => {
-						let linear_velocity = PhysicsSystem::from_vec2((*body).linear_velocity());
-						let comp = force.dot(linear_velocity);
-						if comp < 0. {
-							forces.push((h, center, force));
-						}
+						let speed = PhysicsSystem::from_vec2((*body).linear_velocity()).length();
+						Actuation::Damping(self.brake_damping(speed, (*body).mass(), force.length()))
 					}
-					Intent::RunAway(impulse) => impulses.push((h, center, impulse * dt)),
-					_ => {}
-				}
+					Intent::RunAway(impulse) => Actuation::Impulse(impulse * dt),
+					Intent::Idle => Actuation::Damping(0.),
+				};
+				actuations.push((h, center, actuation));
 			}
 		}
-		for (h, center, force) in forces {
+		for (h, center, actuation) in actuations {
 			let b = &mut self.world.body_mut(h);
-			b.apply_force(&PhysicsSystem::to_vec2(&force), &center, true);
-		}
-
-		for (h, center, impulse) in impulses {
-			let b = &mut self.world.body_mut(h);
-			b.apply_linear_impulse(&PhysicsSystem::to_vec2(&impulse), &center, true);
+			// brakes let go as soon as they're no longer asked for
+			let damping = match actuation {
+				Actuation::Damping(extra) => extra,
+				_ => 0.,
+			};
+			b.set_linear_damping(LINEAR_DAMPING + damping);
+			match actuation {
+				Actuation::Force(force) => b.apply_force(&PhysicsSystem::to_vec2(&force), &center, true),
+				Actuation::Torque(torque) => b.apply_torque(torque, true),
+				Actuation::Impulse(impulse) => b.apply_linear_impulse(&PhysicsSystem::to_vec2(&impulse), &center, true),
+				Actuation::Damping(_) => {}
+			}
 		}
+		self.apply_moves();
+		self.apply_grab(dt);
+		self.apply_boundary_force(dt);
 		self.world.step(dt, 8, 3);
 	}
 }
@@ -77,11 +149,17 @@ struct JointRef<'a> {
 }
 
 impl System for PhysicsSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::INTENTS, schedule::BODIES)
+	}
+
 	fn register(&mut self, agent: &world::agent::Agent) {
 		// build fixtures
-		let joint_refs = PhysicsSystem::build_fixtures(&mut self.world, &agent);
+		let filter = Self::filter(&self.contacts, agent.id().type_of());
+		let joint_refs = PhysicsSystem::build_fixtures(&mut self.world, &agent, &filter);
 		// and then assemble them with joints
-		PhysicsSystem::build_joints(&mut self.world, &joint_refs);
+		let pivots = PhysicsSystem::build_joints(&mut self.world, &joint_refs);
+		self.pivots.extend(pivots);
 		// record them
 		for JointRef { refs, handle, .. } in joint_refs {
 			self.handles.insert(refs, handle);
@@ -96,11 +174,35 @@ impl System for PhysicsSystem {
 			if let Some(handle) = self.handles.remove(&refs) {
 				self.world.destroy_body(handle);
 			}
+			self.pivots.remove(&refs);
 		}
 	}
 
 	fn init(&mut self, world: &world::World) {
-		self.init_extent(&world.extent);
+		self.extent = world.extent;
+		self.fence = world.config().fence.clone();
+		self.contacts = world.contact_policy().clone();
+		if self.fence.rigid {
+			self.init_extent(&world.extent);
+		}
+	}
+
+	fn from_world(&mut self, world: &world::World) {
+		self.fence = world.config().fence.clone();
+		self.contacts = world.contact_policy().clone();
+		self.brake_coefficient = world.config().brake_coefficient;
+		self.touch_memory = world.config().touch_memory;
+		self.grab = world.grabbed().cloned();
+		self.moves = world.moved()
+			.iter()
+			.filter_map(|&id| world.agent(id))
+			.flat_map(|agent| {
+				let id = agent.id();
+				agent.segments()
+					.iter()
+					.map(move |segment| (agent::Key::with_segment(id, segment.index), segment.transform.clone()))
+			})
+			.collect();
 	}
 
 	fn to_world(&self, world: &mut world::World) {
@@ -108,16 +210,29 @@ impl System for PhysicsSystem {
 			let body = b.borrow();
 			let position = (*body).position();
 			let angle = (*body).angle();
+			let velocity = (*body).linear_velocity();
+			let spin = (*body).angular_velocity();
 			let key = (*body).user_data();
 
 			if let Some(agent) = world.agent_mut(key.agent_id) {
 				if let Some(segment) = agent.segment_mut(key.segment_index) {
 					segment.transform_to(&Transform::new(PhysicsSystem::from_vec2(&position), angle));
-					segment.state.last_touched = self.touched.borrow().get(key).map(|r| *r);
+					segment.motion = Some(Motion {
+						velocity: PhysicsSystem::from_vec2(&velocity),
+						spin: spin,
+					});
+					let touch = self.touched.borrow().get(key).cloned();
+					segment.state.last_touched = touch.map(|t| t.other);
+					// the impulse is only felt on the tick of the contact
+					segment.state.last_impulse = touch.map_or(0., |t| if t.age == 0 { t.impulse } else { 0. });
 				}
 			}
 		}
-		self.touched.borrow_mut().clear();
+		Self::forget_touches(&mut self.touched.borrow_mut(), self.touch_memory);
+		if self.grab.as_ref().map_or(false, |grab| grab.release.is_some()) {
+			world.let_go();
+		}
+		world.clear_moved();
 	}
 }
 
@@ -127,7 +242,15 @@ impl Default for PhysicsSystem {
 		PhysicsSystem {
 			world: Self::new_world(touched.clone()),
 			handles: HashMap::new(),
+			pivots: HashMap::new(),
 			touched: touched,
+			extent: Rect::new(0., 0., 0., 0.),
+			fence: FenceConfig::default(),
+			contacts: ContactPolicy::default(),
+			brake_coefficient: 4.,
+			touch_memory: 1,
+			grab: None,
+			moves: Vec::new(),
 		}
 	}
 }
@@ -141,6 +264,73 @@ impl PhysicsSystem {
 		Position::new(p.x, p.y)
 	}
 
+	/// Pushes bodies back towards the inside as they get close to the extent
+	fn apply_boundary_force(&mut self, dt: f32) {
+		let mut impulses = Vec::new();
+		let (min, max) = (self.extent.min, self.extent.max);
+		for (h, b) in self.world.bodies() {
+			let body = b.borrow();
+			let mass = (*body).mass();
+			if mass <= 0. {
+				continue;
+			}
+			let c = (*body).world_center().clone();
+			let push = Position::new(self.fence.repulsion(c.x - min.x) - self.fence.repulsion(max.x - c.x),
+			                         self.fence.repulsion(c.y - min.y) - self.fence.repulsion(max.y - c.y));
+			if push.x != 0. || push.y != 0. {
+				impulses.push((h, c, push * (mass * dt)));
+			}
+		}
+		for (h, center, impulse) in impulses {
+			let b = &mut self.world.body_mut(h);
+			b.apply_linear_impulse(&PhysicsSystem::to_vec2(&impulse), &center, true);
+		}
+	}
+
+	/// Puts the bodies of the agents moved in the world where their segments now are
+	fn apply_moves(&mut self) {
+		for &(key, ref transform) in &self.moves {
+			if let Some(&handle) = self.handles.get(&key) {
+				self.world.body_mut(handle).set_transform(&Self::to_vec2(&transform.position), transform.angle);
+			}
+		}
+		self.moves.clear();
+	}
+
+	/// Moves every body of the held agent with the core, to the mouse or off with the velocity it was flung with
+	fn apply_grab(&mut self, dt: f32) {
+		let grab = match self.grab {
+			Some(ref grab) if dt > 0. => grab.clone(),
+			_ => return,
+		};
+		let velocity = match (grab.release, self.handles.get(&agent::Key::with_segment(grab.id, 0))) {
+			(Some(velocity), _) => velocity,
+			(None, Some(&core)) => (grab.target - Self::from_vec2(self.world.body(core).position())) / dt,
+			(None, None) => return,
+		};
+		let bodies = self.handles
+			.iter()
+			.filter(|&(key, _)| key.agent_id == grab.id)
+			.map(|(_, &handle)| handle)
+			.collect::<Vec<_>>();
+		for handle in bodies {
+			let mut body = self.world.body_mut(handle);
+			body.set_linear_velocity(&Self::to_vec2(&velocity));
+			body.set_angular_velocity(0.);
+		}
+	}
+
+	/// The damping that slows the body by `brake_coefficient` of its speed per second,
+	/// but opposes it with no more than the `limit` force the segment can exert
+	fn brake_damping(&self, speed: f32, mass: f32, limit: f32) -> f32 {
+		let momentum = speed * mass;
+		if momentum * self.brake_coefficient > limit {
+			limit / momentum
+		} else {
+			self.brake_coefficient
+		}
+	}
+
 	fn init_extent(&mut self, extent: &Rect) {
 		let mut f_def = b2::FixtureDef::new();
 		let mut b_def = b2::BodyDef::new();
@@ -164,7 +354,24 @@ impl PhysicsSystem {
 		}
 	}
 
-	fn build_fixtures<'a>(world: &mut b2::World<AgentData>, agent: &'a world::agent::Agent) -> Vec<JointRef<'a>> {
+	fn category(agent_type: AgentType) -> u16 {
+		FENCE_CATEGORY << (1 + agent_type as u16)
+	}
+
+	/// Fixtures of `agent_type` collide with the fence and with every type the policy doesn't have them ignore
+	fn filter(contacts: &ContactPolicy, agent_type: AgentType) -> b2::Filter {
+		b2::Filter {
+			category_bits: Self::category(agent_type),
+			mask_bits: AgentType::all()
+				.iter()
+				.filter(|&&other| contacts.collides(agent_type, other))
+				.fold(FENCE_CATEGORY, |mask, &other| mask | Self::category(other)),
+			group_index: 0,
+		}
+	}
+
+	fn build_fixtures<'a>(world: &mut b2::World<AgentData>, agent: &'a world::agent::Agent, filter: &b2::Filter)
+	                      -> Vec<JointRef<'a>> {
 		let object_id = agent.id();
 		let segments = agent.segments();
 		segments.into_iter()
@@ -175,11 +382,12 @@ impl PhysicsSystem {
 				f_def.density = material.density;
 				f_def.restitution = material.restitution;
 				f_def.friction = material.friction;
+				f_def.filter = filter.clone();
 
 				let transform = segment.transform();
 				let mut b_def = b2::BodyDef::new();
 				b_def.body_type = b2::BodyType::Dynamic;
-				b_def.linear_damping = 0.8;
+				b_def.linear_damping = LINEAR_DAMPING;
 				b_def.angular_damping = 0.9;
 				b_def.angle = transform.angle;
 				b_def.position = Self::vec2(&transform.position, 1.);
@@ -217,25 +425,15 @@ impl PhysicsSystem {
 						world.body_mut(handle).create_fixture_with(&poly, &mut f_def, refs);
 
 					}
-					obj::Shape::Star { radius, n, .. } => {
-						let p = &mesh.vertices;
-						for i in 0..n {
-							let mut quad = b2::PolygonShape::new();
-							let i1 = (i * 2 + 1) as usize;
-							let i2 = (i * 2) as usize;
-							let i3 = ((i * 2 + (n * 2) - 1) % (n * 2)) as usize;
-							let (p1, p2, p3) = match mesh.winding() {
-								obj::Winding::CW => (&p[i1], &p[i2], &p[i3]),
-								obj::Winding::CCW => (&p[i1], &p[i3], &p[i2]),
-							};
-							quad.set(&[b2::Vec2 { x: 0., y: 0. },
-							           Self::vec2(&p1, radius),
-							           Self::vec2(&p2, radius),
-							           Self::vec2(&p3, radius)]);
+					obj::Shape::Star { radius, .. } |
+					obj::Shape::Polygon { radius, .. } => {
+						let pieces = convex_decomposition(&mesh.vertices, MAX_POLYGON_VERTICES);
+						for (i, piece) in pieces.iter().enumerate() {
+							let mut poly = b2::PolygonShape::new();
+							poly.set(piece.iter().map(|v| Self::vec2(v, radius)).collect::<Vec<_>>().as_slice());
 							let refs = agent::Key::with_bone(object_id, segment_index as u8, i as u8);
-							world.body_mut(handle).create_fixture_with(&quad, &mut f_def, refs);
+							world.body_mut(handle).create_fixture_with(&poly, &mut f_def, refs);
 						}
-
 					}
 					obj::Shape::Triangle { radius, .. } => {
 						let p = &mesh.vertices;
@@ -259,8 +457,10 @@ impl PhysicsSystem {
 			.collect::<Vec<_>>()
 	}
 
-	fn build_joints(world: &mut b2::World<AgentData>, joint_refs: &Vec<JointRef>) {
-		for &JointRef { handle: distal, mesh, attachment, flags, .. } in joint_refs {
+	/// Joins every attached segment to its parent, returning where each one is pinned in its own frame
+	fn build_joints(world: &mut b2::World<AgentData>, joint_refs: &Vec<JointRef>) -> Vec<(agent::Key, b2::Vec2)> {
+		let mut pivots = Vec::new();
+		for &JointRef { refs, handle: distal, mesh, attachment, flags } in joint_refs {
 			if let Some(attachment) = attachment {
 				let upstream = &joint_refs[attachment.index as usize];
 				let medial = upstream.handle;
@@ -270,6 +470,7 @@ impl PhysicsSystem {
 				let v1 = mesh.vertices[0] * mesh.shape.radius();
 				let a = b2::Vec2 { x: v0.x, y: v0.y };
 				let b = b2::Vec2 { x: v1.x, y: v1.y };
+				pivots.push((refs, b));
 				macro_rules! common_joint (
 					($joint:ident) => {
 						$joint.collide_connected = false;
@@ -293,6 +494,21 @@ impl PhysicsSystem {
 				}
 			}
 		}
+		pivots
+	}
+
+	/// Ages every touch by a tick, forgetting those reported for `memory` ticks already
+	fn forget_touches(touched: &mut HashMap<agent::Key, Touch>, memory: u32) {
+		let mut forgotten = Vec::new();
+		for (key, touch) in touched.iter_mut() {
+			touch.age += 1;
+			if touch.age >= memory {
+				forgotten.push(*key);
+			}
+		}
+		for key in forgotten {
+			touched.remove(&key);
+		}
 	}
 
 	fn new_world(touched: ContactSet) -> b2::World<AgentData> {
@@ -300,49 +516,396 @@ impl PhysicsSystem {
 		world.set_contact_listener(Box::new(ContactListener { touched: touched }));
 		world
 	}
-
-	pub fn pick(&self, pos: Position) -> Option<Id> {
-		let point = Self::to_vec2(&pos);
-		let eps = 0.001f32;
-		let aabb = b2::AABB {
-			lower: b2::Vec2 {
-				x: pos.x - eps,
-				y: pos.y - eps,
-			},
-			upper: b2::Vec2 {
-				x: pos.x + eps,
-				y: pos.y + eps,
-			},
-		};
-		let mut result = None;
-		{
-			let mut callback = |body_h: b2::BodyHandle, fixture_h: b2::FixtureHandle| {
-				let body = self.world.body(body_h);
-				let fixture = body.fixture(fixture_h);
-				if fixture.test_point(&point) {
-					result = Some(body.user_data().id());
-					false
-				} else {
-					true
-				}
-			};
-			self.world.query_aabb(&mut callback, &aabb);
-		}
-		result
-	}
 }
 
 struct ContactListener {
 	touched: ContactSet,
 }
 
-impl b2::ContactListener<AgentData> for ContactListener {
-	fn post_solve(&mut self, ca: ContactAccess<AgentData>, _: &b2::ContactImpulse) {
+impl ContactListener {
+	/// Has both segments of the contact touch each other, unless they belong to the same agent, keeping the
+	/// hardest contact of the tick for each
+	fn touch(&self, ca: &ContactAccess<AgentData>, impulse: f32) {
 		let body_a = ca.fixture_a.user_data();
 		let body_b = ca.fixture_b.user_data();
 		if body_a.agent_id != body_b.agent_id {
-			self.touched.borrow_mut().insert(body_a.no_bone(), body_b.no_bone());
-			self.touched.borrow_mut().insert(body_b.no_bone(), body_a.no_bone());
+			let mut touched = self.touched.borrow_mut();
+			for &(key, other) in &[(body_a.no_bone(), body_b.no_bone()), (body_b.no_bone(), body_a.no_bone())] {
+				let touch = Touch {
+					other: other,
+					impulse: impulse,
+					age: 0,
+				};
+				let contact = touched.entry(key).or_insert(touch);
+				if contact.age > 0 || impulse >= contact.impulse {
+					*contact = touch;
+				}
+			}
+		}
+	}
+}
+
+impl b2::ContactListener<AgentData> for ContactListener {
+	fn begin_contact(&mut self, ca: ContactAccess<AgentData>) {
+		self.touch(&ca, 0.);
+	}
+
+	/// Contacts that last are touched again on every tick they're solved, with how hard they push
+	fn post_solve(&mut self, ca: ContactAccess<AgentData>, impulse: &b2::ContactImpulse) {
+		let magnitude = impulse.normal_impulses[..impulse.count as usize].iter().fold(0f32, |sum, &i| sum + i);
+		self.touch(&ca, magnitude);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::world::gen;
+	use backend::world::phen;
+
+	fn drop_ball(physics: &mut PhysicsSystem, material: Material, position: Position, velocity: Velocity)
+	             -> b2::BodyHandle {
+		let id = 1 << 8 | agent::AgentType::Resource as usize;
+		let dna = vec![0u8; 8].into_boxed_slice();
+		let agent = phen::AgentBuilder::new(id, material, Livery::default(), &dna, segment::State::default())
+			.start(&Transform::new(position, 0.),
+			       Some(&Motion {
+				       velocity: velocity,
+				       spin: 0.,
+			       }),
+			       &Shape::new_ball(0.5))
+			.build();
+		physics.register(&agent);
+		physics.handles[&agent::Key::with_segment(id, 0)]
+	}
+
+	fn bounce_height(restitution: f32) -> f32 {
+		let mut physics = PhysicsSystem::default();
+		physics.init_extent(&Rect::new(-10., -10., 10., 10.));
+		let handle = drop_ball(&mut physics,
+		                       Material { restitution: restitution, ..Default::default() },
+		                       Position::new(0., -5.),
+		                       Velocity::new(0., -20.));
+		let mut bounced = false;
+		let mut height = -10f32;
+		for _ in 0..120 {
+			physics.world.step(1. / 60., 8, 3);
+			let body = physics.world.body(handle);
+			bounced = bounced || body.linear_velocity().y > 0.;
+			if bounced {
+				height = height.max(body.position().y);
+			}
+		}
+		height
+	}
+
+	fn impact(speed: f32) -> f32 {
+		let mut physics = PhysicsSystem::default();
+		physics.init_extent(&Rect::new(-10., -10., 10., 10.));
+		let handle = drop_ball(&mut physics, Material::default(), Position::new(0., -5.), Velocity::new(0., -speed));
+		let key = *physics.world.body(handle).user_data();
+		let mut strongest = 0f32;
+		for _ in 0..60 {
+			physics.world.step(1. / 60., 8, 3);
+			if let Some(touch) = physics.touched.borrow().get(&key) {
+				strongest = strongest.max(touch.impulse);
+			}
+			physics.touched.borrow_mut().clear();
+		}
+		strongest
+	}
+
+	#[test]
+	fn segments_get_a_body_each_and_a_joint_per_attachment() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let mut physics = PhysicsSystem::default();
+		physics.register(world.agent(id).unwrap());
+		{
+			let segments = world.agent(id).unwrap().segments();
+			let attached = segments.iter().filter(|s| s.attached_to.is_some()).count();
+			assert!(attached > 0);
+			assert_eq!(segments.len() as i32, physics.world.body_count());
+			assert_eq!(attached as i32, physics.world.joint_count());
+			for segment in segments {
+				assert!(physics.handles.contains_key(&agent::Key::with_segment(id, segment.index)));
+			}
+		}
+
+		physics.init(&world);
+		for segment in world.agent_mut(id).unwrap().segments_mut().iter_mut() {
+			segment.motion = None;
+		}
+		physics.update_world(&mut world, 1. / 60.);
+		for segment in world.agent(id).unwrap().segments() {
+			assert!(segment.motion.is_some());
+		}
+	}
+
+	#[test]
+	fn bodies_weigh_their_area_times_density() {
+		let mut physics = PhysicsSystem::default();
+		let material = Material { density: 2.5, ..Default::default() };
+		let handle = drop_ball(&mut physics, material, Position::new(0., 0.), Velocity::new(0., 0.));
+		let mass = Shape::new_ball(0.5).area() * 2.5;
+		assert!((physics.world.body(handle).mass() - mass).abs() < 1e-4);
+	}
+
+	#[test]
+	fn colliding_agents_touch_each_other_for_a_while() {
+		const MEMORY: u32 = 30;
+		let ball = |physics: &mut PhysicsSystem, serial: usize, x: f32, speed: f32| {
+			let id = serial << 8 | agent::AgentType::Resource as usize;
+			let dna = vec![0u8; 8].into_boxed_slice();
+			let state = segment::State::default();
+			let mut builder = phen::AgentBuilder::new(id, Material::default(), Livery::default(), &dna, state);
+			let agent = builder.start(&Transform::new(Position::new(x, 0.), 0.),
+			                          Some(&Motion {
+				                          velocity: Velocity::new(speed, 0.),
+				                          spin: 0.,
+			                          }),
+			                          &Shape::new_ball(0.5))
+				.build();
+			physics.register(&agent);
+			agent::Key::with_segment(id, 0)
+		};
+		let mut physics = PhysicsSystem::default();
+		let a = ball(&mut physics, 1, -2., 10.);
+		let b = ball(&mut physics, 2, 2., -10.);
+		// what each of the two reports after a tick, as `to_world` would
+		let tick = |physics: &mut PhysicsSystem| {
+			physics.world.step(1. / 60., 8, 3);
+			let touches = {
+				let touched = physics.touched.borrow();
+				(touched.get(&a).map(|t| t.other), touched.get(&b).map(|t| t.other))
+			};
+			PhysicsSystem::forget_touches(&mut physics.touched.borrow_mut(), MEMORY);
+			touches
+		};
+
+		let mut ticks = 0;
+		while tick(&mut physics) == (None, None) {
+			ticks += 1;
+			assert!(ticks < 60, "the two never met");
+		}
+		// both at once, and long after bouncing apart
+		for _ in 0..MEMORY - 1 {
+			assert_eq!((Some(b), Some(a)), tick(&mut physics));
+		}
+		assert!(physics.world.body(physics.handles[&a]).linear_velocity().x < 0.);
+		assert!(physics.world.body(physics.handles[&b]).linear_velocity().x > 0.);
+		// the tick they last touched on starts the count again, so forgetting may take a few more
+		ticks = 0;
+		while tick(&mut physics) != (None, None) {
+			ticks += 1;
+			assert!(ticks < 10, "the contact was never forgotten");
+		}
+	}
+
+	#[test]
+	fn grabbed_agents_follow_the_mouse_and_fly_off_when_let_go() {
+		const DT: f32 = 1. / 60.;
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let mut physics = PhysicsSystem::default();
+		physics.extent = Rect::new(-1000., -1000., 1000., 1000.);
+		physics.register(world.agent(id).unwrap());
+		let tick = |physics: &mut PhysicsSystem, world: &mut world::World| {
+			physics.from_world(&*world);
+			physics.update(&*world, DT);
+			physics.to_world(world);
+		};
+		let distance = |world: &world::World, target: Position| {
+			(world.agent(id).unwrap().transform().position - target).length()
+		};
+		assert!(world.grab(id, Position::new(5., 0.)));
+		for _ in 0..10 {
+			tick(&mut physics, &mut world);
+		}
+		assert!(distance(&world, Position::new(5., 0.)) < 0.1);
+		world.move_grabbed(Position::new(5., 5.));
+		tick(&mut physics, &mut world);
+		assert!(distance(&world, Position::new(5., 5.)) < 0.5);
+
+		assert!(world.fling(Velocity::new(0., -20.)));
+		tick(&mut physics, &mut world);
+		assert!(world.grabbed().is_none());
+		assert!(world.agent(id).unwrap().segments()[0].motion.as_ref().unwrap().velocity.y < -10.);
+		// nothing is held any more
+		assert!(!world.fling(Velocity::new(0., 20.)));
+	}
+
+	#[test]
+	fn moved_agents_take_their_bodies_along() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let mut physics = PhysicsSystem::default();
+		physics.extent = Rect::new(-1000., -1000., 1000., 1000.);
+		physics.register(world.agent(id).unwrap());
+		let before = world.agent(id).unwrap().segments().iter().map(|s| s.transform.position).collect::<Vec<_>>();
+		assert!(world.move_to(AgentType::Minion, id, Position::new(20., -10.)));
+		physics.update_world(&mut world, 1. / 60.);
+		assert!(world.moved().is_empty());
+		let after = world.agent(id).unwrap().segments().iter().map(|s| s.transform.position).collect::<Vec<_>>();
+		assert!((after[0] - Position::new(20., -10.)).length() < 0.5);
+		// every segment is taken along by the same offset, the body keeps its shape
+		let offset = Position::new(20., -10.) - before[0];
+		for (was, now) in before.iter().zip(after.iter()) {
+			assert!((*was + offset - *now).length() < 0.5);
+		}
+	}
+
+	#[test]
+	fn harder_collisions_report_larger_impulses() {
+		let gentle = impact(2.);
+		assert!(gentle > 0.);
+		assert!(impact(20.) > gentle);
+	}
+
+	#[test]
+	fn bouncy_bodies_bounce_higher() {
+		assert!(bounce_height(0.9) > bounce_height(0.1));
+	}
+
+	#[test]
+	fn soft_fence_turns_bodies_back() {
+		let mut physics = PhysicsSystem::default();
+		physics.extent = Rect::new(-10., -10., 10., 10.);
+		physics.fence.rigid = false;
+		let handle = drop_ball(&mut physics, Material::default(), Position::new(0., 0.), Velocity::new(10., 0.));
+		let mut farthest = 0f32;
+		let mut turned = false;
+		for _ in 0..240 {
+			physics.apply_boundary_force(1. / 60.);
+			physics.world.step(1. / 60., 8, 3);
+			let body = physics.world.body(handle);
+			farthest = farthest.max(body.position().x);
+			turned = turned || body.linear_velocity().x < 0.;
+		}
+		assert!(turned);
+		assert!(farthest < 10. - 0.5);
+	}
+
+	#[test]
+	fn rigid_fence_throws_escaping_agents_back_in() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let id = world.new_minion(Position::new(0., world.extent.max.y - 20.), None);
+		for segment in world.agent_mut(id).unwrap().segments_mut().iter_mut() {
+			segment.motion = Some(Motion {
+				velocity: Velocity::new(0., 60.),
+				spin: 0.,
+			});
+		}
+		let mut physics = PhysicsSystem::default();
+		physics.init(&world);
+		// only the wall, no soft push
+		physics.fence.onset = 0.;
+		physics.register(world.agent(id).unwrap());
+		let mut turned = false;
+		for _ in 0..120 {
+			physics.update(&world, 1. / 60.);
+			physics.to_world(&mut world);
+			let agent = world.agent(id).unwrap();
+			turned = turned || agent.segments()[0].motion.as_ref().unwrap().velocity.y < 0.;
+			for segment in agent.segments() {
+				assert!(segment.transform.position.y < world.extent.max.y);
+			}
+		}
+		assert!(turned);
+	}
+
+	#[test]
+	fn ignored_types_are_masked_out() {
+		use backend::world::contact::Effect;
+		let mut contacts = ContactPolicy::default();
+		contacts.set(AgentType::Spore, AgentType::Minion, Effect::Ignore);
+		let spore = PhysicsSystem::filter(&contacts, AgentType::Spore);
+		let minion = PhysicsSystem::filter(&contacts, AgentType::Minion);
+		let resource = PhysicsSystem::filter(&contacts, AgentType::Resource);
+		assert_eq!(0, spore.mask_bits & minion.category_bits);
+		assert_eq!(0, minion.mask_bits & spore.category_bits);
+		assert!(minion.mask_bits & resource.category_bits != 0);
+		assert!(spore.mask_bits & FENCE_CATEGORY != 0);
+	}
+
+	/// Just the one agent
+	struct Lone(agent::Agent);
+
+	impl world::WorldState for Lone {
+		fn agent(&self, id: Id) -> Option<&agent::Agent> {
+			if id == self.0.id() { Some(&self.0) } else { None }
+		}
+	}
+
+	/// A minion of a single ball facing `angle`, with the given flags and intent
+	fn actuator(flags: segment::Flags, intent: Intent, angle: f32, velocity: Velocity)
+	            -> (PhysicsSystem, Lone, b2::BodyHandle) {
+		let id = 1 << 8 | agent::AgentType::Minion as usize;
+		let dna = vec![0u8; 8].into_boxed_slice();
+		let mut builder = phen::AgentBuilder::new(id, Material::default(), Livery::default(), &dna, Default::default());
+		let mut agent = builder.start(&Transform::new(Position::new(0., 0.), angle),
+		                              Some(&Motion {
+			                              velocity: velocity,
+			                              spin: 0.,
+		                              }),
+		                              &Shape::new_ball(0.5))
+			.build();
+		{
+			let segment = &mut agent.segments_mut()[0];
+			segment.flags = flags;
+			segment.state.intent = intent;
+		}
+		let mut physics = PhysicsSystem::default();
+		// far enough for the fence never to push
+		physics.extent = Rect::new(-1000., -1000., 1000., 1000.);
+		physics.register(&agent);
+		let handle = physics.handles[&agent::Key::with_segment(id, 0)];
+		(physics, Lone(agent), handle)
+	}
+
+	#[test]
+	fn thrusters_push_along_their_facing() {
+		let angle = consts::PI / 3.;
+		let facing = Position::new(-angle.sin(), angle.cos());
+		let (mut physics, lone, handle) = actuator(segment::ACTUATOR | segment::THRUSTER,
+		                                           Intent::Move(facing * 5.),
+		                                           angle,
+		                                           Velocity::new(0., 0.));
+		let mut speed = 0.;
+		for _ in 0..10 {
+			physics.update(&lone, 1. / 60.);
+			let velocity = PhysicsSystem::from_vec2(physics.world.body(handle).linear_velocity());
+			assert!(velocity.dot(facing) > speed);
+			// only the weak gravity pulls it sideways
+			assert!(velocity.perp_dot(facing).abs() < velocity.dot(facing) * 0.2);
+			speed = velocity.dot(facing);
+		}
+	}
+
+	#[test]
+	fn braking_slows_in_proportion_to_the_coefficient() {
+		const DT: f32 = 1. / 60.;
+		let speed_after = |brake_coefficient: f32| {
+			let (mut physics, mut lone, handle) = actuator(segment::ACTUATOR | segment::BRAKE,
+			                                               Intent::Brake(Position::new(-1e6, 0.)),
+			                                               0.,
+			                                               Velocity::new(10., 0.));
+			physics.brake_coefficient = brake_coefficient;
+			physics.update(&lone, DT);
+			let speed = physics.world.body(handle).linear_velocity().x;
+			// and the brake lets go once no longer wanted
+			lone.0.segments_mut()[0].state.intent = Intent::Idle;
+			physics.update(&lone, DT);
+			assert_eq!(LINEAR_DAMPING, physics.world.body(handle).linear_damping());
+			speed
+		};
+		let coasting = speed_after(0.);
+		for &k in &[1., 2., 4.] {
+			// Box2D damps the velocity by 1 / (1 + dt * damping) every step
+			let expected = (1. + LINEAR_DAMPING * DT) / (1. + (LINEAR_DAMPING + k) * DT);
+			assert!((speed_after(k) / coasting - expected).abs() < 1e-4);
 		}
+		// a weak brake only damps as hard as its force allows
+		assert_eq!(0.1, PhysicsSystem::default().brake_damping(10., 1., 1.));
 	}
 }
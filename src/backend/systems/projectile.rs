@@ -0,0 +1,227 @@
+use super::*;
+use cgmath::EuclideanVector;
+use core::geometry::*;
+use backend::world;
+use backend::world::agent;
+use backend::world::agent::AgentType;
+use backend::world::agent::TypedAgent;
+use backend::world::gen;
+use backend::world::segment;
+use backend::world::WorldState;
+use backend::config::ProjectileConfig;
+
+/// Who fires into which swarm, and at what
+const SHOOTERS: &'static [(AgentType, AgentType, AgentType)] = &[(AgentType::Minion,
+                                                                   AgentType::FriendlyBullet,
+                                                                   AgentType::Enemy),
+                                                                  (AgentType::Enemy,
+                                                                   AgentType::EnemyBullet,
+                                                                   AgentType::Minion)];
+
+/// A shot about to leave a weapon
+struct Shot {
+	agent_type: AgentType,
+	transform: Transform,
+	motion: Motion,
+	dna: gen::Dna,
+}
+
+/// Has agents fire at the targets they hunt from their weapon segment, which pays for each shot with its charge
+/// and so can only fire again once it has recharged. Shots are gone once they hit something or grow too old.
+pub struct ProjectileSystem {
+	dt: f32,
+	config: ProjectileConfig,
+}
+
+impl Updateable for ProjectileSystem {
+	fn update(&mut self, _: &WorldState, dt: f32) {
+		self.dt = dt;
+	}
+}
+
+impl System for ProjectileSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::BODIES | schedule::INTENTS | schedule::METABOLISM,
+		                   schedule::AGENTS | schedule::METABOLISM)
+	}
+
+	fn from_world(&mut self, world: &world::World) {
+		self.config = world.config().projectile.clone();
+	}
+
+	fn to_world(&self, world: &mut world::World) {
+		for &(_, bullet_type, _) in SHOOTERS {
+			Self::update_projectiles(self.dt, &self.config, &mut world.agents_mut(bullet_type));
+		}
+		// shots are only spawned once the shooters are done with, spawning inserts into the swarms
+		let mut shots = Vec::new();
+		for &(shooter_type, bullet_type, prey) in SHOOTERS {
+			let marks = world.agents(prey)
+				.values()
+				.filter(|agent| agent.state.is_active())
+				.map(|agent| agent.transform().position)
+				.collect::<Vec<_>>();
+			for (_, agent) in world.agents_mut(shooter_type).iter_mut() {
+				shots.extend(Self::fire(&self.config, bullet_type, prey, &marks, agent));
+			}
+		}
+		for shot in shots {
+			world.new_projectile(shot.agent_type, &shot.transform, &shot.motion, &shot.dna);
+		}
+	}
+}
+
+impl Default for ProjectileSystem {
+	fn default() -> Self {
+		ProjectileSystem {
+			dt: 1. / 60.,
+			config: ProjectileConfig::default(),
+		}
+	}
+}
+
+impl ProjectileSystem {
+	/// Takes a shot into `bullet_type` if the agent's weapon is charged and there is a `prey` within range:
+	/// its target if it's after one, or any of the `marks`, where the agents of that type are. Minions, who go
+	/// after resources, so only shoot at the enemies that come close.
+	fn fire(config: &ProjectileConfig, bullet_type: AgentType, prey: AgentType, marks: &[Position],
+	        agent: &mut agent::Agent)
+	        -> Option<Shot> {
+		if !agent.state.is_active() {
+			return None;
+		}
+		let target = if agent.state.target().map_or(false, |id| id.type_of() == prey) {
+			Some(*agent.state.target_position())
+		} else {
+			None
+		};
+		let dna = agent.dna().clone();
+		match agent.segments_mut().iter_mut().find(|segment| segment.flags.contains(segment::WEAPON)) {
+			Some(weapon) => {
+				let charge = weapon.state.get_charge();
+				let position = weapon.transform.position;
+				let in_range = target.iter().chain(marks).any(|&p| (p - position).length() <= config.range);
+				if charge < config.charge || !in_range {
+					return None;
+				}
+				weapon.state.set_charge(charge - config.charge);
+				let angle = weapon.transform.angle;
+				let facing = Position::new(-angle.sin(), angle.cos());
+				let velocity = weapon.motion.as_ref().map_or(Velocity::new(0., 0.), |m| m.velocity);
+				Some(Shot {
+					agent_type: bullet_type,
+					transform: Transform::new(position + facing * weapon.mesh.shape.radius(), angle),
+					motion: Motion {
+						velocity: velocity + facing * config.speed,
+						spin: 0.,
+					},
+					dna: dna,
+				})
+			}
+			None => None,
+		}
+	}
+
+	fn update_projectiles(dt: f32, config: &ProjectileConfig, projectiles: &mut agent::AgentMap) {
+		for (_, projectile) in projectiles.iter_mut() {
+			projectile.state.grow_older(dt);
+			let hit = projectile.segments().iter().any(|segment| segment.state.last_touched.is_some());
+			if hit || projectile.state.age() >= config.lifetime {
+				projectile.state.die();
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::*;
+	use backend::config::ProjectileConfig;
+	use backend::world::gen;
+	use backend::world::phen;
+	use backend::world::phen::Phenotype;
+
+	fn shooter() -> agent::Agent {
+		let mut genome = gen::GenePool::for_tests().next();
+		let mut agent = phen::Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., 32);
+		agent.state.retarget(Some(2 << 8 | AgentType::Minion as usize), Position::new(0., 10.));
+		agent
+	}
+
+	fn weapon_charge(agent: &agent::Agent) -> f32 {
+		agent.first_segment(segment::WEAPON).unwrap().state.get_charge()
+	}
+
+	#[test]
+	fn firing_takes_charge_and_makes_one_shot() {
+		let config = ProjectileConfig::default();
+		let mut agent = shooter();
+		for segment in agent.segments_mut().iter_mut() {
+			segment.state.set_charge(config.charge * 1.5);
+		}
+		let shot = ProjectileSystem::fire(&config, AgentType::EnemyBullet, AgentType::Minion, &[], &mut agent);
+		assert!(shot.is_some());
+		assert_eq!(AgentType::EnemyBullet, shot.unwrap().agent_type);
+		assert!((weapon_charge(&agent) - config.charge * 0.5).abs() < 1e-6);
+		// not enough left for another
+		assert!(ProjectileSystem::fire(&config, AgentType::EnemyBullet, AgentType::Minion, &[], &mut agent).is_none());
+
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let enemy = world.new_enemy(Position::new(0., 0.), None);
+		let minion = world.new_minion(Position::new(0., 10.), None);
+		{
+			let agent = world.agent_mut(enemy).unwrap();
+			agent.state.retarget(Some(minion), Position::new(0., 10.));
+			for segment in agent.segments_mut().iter_mut() {
+				segment.state.set_charge(config.charge * 1.5);
+			}
+		}
+		// the minion has the enemy in range too, but nothing to shoot with
+		for segment in world.agent_mut(minion).unwrap().segments_mut().iter_mut() {
+			segment.state.set_charge(0.);
+		}
+		let system = ProjectileSystem::default();
+		system.to_world(&mut world);
+		system.to_world(&mut world);
+		assert_eq!(1, world.agents(AgentType::EnemyBullet).len());
+		assert!(world.agents(AgentType::FriendlyBullet).is_empty());
+		assert!((weapon_charge(world.agent(enemy).unwrap()) - config.charge * 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn minions_shoot_at_enemies_that_come_close() {
+		let config = ProjectileConfig::default();
+		let mut agent = shooter();
+		agent.state.retarget(None, Position::new(0., 0.));
+		for segment in agent.segments_mut().iter_mut() {
+			segment.state.set_charge(config.charge * 1.5);
+		}
+		let far = [Position::new(0., config.range * 2.)];
+		assert!(ProjectileSystem::fire(&config, AgentType::FriendlyBullet, AgentType::Enemy, &far, &mut agent)
+			.is_none());
+		let near = [Position::new(0., config.range * 0.5)];
+		let shot = ProjectileSystem::fire(&config, AgentType::FriendlyBullet, AgentType::Enemy, &near, &mut agent);
+		assert_eq!(AgentType::FriendlyBullet, shot.unwrap().agent_type);
+	}
+
+	#[test]
+	fn shots_are_gone_on_a_hit_or_when_too_old() {
+		let config = ProjectileConfig::default();
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		let motion = Motion {
+			velocity: Velocity::new(0., 1.),
+			spin: 0.,
+		};
+		let dna = gen::GenePool::for_tests().next().dna().clone();
+		let stray = world.new_projectile(AgentType::EnemyBullet, &Transform::default(), &motion, &dna);
+		let hit = world.new_projectile(AgentType::EnemyBullet, &Transform::default(), &motion, &dna);
+		world.agent_mut(hit).unwrap().segments_mut()[0].state.last_touched = Some(agent::Key::with_id(stray));
+		let projectiles = world.agents_mut(AgentType::EnemyBullet);
+		ProjectileSystem::update_projectiles(config.lifetime * 0.5, &config, projectiles);
+		assert!(projectiles[&stray].state.is_alive());
+		assert!(!projectiles[&hit].state.is_alive());
+		ProjectileSystem::update_projectiles(config.lifetime * 0.5, &config, projectiles);
+		assert!(!projectiles[&stray].state.is_alive());
+	}
+}
@@ -0,0 +1,134 @@
+use super::*;
+use cgmath::EuclideanVector;
+use core::geometry::*;
+use backend::obj::Transformable;
+use backend::world;
+use backend::world::agent;
+use backend::world::gen;
+use backend::config::ReproductionConfig;
+
+/// Lets well fed minions bud off a child, paying for it with their own energy
+pub struct ReproductionSystem {
+	config: ReproductionConfig,
+}
+
+impl Updateable for ReproductionSystem {}
+
+impl System for ReproductionSystem {
+	fn access(&self) -> schedule::Job {
+		schedule::Job::new(schedule::AGENTS | schedule::BODIES | schedule::METABOLISM,
+		                   schedule::AGENTS | schedule::METABOLISM)
+	}
+
+	fn from_world(&mut self, world: &world::World) {
+		self.config = world.config().reproduction.clone();
+	}
+
+	fn to_world(&self, world: &mut world::World) {
+		// children are only spawned once the parents are done with, spawning inserts into the map being walked
+		let children = Self::find_parents(&self.config, &mut world.agents_mut(agent::AgentType::Minion));
+		for &(ref transform, ref dna, ref brain) in children.into_iter() {
+			// mutated with the minions' own stream, so that a seed always breeds the same children
+			let brain = brain.mutate(world.swarm_mut(&agent::AgentType::Minion).rng(), self.config.mutation_rate);
+			world.bud(transform, dna, &brain);
+		}
+	}
+}
+
+impl Default for ReproductionSystem {
+	fn default() -> Self {
+		ReproductionSystem { config: ReproductionConfig::default() }
+	}
+}
+
+impl ReproductionSystem {
+	/// Charges every minion over the threshold for a child, returning where each child goes and its parent's dna
+	/// and brain
+	fn find_parents(config: &ReproductionConfig, minions: &mut agent::AgentMap)
+	                -> Box<[(Transform, gen::Dna, agent::Brain)]> {
+		let mut children = Vec::new();
+		for (_, agent) in minions.iter_mut() {
+			if agent.state.is_active() && agent.state.energy_ratio() >= config.threshold &&
+			   agent.state.consume_ratio(config.cost) {
+				let head = agent.transform().clone();
+				let tail = agent.last_segment().transform().clone();
+				let back = tail.position - head.position;
+				let direction = if back.length2() > 0. { back.normalize() } else { -Position::unit_y() };
+				children.push((Transform::new(tail.position + direction * config.spacing, tail.angle),
+				               agent.dna().clone(),
+				               agent.brain().clone()));
+			}
+		}
+		children.into_boxed_slice()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+	use backend::obj::Identified;
+	use backend::world::agent::AgentType;
+
+	#[test]
+	fn well_fed_minions_multiply() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 7);
+		for i in 0..3 {
+			world.new_minion(Position::new(i as f32 * 20., 0.), None);
+		}
+		let mut ids = world.registered().iter().cloned().collect::<HashSet<_>>();
+		let mut system = ReproductionSystem::default();
+		let mut population = world.agents(AgentType::Minion).len();
+		for _ in 0..3 {
+			// plenty of resources keep everyone full
+			for (_, agent) in world.agents_mut(AgentType::Minion).iter_mut() {
+				agent.state.absorb(1e6);
+			}
+			system.update_world(&mut world, 1. / 60.);
+			let grown = world.agents(AgentType::Minion).len();
+			assert_eq!(population * 2, grown);
+			// every child got an id of its own
+			for id in world.registered().iter() {
+				assert!(ids.insert(*id));
+			}
+			assert_eq!(grown, ids.len());
+			population = grown;
+		}
+		for (_, agent) in world.agents(AgentType::Minion).iter() {
+			assert!(agent.state.energy_ratio() < ReproductionConfig::default().threshold);
+		}
+	}
+
+	#[test]
+	fn children_take_after_their_parents_brain() {
+		let breed = |seed: u64| {
+			let mut world = world::World::new(gen::GenePool::for_tests(), seed);
+			let parent_id = world.new_minion(Position::new(0., 0.), None);
+			let parent = {
+				let agent = world.agent_mut(parent_id).unwrap();
+				agent.brain_mut().hunger = 0.45;
+				agent.brain_mut().fear = 2.5;
+				agent.state.absorb(1e6);
+				agent.brain().clone()
+			};
+			ReproductionSystem::default().update_world(&mut world, 1. / 60.);
+			let child = world.agents(AgentType::Minion)
+				.iter()
+				.map(|(_, agent)| agent)
+				.find(|agent| agent.id() != parent_id)
+				.unwrap()
+				.brain()
+				.clone();
+			(parent, child)
+		};
+		let (parent, child) = breed(7);
+		// the same seed always breeds the same child
+		let (_, again) = breed(7);
+		assert_eq!((child.hunger, child.fear), (again.hunger, again.fear));
+		// away from the edges of their ranges the traits shift, by a few deviations at most
+		assert!(child.hunger != parent.hunger || child.fear != parent.fear);
+		assert!((child.hunger - parent.hunger).abs() < 0.5);
+		assert!((child.fear - parent.fear).abs() < 2.5);
+		assert_eq!(parent.spec(), child.spec());
+	}
+}
@@ -0,0 +1,484 @@
+//! Runs the systems of a frame in stages, worked out from the world data each one declares to read and write.
+//! Within a stage no system writes what another one reads or writes, so their `from_world` and `update` can
+//! overlap on a thread pool, all but the `Local` ones, which stay on the thread running the scheduler. Their
+//! `to_world` calls are then applied one at a time in registration order, so a
+//! frame ends up exactly as if the systems had run one after the other.
+//! `SystemScheduler` owns the systems themselves and decides which run and in what order.
+use std::cell::Cell;
+use std::time::Instant;
+use core::profiler;
+use backend::world;
+use super::System;
+use super::Updateable;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+bitflags! {
+	pub flags Access: u32 {
+		/// which agents exist
+		const AGENTS = 0x01,
+		/// segment transforms, motion and contacts
+		const BODIES = 0x02,
+		/// segment intents and agent targets
+		const INTENTS = 0x04,
+		/// energy, charge, lifecycle and fertility
+		const METABOLISM = 0x08,
+		const EMITTERS = 0x10,
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Job {
+	pub reads: Access,
+	pub writes: Access,
+}
+
+impl Job {
+	pub fn new(reads: Access, writes: Access) -> Self {
+		Job {
+			reads: reads,
+			writes: writes,
+		}
+	}
+
+	/// What a system that declares nothing gets: it always runs on its own
+	pub fn exclusive() -> Self {
+		Job::new(Access::all(), Access::all())
+	}
+
+	fn conflicts_with(&self, other: &Job) -> bool {
+		self.writes.intersects(other.reads | other.writes) || self.reads.intersects(other.writes)
+	}
+}
+
+/// A system as a scheduler runs it: `Shared` ones may go to the thread pool, `Local` ones are not `Send`
+/// and always run on the thread the scheduler runs on
+pub enum Runnable<'a> {
+	Shared(&'a mut (System + Send)),
+	Local(&'a mut System),
+}
+
+impl<'a> Runnable<'a> {
+	fn system(&mut self) -> &mut System {
+		match *self {
+			Runnable::Shared(ref mut system) => &mut **system,
+			Runnable::Local(ref mut system) => &mut **system,
+		}
+	}
+
+	fn access(&self) -> Job {
+		match *self {
+			Runnable::Shared(ref system) => system.access(),
+			Runnable::Local(ref system) => system.access(),
+		}
+	}
+}
+
+pub struct Scheduler {
+	stages: Vec<Vec<usize>>,
+}
+
+impl Scheduler {
+	/// `jobs` are listed in the order the systems would run one at a time. Each one goes in the stage following
+	/// the last earlier job it conflicts with.
+	pub fn new(jobs: &[Job]) -> Self {
+		let mut stage_of: Vec<usize> = Vec::with_capacity(jobs.len());
+		let mut stages: Vec<Vec<usize>> = Vec::new();
+		for (i, job) in jobs.iter().enumerate() {
+			let stage = (0..i)
+				.filter(|&j| jobs[j].conflicts_with(job))
+				.map(|j| stage_of[j] + 1)
+				.max()
+				.unwrap_or(0);
+			if stage == stages.len() {
+				stages.push(Vec::new());
+			}
+			stages[stage].push(i);
+			stage_of.push(stage);
+		}
+		Scheduler { stages: stages }
+	}
+
+	pub fn stages(&self) -> &[Vec<usize>] {
+		&self.stages
+	}
+
+	/// Runs a frame over `systems`, which must be listed as the jobs the scheduler was built from
+	pub fn run(&self, systems: &mut [Runnable], world: &mut world::World, dt: f32) {
+		for stage in &self.stages {
+			Self::prepare(stage, systems, world, dt);
+			for &i in stage {
+				systems[i].system().to_world(world);
+			}
+		}
+	}
+
+	#[cfg(not(feature = "parallel"))]
+	fn prepare(stage: &[usize], systems: &mut [Runnable], world: &world::World, dt: f32) {
+		for &i in stage {
+			let system = systems[i].system();
+			system.from_world(world);
+			system.update(world, dt);
+		}
+	}
+
+	#[cfg(feature = "parallel")]
+	fn prepare(stage: &[usize], systems: &mut [Runnable], world: &world::World, dt: f32) {
+		let mut jobs = Vec::new();
+		for (_, runnable) in systems.iter_mut().enumerate().filter(|&(i, _)| stage.contains(&i)) {
+			match *runnable {
+				Runnable::Shared(ref mut system) => jobs.push(&mut **system),
+				Runnable::Local(ref mut system) => {
+					system.from_world(world);
+					system.update(world, dt);
+				}
+			}
+		}
+		jobs.par_iter_mut().for_each(|s| {
+			s.from_world(world);
+			s.update(world, dt);
+		});
+	}
+}
+
+/// A system added to a `SystemScheduler`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SystemId(usize);
+
+enum Owned {
+	Shared(Box<System + Send>),
+	Local(Box<System>),
+}
+
+impl Owned {
+	fn system(&mut self) -> &mut System {
+		match *self {
+			Owned::Shared(ref mut system) => &mut **system,
+			Owned::Local(ref mut system) => &mut **system,
+		}
+	}
+
+	fn runnable(&mut self) -> Runnable {
+		match *self {
+			Owned::Shared(ref mut system) => Runnable::Shared(&mut **system),
+			Owned::Local(ref mut system) => Runnable::Local(&mut **system),
+		}
+	}
+}
+
+struct Entry {
+	id: SystemId,
+	name: &'static str,
+	order: i32,
+	enabled: bool,
+	system: Owned,
+}
+
+/// Owns the systems of the world and runs the enabled ones each tick, by ascending order key,
+/// systems sharing a key in the order they were added
+#[derive(Default)]
+pub struct SystemScheduler {
+	entries: Vec<Entry>,
+}
+
+impl SystemScheduler {
+	/// `name` is what the profiler reports the system's timings under
+	pub fn add<S: System + Send + 'static>(&mut self, name: &'static str, system: S, order: i32) -> SystemId {
+		self.insert(name, Owned::Shared(Box::new(system)), order)
+	}
+
+	/// Adds a system that must stay on the thread that owns it, as `add` does otherwise
+	pub fn add_local<S: System + 'static>(&mut self, name: &'static str, system: S, order: i32) -> SystemId {
+		self.insert(name, Owned::Local(Box::new(system)), order)
+	}
+
+	fn insert(&mut self, name: &'static str, system: Owned, order: i32) -> SystemId {
+		let id = SystemId(self.entries.len());
+		let at = self.entries.iter().position(|e| e.order > order).unwrap_or(self.entries.len());
+		self.entries.insert(at,
+		                    Entry {
+			                    id: id,
+			                    name: name,
+			                    order: order,
+			                    enabled: true,
+			                    system: system,
+		                    });
+		id
+	}
+
+	/// A disabled system is left out of the ticks, but still sees agents come and go
+	pub fn set_enabled(&mut self, id: SystemId, enabled: bool) {
+		for entry in self.entries.iter_mut().filter(|e| e.id == id) {
+			entry.enabled = enabled;
+		}
+	}
+
+	pub fn is_enabled(&self, id: SystemId) -> bool {
+		self.entries.iter().any(|e| e.id == id && e.enabled)
+	}
+
+	/// Applies `f` to every system in order, disabled or not
+	pub fn for_each(&mut self, f: &Fn(&mut System)) {
+		for entry in self.entries.iter_mut() {
+			f(entry.system.system());
+		}
+	}
+
+	/// Runs a tick of the enabled systems, staged by the world data they access
+	pub fn run(&mut self, world: &mut world::World, dt: f32) {
+		let mut systems = self.entries
+			.iter_mut()
+			.filter(|e| e.enabled)
+			.map(|e| e.system.runnable())
+			.collect::<Vec<_>>();
+		let jobs = systems.iter().map(|s| s.access()).collect::<Vec<_>>();
+		Scheduler::new(&jobs).run(systems.as_mut_slice(), world, dt);
+	}
+
+	/// Runs a tick like `run`, recording how long each system took while `profiler` is enabled
+	pub fn run_profiled(&mut self, world: &mut world::World, dt: f32, profiler: &profiler::Profiler) {
+		if !profiler.is_enabled() {
+			return self.run(world, dt);
+		}
+		let mut timed = self.entries
+			.iter_mut()
+			.filter(|e| e.enabled)
+			.map(|e| {
+				let name = e.name;
+				match e.system {
+					Owned::Shared(ref mut system) => TimedEntry::Shared(Timed::new(name, &mut **system)),
+					Owned::Local(ref mut system) => TimedEntry::Local(Timed::new(name, &mut **system)),
+				}
+			})
+			.collect::<Vec<_>>();
+		{
+			let mut systems = timed.iter_mut().map(|t| t.runnable()).collect::<Vec<_>>();
+			let jobs = systems.iter().map(|s| s.access()).collect::<Vec<_>>();
+			Scheduler::new(&jobs).run(systems.as_mut_slice(), world, dt);
+		}
+		for t in &timed {
+			let (name, spent) = t.timing();
+			profiler.record(name, spent);
+		}
+	}
+}
+
+/// Adds up the time a system takes over a tick, across its calls
+struct Timed<'a, S: ?Sized + 'a> {
+	name: &'static str,
+	system: &'a mut S,
+	spent: Cell<f32>,
+}
+
+/// A timed system, as `Send` as the one it times
+enum TimedEntry<'a> {
+	Shared(Timed<'a, System + Send + 'a>),
+	Local(Timed<'a, System + 'a>),
+}
+
+impl<'a> TimedEntry<'a> {
+	fn runnable(&mut self) -> Runnable {
+		match *self {
+			TimedEntry::Shared(ref mut timed) => Runnable::Shared(timed),
+			TimedEntry::Local(ref mut timed) => Runnable::Local(timed),
+		}
+	}
+
+	fn timing(&self) -> (&'static str, f32) {
+		match *self {
+			TimedEntry::Shared(ref timed) => (timed.name, timed.spent.get()),
+			TimedEntry::Local(ref timed) => (timed.name, timed.spent.get()),
+		}
+	}
+}
+
+impl<'a, S: ?Sized + System + 'a> Timed<'a, S> {
+	fn new(name: &'static str, system: &'a mut S) -> Self {
+		Timed {
+			name: name,
+			system: system,
+			spent: Cell::new(0.),
+		}
+	}
+
+	fn add_since(&self, start: Instant) {
+		self.spent.set(self.spent.get() + profiler::seconds_since(start));
+	}
+}
+
+impl<'a, S: ?Sized + System + 'a> Updateable for Timed<'a, S> {
+	fn update(&mut self, world_state: &world::WorldState, dt: f32) {
+		let start = Instant::now();
+		self.system.update(world_state, dt);
+		self.add_since(start);
+	}
+}
+
+impl<'a, S: ?Sized + System + 'a> System for Timed<'a, S> {
+	fn access(&self) -> Job {
+		self.system.access()
+	}
+
+	fn from_world(&mut self, world: &world::World) {
+		let start = Instant::now();
+		self.system.from_world(world);
+		self.add_since(start);
+	}
+
+	fn to_world(&self, world: &mut world::World) {
+		let start = Instant::now();
+		self.system.to_world(world);
+		self.add_since(start);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+	use std::rc::Rc;
+	use std::sync::{Arc, Mutex};
+	use core::profiler;
+	use backend::systems::*;
+	use backend::world;
+	use backend::world::gen;
+
+	#[test]
+	fn conflicting_jobs_wait_for_earlier_ones() {
+		let scheduler = Scheduler::new(&[Job::new(INTENTS, BODIES),
+		                                 Job::new(BODIES, METABOLISM),
+		                                 Job::new(EMITTERS, AGENTS),
+		                                 Job::new(METABOLISM, INTENTS),
+		                                 Job::exclusive()]);
+		assert_eq!(&[vec![0, 2], vec![1], vec![3], vec![4]], scheduler.stages());
+	}
+
+	struct Writer;
+
+	impl Updateable for Writer {}
+
+	impl System for Writer {
+		fn to_world(&self, world: &mut world::World) {
+			world.extent.max.x = 42.;
+		}
+	}
+
+	struct Reader {
+		seen: f32,
+	}
+
+	impl Updateable for Reader {}
+
+	impl System for Reader {
+		fn from_world(&mut self, world: &world::World) {
+			self.seen = world.extent.max.x;
+		}
+	}
+
+	#[test]
+	fn readers_see_earlier_writes() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 0);
+		let mut writer = Writer;
+		let mut reader = Reader { seen: 0. };
+		let scheduler = Scheduler::new(&[Job::new(Access::empty(), EMITTERS), Job::new(EMITTERS, Access::empty())]);
+		assert_eq!(2, scheduler.stages().len());
+		scheduler.run(&mut [Runnable::Shared(&mut writer), Runnable::Local(&mut reader)],
+		              &mut world,
+		              1. / 60.);
+		assert_eq!(42., reader.seen);
+	}
+
+	/// Holds an `Rc`, so it can't leave the thread it was made on
+	struct Counted {
+		ticks: Rc<Cell<u32>>,
+	}
+
+	impl Updateable for Counted {
+		fn update(&mut self, _: &world::WorldState, _: f32) {
+			self.ticks.set(self.ticks.get() + 1);
+		}
+	}
+
+	impl System for Counted {}
+
+	#[test]
+	fn local_systems_run_alongside_shared_ones() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 0);
+		let log = Arc::new(Mutex::new(Vec::new()));
+		let ticks = Rc::new(Cell::new(0));
+		let mut scheduler = SystemScheduler::default();
+		scheduler.add_local("counted", Counted { ticks: ticks.clone() }, 0);
+		scheduler.add("logged",
+		              Logged {
+			              name: "logged",
+			              log: log.clone(),
+		              },
+		              1);
+		scheduler.run(&mut world, 1. / 60.);
+		let mut profiler = profiler::Profiler::default();
+		profiler.set_enabled(true);
+		scheduler.run_profiled(&mut world, 1. / 60., &profiler);
+		assert_eq!(2, ticks.get());
+		assert_eq!(6, log.lock().unwrap().len());
+		assert_eq!(vec!["counted", "logged"], profiler.timings().iter().map(|t| t.name).collect::<Vec<_>>());
+	}
+
+	struct Logged {
+		name: &'static str,
+		log: Arc<Mutex<Vec<String>>>,
+	}
+
+	impl Logged {
+		fn log(&self, call: &str) {
+			self.log.lock().unwrap().push(format!("{} {}", self.name, call));
+		}
+	}
+
+	impl Updateable for Logged {
+		fn update(&mut self, _: &world::WorldState, _: f32) {
+			self.log("update");
+		}
+	}
+
+	impl System for Logged {
+		fn from_world(&mut self, _: &world::World) {
+			self.log("from_world");
+		}
+
+		fn to_world(&self, _: &mut world::World) {
+			self.log("to_world");
+		}
+	}
+
+	#[test]
+	fn systems_run_by_order_key_unless_disabled() {
+		let mut world = world::World::new(gen::GenePool::for_tests(), 0);
+		let log = Arc::new(Mutex::new(Vec::new()));
+		let mut scheduler = SystemScheduler::default();
+		scheduler.add("late",
+		              Logged {
+			              name: "late",
+			              log: log.clone(),
+		              },
+		              2);
+		let early = scheduler.add("early",
+		                          Logged {
+			                          name: "early",
+			                          log: log.clone(),
+		                          },
+		                          1);
+		let mut profiler = profiler::Profiler::default();
+		profiler.set_enabled(true);
+		scheduler.run_profiled(&mut world, 1. / 60., &profiler);
+		assert_eq!(vec!["early from_world", "early update", "early to_world", "late from_world", "late update",
+		                "late to_world"],
+		           *log.lock().unwrap());
+		assert_eq!(vec!["early", "late"], profiler.timings().iter().map(|t| t.name).collect::<Vec<_>>());
+
+		log.lock().unwrap().clear();
+		scheduler.set_enabled(early, false);
+		assert!(!scheduler.is_enabled(early));
+		scheduler.run(&mut world, 1. / 60.);
+		assert_eq!(vec!["late from_world", "late update", "late to_world"], *log.lock().unwrap());
+	}
+}
@@ -1,14 +1,20 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::slice;
 use std::f32;
 use num::Float;
 use num::FromPrimitive;
+use rand;
+use rand::distributions::{IndependentSample, Normal};
 use core::geometry::*;
 use core::clock::*;
 use core::util;
+use core::color::Rgba;
+use serialize::json::{Json, ToJson};
 use backend::obj;
 use backend::obj::*;
 use backend::world::gen::Dna;
+use backend::world::persist;
 use backend::world::segment;
 use backend::world::segment::Segment;
 
@@ -116,18 +122,98 @@ impl AgentType {
 	pub fn all() -> &'static [AgentType] {
 		AGENT_TYPES
 	}
+
+	/// Whether agents of the type are shots, the fire swarms the projectile system fills
+	pub fn is_projectile(&self) -> bool {
+		match self {
+			&AgentType::FriendlyBullet | &AgentType::EnemyBullet => true,
+			_ => false,
+		}
+	}
+
+	/// Whether agents of the type are born small and grow up, aged every frame by the alife system
+	pub fn grows(&self) -> bool {
+		match self {
+			&AgentType::Minion | &AgentType::Spore | &AgentType::Enemy | &AgentType::Resource => true,
+			_ => false,
+		}
+	}
+
+	/// Whether agents of the type think for themselves, steered by the AI through their brain
+	pub fn has_brain(&self) -> bool {
+		match self {
+			&AgentType::Minion | &AgentType::Enemy => true,
+			_ => false,
+		}
+	}
+
+	/// Fixed color standing for the type in every debug overlay, picked to tell the types apart at a glance
+	pub fn debug_color(&self) -> Rgba<f32> {
+		match self {
+			&AgentType::Minion => [0.2, 0.6, 1.0, 1.0],
+			&AgentType::Spore => [1.0, 0.4, 0.8, 1.0],
+			&AgentType::Player => [1.0, 1.0, 1.0, 1.0],
+			&AgentType::FriendlyBullet => [0.4, 1.0, 1.0, 1.0],
+			&AgentType::Enemy => [1.0, 0.2, 0.2, 1.0],
+			&AgentType::EnemyBullet => [1.0, 0.6, 0.1, 1.0],
+			&AgentType::Resource => [0.3, 1.0, 0.3, 1.0],
+			&AgentType::Prop => [0.6, 0.6, 0.6, 1.0],
+		}
+	}
 }
 
-// for simplicity, inputs = intermediate = output
+/// How many values `Personality::response` gives
 pub const N_WEIGHTS: usize = 4;
+/// How many senses feed `Personality::response`; the genome encodes the weights of the first `N_WEIGHTS`
+/// along with the rest of the network, those of the senses added since after everything else
+pub const N_INPUTS: usize = 7;
 
-pub type InputVector<S> where S: Copy + Default + Float = [S; N_WEIGHTS];
+pub type InputVector<S> where S: Copy + Default + Float = [S; N_INPUTS];
 pub type OutputVector<S> where S: Copy + Default + Float = [S; N_WEIGHTS];
 
-pub type WeightVector<T> where T: Copy + Default = [T; N_WEIGHTS];
-pub type WeightMatrix<T> where T: Copy + Default = [WeightVector<T>; N_WEIGHTS];
+/// The weights of a fully connected layer, `[i][j]` weighs input `j` into neuron `i`
+pub type Weights<T> = Vec<Vec<T>>;
+
+/// The range network weights are drawn from, by the genome and at random alike
+pub const WEIGHT_RANGE: (f32, f32) = (-4., 4.);
+
+/// The shape of the network behind `Personality::response`: how many inputs it reads, how many neurons
+/// each hidden layer has and how many outputs it gives. `response` always takes `N_INPUTS` values and gives
+/// `N_WEIGHTS`, inputs past the network's are dropped and outputs it doesn't have read as zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrainSpec {
+	pub inputs: usize,
+	pub hidden: Vec<usize>,
+	pub outputs: usize,
+}
+
+impl Default for BrainSpec {
+	/// The network the genome encodes
+	fn default() -> Self {
+		BrainSpec {
+			inputs: N_INPUTS,
+			hidden: vec![N_WEIGHTS, N_WEIGHTS],
+			outputs: N_WEIGHTS,
+		}
+	}
+}
+
+impl BrainSpec {
+	/// Inputs and neurons of each layer, input layer first
+	pub fn layers(&self) -> Vec<(usize, usize)> {
+		let mut widths = vec![self.inputs];
+		widths.extend(self.hidden.iter().cloned());
+		widths.push(self.outputs);
+		widths.windows(2).map(|w| (w[0], w[1])).collect()
+	}
+
+	/// Whether a network can be built to it, with at least one input and one neuron in every layer
+	pub fn is_valid(&self) -> bool {
+		self.inputs > 0 && self.outputs > 0 && self.hidden.iter().all(|&n| n > 0)
+	}
+}
 
-#[derive(Clone,Default,Debug)]
+#[derive(Clone,Debug)]
 pub struct GBrain<T: Copy + Default> {
 	pub hunger: T,
 	pub haste: T,
@@ -135,15 +221,32 @@ pub struct GBrain<T: Copy + Default> {
 	pub fear: T,
 	pub rest: T,
 	pub thrust: T,
-	pub weights_in: WeightMatrix<T>,
-	pub weights_hidden: WeightMatrix<T>,
-	pub weights_out: WeightMatrix<T>,
+	/// input layer first, each layer reading the outputs of the one before
+	layers: Vec<Weights<T>>,
+}
+
+impl<T: Copy + Default> Default for GBrain<T> {
+	/// No personality, and the network of the default spec with all its weights at zero
+	fn default() -> Self {
+		GBrain {
+			hunger: T::default(),
+			haste: T::default(),
+			prudence: T::default(),
+			fear: T::default(),
+			rest: T::default(),
+			thrust: T::default(),
+			layers: BrainSpec::default()
+				.layers()
+				.iter()
+				.map(|&(inputs, neurons)| vec![vec![T::default(); inputs]; neurons])
+				.collect(),
+		}
+	}
 }
 
 pub trait TypedBrain {
 	type Parameter: Float;
-	type WeightVector;
-	type WeightMatrix;
+	type Weights;
 }
 
 pub trait Personality<S>
@@ -155,26 +258,53 @@ pub trait Personality<S>
 	fn fear(&self) -> S;
 	fn rest(&self) -> S;
 	fn thrust(&self) -> S;
-	fn response(&self, input: &InputVector<S>) -> OutputVector<S>;
+	fn response(&self, config: &BrainConfig, input: &InputVector<S>) -> OutputVector<S>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(dead_code)]
+pub enum Activation {
+	/// x / (1 + |x|), in (-1, 1)
+	SoftSign,
+	/// in (-1, 1)
+	Tanh,
+	/// in (0, 1)
+	Sigmoid,
+}
+
+impl Default for Activation {
+	fn default() -> Self {
+		Activation::SoftSign
+	}
+}
+
+impl Activation {
+	pub fn apply<S: Float>(&self, x: S) -> S {
+		match self {
+			&Activation::SoftSign => x / (S::one() + x.abs()),
+			&Activation::Tanh => x.tanh(),
+			&Activation::Sigmoid => S::one() / (S::one() + (-x).exp()),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BrainConfig {
+	/// applied to the last layer only, so that personality thresholds compare against a known range
+	pub output_activation: Activation,
 }
 
 pub trait Layer<S, T>
 	where T: Copy,
 	      S: Float + From<T>
 {
-	fn activation(x: S) -> S {
-		x / (S::one() + x.abs())
-	}
-
-	fn layer(inputs: &[S], weights: &[WeightVector<T>]) -> OutputVector<S> {
-		let mut outputs = [S::zero(); N_WEIGHTS];
-		for i in 0..outputs.len() {
-			for j in 0..inputs.len() {
-				outputs[i] = outputs[i] + inputs[j] * weights[i][j].into();
-			}
-			outputs[i] = Self::activation(outputs[i])
-		}
-		outputs
+	fn layer(inputs: &[S], weights: &[Vec<T>], activation: Activation) -> Vec<S> {
+		weights.iter()
+			.map(|row| {
+				let sum = row.iter().zip(inputs).fold(S::zero(), |sum, (&w, &x)| sum + x * w.into());
+				activation.apply(sum)
+			})
+			.collect()
 	}
 }
 
@@ -207,11 +337,66 @@ impl<T, S> Personality<S> for GBrain<T>
 		self.thrust.into()
 	}
 
-	fn response(&self, input: &InputVector<S>) -> OutputVector<S> {
-		let output_in = Self::layer(input, &self.weights_in);
-		let output_hidden = Self::layer(&output_in, &self.weights_hidden);
-		let output_out = Self::layer(&output_hidden, &self.weights_out);
-		output_out
+	/// Feeds the input through every layer, softsign between them and the configured activation out of the last
+	fn response(&self, config: &BrainConfig, input: &InputVector<S>) -> OutputVector<S> {
+		let last = self.layers.len().saturating_sub(1);
+		let mut signal = input.to_vec();
+		for (i, weights) in self.layers.iter().enumerate() {
+			let activation = if i == last { config.output_activation } else { Activation::SoftSign };
+			signal = Self::layer(&signal, weights, activation);
+		}
+		let mut output = [S::zero(); N_WEIGHTS];
+		for (o, s) in output.iter_mut().zip(signal) {
+			*o = s;
+		}
+		output
+	}
+}
+
+impl<T: Copy + Default> GBrain<T> {
+	/// How the network is laid out
+	pub fn spec(&self) -> BrainSpec {
+		let n = self.layers.len();
+		BrainSpec {
+			inputs: self.layers.first().and_then(|layer| layer.first()).map_or(0, |row| row.len()),
+			hidden: self.layers.iter().take(n.saturating_sub(1)).map(|layer| layer.len()).collect(),
+			outputs: self.layers.last().map_or(0, |layer| layer.len()),
+		}
+	}
+
+	/// The weights of each layer, input layer first
+	pub fn weights(&self) -> &[Weights<T>] {
+		&self.layers
+	}
+
+	/// Replaces the weights of a layer, counted as in `weights`, keeping its shape;
+	/// false if there's no such layer or `weights` is shaped otherwise
+	pub fn set_weights(&mut self, layer: usize, weights: Weights<T>) -> bool {
+		let fits = match self.spec().layers().get(layer) {
+			Some(&(inputs, neurons)) => weights.len() == neurons && weights.iter().all(|row| row.len() == inputs),
+			None => false,
+		};
+		if fits {
+			self.layers[layer] = weights;
+		}
+		fits
+	}
+
+	/// Replaces the whole network, which may take another shape; false, leaving the brain alone, unless every
+	/// layer has neurons and each reads as many inputs as the one before has neurons
+	pub fn set_layers(&mut self, layers: Vec<Weights<T>>) -> bool {
+		let mut inputs = match layers.first().and_then(|layer| layer.first()) {
+			Some(row) if !row.is_empty() => row.len(),
+			_ => return false,
+		};
+		for layer in &layers {
+			if layer.is_empty() || layer.iter().any(|row| row.len() != inputs) {
+				return false;
+			}
+			inputs = layer.len();
+		}
+		self.layers = layers;
+		true
 	}
 }
 
@@ -219,12 +404,155 @@ impl<T> TypedBrain for GBrain<T>
     where T: Default + Copy + Float
 {
 	type Parameter = T;
-	type WeightVector = WeightVector<Self::Parameter>;
-	type WeightMatrix = WeightMatrix<Self::Parameter>;
+	type Weights = Weights<Self::Parameter>;
 }
 
 pub type Brain = GBrain<f32>;
 
+/// The values each personality trait may take, as `(min, max)`
+pub const HUNGER_RANGE: (f32, f32) = (0., 0.9);
+pub const HASTE_RANGE: (f32, f32) = (0., 0.9);
+pub const PRUDENCE_RANGE: (f32, f32) = (0., 0.9);
+pub const FEAR_RANGE: (f32, f32) = (0.1, 5.);
+pub const REST_RANGE: (f32, f32) = (0.2, 1.);
+pub const THRUST_RANGE: (f32, f32) = (0.2, 1.);
+
+/// Personality and weights at full precision, so that a loaded brain responds exactly like the saved one
+impl ToJson for Brain {
+	fn to_json(&self) -> Json {
+		let mut obj = BTreeMap::new();
+		for &(name, value) in &[("hunger", self.hunger),
+		                        ("haste", self.haste),
+		                        ("prudence", self.prudence),
+		                        ("fear", self.fear),
+		                        ("rest", self.rest),
+		                        ("thrust", self.thrust)] {
+			obj.insert(name.to_string(), Json::F64(value as f64));
+		}
+		let layers = self.layers
+			.iter()
+			.map(|layer| {
+				Json::Array(layer.iter()
+					.map(|row| Json::Array(row.iter().map(|&w| Json::F64(w as f64)).collect()))
+					.collect())
+			})
+			.collect();
+		obj.insert("layers".to_string(), Json::Array(layers));
+		Json::Object(obj)
+	}
+}
+
+#[allow(dead_code)]
+impl Brain {
+	/// Reads back a brain written by `to_json`
+	pub fn from_json(json: &Json) -> persist::Result<Brain> {
+		let float = |name: &str| {
+			json.find(name).and_then(|v| v.as_f64()).map(|v| v as f32).ok_or(persist::missing(name))
+		};
+		let mut brain = Brain {
+			hunger: try!(float("hunger")),
+			haste: try!(float("haste")),
+			prudence: try!(float("prudence")),
+			fear: try!(float("fear")),
+			rest: try!(float("rest")),
+			thrust: try!(float("thrust")),
+			..Brain::default()
+		};
+		let saved = try!(json.find("layers").and_then(|v| v.as_array()).ok_or(persist::missing("layers")));
+		let mut layers = Vec::new();
+		for layer in saved {
+			let mut weights = Vec::new();
+			for row in try!(layer.as_array().ok_or(persist::missing("layers"))) {
+				let row = try!(row.as_array().ok_or(persist::missing("layers")));
+				let mut values = Vec::new();
+				for v in row {
+					values.push(try!(v.as_f64().ok_or(persist::missing("layers"))) as f32);
+				}
+				weights.push(values);
+			}
+			layers.push(weights);
+		}
+		if !brain.set_layers(layers) {
+			return Err(persist::Error::Format("the layers of a brain don't fit together".to_string()));
+		}
+		Ok(brain)
+	}
+
+	/// Widens the input layer to `inputs` with zero weights, so that a brain from before some senses were added
+	/// goes on ignoring them; wider layers are left alone
+	pub fn pad_inputs(&mut self, inputs: usize) {
+		if let Some(layer) = self.layers.first_mut() {
+			for row in layer.iter_mut() {
+				while row.len() < inputs {
+					row.push(0.);
+				}
+			}
+		}
+	}
+
+	/// Gives the brain a fresh network shaped as `spec`, every weight drawn uniformly from `WEIGHT_RANGE`
+	pub fn randomize_network<R: rand::Rng>(&mut self, spec: &BrainSpec, rng: &mut R) {
+		let mut layers = Vec::new();
+		for (inputs, neurons) in spec.layers() {
+			let mut weights: Weights<f32> = Vec::new();
+			for _ in 0..neurons {
+				weights.push((0..inputs).map(|_| rng.gen_range(WEIGHT_RANGE.0, WEIGHT_RANGE.1)).collect());
+			}
+			layers.push(weights);
+		}
+		self.layers = layers;
+	}
+
+	/// A copy with each personality trait moved by a normally distributed amount, its deviation `rate` times
+	/// the width of the trait's range, and clamped back into the range. The weights are left alone.
+	pub fn mutate<R: rand::Rng>(&self, rng: &mut R, rate: f32) -> Brain {
+		let mut shift = |value: f32, range: (f32, f32)| {
+			let normal = Normal::new(0., (rate.max(0.) * (range.1 - range.0)) as f64);
+			(value + normal.ind_sample(rng) as f32).max(range.0).min(range.1)
+		};
+		Brain {
+			hunger: shift(self.hunger, HUNGER_RANGE),
+			haste: shift(self.haste, HASTE_RANGE),
+			prudence: shift(self.prudence, PRUDENCE_RANGE),
+			fear: shift(self.fear, FEAR_RANGE),
+			rest: shift(self.rest, REST_RANGE),
+			thrust: shift(self.thrust, THRUST_RANGE),
+			..self.clone()
+		}
+	}
+
+	/// Halfway between the two parents, trait by trait and weight by weight.
+	/// Parents with networks of different shapes can't be blended, the child takes the network of the first.
+	pub fn crossover(a: &Brain, b: &Brain) -> Brain {
+		fn blend(x: f32, y: f32) -> f32 {
+			(x + y) * 0.5
+		}
+		let layers: Vec<Weights<f32>> = if a.spec() == b.spec() {
+			a.layers
+				.iter()
+				.zip(&b.layers)
+				.map(|(x, y)| {
+					x.iter()
+						.zip(y)
+						.map(|(u, v)| u.iter().zip(v).map(|(&p, &q)| blend(p, q)).collect::<Vec<_>>())
+						.collect::<Vec<_>>()
+				})
+				.collect()
+		} else {
+			a.layers.clone()
+		};
+		Brain {
+			hunger: blend(a.hunger, b.hunger),
+			haste: blend(a.haste, b.haste),
+			prudence: blend(a.prudence, b.prudence),
+			fear: blend(a.fear, b.fear),
+			rest: blend(a.rest, b.rest),
+			thrust: blend(a.thrust, b.thrust),
+			layers: layers,
+		}
+	}
+}
+
 
 bitflags! {
 	pub flags Flags: u32 {
@@ -241,7 +569,7 @@ pub struct Limits {
 
 #[derive(Clone,Debug)]
 pub struct State {
-	lifecycle: Hourglass<SystemStopwatch>,
+	lifecycle: Hourglass<SimStopwatch>,
 	flags: Flags,
 	energy: f32,
 	target: Option<Id>,
@@ -249,11 +577,16 @@ pub struct State {
 	limits: Limits,
 	foreign_dna: Option<Dna>,
 	trajectory: util::History<Position>,
+	boredom: f32,
+	/// seconds of simulation the agent has been active for
+	age: f32,
+	/// seconds of simulation the agent lasts for from its birth, `None` when it lasts until something ends it
+	ttl: Option<f32>,
 }
 
 impl State {
 	#[inline]
-	pub fn lifecycle(&self) -> &Hourglass<SystemStopwatch> {
+	pub fn lifecycle(&self) -> &Hourglass<SimStopwatch> {
 		&self.lifecycle
 	}
 
@@ -265,6 +598,11 @@ impl State {
 		self.energy
 	}
 
+	/// Restores the energy of a saved agent, up to its limit
+	pub fn set_energy(&mut self, energy: f32) {
+		self.energy = self.limits.max_energy.min(energy);
+	}
+
 	pub fn energy_ratio(&self) -> f32 {
 		self.energy / self.limits.max_energy
 	}
@@ -278,6 +616,12 @@ impl State {
 		}
 	}
 
+	/// Takes up to `q`, leaving the agent with no energy if it had less; true while some is left
+	pub fn drain(&mut self, q: f32) -> bool {
+		self.energy = (self.energy - q).max(0.);
+		self.energy > 0.
+	}
+
 	pub fn consume_ratio(&mut self, ratio: f32) -> bool {
 		let max = self.limits.max_energy;
 		self.consume(max * ratio)
@@ -285,6 +629,55 @@ impl State {
 
 	pub fn absorb(&mut self, q: f32) {
 		self.energy = self.limits.max_energy.min(self.energy + q);
+		self.boredom = 0.;
+	}
+
+	/// Grows while the agent goes unfed, up to `max`; feeding resets it
+	pub fn get_bored(&mut self, q: f32, max: f32) {
+		self.boredom = max.min(self.boredom + q);
+	}
+
+	pub fn boredom(&self) -> f32 {
+		self.boredom
+	}
+
+	pub fn age(&self) -> f32 {
+		self.age
+	}
+
+	/// Moves the agent's age and its lifecycle on by `dt` seconds of simulation
+	pub fn grow_older(&mut self, dt: f32) {
+		self.age += dt;
+		self.lifecycle.tick(dt);
+	}
+
+	/// Seconds of simulation the agent lasts for from its birth, if it fades out by itself
+	pub fn ttl(&self) -> Option<f32> {
+		self.ttl
+	}
+
+	/// Has the agent last `seconds` of simulation from its birth
+	pub fn set_ttl(&mut self, seconds: f32) {
+		self.ttl = Some(seconds.max(0.));
+	}
+
+	/// Share of its time to live the agent has left, what it fades with; always 1 without one
+	pub fn ttl_ratio(&self) -> f32 {
+		match self.ttl {
+			Some(ttl) if ttl > 0. => ((ttl - self.age) / ttl).max(0.).min(1.),
+			Some(_) => 0.,
+			None => 1.,
+		}
+	}
+
+	/// True once the agent has lived out its time to live
+	pub fn has_outlived(&self) -> bool {
+		self.ttl.map_or(false, |ttl| self.age >= ttl)
+	}
+
+	/// How far the agent looks for targets given the reach of its sensor, bored agents look further
+	pub fn search_range(&self, sensor_range: f32) -> f32 {
+		sensor_range * (1. + self.boredom)
 	}
 
 	pub fn is_fertilised(&self) -> bool {
@@ -352,6 +745,20 @@ impl State {
 	}
 }
 
+pub struct SegmentsWith<'a> {
+	segments: slice::Iter<'a, Segment>,
+	flags: segment::Flags,
+}
+
+impl<'a> Iterator for SegmentsWith<'a> {
+	type Item = &'a Segment;
+
+	fn next(&mut self) -> Option<&'a Segment> {
+		let flags = self.flags;
+		self.segments.find(|segment| segment.flags.contains(flags))
+	}
+}
+
 pub struct Agent {
 	id: Id,
 	brain: Brain,
@@ -413,11 +820,20 @@ impl Agent {
 		&self.brain
 	}
 
+	pub fn brain_mut(&mut self) -> &mut Brain {
+		&mut self.brain
+	}
+
 	pub fn first_segment(&self, flags: segment::Flags) -> Option<Segment> {
-		self.segments
-			.iter()
-			.find(|segment| segment.flags.contains(flags))
-			.map(|sensor| sensor.clone())
+		self.segments_with(flags).next().cloned()
+	}
+
+	/// The segments carrying all of `flags`
+	pub fn segments_with(&self, flags: segment::Flags) -> SegmentsWith {
+		SegmentsWith {
+			segments: self.segments.iter(),
+			flags: flags,
+		}
 	}
 
 	pub fn new(id: Id, gender: u8, brain: &Brain, dna: &Dna, segments: Box<[Segment]>) -> Self {
@@ -437,6 +853,9 @@ impl Agent {
 				limits: Limits { max_energy: max_energy },
 				foreign_dna: None,
 				trajectory: util::History::new(600),
+				boredom: 0.,
+				age: 0.,
+				ttl: None,
 			},
 			brain: brain.clone(),
 			gender: gender,
@@ -446,4 +865,232 @@ impl Agent {
 	}
 }
 
-pub type AgentMap = HashMap<Id, Agent>;
+/// Ordered by id, so that walking the agents goes the same way on every run of a seed
+pub type AgentMap = BTreeMap<Id, Agent>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand;
+	use rand::Rng;
+	use rand::SeedableRng;
+
+	#[test]
+	fn segments_with_matches_minion_body_plan() {
+		use backend::world::gen;
+		use backend::world::phen;
+		use backend::world::phen::Phenotype;
+		let mut genome = gen::GenePool::for_tests().next();
+		let minion = phen::Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., usize::max_value());
+		let count = |flags| minion.segments_with(flags).count();
+		assert_eq!(minion.segments().len(), count(segment::Flags::empty()));
+		assert_eq!(1, count(segment::MOUTH | segment::SENSOR));
+		assert_eq!(3, count(segment::HEAD));
+		assert_eq!(2, count(segment::HEAD | segment::RUDDER));
+		assert_eq!(2, count(segment::LEG | segment::THRUSTER));
+		assert_eq!(1, count(segment::LEG | segment::LEFT));
+		assert_eq!(1, count(segment::TAIL | segment::BRAKE));
+		assert_eq!(1, count(segment::CORE));
+		assert!(count(segment::ACTUATOR) >= 7);
+		assert!(minion.segments_with(segment::LEG).all(|s| s.flags.contains(segment::ACTUATOR)));
+	}
+
+	#[test]
+	fn debug_colors_are_distinct() {
+		for (i, a) in AgentType::all().iter().enumerate() {
+			for b in &AgentType::all()[i + 1..] {
+				assert!(a.debug_color() != b.debug_color(), "{} and {} share a color", a, b);
+			}
+		}
+	}
+
+	#[test]
+	fn unfed_search_range_grows() {
+		let mut state = State {
+			flags: ACTIVE,
+			lifecycle: Hourglass::new(5.),
+			energy: 50.,
+			target: None,
+			target_position: Position::new(0., 0.),
+			limits: Limits { max_energy: 100. },
+			foreign_dna: None,
+			trajectory: util::History::new(1),
+			boredom: 0.,
+			age: 0.,
+			ttl: None,
+		};
+		let mut range = state.search_range(10.);
+		assert_eq!(10., range);
+		for _ in 0..10 {
+			state.get_bored(0.1, 4.);
+			assert!(state.search_range(10.) > range);
+			range = state.search_range(10.);
+		}
+		state.absorb(1.);
+		assert_eq!(10., state.search_range(10.));
+	}
+
+	fn middle(range: (f32, f32)) -> f32 {
+		(range.0 + range.1) * 0.5
+	}
+
+	fn average_brain() -> Brain {
+		Brain {
+			hunger: middle(HUNGER_RANGE),
+			haste: middle(HASTE_RANGE),
+			prudence: middle(PRUDENCE_RANGE),
+			fear: middle(FEAR_RANGE),
+			rest: middle(REST_RANGE),
+			thrust: middle(THRUST_RANGE),
+			..Brain::default()
+		}
+	}
+
+	#[test]
+	fn mutation_spreads_traits_within_their_range() {
+		let mut rng = rand::XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+		let brain = average_brain();
+		let n = 2000;
+		let mut sum = 0.;
+		let mut sum2 = 0.;
+		for _ in 0..n {
+			let delta = brain.mutate(&mut rng, 0.05).fear - brain.fear;
+			sum += delta;
+			sum2 += delta * delta;
+		}
+		let mean = sum / n as f32;
+		let deviation = (sum2 / n as f32 - mean * mean).sqrt();
+		let expected = 0.05 * (FEAR_RANGE.1 - FEAR_RANGE.0);
+		assert!(mean.abs() < expected * 0.1, "mean {}", mean);
+		assert!((deviation - expected).abs() < expected * 0.1, "deviation {} for {}", deviation, expected);
+
+		for _ in 0..n {
+			let m = brain.mutate(&mut rng, 10.);
+			for &(value, range) in &[(m.hunger, HUNGER_RANGE),
+			                         (m.haste, HASTE_RANGE),
+			                         (m.prudence, PRUDENCE_RANGE),
+			                         (m.fear, FEAR_RANGE),
+			                         (m.rest, REST_RANGE),
+			                         (m.thrust, THRUST_RANGE)] {
+				assert!(value >= range.0 && value <= range.1);
+			}
+		}
+	}
+
+	#[test]
+	fn crossover_of_identical_parents_changes_nothing() {
+		let mut rng = rand::XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+		let mut parent = average_brain().mutate(&mut rng, 0.2);
+		let mut weights = parent.weights()[1].clone();
+		weights[1][2] = rng.gen_range(-4., 4.);
+		assert!(parent.set_weights(1, weights));
+		let child = Brain::crossover(&parent, &parent);
+		assert_eq!(format!("{:?}", parent), format!("{:?}", child));
+
+		let other = average_brain();
+		let child = Brain::crossover(&parent, &other);
+		assert_eq!((parent.fear + other.fear) * 0.5, child.fear);
+	}
+
+	fn random_input<R: Rng>(rng: &mut R, range: f32) -> InputVector<f32> {
+		let mut input = [0.; N_INPUTS];
+		for x in input.iter_mut() {
+			*x = rng.gen_range(-range, range);
+		}
+		input
+	}
+
+	#[test]
+	fn sigmoid_response_is_bounded() {
+		let mut rng = rand::XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+		let config = BrainConfig { output_activation: Activation::Sigmoid };
+		for _ in 0..1000 {
+			let mut brain = Brain::default();
+			brain.randomize_network(&BrainSpec::default(), &mut rng);
+			let input = random_input(&mut rng, 1000.);
+			for r in brain.response(&config, &input).iter() {
+				assert!(*r > 0. && *r < 1., "{} is out of (0, 1)", r);
+			}
+		}
+	}
+
+	#[test]
+	fn saved_brains_respond_like_the_original() {
+		use serialize::json::{Json, ToJson};
+		let mut rng = rand::XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+		let mut brain = Brain::default().mutate(&mut rng, 0.5);
+		brain.randomize_network(&BrainSpec {
+			                        inputs: N_INPUTS,
+			                        hidden: vec![6, 3, 5],
+			                        outputs: N_WEIGHTS,
+		                        },
+		                        &mut rng);
+		let mut weights = brain.weights()[1].clone();
+		weights[2][5] = 1.5;
+		assert!(brain.set_weights(1, weights.clone()));
+		assert_eq!(weights, brain.weights()[1]);
+		// a layer keeps its shape
+		assert!(!brain.set_weights(1, vec![vec![0.; N_WEIGHTS]; N_WEIGHTS]));
+		assert!(!brain.set_weights(4, weights));
+
+		let text = brain.to_json().to_string();
+		let loaded = Brain::from_json(&Json::from_str(&text).unwrap()).unwrap();
+		assert_eq!(brain.fear, loaded.fear);
+		for &activation in &[Activation::SoftSign, Activation::Tanh, Activation::Sigmoid] {
+			let config = BrainConfig { output_activation: activation };
+			for _ in 0..100 {
+				let input = random_input(&mut rng, 10.);
+				assert_eq!(brain.response(&config, &input), loaded.response(&config, &input));
+			}
+		}
+		assert!(Brain::from_json(&Json::from_str("{}").unwrap()).is_err());
+	}
+
+	#[test]
+	fn a_hidden_layer_tells_apart_what_one_layer_cannot() {
+		// the output goes over the threshold for exactly one of the two inputs on, the third input is held at 1
+		// to serve as a bias, no single layer can draw that line
+		fn is_xor(brain: &Brain) -> bool {
+			let config = BrainConfig::default();
+			[(0., 0., false), (1., 0., true), (0., 1., true), (1., 1., false)].iter().all(|&(x, y, on)| {
+				let mut input = [0.; N_INPUTS];
+				input[0] = x;
+				input[1] = y;
+				input[2] = 1.;
+				(brain.response(&config, &input)[0] > 0.5) == on
+			})
+		}
+		let single = BrainSpec {
+			inputs: N_WEIGHTS,
+			hidden: Vec::new(),
+			outputs: N_WEIGHTS,
+		};
+		assert_eq!(vec![(N_WEIGHTS, N_WEIGHTS)], single.layers());
+		assert_eq!(BrainSpec::default(), Brain::default().spec());
+
+		let mut rng = rand::XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+		let mut brain = Brain::default();
+		for _ in 0..10000 {
+			brain.randomize_network(&single, &mut rng);
+			assert_eq!(single, brain.spec());
+			assert!(!is_xor(&brain));
+		}
+
+		// one neuron lights up for either input, the other only for both
+		let hidden = vec![vec![10., 10., -5., 0.], vec![10., 10., -15., 0.]];
+		let mut output = vec![vec![0.; 2]; N_WEIGHTS];
+		output[0] = vec![2., -2.];
+		assert!(brain.set_layers(vec![hidden, output]));
+		assert_eq!(vec![2], brain.spec().hidden);
+		assert!(is_xor(&brain));
+		// layers that don't fit together are refused
+		assert!(!brain.set_layers(vec![vec![vec![1.; 3]; 2], vec![vec![1.; 3]; 4]]));
+		assert!(!brain.set_layers(Vec::new()));
+		assert!(is_xor(&brain));
+
+		// padding the inputs changes nothing for the ones it already read
+		brain.pad_inputs(N_INPUTS);
+		assert_eq!(N_INPUTS, brain.spec().inputs);
+		assert!(is_xor(&brain));
+	}
+}
@@ -0,0 +1,89 @@
+//! What comes of two agents touching, looked up by their types so that the rules live in one table
+//! rather than in type matches spread across the systems.
+use std::collections::HashMap;
+use backend::world::agent::AgentType;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+	/// the toucher feeds on what it touched
+	Eat,
+	/// the toucher loses energy in proportion to the impact, and flees
+	Damage,
+	/// the toucher flees
+	Bounce,
+	/// the two pass through each other
+	Ignore,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContactPolicy {
+	effects: HashMap<(AgentType, AgentType), Effect>,
+	fallback: Effect,
+}
+
+impl Default for ContactPolicy {
+	fn default() -> Self {
+		let mut policy = ContactPolicy::new(Effect::Bounce);
+		policy.set(AgentType::Minion, AgentType::Resource, Effect::Eat);
+		policy.set(AgentType::Minion, AgentType::Enemy, Effect::Damage);
+		policy.set(AgentType::Minion, AgentType::EnemyBullet, Effect::Damage);
+		policy.set(AgentType::Enemy, AgentType::FriendlyBullet, Effect::Damage);
+		// nobody is hit by their own side's fire
+		policy.set(AgentType::Enemy, AgentType::EnemyBullet, Effect::Ignore);
+		policy.set(AgentType::Minion, AgentType::FriendlyBullet, Effect::Ignore);
+		policy
+	}
+}
+
+impl ContactPolicy {
+	/// A policy where every pair of types has the `fallback` effect
+	pub fn new(fallback: Effect) -> Self {
+		ContactPolicy {
+			effects: HashMap::new(),
+			fallback: fallback,
+		}
+	}
+
+	/// Sets what happens to `toucher` when it touches `touched`, the other way round is left as it is
+	pub fn set(&mut self, toucher: AgentType, touched: AgentType, effect: Effect) -> &mut Self {
+		self.effects.insert((toucher, touched), effect);
+		self
+	}
+
+	pub fn effect(&self, toucher: AgentType, touched: AgentType) -> Effect {
+		self.effects.get(&(toucher, touched)).cloned().unwrap_or(self.fallback)
+	}
+
+	/// Whether the bodies of the two types collide at all, which they do unless either side ignores the other
+	pub fn collides(&self, a: AgentType, b: AgentType) -> bool {
+		self.effect(a, b) != Effect::Ignore && self.effect(b, a) != Effect::Ignore
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::world::agent::AgentType;
+
+	#[test]
+	fn default_policy_eats_resources_and_bounces_off_props() {
+		let policy = ContactPolicy::default();
+		assert_eq!(Effect::Eat, policy.effect(AgentType::Minion, AgentType::Resource));
+		assert_eq!(Effect::Bounce, policy.effect(AgentType::Minion, AgentType::Prop));
+		assert_eq!(Effect::Bounce, policy.effect(AgentType::Resource, AgentType::Minion));
+		assert_eq!(Effect::Damage, policy.effect(AgentType::Minion, AgentType::Enemy));
+		assert_eq!(Effect::Damage, policy.effect(AgentType::Minion, AgentType::EnemyBullet));
+		assert_eq!(Effect::Damage, policy.effect(AgentType::Enemy, AgentType::FriendlyBullet));
+		assert!(!policy.collides(AgentType::EnemyBullet, AgentType::Enemy));
+		assert!(policy.collides(AgentType::Minion, AgentType::Resource));
+	}
+
+	#[test]
+	fn ignoring_either_way_stops_collisions() {
+		let mut policy = ContactPolicy::default();
+		policy.set(AgentType::Spore, AgentType::Prop, Effect::Ignore);
+		assert!(!policy.collides(AgentType::Spore, AgentType::Prop));
+		assert!(!policy.collides(AgentType::Prop, AgentType::Spore));
+		assert!(policy.collides(AgentType::Spore, AgentType::Minion));
+	}
+}
@@ -0,0 +1,226 @@
+//! Structural comparison of two agents, to see what a mutation or a crossover actually changed.
+//! Segments are paired by where they hang off the body rather than by index, so that a limb gained
+//! or lost early in the body plan doesn't make every later segment look different.
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use core::util;
+use backend::obj::*;
+use backend::world::agent::Agent;
+use backend::world::agent::Brain;
+use backend::world::segment;
+use backend::world::segment::Segment;
+
+/// Attachment points from the core down to a segment, each with its rank among the siblings sharing the point
+pub type SegmentPath = Vec<(AttachmentIndex, usize)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SegmentChange {
+	/// only the first agent has a segment here
+	Missing,
+	/// only the second agent has a segment here
+	Extra,
+	Shape(Shape, Shape),
+	Flags(segment::Flags, segment::Flags),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentDiff {
+	pub path: SegmentPath,
+	pub change: SegmentChange,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentDiff {
+	pub same_dna: bool,
+	pub segments: Vec<SegmentDiff>,
+	/// brain parameters that differ, with the first and the second agent's value
+	pub brain: Vec<(String, f32, f32)>,
+}
+
+impl AgentDiff {
+	pub fn is_empty(&self) -> bool {
+		self.same_dna && self.segments.is_empty() && self.brain.is_empty()
+	}
+}
+
+impl fmt::Display for AgentDiff {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.is_empty() {
+			return write!(f, "no differences");
+		}
+		if !self.same_dna {
+			try!(writeln!(f, "dna differs"));
+		}
+		for d in &self.segments {
+			let path = d.path.iter().map(|&(point, rank)| format!("{}.{}", point, rank)).collect::<Vec<_>>().join("/");
+			try!(match d.change {
+				SegmentChange::Missing => writeln!(f, "segment /{}: missing", path),
+				SegmentChange::Extra => writeln!(f, "segment /{}: extra", path),
+				SegmentChange::Shape(ref a, ref b) => writeln!(f, "segment /{}: shape {:?} -> {:?}", path, a, b),
+				SegmentChange::Flags(a, b) => writeln!(f, "segment /{}: flags {:?} -> {:?}", path, a, b),
+			});
+		}
+		for &(ref name, a, b) in &self.brain {
+			try!(writeln!(f,
+			              "brain {}: {} -> {} ({})",
+			              name,
+			              util::format_significant(a, 4),
+			              util::format_significant(b, 4),
+			              util::format_significant(b - a, 4)));
+		}
+		Ok(())
+	}
+}
+
+fn dna_hash(agent: &Agent) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	agent.dna().hash(&mut hasher);
+	hasher.finish()
+}
+
+fn segment_paths(segments: &[Segment]) -> BTreeMap<SegmentPath, &Segment> {
+	let mut paths: Vec<SegmentPath> = Vec::with_capacity(segments.len());
+	let mut siblings: BTreeMap<(SegmentIndex, AttachmentIndex), usize> = BTreeMap::new();
+	for segment in segments {
+		// parents always come before their children
+		let path = match segment.attached_to {
+			Some(segment::Attachment { index, attachment_point }) => {
+				let rank = siblings.entry((index, attachment_point)).or_insert(0);
+				let mut path = paths.get(index as usize).cloned().unwrap_or_else(Vec::new);
+				path.push((attachment_point, *rank));
+				*rank += 1;
+				path
+			}
+			None => Vec::new(),
+		};
+		paths.push(path);
+	}
+	paths.into_iter().zip(segments.iter()).collect()
+}
+
+impl Agent {
+	/// What it takes to turn this agent into `other`
+	pub fn diff(&self, other: &Agent) -> AgentDiff {
+		let mine = segment_paths(self.segments());
+		let theirs = segment_paths(other.segments());
+		let mut segments = Vec::new();
+		for (path, a) in &mine {
+			match theirs.get(path) {
+				None => {
+					segments.push(SegmentDiff {
+						path: path.clone(),
+						change: SegmentChange::Missing,
+					})
+				}
+				Some(b) => {
+					if a.mesh.shape != b.mesh.shape {
+						segments.push(SegmentDiff {
+							path: path.clone(),
+							change: SegmentChange::Shape(a.mesh.shape.clone(), b.mesh.shape.clone()),
+						});
+					}
+					if a.flags != b.flags {
+						segments.push(SegmentDiff {
+							path: path.clone(),
+							change: SegmentChange::Flags(a.flags, b.flags),
+						});
+					}
+				}
+			}
+		}
+		for path in theirs.keys().filter(|path| !mine.contains_key(*path)) {
+			segments.push(SegmentDiff {
+				path: path.clone(),
+				change: SegmentChange::Extra,
+			});
+		}
+
+		let (a, b) = (self.brain(), other.brain());
+		let mut brain = Vec::new();
+		for &(name, x, y) in &[("hunger", a.hunger, b.hunger),
+		                       ("haste", a.haste, b.haste),
+		                       ("prudence", a.prudence, b.prudence),
+		                       ("fear", a.fear, b.fear),
+		                       ("rest", a.rest, b.rest),
+		                       ("thrust", a.thrust, b.thrust)] {
+			if x != y {
+				brain.push((name.to_string(), x, y));
+			}
+		}
+		// networks of different shapes can't be compared weight by weight, only their sizes are
+		if a.spec() != b.spec() {
+			let count = |brain: &Brain| {
+				brain.weights().iter().flat_map(|layer| layer.iter()).map(|row| row.len()).sum::<usize>()
+			};
+			brain.push(("weights".to_string(), count(a) as f32, count(b) as f32));
+		} else {
+			for (layer, (x, y)) in a.weights().iter().zip(b.weights()).enumerate() {
+				for i in 0..x.len() {
+					for j in 0..x[i].len() {
+						if x[i][j] != y[i][j] {
+							brain.push((format!("layers[{}][{}][{}]", layer, i, j), x[i][j], y[i][j]));
+						}
+					}
+				}
+			}
+		}
+
+		AgentDiff {
+			same_dna: dna_hash(self) == dna_hash(other),
+			segments: segments,
+			brain: brain,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::obj::*;
+	use backend::world::gen;
+	use backend::world::phen;
+	use backend::world::phen::Phenotype;
+	use backend::world::swarm::Swarm;
+	use backend::world::agent::AgentType;
+	use core::geometry::*;
+
+	#[test]
+	fn diff_reports_only_what_changed() {
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let mut genome = gen::GenePool::for_tests().next();
+		let id = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		let clone_id = swarm.clone_agent(id, Position::new(10., 0.)).unwrap();
+		let diff = swarm.get(id).unwrap().diff(swarm.get(clone_id).unwrap());
+		assert!(diff.is_empty(), "{}", diff);
+
+		let last = swarm.get(clone_id).unwrap().segments().len() - 1;
+		let tail_path = segment_paths(swarm.get(clone_id).unwrap().segments())
+			.into_iter()
+			.find(|&(_, s)| s.index as usize == last)
+			.map(|(path, _)| path)
+			.unwrap();
+		swarm.get_mut(clone_id).unwrap().segments_mut()[last].mesh.shape = Shape::new_ball(0.3);
+		let diff = swarm.get(id).unwrap().diff(swarm.get(clone_id).unwrap());
+		assert!(diff.same_dna);
+		assert!(diff.brain.is_empty());
+		assert_eq!(1, diff.segments.len());
+		assert_eq!(tail_path, diff.segments[0].path);
+		match diff.segments[0].change {
+			SegmentChange::Shape(_, Shape::Ball { radius }) => assert_eq!(0.3, radius),
+			ref c => panic!("unexpected change {:?}", c),
+		}
+	}
+
+	#[test]
+	fn a_missing_limb_leaves_later_segments_aligned() {
+		let mut genome = gen::GenePool::for_tests().next();
+		let whole = phen::Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., usize::max_value());
+		let mut genome = gen::GenePool::for_tests().next();
+		let stunted = phen::Minion::develop(&mut genome, 2 << 8, &Transform::default(), None, 1., 4);
+		let diff = whole.diff(&stunted);
+		assert!(diff.segments.iter().all(|d| d.change == SegmentChange::Missing));
+		assert_eq!(whole.segments().len() - 4, diff.segments.len());
+	}
+}
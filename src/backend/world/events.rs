@@ -0,0 +1,116 @@
+//! What happened in the world over a tick, for whatever needs to react to it without being a system.
+//! The world pushes to its `EventBus` as agents come and go, systems push from `to_world`,
+//! and once per tick the bus is drained into the subscribers.
+use core::geometry::Position;
+use backend::obj::Id;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorldEvent {
+	/// an agent was added to the world, by spawning, budding or breaking off another one
+	Born(Id),
+	/// a minion got `energy` out of a resource at `position`, one event per minion sharing it
+	Eaten {
+		resource: Id,
+		eater: Id,
+		energy: f32,
+		position: Position,
+	},
+	/// a dead agent was taken out of the world
+	Died(Id),
+}
+
+pub trait Subscriber {
+	fn on_event(&mut self, event: &WorldEvent);
+}
+
+/// The events of the current tick, kept in a buffer reused from one tick to the next
+pub struct EventBus {
+	events: Vec<WorldEvent>,
+}
+
+impl EventBus {
+	pub fn new() -> Self {
+		EventBus { events: Vec::new() }
+	}
+
+	pub fn push(&mut self, event: WorldEvent) {
+		self.events.push(event);
+	}
+
+	/// What was pushed since the last drain, in order
+	#[cfg(test)]
+	pub fn pending(&self) -> &[WorldEvent] {
+		&self.events
+	}
+
+	/// Drops what was pushed without telling anyone
+	pub fn clear(&mut self) {
+		self.events.clear();
+	}
+
+	/// Hands every pending event to each subscriber in turn, then empties the bus
+	pub fn drain(&mut self, subscribers: &mut [&mut Subscriber]) {
+		for event in &self.events {
+			for subscriber in subscribers.iter_mut() {
+				subscriber.on_event(event);
+			}
+		}
+		self.events.clear();
+	}
+}
+
+/// Logs every event, for following a run from the console
+pub struct EventLog;
+
+impl Subscriber for EventLog {
+	fn on_event(&mut self, event: &WorldEvent) {
+		debug!("{:?}", event);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::Position;
+	use backend::world::World;
+	use backend::world::agent::AgentType;
+	use backend::world::gen;
+
+	#[derive(Default)]
+	struct Census {
+		born: usize,
+		died: usize,
+	}
+
+	impl Subscriber for Census {
+		fn on_event(&mut self, event: &WorldEvent) {
+			match *event {
+				WorldEvent::Born(_) => self.born += 1,
+				WorldEvent::Died(_) => self.died += 1,
+				WorldEvent::Eaten { .. } => {}
+			}
+		}
+	}
+
+	#[test]
+	fn subscribers_hear_of_births_and_deaths() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		let mut census = Census::default();
+		let mut ids = Vec::new();
+		for tick in 0..5 {
+			world.sweep();
+			ids.push(world.new_minion(Position::new(tick as f32 * 20., 0.), None));
+			ids.push(world.new_minion(Position::new(tick as f32 * 20., 20.), None));
+			// one of those born last tick dies every tick
+			if tick > 0 {
+				world.kill(AgentType::Minion, ids[2 * tick - 1]);
+			}
+			world.events_mut().drain(&mut [&mut census as &mut Subscriber]);
+			assert_eq!(2 * (tick + 1), census.born);
+			// and is only taken out on the sweep that starts the next one
+			assert_eq!(::std::cmp::max(tick, 1) - 1, census.died);
+			assert!(world.events_mut().pending().is_empty());
+		}
+		assert_eq!(10 - 3, world.agents(AgentType::Minion).len());
+	}
+}
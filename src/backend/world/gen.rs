@@ -6,11 +6,16 @@ use std::cmp;
 use rand;
 use rand::Rng;
 use backend::obj::*;
+use backend::world::persist;
 use serialize::base64::{self, ToBase64, FromBase64};
+use serialize::json::{Json, ToJson};
+use std::collections::BTreeMap;
 
 pub type Dna = Box<[u8]>;
 
 const MAX_POLY_SIDES: u8 = 8; // in conformity with box2d?
+/// most a single bit of dna turns the hue of its lineage by, as a share of the colour wheel
+const HUE_STEP: f32 = 1. / 16.;
 
 fn bit_count(p: usize) -> usize {
 	p << 3
@@ -20,12 +25,22 @@ fn split_bit(p: usize) -> (usize, u8) {
 	(p >> 3, (p & 0x7) as u8)
 }
 
+#[cfg(test)]
+const TEST_DNA: &'static str = "GzB2lQVwM00tTAm5gwajjf4wc0a5";
+
+#[derive(Clone, Hash)]
 pub struct GenePool {
 	gene_pool: Box<[Dna]>,
 	round_robin: usize,
 }
 
 impl GenePool {
+	/// The minion every test grows from, so that they all start from the same genes
+	#[cfg(test)]
+	pub fn for_tests() -> Self {
+		Self::parse_from_base64(&[TEST_DNA])
+	}
+
 	pub fn parse_from_base64(base64: &[&str]) -> Self {
 		GenePool {
 			gene_pool: base64.iter()
@@ -40,16 +55,21 @@ impl GenePool {
 		}
 	}
 
-	pub fn parse_from_resource(data: &[u8]) -> Self {
+	fn parse_rows(data: &[u8]) -> persist::Result<Self> {
 		let mut gene_pool = Vec::new();
 		let mut csv = csv::Reader::from_bytes(data).has_headers(false);
 		for row in csv.records() {
-			let fields = row.unwrap();
-			gene_pool.push(fields[0].from_base64().unwrap().into_boxed_slice());
+			let fields = try!(row.map_err(|e| persist::Error::Format(e.to_string())));
+			let dna = try!(fields[0].from_base64().map_err(|e| persist::Error::Format(e.to_string())));
+			gene_pool.push(dna.into_boxed_slice());
 		}
-		GenePool {
-			gene_pool: gene_pool.to_vec().into_boxed_slice(),
-			round_robin: 0,
+		if gene_pool.is_empty() {
+			Err(persist::Error::Format("empty gene pool".to_string()))
+		} else {
+			Ok(GenePool {
+				gene_pool: gene_pool.into_boxed_slice(),
+				round_robin: 0,
+			})
 		}
 	}
 
@@ -65,20 +85,74 @@ impl GenePool {
 		}
 	}
 
-	pub fn randomize(&mut self) {
-		let mut rnd = Randomizer::new();
+	/// Replaces the next genome with random dna drawn from `rng`
+	pub fn randomize<R: rand::Rng>(&mut self, rng: &mut R) {
+		let mut rnd = Randomizer::with_rng(rng);
 		self.gene_pool[self.round_robin] = rnd.seed().dna().clone();
 	}
 
+	/// The next genome in turn, leaving a mutation of it in its place, always mutated the same way
+	#[cfg(test)]
 	pub fn next(&mut self) -> Genome {
+		self.next_with(&mut rand::XorShiftRng::new_unseeded())
+	}
+
+	/// Like `next`, mutating with `rng` so that a seeded rng always gives the same genomes
+	pub fn next_with<R: rand::Rng>(&mut self, rng: &mut R) -> Genome {
 		let gen = Genome::new(&self.gene_pool[self.round_robin].clone());
-		let mutated = gen.mutate(&mut rand::thread_rng());
+		let mutated = gen.mutate(rng);
 		self.gene_pool[self.round_robin] = mutated.dna().clone();
 		self.round_robin = (self.round_robin + 1) % self.gene_pool.len();
 		gen
 	}
 }
 
+impl ToJson for GenePool {
+	fn to_json(&self) -> Json {
+		let mut obj = BTreeMap::new();
+		let genes = self.gene_pool
+			.iter()
+			.map(|dna| Json::String(dna.to_base64(base64::STANDARD)))
+			.collect();
+		obj.insert("genes".to_string(), Json::Array(genes));
+		obj.insert("round_robin".to_string(), Json::U64(self.round_robin as u64));
+		Json::Object(obj)
+	}
+}
+
+impl GenePool {
+	pub fn from_json(json: &Json) -> persist::Result<Self> {
+		let genes = try!(json.find("genes").and_then(|g| g.as_array()).ok_or(persist::missing("genes")));
+		let mut gene_pool = Vec::new();
+		for gene in genes {
+			let encoded = try!(gene.as_string().ok_or(persist::missing("gene")));
+			let dna = try!(encoded.from_base64().map_err(|e| persist::Error::Format(e.to_string())));
+			gene_pool.push(dna.into_boxed_slice());
+		}
+		let round_robin = try!(json.find("round_robin")
+			.and_then(|r| r.as_u64())
+			.ok_or(persist::missing("round_robin")));
+		if gene_pool.is_empty() || round_robin as usize >= gene_pool.len() {
+			return Err(persist::Error::Format("inconsistent gene pool".to_string()));
+		}
+		Ok(GenePool {
+			gene_pool: gene_pool.into_boxed_slice(),
+			round_robin: round_robin as usize,
+		})
+	}
+}
+
+impl persist::Versioned for GenePool {
+	fn migrate(version: persist::SaveVersion, data: &[u8]) -> persist::Result<Self> {
+		match version {
+			// v1 is a bare list of base64 genomes, v2 only prepends the version header,
+			// v3 to v6 changed world saves only
+			1 | 2 | 3 | 4 | 5 | 6 => Self::parse_rows(data),
+			_ => Err(persist::Error::Unsupported(version)),
+		}
+	}
+}
+
 #[allow(dead_code)]
 pub trait Generator {
 	fn next_float<T>(&mut self, min: T, max: T) -> T where T: rand::Rand + num::Float;
@@ -174,14 +248,17 @@ pub struct Randomizer<R>
 	rng: R,
 }
 
-#[allow(dead_code)]
-impl Randomizer<rand::ThreadRng> {
-	pub fn new() -> Randomizer<rand::ThreadRng> {
-		Randomizer { rng: rand::thread_rng() }
+impl<R> Randomizer<R>
+    where R: rand::Rng
+{
+	pub fn with_rng(rng: R) -> Randomizer<R> {
+		Randomizer { rng: rng }
 	}
 }
 
-impl Generator for Randomizer<rand::ThreadRng> {
+impl<R> Generator for Randomizer<R>
+    where R: rand::Rng
+{
 	fn next_float<T>(&mut self, min: T, max: T) -> T
 		where T: rand::Rand + num::Float {
 		self.rng.gen::<T>() * (max - min) + min
@@ -293,6 +370,23 @@ impl Genome {
 	pub fn dna(&self) -> &Box<[u8]> {
 		&self.dna
 	}
+
+	/// A hue in [0, 1) read off the whole dna rather than off any one gene: each set bit turns it by an amount of
+	/// its own, less than `HUE_STEP`. The same dna always has the same hue and a mutation only nudges it,
+	/// so that a family shows as shades of one colour.
+	pub fn hue(&self) -> f32 {
+		let mut hue = 0.;
+		for (i, byte) in self.dna.iter().enumerate() {
+			for bit in 0..8 {
+				if byte & (1 << bit) != 0 {
+					// a multiplicative hash spreads the amounts over the bits
+					let spread = ((i * 8 + bit) as u32).wrapping_mul(2654435761) >> 22;
+					hue += spread as f32 / 1024. * HUE_STEP;
+				}
+			}
+		}
+		hue.fract()
+	}
 }
 
 impl fmt::Display for Genome {
@@ -321,4 +415,55 @@ impl Generator for Genome {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+	use super::*;
+	use rand;
+	use rand::Rng;
+	use backend::world::persist;
+	use backend::world::persist::Versioned;
+
+	#[test]
+	fn hue_stays_with_the_dna_and_mutations_only_nudge_it() {
+		let genome = GenePool::for_tests().next();
+		let hue = genome.hue();
+		assert!(hue >= 0. && hue < 1.);
+		assert_eq!(hue, Genome::new(genome.dna()).hue());
+		let distance = |a: f32, b: f32| {
+			let d = (a - b).abs();
+			d.min(1. - d)
+		};
+		let mut rng = rand::XorShiftRng::new_unseeded();
+		for _ in 0..100 {
+			let mutant = genome.mutate(&mut rng);
+			let flips = genome.dna().iter().zip(mutant.dna().iter()).map(|(a, b)| (a ^ b).count_ones()).sum::<u32>();
+			assert!(distance(hue, mutant.hue()) <= flips as f32 * HUE_STEP + 1e-5);
+		}
+		// while strangers are all over the wheel
+		let strangers = (0..20)
+			.map(|_| Genome::new(&(0..21).map(|_| rng.gen::<u8>()).collect::<Vec<_>>()).hue())
+			.collect::<Vec<_>>();
+		assert!(strangers.iter().any(|&h| distance(h, strangers[0]) > 0.25));
+	}
+
+	#[test]
+	fn load_unversioned_gene_pool() {
+		let pool = GenePool::load(include_bytes!("../../../resources/minion_gene_pool.csv")).unwrap();
+		assert!(pool.len() > 0);
+	}
+
+	#[test]
+	fn load_versioned_gene_pool() {
+		let data = format!("{}{}\n", persist::header(persist::CURRENT_VERSION), TEST_DNA);
+		let pool = GenePool::load(data.as_bytes()).unwrap();
+		assert_eq!(1, pool.len());
+	}
+
+	#[test]
+	fn reject_newer_gene_pool() {
+		let data = format!("{}{}\n", persist::header(persist::CURRENT_VERSION + 1), TEST_DNA);
+		match GenePool::load(data.as_bytes()) {
+			Err(persist::Error::Unsupported(v)) => assert_eq!(persist::CURRENT_VERSION + 1, v),
+			_ => panic!("a newer save must be rejected"),
+		}
+	}
+}
@@ -0,0 +1,213 @@
+//! Appends the personality of every living minion to a CSV file at regular intervals, to follow how it
+//! evolves over a run. Rows are written on a writer thread and never wait for the disk, however slow.
+use std::io;
+use std::fs;
+use std::path;
+use std::thread;
+use std::sync::mpsc;
+use csv;
+use core::util;
+use backend::obj::Id;
+use backend::world::World;
+use backend::world::agent::AgentType;
+
+/// The first row of every log, in the order the fields are written
+pub const HEADERS: &'static [&'static str] = &["id", "age", "energy", "hunger", "haste", "prudence", "fear",
+                                               "rest", "thrust"];
+/// Significant digits written for every value, enough to follow a trait drifting
+pub const LOG_DIGITS: u32 = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenomeRow {
+	pub id: Id,
+	pub age: f32,
+	pub energy: f32,
+	pub hunger: f32,
+	pub haste: f32,
+	pub prudence: f32,
+	pub fear: f32,
+	pub rest: f32,
+	pub thrust: f32,
+}
+
+impl GenomeRow {
+	fn record(&self) -> Vec<String> {
+		let value = |v: f32| util::format_significant(v, LOG_DIGITS);
+		vec![self.id.to_string(),
+		     value(self.age),
+		     value(self.energy),
+		     value(self.hunger),
+		     value(self.haste),
+		     value(self.prudence),
+		     value(self.fear),
+		     value(self.rest),
+		     value(self.thrust)]
+	}
+
+	/// Reads back a row as written, `None` if it has the wrong fields
+	#[allow(dead_code)]
+	pub fn parse(record: &[String]) -> Option<GenomeRow> {
+		if record.len() != HEADERS.len() {
+			return None;
+		}
+		let mut values = [0f32; 8];
+		for (value, field) in values.iter_mut().zip(record[1..].iter()) {
+			match field.parse() {
+				Ok(v) => *value = v,
+				Err(_) => return None,
+			}
+		}
+		record[0].parse().ok().map(|id| {
+			GenomeRow {
+				id: id,
+				age: values[0],
+				energy: values[1],
+				hunger: values[2],
+				haste: values[3],
+				prudence: values[4],
+				fear: values[5],
+				rest: values[6],
+				thrust: values[7],
+			}
+		})
+	}
+}
+
+pub struct GenomeLogger {
+	every_n: u32,
+	ticks: u32,
+	sender: Option<mpsc::Sender<Vec<GenomeRow>>>,
+	writer: Option<thread::JoinHandle<io::Result<usize>>>,
+}
+
+impl GenomeLogger {
+	/// Creates or truncates the log at `path` and logs every `every_n` ticks
+	pub fn start(path: &path::Path, every_n: u32) -> io::Result<GenomeLogger> {
+		Self::with_writer(try!(fs::File::create(path)), every_n)
+	}
+
+	fn with_writer<W: io::Write + Send + 'static>(w: W, every_n: u32) -> io::Result<GenomeLogger> {
+		fn to_io(e: csv::Error) -> io::Error {
+			io::Error::new(io::ErrorKind::Other, e.to_string())
+		}
+		let mut csv = csv::Writer::from_writer(w);
+		try!(csv.write(HEADERS.iter()).map_err(to_io));
+		try!(csv.flush().map_err(to_io));
+		// unbounded, so that the simulation never waits on the disk
+		let (sender, receiver) = mpsc::channel::<Vec<GenomeRow>>();
+		let writer = thread::spawn(move || {
+			let mut written = 0;
+			for rows in receiver.iter() {
+				for row in &rows {
+					try!(csv.write(row.record().iter()).map_err(to_io));
+				}
+				try!(csv.flush().map_err(to_io));
+				written += rows.len();
+			}
+			Ok(written)
+		});
+		Ok(GenomeLogger {
+			every_n: ::std::cmp::max(every_n, 1),
+			ticks: 0,
+			sender: Some(sender),
+			writer: Some(writer),
+		})
+	}
+
+	/// Called once per simulation tick, queues a row per living minion every `every_n` ticks
+	pub fn tick(&mut self, world: &World) {
+		let wanted = self.ticks % self.every_n == 0;
+		self.ticks += 1;
+		if wanted {
+			let rows = world.agents(AgentType::Minion)
+				.iter()
+				.filter(|&(_, agent)| agent.state.is_alive())
+				.map(|(&id, agent)| {
+					let brain = agent.brain();
+					GenomeRow {
+						id: id,
+						age: agent.state.age(),
+						energy: agent.state.energy(),
+						hunger: brain.hunger,
+						haste: brain.haste,
+						prudence: brain.prudence,
+						fear: brain.fear,
+						rest: brain.rest,
+						thrust: brain.thrust,
+					}
+				})
+				.collect::<Vec<_>>();
+			if let Some(ref sender) = self.sender {
+				if sender.send(rows).is_err() {
+					error!("Genome log writer has stopped");
+				}
+			}
+		}
+	}
+
+	/// Writes out whatever is queued and returns how many rows made it to the log
+	pub fn stop(&mut self) -> io::Result<usize> {
+		self.sender = None;
+		match self.writer.take() {
+			Some(writer) => {
+				match writer.join() {
+					Ok(result) => result,
+					Err(_) => Err(io::Error::new(io::ErrorKind::Other, "genome log writer panicked")),
+				}
+			}
+			None => Ok(0),
+		}
+	}
+}
+
+impl Drop for GenomeLogger {
+	fn drop(&mut self) {
+		let _ = self.stop();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::fs;
+	use csv;
+	use core::util;
+	use core::geometry::Position;
+	use backend::world::World;
+	use backend::world::agent::AgentType;
+	use backend::world::gen;
+
+	#[test]
+	fn logged_rows_read_back_the_same() {
+		let file = env::temp_dir().join("rust-oids-genome-log-test.csv");
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		for i in 0..3 {
+			world.new_minion(Position::new(i as f32 * 20., 0.), None);
+		}
+		let mut logger = GenomeLogger::start(&file, 2).unwrap();
+		for _ in 0..4 {
+			logger.tick(&world);
+		}
+		assert_eq!(6, logger.stop().unwrap());
+
+		let mut reader = csv::Reader::from_file(&file).unwrap();
+		assert_eq!(HEADERS.iter().map(|h| h.to_string()).collect::<Vec<_>>(), reader.headers().unwrap());
+		let rows = reader.records().map(|r| GenomeRow::parse(&r.unwrap()).unwrap()).collect::<Vec<_>>();
+		assert_eq!(6, rows.len());
+		let logged = |v: f32| util::format_significant(v, LOG_DIGITS).parse::<f32>().unwrap();
+		for row in &rows {
+			let agent = world.agent(row.id).unwrap();
+			assert_eq!(logged(agent.state.energy()), row.energy);
+			assert_eq!(logged(agent.state.age()), row.age);
+			assert_eq!(logged(agent.brain().hunger), row.hunger);
+			assert_eq!(logged(agent.brain().fear), row.fear);
+			assert_eq!(logged(agent.brain().thrust), row.thrust);
+		}
+		// logged at the first and third tick
+		for (&id, _) in world.agents(AgentType::Minion).iter() {
+			assert_eq!(2, rows.iter().filter(|r| r.id == id).count());
+		}
+		fs::remove_file(&file).unwrap();
+	}
+}
@@ -3,34 +3,160 @@ pub mod agent;
 pub mod swarm;
 pub mod gen;
 pub mod phen;
+pub mod persist;
+pub mod contact;
+pub mod diff;
+pub mod snapshot;
+pub mod genome_log;
+pub mod events;
 
 use backend::obj;
 use backend::obj::*;
+use backend::config::SimConfig;
 use rand;
+use rand::Rng;
+use rand::SeedableRng;
 use chrono::*;
 use std::f32::consts;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::io::Read;
 use std::io::Write;
 use std::fs;
+use std::path;
+use std::mem;
+use std::collections::BTreeMap;
 
 use core::geometry::*;
 use core::resource::ResourceLoader;
+use core::util;
 use backend::world::agent::Agent;
 use backend::world::agent::AgentType;
 use backend::world::agent::TypedAgent;
 use backend::world::swarm::*;
-use serialize::base64::{self, ToBase64};
+use backend::world::persist::Versioned;
+use backend::world::events::{EventBus, WorldEvent};
+use serialize::base64::{self, ToBase64, FromBase64};
+use serialize::json::{Json, ToJson};
+use cgmath::EuclideanVector;
+
+/// Significant digits of the coordinates in a save, enough for every f32 to load back as it was
+const SAVE_DIGITS: u32 = 9;
+
+fn save_float(value: f32) -> Json {
+	Json::F64(util::round_significant(value, SAVE_DIGITS))
+}
+
+fn save_segment(segment: &segment::Segment) -> Json {
+	let mut obj = BTreeMap::new();
+	for &(name, value) in &[("x", segment.transform.position.x),
+	                        ("y", segment.transform.position.y),
+	                        ("angle", segment.transform.angle),
+	                        ("charge", segment.state.get_charge()),
+	                        ("target_charge", segment.state.get_target_charge()),
+	                        ("recharge", segment.state.get_recharge()),
+	                        ("age", segment.state.age_seconds()),
+	                        ("density", segment.material.density),
+	                        ("restitution", segment.material.restitution),
+	                        ("friction", segment.material.friction),
+	                        ("frequency", segment.livery.frequency),
+	                        ("phase", segment.livery.phase),
+	                        ("amplitude", segment.livery.amplitude),
+	                        ("livery_seed", segment.livery.seed)] {
+		obj.insert(name.to_string(), save_float(value));
+	}
+	obj.insert("flags".to_string(), Json::U64(segment.flags.bits() as u64));
+	obj.insert("albedo".to_string(),
+	           Json::Array(segment.livery.albedo.iter().map(|&c| save_float(c)).collect()));
+	Json::Object(obj)
+}
+
+/// Puts a segment developed from dna back the way it was saved in a `version` save
+fn load_segment(json: &Json, version: persist::SaveVersion, segment: &mut segment::Segment) -> persist::Result<()> {
+	let float = |name: &str| json.find(name).and_then(|v| v.as_f64()).map(|v| v as f32).ok_or(persist::missing(name));
+	segment.transform = Transform::new(Position::new(try!(float("x")), try!(float("y"))), try!(float("angle")));
+	segment.state.set_charge(try!(float("charge")));
+	segment.state.set_target_charge(try!(float("target_charge")));
+	segment.state.set_recharge(try!(float("recharge")));
+	// saves from before ages were kept hold grown up agents
+	segment.state.set_age_seconds(if version >= 5 { try!(float("age")) } else { ::std::f32::MAX });
+	segment.material.density = try!(float("density"));
+	segment.material.restitution = try!(float("restitution"));
+	segment.material.friction = try!(float("friction"));
+	segment.livery.frequency = try!(float("frequency"));
+	segment.livery.phase = try!(float("phase"));
+	segment.livery.amplitude = try!(float("amplitude"));
+	segment.livery.seed = try!(float("livery_seed"));
+	let bits = try!(json.find("flags").and_then(|v| v.as_u64()).ok_or(persist::missing("flags")));
+	segment.flags = try!(segment::Flags::from_bits(bits as u32).ok_or(persist::missing("flags")));
+	let albedo = try!(json.find("albedo").and_then(|v| v.as_array()).ok_or(persist::missing("albedo")));
+	if albedo.len() != segment.livery.albedo.len() {
+		return Err(persist::missing("albedo"));
+	}
+	for (c, v) in segment.livery.albedo.iter_mut().zip(albedo) {
+		*c = try!(v.as_f64().ok_or(persist::missing("albedo"))) as f32;
+	}
+	Ok(())
+}
+
+fn save_emitter(emitter: &Emitter) -> Json {
+	let (emission, angle) = match emitter.emission {
+		Emission::CW(angle) => ("cw", angle),
+		Emission::CCW(angle) => ("ccw", angle),
+		Emission::Random => ("random", 0.),
+	};
+	let mut obj = BTreeMap::new();
+	obj.insert("id".to_string(), Json::U64(emitter.id as u64));
+	obj.insert("x".to_string(), save_float(emitter.transform.position.x));
+	obj.insert("y".to_string(), save_float(emitter.transform.position.y));
+	obj.insert("rate".to_string(), save_float(emitter.rate));
+	obj.insert("emission".to_string(), Json::String(emission.to_string()));
+	obj.insert("angle".to_string(), save_float(angle));
+	Json::Object(obj)
+}
+
+fn load_emitter(json: &Json) -> persist::Result<Emitter> {
+	let float = |name: &str| json.find(name).and_then(|v| v.as_f64()).map(|v| v as f32).ok_or(persist::missing(name));
+	let id = try!(json.find("id").and_then(|v| v.as_u64()).ok_or(persist::missing("id")));
+	let emission = match json.find("emission").and_then(|v| v.as_string()) {
+		Some("cw") => Emission::CW(try!(float("angle"))),
+		Some("ccw") => Emission::CCW(try!(float("angle"))),
+		Some("random") => Emission::Random,
+		_ => return Err(persist::missing("emission")),
+	};
+	Ok(Emitter::new(id as obj::Id, try!(float("x")), try!(float("y")), try!(float("rate")), emission))
+}
+
+/// An agent held by the mouse, see `World::grab`
+#[derive(Clone, Debug)]
+pub struct Grab {
+	pub id: obj::Id,
+	/// where the agent is pulled to while held
+	pub target: Position,
+	/// what it was let go with, until the physics has given it that velocity
+	pub release: Option<Velocity>,
+}
 
 pub struct World {
 	pub extent: Rect,
 	swarms: HashMap<AgentType, Swarm>,
 	emitters: Vec<Emitter>,
+	emitter_seq: obj::Id,
+	grab: Option<Grab>,
 	registered: HashSet<Id>,
+	/// agents put somewhere else by `move_to` that the physics has yet to catch up with
+	moved: HashSet<Id>,
 	extinctions: usize,
 	minion_gene_pool: gen::GenePool,
 	resource_gene_pool: gen::GenePool,
+	seed: u64,
+	rng: rand::XorShiftRng,
+	config: SimConfig,
+	contact_policy: contact::ContactPolicy,
+	events: EventBus,
 }
 
 pub trait WorldState {
@@ -39,7 +165,7 @@ pub trait WorldState {
 
 impl WorldState for World {
 	fn agent(&self, id: obj::Id) -> Option<&Agent> {
-		self.swarms.get(&id.type_of()).and_then(|m| m.get(id))
+		self.get(id.type_of(), id)
 	}
 }
 
@@ -52,14 +178,16 @@ pub enum Emission {
 
 #[derive(Clone)]
 pub struct Emitter {
+	id: obj::Id,
 	transform: Transform,
 	rate: f32,
 	emission: Emission,
 }
 
 impl Emitter {
-	pub fn new(x: f32, y: f32, rate: f32, emission: Emission) -> Self {
+	pub fn new(id: obj::Id, x: f32, y: f32, rate: f32, emission: Emission) -> Self {
 		Emitter {
+			id: id,
 			transform: Transform::from_position(Position::new(x, y)),
 			rate: rate,
 			emission: emission,
@@ -73,6 +201,12 @@ impl Emitter {
 	}
 }
 
+impl obj::Identified for Emitter {
+	fn id(&self) -> obj::Id {
+		self.id
+	}
+}
+
 impl Transformable for Emitter {
 	fn transform(&self) -> &Transform {
 		&self.transform
@@ -83,35 +217,154 @@ impl Transformable for Emitter {
 	}
 }
 
+#[derive(Clone)]
+pub enum Placement {
+	/// anywhere inside the extent
+	Uniform,
+	/// on a circle of the given radius around the origin
+	Ring(f32),
+}
+
+/// Initial population, spawned by `World::populate`
+#[derive(Clone)]
+pub struct WorldInit {
+	pub minions: usize,
+	pub resources: usize,
+	pub obstacles: usize,
+	/// predators, hunting the minions
+	pub enemies: usize,
+	pub placement: Placement,
+	/// minimum distance between any two spawn points
+	pub spacing: f32,
+}
+
+impl Default for WorldInit {
+	fn default() -> Self {
+		WorldInit {
+			minions: 0,
+			resources: 0,
+			obstacles: 0,
+			enemies: 0,
+			placement: Placement::Uniform,
+			spacing: 4.,
+		}
+	}
+}
+
 impl World {
-	pub fn new<R>(res: &R, minion_gene_pool: &str) -> Self
+	pub fn load_gene_pool<R>(res: &R, minion_gene_pool: &str) -> gen::GenePool
 		where R: ResourceLoader<u8> {
-		let mut swarms = HashMap::new();
-		let types = AgentType::all();
-		for t in types {
-			swarms.insert(*t, Swarm::new(*t));
-		}
-		fn default_gene_pool(_: io::Error) -> gen::GenePool {
+		fn default_gene_pool(e: persist::Error) -> gen::GenePool {
+			warn!("Using default gene pool: {}", e);
 			gen::GenePool::parse_from_base64(&["AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
 			                                   "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
 			                                   "GzB2lQVwM00tTAm5gwajjf4wc0a5GzB2lQVwM00tTAm5gwajjf4wc0a5",
 			                                   "GzB2lQdwM10vQEu5zwaPgDhfq2v8GzB2lQdwM10vQEu5zwaPgDhfq2v8"])
 		}
+		res.load(minion_gene_pool)
+			.map_err(persist::Error::from)
+			.and_then(|data| gen::GenePool::load(&data))
+			.unwrap_or_else(default_gene_pool)
+	}
+
+	pub fn new(minion_gene_pool: gen::GenePool, seed: u64) -> Self {
+		let config = SimConfig::default();
+		let mut rng = Self::seeded_rng(seed);
+		let mut swarms = HashMap::new();
+		let types = AgentType::all();
+		for t in types {
+			// each swarm gets its own stream, so that spawning one type doesn't change what the others get
+			let mut swarm_seed = [0u8; 16];
+			rng.fill_bytes(&mut swarm_seed);
+			swarms.insert(*t, Swarm::new(*t, config.max_segments).with_seed(swarm_seed));
+		}
 
 		World {
 			extent: Rect::new(-80., -80., 80., 80.),
 			swarms: swarms,
-			emitters: vec![Emitter::new(-20., -20., 0.4, Emission::CW(consts::PI / 12.)),
-			               Emitter::new(-20., 20., 0.4, Emission::Random),
-			               Emitter::new(20., 20., 0.4, Emission::CCW(consts::PI / 12.)),
-			               Emitter::new(20., -20., 0.4, Emission::Random)],
-			minion_gene_pool: res.load(minion_gene_pool)
-				.map(|data| gen::GenePool::parse_from_resource(&data))
-				.unwrap_or_else(default_gene_pool),
+			emitters: vec![Emitter::new(1, -20., -20., 0.4, Emission::CW(consts::PI / 12.)),
+			               Emitter::new(2, -20., 20., 0.4, Emission::Random),
+			               Emitter::new(3, 20., 20., 0.4, Emission::CCW(consts::PI / 12.)),
+			               Emitter::new(4, 20., -20., 0.4, Emission::Random)],
+			emitter_seq: 4,
+			grab: None,
+			minion_gene_pool: minion_gene_pool,
 			resource_gene_pool: gen::GenePool::parse_from_base64(&["GyA21QoQ", "M00sWS0M"]),
 			registered: HashSet::new(),
+			moved: HashSet::new(),
 			extinctions: 0usize,
+			seed: seed,
+			rng: rng,
+			config: config,
+			contact_policy: contact::ContactPolicy::default(),
+			events: EventBus::new(),
+		}
+	}
+
+	fn seeded_rng(seed: u64) -> rand::XorShiftRng {
+		// xorshift must not be seeded with all zeroes
+		let (lo, hi) = (seed as u32, (seed >> 32) as u32);
+		rand::XorShiftRng::from_seed([lo, hi, lo ^ 0x9e3779b9, hi ^ 0x7f4a7c15])
+	}
+
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	pub fn config(&self) -> &SimConfig {
+		&self.config
+	}
+
+	pub fn contact_policy(&self) -> &contact::ContactPolicy {
+		&self.contact_policy
+	}
+
+	pub fn set_config(&mut self, config: SimConfig) {
+		for (agent_type, swarm) in self.swarms.iter_mut() {
+			swarm.set_max_segments(config.max_segments);
+			swarm.set_charge_cycle(config.charge);
+			if agent_type.has_brain() {
+				swarm.set_brain_spec(config.brain.clone());
+			}
+		}
+		self.config = config;
+	}
+
+	/// Hashes the seed, the gene pools and every agent's id and dna, in id order
+	pub fn digest(&self) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		self.seed.hash(&mut hasher);
+		self.extinctions.hash(&mut hasher);
+		self.minion_gene_pool.hash(&mut hasher);
+		self.resource_gene_pool.hash(&mut hasher);
+		for agent_type in AgentType::all() {
+			let mut ids = self.agents(*agent_type).keys().collect::<Vec<_>>();
+			ids.sort();
+			for id in ids {
+				id.hash(&mut hasher);
+				self.agents(*agent_type)[id].dna().hash(&mut hasher);
+			}
+		}
+		hasher.finish()
+	}
+
+	/// Extends `digest` with every segment's transform, bit for bit, so that two worlds only hash alike
+	/// if they have played out exactly alike
+	pub fn state_digest(&self) -> u64 {
+		let bits = |x: f32| unsafe { mem::transmute::<f32, u32>(x) };
+		let mut hasher = DefaultHasher::new();
+		self.digest().hash(&mut hasher);
+		for agent_type in AgentType::all() {
+			let mut ids = self.agents(*agent_type).keys().collect::<Vec<_>>();
+			ids.sort();
+			for id in ids {
+				for segment in self.agents(*agent_type)[id].segments() {
+					let transform = &segment.transform;
+					(bits(transform.position.x), bits(transform.position.y), bits(transform.angle)).hash(&mut hasher);
+				}
+			}
 		}
+		hasher.finish()
 	}
 
 	pub fn extinctions(&self) -> usize {
@@ -119,7 +372,7 @@ impl World {
 	}
 
 	pub fn new_resource(&mut self, transform: &Transform, motion: Option<&Motion>) -> obj::Id {
-		let mut gen = &mut self.resource_gene_pool.next();
+		let mut gen = &mut self.resource_gene_pool.next_with(self.swarms.get_mut(&AgentType::Resource).unwrap().rng());
 		let id = self.swarm_mut(&AgentType::Resource)
 			.spawn::<phen::Resource>(&mut gen, transform, motion, 0.8);
 		self.register(id)
@@ -132,11 +385,9 @@ impl World {
 	}
 
 	pub fn new_spore(&mut self, transform: &Transform, dna: &gen::Dna) -> obj::Id {
+		let mut gen = gen::Genome::new(dna).mutate(self.swarm_mut(&AgentType::Spore).rng());
 		let id = self.swarm_mut(&AgentType::Spore)
-			.spawn::<phen::Spore>(&mut gen::Genome::new(dna).mutate(&mut rand::thread_rng()),
-			                      transform,
-			                      None,
-			                      0.8);
+			.spawn::<phen::Spore>(&mut gen, transform, None, 0.8);
 		self.register(id)
 	}
 
@@ -146,8 +397,104 @@ impl World {
 		self.register(id)
 	}
 
+	/// A minion budding off a parent, with the parent's dna and the `brain` it was handed down
+	pub fn bud(&mut self, transform: &Transform, dna: &gen::Dna, brain: &agent::Brain) -> obj::Id {
+		let id = self.swarm_mut(&AgentType::Minion)
+			.spawn::<phen::Minion>(&mut gen::Genome::new(dna), transform, None, 0.3);
+		if let Some(child) = self.agent_mut(id) {
+			*child.brain_mut() = brain.clone();
+		}
+		self.register(id)
+	}
+
+	/// Puts a copy of an agent at `position`, see `Swarm::clone_agent`
+	pub fn clone_agent(&mut self, id: obj::Id, position: Position) -> Option<obj::Id> {
+		match self.swarm_mut(&id.type_of()).clone_agent(id, position) {
+			Some(id) => Some(self.register(id)),
+			None => None,
+		}
+	}
+
+	/// Breaks an agent apart, each of its segments drifting away as an edible remain that fades out over
+	/// the configured `remains_ttl`
+	pub fn shatter(&mut self, id: obj::Id) -> Vec<obj::Id> {
+		let (dna, remains) = match self.agent_mut(id) {
+			Some(agent) => {
+				agent.state.die();
+				(agent.dna().clone(), agent.segments().to_vec())
+			}
+			None => return Vec::new(),
+		};
+		let ttl = self.config.remains_ttl;
+		let mut ids = Vec::new();
+		for segment in &remains {
+			let id = self.swarm_mut(&AgentType::Resource).adopt(&dna, segment);
+			if let Some(remain) = self.agent_mut(id) {
+				remain.state.set_ttl(ttl);
+			}
+			ids.push(self.register(id));
+		}
+		ids
+	}
+
+	/// The part of the extent spawners may place agents in
+	pub fn spawn_area(&self) -> Rect {
+		self.extent.inset(self.config.spawn_margin)
+	}
+
+	/// Spawns the initial population, placing everything with the world rng so that a seed
+	/// always gives the same layout. Points closer than `spacing` to an earlier one are rejected,
+	/// up to a few attempts each, after which the last candidate is used anyway.
+	pub fn populate(&mut self, init: &WorldInit) -> Vec<obj::Id> {
+		const MAX_ATTEMPTS: usize = 32;
+		let area = self.spawn_area();
+		let inner = area.inset(init.spacing * 0.5);
+		let (min, max) = (inner.min, inner.max);
+		let mut placed: Vec<Position> = Vec::new();
+		let mut ids = Vec::new();
+		let total = init.obstacles + init.resources + init.minions + init.enemies;
+		for i in 0..total {
+			let mut candidate = Position::new(0., 0.);
+			for _ in 0..MAX_ATTEMPTS {
+				candidate = match init.placement {
+					Placement::Uniform if min.x < max.x && min.y < max.y => {
+						Position::new(self.rng.gen_range(min.x, max.x), self.rng.gen_range(min.y, max.y))
+					}
+					Placement::Uniform => min,
+					Placement::Ring(radius) => {
+						let angle = self.rng.gen_range(0., 2. * consts::PI);
+						area.clamp(Position::new(radius * angle.cos(), radius * angle.sin()))
+					}
+				};
+				if placed.iter().all(|p| (p - candidate).length() >= init.spacing) {
+					break;
+				}
+			}
+			placed.push(candidate);
+			let angle = self.rng.gen_range(0., 2. * consts::PI);
+			let id = if i < init.obstacles {
+				self.new_obstacle(&Transform::new(candidate, angle))
+			} else if i < init.obstacles + init.resources {
+				self.new_resource(&Transform::new(candidate, angle), None)
+			} else if i < init.obstacles + init.resources + init.minions {
+				self.new_minion(candidate, None)
+			} else {
+				self.new_enemy(candidate, None)
+			};
+			ids.push(id);
+		}
+		ids
+	}
+
+	pub fn new_obstacle(&mut self, transform: &Transform) -> obj::Id {
+		let mut gen = &mut self.resource_gene_pool.next_with(self.swarms.get_mut(&AgentType::Prop).unwrap().rng());
+		let id = self.swarm_mut(&AgentType::Prop)
+			.spawn::<phen::Prop>(&mut gen, transform, None, 1.);
+		self.register(id)
+	}
+
 	pub fn randomize_minion(&mut self, pos: Position, motion: Option<&Motion>) -> obj::Id {
-		self.minion_gene_pool.randomize();
+		self.minion_gene_pool.randomize(self.swarms.get_mut(&AgentType::Minion).unwrap().rng());
 		self.new_minion(pos, motion)
 	}
 
@@ -157,9 +504,10 @@ impl World {
 		let mut r = self.extent.top_right().x * 0.25;
 		let mut angle = 0.0f32;
 		let angle_delta = consts::PI * 2. / 16. as f32;
+		let area = self.spawn_area();
 		for _ in 0..n {
-			let pos = Position::new(r * angle.cos(), r * angle.sin());
-			let mut gen = self.minion_gene_pool.next();
+			let pos = area.clamp(Position::new(r * angle.cos(), r * angle.sin()));
+			let mut gen = self.minion_gene_pool.next_with(self.swarms.get_mut(&AgentType::Minion).unwrap().rng());
 			let id = self.swarm_mut(&AgentType::Minion)
 				.spawn::<phen::Minion>(&mut gen,
 				                       &Transform::new(pos, angle + consts::PI / 2.),
@@ -173,37 +521,162 @@ impl World {
 
 	pub fn new_minion(&mut self, pos: Position, motion: Option<&Motion>) -> obj::Id {
 		let angle = consts::PI / 2. + f32::atan2(pos.y, pos.x);
-		let mut gen = self.minion_gene_pool.next();
+		let mut gen = self.minion_gene_pool.next_with(self.swarms.get_mut(&AgentType::Minion).unwrap().rng());
 		let id = self.swarm_mut(&AgentType::Minion)
 			.spawn::<phen::Minion>(&mut gen, &Transform::new(pos, angle), motion, 0.3);
 		self.register(id)
 	}
 
+	/// A predator with the body of a minion from the gene pool
+	pub fn new_enemy(&mut self, pos: Position, motion: Option<&Motion>) -> obj::Id {
+		let angle = consts::PI / 2. + f32::atan2(pos.y, pos.x);
+		let mut gen = self.minion_gene_pool.next_with(self.swarms.get_mut(&AgentType::Enemy).unwrap().rng());
+		let id = self.swarm_mut(&AgentType::Enemy)
+			.spawn::<phen::Minion>(&mut gen, &Transform::new(pos, angle), motion, 0.3);
+		self.register(id)
+	}
+
+	/// A shot of `agent_type`, one of the fire swarms, carrying the dna of whoever fired it
+	pub fn new_projectile(&mut self, agent_type: AgentType, transform: &Transform, motion: &Motion, dna: &gen::Dna)
+	                      -> obj::Id {
+		let id = self.swarm_mut(&agent_type)
+			.spawn::<phen::Bullet>(&mut gen::Genome::new(dna), transform, Some(motion), 1.);
+		self.register(id)
+	}
+
+	/// Holds agent `id` at `target`: the physics moves it there on every tick and leaves out its intents,
+	/// which take over again once it is let go. False if there's no such agent
+	pub fn grab(&mut self, id: obj::Id, target: Position) -> bool {
+		if self.agent(id).is_none() {
+			return false;
+		}
+		self.grab = Some(Grab {
+			id: id,
+			target: target,
+			release: None,
+		});
+		true
+	}
+
+	pub fn move_grabbed(&mut self, target: Position) {
+		if let Some(ref mut grab) = self.grab {
+			grab.target = target;
+		}
+	}
+
+	/// Lets go of the held agent with `velocity`, false if nothing was held
+	pub fn fling(&mut self, velocity: Velocity) -> bool {
+		match self.grab {
+			Some(ref mut grab) if grab.release.is_none() => {
+				grab.release = Some(velocity);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	pub fn grabbed(&self) -> Option<&Grab> {
+		self.grab.as_ref()
+	}
+
+	/// Forgets the held agent, once the physics has flung it
+	pub fn let_go(&mut self) {
+		self.grab = None;
+	}
+
 	pub fn register(&mut self, id: obj::Id) -> obj::Id {
 		self.registered.insert(id);
+		self.events.push(WorldEvent::Born(id));
 		id
 	}
 
+	/// In id order, for bodies to be added to the physics alike on every run of a seed
 	pub fn registered(&mut self) -> Box<[Id]> {
-		let collection = self.registered.iter().map(|r| *r).collect::<Vec<_>>().into_boxed_slice();
+		let mut collection = self.registered.iter().map(|r| *r).collect::<Vec<_>>();
+		collection.sort();
 		self.registered.clear();
-		collection
+		collection.into_boxed_slice()
 	}
 
 	#[allow(dead_code)]
 	pub fn agent(&self, id: obj::Id) -> Option<&Agent> {
-		self.swarms.get(&id.type_of()).and_then(|m| m.get(id))
+		self.get(id.type_of(), id)
+	}
+
+	/// The agent `id` if it's one of `agent_type`
+	pub fn get(&self, agent_type: AgentType, id: obj::Id) -> Option<&Agent> {
+		self.swarm(agent_type).get(id)
+	}
+
+	/// Has the agent `id` of `agent_type` die, to be swept with the others; false if there's no such agent
+	pub fn kill(&mut self, agent_type: AgentType, id: obj::Id) -> bool {
+		self.swarm_mut(&agent_type).kill(id)
+	}
+
+	/// Puts the agent `id` of `agent_type` with its core at `position`, the rest of its body following as it is;
+	/// false if there's no such agent
+	#[allow(dead_code)]
+	pub fn move_to(&mut self, agent_type: AgentType, id: obj::Id, position: Position) -> bool {
+		match self.swarm_mut(&agent_type).get_mut(id) {
+			Some(agent) => {
+				let offset = position - agent.segments()[0].transform.position;
+				for segment in agent.segments_mut().iter_mut() {
+					segment.transform.position = segment.transform.position + offset;
+				}
+			}
+			None => return false,
+		}
+		self.moved.insert(id);
+		true
+	}
+
+	/// The agents moved since the physics last caught up
+	pub fn moved(&self) -> &HashSet<Id> {
+		&self.moved
+	}
+
+	/// Once the bodies of the moved agents are where the agents are
+	pub fn clear_moved(&mut self) {
+		self.moved.clear();
+	}
+
+	/// The agent of any type with a segment under `p`, the one centred nearest where several overlap
+	pub fn pick(&self, p: Position) -> Option<obj::Id> {
+		self.swarms
+			.values()
+			.filter_map(|swarm| swarm.pick(p))
+			.fold(None, |nearest: Option<(obj::Id, f32)>, (id, distance)| match nearest {
+				Some((_, nearest_distance)) if nearest_distance <= distance => nearest,
+				_ => Some((id, distance)),
+			})
+			.map(|(id, _)| id)
 	}
 
 	pub fn for_all_agents<F>(&mut self, callback: &mut F)
 		where F: FnMut(&mut Agent) {
-		for (_, swarm) in self.swarms.iter_mut() {
-			for (_, mut agent) in swarm.agents_mut().iter_mut() {
-				callback(&mut agent)
-			}
+		for (_, agent) in self.all_agents_mut() {
+			callback(agent)
 		}
 	}
 
+	/// Every agent along with its swarm, the swarms in `AgentType::all` order and the agents of each by id
+	pub fn all_agents<'a>(&'a self) -> Box<Iterator<Item = (&'a Swarm, &'a Agent)> + 'a> {
+		Box::new(AgentType::all()
+			.iter()
+			.map(move |agent_type| &self.swarms[agent_type])
+			.flat_map(|swarm| swarm.agents().values().map(move |agent| (swarm, agent))))
+	}
+
+	/// Every agent in the order of `all_agents`, along with its type: the swarms themselves stay out of reach
+	/// while their agents are lent out
+	pub fn all_agents_mut<'a>(&'a mut self) -> Box<Iterator<Item = (AgentType, &'a mut Agent)> + 'a> {
+		let mut swarms = self.swarms.iter_mut().collect::<Vec<_>>();
+		swarms.sort_by_key(|&(agent_type, _)| *agent_type as usize);
+		Box::new(swarms.into_iter().flat_map(|(&agent_type, swarm)| {
+			swarm.agents_mut().values_mut().map(move |agent| (agent_type, agent))
+		}))
+	}
+
 	pub fn agent_mut(&mut self, id: obj::Id) -> Option<&mut Agent> {
 		self.swarms.get_mut(&id.type_of()).and_then(|m| m.get_mut(id))
 	}
@@ -212,38 +685,543 @@ impl World {
 		self.swarms.get(&agent_type).unwrap().agents()
 	}
 
+	/// The segments carrying all of `flags` across every agent of a type
+	pub fn segments_with<'a>(&'a self, agent_type: AgentType, flags: segment::Flags)
+	                         -> Box<Iterator<Item = &'a segment::Segment> + 'a> {
+		Box::new(self.agents(agent_type).values().flat_map(move |agent| agent.segments_with(flags)))
+	}
+
 	pub fn agents_mut(&mut self, agent_type: AgentType) -> &mut agent::AgentMap {
 		self.swarms.get_mut(&agent_type).unwrap().agents_mut()
 	}
 
+	pub fn swarm(&self, agent_type: AgentType) -> &Swarm {
+		&self.swarms[&agent_type]
+	}
+
 	pub fn swarm_mut(&mut self, agent_type: &AgentType) -> &mut Swarm {
 		self.swarms.get_mut(&agent_type).unwrap()
 	}
 
+	/// The beacons the minions head for when they have no target, each also spawning resources and lighting the scene
 	pub fn emitters(&self) -> &[Emitter] {
 		self.emitters.as_slice()
 	}
 
+	/// Places a beacon at `position`, after the others. Its id is never handed out again, even once it's removed
+	pub fn add_emitter(&mut self, position: Position) -> obj::Id {
+		self.emitter_seq += 1;
+		let id = self.emitter_seq;
+		self.emitters.push(Emitter::new(id, position.x, position.y, 0.4, Emission::Random));
+		id
+	}
+
+	/// False if there was no emitter `id`
+	pub fn remove_emitter(&mut self, id: obj::Id) -> bool {
+		let before = self.emitters.len();
+		self.emitters.retain(|e| e.id != id);
+		self.emitters.len() < before
+	}
+
+	/// False if there was no emitter `id`
+	pub fn move_emitter(&mut self, id: obj::Id, position: Position) -> bool {
+		match self.emitters.iter_mut().find(|e| e.id == id) {
+			Some(emitter) => {
+				emitter.transform.position = position;
+				true
+			}
+			None => false,
+		}
+	}
+
 	pub fn swarms(&self) -> &SwarmMap {
 		&self.swarms
 	}
 
+	/// Brings the index of every swarm up to where its agents now are, see `Swarm::agents_in_rect`
+	pub fn reindex(&mut self) {
+		let (kind, extent) = (self.config.broadphase, self.extent);
+		for swarm in self.swarms.values_mut() {
+			swarm.reindex(kind, extent);
+		}
+	}
+
 	pub fn sweep(&mut self) -> Box<[Agent]> {
 		let mut v = Vec::new();
 		for (_, agents) in self.swarms.iter_mut() {
 			agents.free_resources(&mut v);
 		}
+		for agent in &v {
+			self.events.push(WorldEvent::Died(agent.id()));
+			if self.grab.as_ref().map_or(false, |grab| grab.id == agent.id()) {
+				self.grab = None;
+			}
+		}
 		v.into_boxed_slice()
 	}
 
+	/// What happened since the events were last drained
+	pub fn events_mut(&mut self) -> &mut EventBus {
+		&mut self.events
+	}
+
 	pub fn dump(&self) -> io::Result<String> {
 		let now: DateTime<UTC> = UTC::now();
 		let file_name = now.format("resources/%Y%m%d_%H%M%S.csv").to_string();
 		let mut f = try!(fs::File::create(&file_name));
+		try!(f.write_all(persist::header(persist::CURRENT_VERSION).as_bytes()));
 		for (_, agent) in self.agents(agent::AgentType::Minion).iter() {
 			info!("{}", agent.dna().to_base64(base64::STANDARD));
 			try!(f.write_fmt(format_args!("{}\n", agent.dna().to_base64(base64::STANDARD))));
 		}
 		Ok(file_name)
 	}
+
+	/// Writes the seed, the id counters, the gene pools, the emitters and every agent, each as the dna it develops
+	/// from, its brain and the state of its segments. The random number generators are not written, a loaded world
+	/// draws afresh from its seed. Agents that fade out by themselves, like the remains of shattered ones,
+	/// are left out. The file is replaced atomically so a crash never leaves a partial save.
+	/// Agents are listed in id order and coordinates rounded to `SAVE_DIGITS`, so that saving the same world
+	/// always writes the same bytes.
+	pub fn save(&self, path: &path::Path) -> persist::Result<()> {
+		let mut agents = Vec::new();
+		let mut seqs = BTreeMap::new();
+		for agent_type in AgentType::all() {
+			seqs.insert(agent_type.to_string(), Json::U64(self.swarms[agent_type].seq() as u64));
+			// shots in flight are gone long before a save could be loaded
+			if agent_type.is_projectile() {
+				continue;
+			}
+			let swarm = self.agents(*agent_type);
+			let mut ids = swarm.keys().collect::<Vec<_>>();
+			ids.sort();
+			for id in ids {
+				let agent = &swarm[id];
+				// remains are gone within seconds, and their segment could not be developed back from the dna
+				if agent.state.ttl().is_some() {
+					continue;
+				}
+				let transform = agent.transform();
+				let mut obj = BTreeMap::new();
+				obj.insert("id".to_string(), Json::U64(*id as u64));
+				obj.insert("dna".to_string(), Json::String(agent.dna().to_base64(base64::STANDARD)));
+				obj.insert("x".to_string(), save_float(transform.position.x));
+				obj.insert("y".to_string(), save_float(transform.position.y));
+				obj.insert("angle".to_string(), save_float(transform.angle));
+				obj.insert("energy".to_string(), save_float(agent.state.energy()));
+				obj.insert("brain".to_string(), agent.brain().to_json());
+				obj.insert("segments".to_string(),
+				           Json::Array(agent.segments().iter().map(save_segment).collect()));
+				agents.push(Json::Object(obj));
+			}
+		}
+		let mut obj = BTreeMap::new();
+		obj.insert("seed".to_string(), Json::U64(self.seed));
+		obj.insert("extinctions".to_string(), Json::U64(self.extinctions as u64));
+		obj.insert("minion_gene_pool".to_string(), self.minion_gene_pool.to_json());
+		obj.insert("resource_gene_pool".to_string(), self.resource_gene_pool.to_json());
+		obj.insert("agents".to_string(), Json::Array(agents));
+		obj.insert("seq".to_string(), Json::Object(seqs));
+		obj.insert("emitters".to_string(),
+		           Json::Array(self.emitters.iter().map(save_emitter).collect()));
+		obj.insert("emitter_seq".to_string(), Json::U64(self.emitter_seq as u64));
+
+		let partial = path.with_extension("partial");
+		{
+			let mut f = try!(fs::File::create(&partial));
+			try!(f.write_all(persist::header(persist::CURRENT_VERSION).as_bytes()));
+			try!(f.write_fmt(format_args!("{}\n", Json::Object(obj))));
+			try!(f.sync_all());
+		}
+		try!(fs::rename(&partial, path));
+		Ok(())
+	}
+
+	pub fn load(path: &path::Path) -> persist::Result<World> {
+		let mut data = Vec::new();
+		try!(fs::File::open(path).and_then(|mut f| f.read_to_end(&mut data)));
+		<World as persist::Versioned>::load(&data)
+	}
+
+	/// Version 2 saves only hold the dna and the core's placement, their agents are developed afresh.
+	/// Brains came with version 4, older ones keep the brain their dna develops,
+	/// and segment ages with version 5, older ones are fully grown. Emitters came with version 6,
+	/// older saves get the ones a new world starts with.
+	fn from_json(json: &Json, version: persist::SaveVersion) -> persist::Result<World> {
+		fn field<'a>(json: &'a Json, name: &str) -> persist::Result<&'a Json> {
+			json.find(name).ok_or(persist::missing(name))
+		}
+		fn float(json: &Json, name: &str) -> persist::Result<f32> {
+			try!(field(json, name)).as_f64().map(|v| v as f32).ok_or(persist::missing(name))
+		}
+		let seed = try!(try!(field(json, "seed")).as_u64().ok_or(persist::missing("seed")));
+		let minion_gene_pool = try!(gen::GenePool::from_json(try!(field(json, "minion_gene_pool"))));
+		let mut world = World::new(minion_gene_pool, seed);
+		world.resource_gene_pool = try!(gen::GenePool::from_json(try!(field(json, "resource_gene_pool"))));
+		world.extinctions = try!(try!(field(json, "extinctions")).as_u64().ok_or(persist::missing("extinctions"))) as
+		                    usize;
+		let agents = try!(try!(field(json, "agents")).as_array().ok_or(persist::missing("agents")));
+		for agent in agents {
+			let id = try!(try!(field(agent, "id")).as_u64().ok_or(persist::missing("id"))) as obj::Id;
+			let encoded = try!(try!(field(agent, "dna")).as_string().ok_or(persist::missing("dna")));
+			let dna = try!(encoded.from_base64().map_err(|e| persist::Error::Format(e.to_string())));
+			let transform = Transform::new(Position::new(try!(float(agent, "x")), try!(float(agent, "y"))),
+			                               try!(float(agent, "angle")));
+			let mut genome = gen::Genome::new(&dna);
+			let swarm = world.swarm_mut(&id.type_of());
+			match id.type_of() {
+				AgentType::Minion => swarm.respawn::<phen::Minion>(id, &mut genome, &transform, 0.3),
+				AgentType::Enemy => swarm.respawn::<phen::Minion>(id, &mut genome, &transform, 0.3),
+				AgentType::Spore => swarm.respawn::<phen::Spore>(id, &mut genome, &transform, 0.8),
+				AgentType::Resource => swarm.respawn::<phen::Resource>(id, &mut genome, &transform, 0.8),
+				AgentType::Prop => swarm.respawn::<phen::Prop>(id, &mut genome, &transform, 1.),
+				t => return Err(persist::Error::Format(format!("can't restore agents of type {}", t))),
+			};
+			if version >= 3 {
+				let energy = try!(float(agent, "energy"));
+				let saved = try!(try!(field(agent, "segments")).as_array().ok_or(persist::missing("segments")));
+				let restored = world.agent_mut(id).unwrap();
+				if saved.len() != restored.segments().len() {
+					return Err(persist::Error::Format(format!("agent {} has {} segments, its dna develops {}",
+					                                          id,
+					                                          saved.len(),
+					                                          restored.segments().len())));
+				}
+				restored.state.set_energy(energy);
+				for (json, segment) in saved.iter().zip(restored.segments_mut().iter_mut()) {
+					try!(load_segment(json, version, segment));
+				}
+				if version >= 4 {
+					let mut brain = try!(agent::Brain::from_json(try!(field(agent, "brain"))));
+					brain.pad_inputs(agent::N_INPUTS);
+					*restored.brain_mut() = brain;
+				}
+			}
+			world.register(id);
+		}
+		if let Some(seqs) = json.find("seq").and_then(|seqs| seqs.as_object()) {
+			for agent_type in AgentType::all() {
+				if let Some(seq) = seqs.get(&agent_type.to_string()).and_then(|seq| seq.as_u64()) {
+					world.swarm_mut(agent_type).resume_seq(seq as obj::Id);
+				}
+			}
+		}
+		if version >= 6 {
+			let emitters = try!(try!(field(json, "emitters")).as_array().ok_or(persist::missing("emitters")));
+			world.emitters = try!(emitters.iter().map(load_emitter).collect::<persist::Result<Vec<_>>>());
+			world.emitter_seq = try!(try!(field(json, "emitter_seq"))
+				.as_u64()
+				.ok_or(persist::missing("emitter_seq"))) as obj::Id;
+		}
+		// restored agents were born in another run
+		world.events.clear();
+		Ok(world)
+	}
+}
+
+impl persist::Versioned for World {
+	fn migrate(version: persist::SaveVersion, data: &[u8]) -> persist::Result<Self> {
+		match version {
+			2 | 3 | 4 | 5 | 6 => {
+				let text = try!(::std::str::from_utf8(data).map_err(|e| persist::Error::Format(e.to_string())));
+				let json = try!(Json::from_str(text).map_err(|e| persist::Error::Format(e.to_string())));
+				Self::from_json(&json, version)
+			}
+			_ => Err(persist::Error::Unsupported(version)),
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn emitters_are_added_moved_and_removed() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		let positions = |world: &World| world.emitters().iter().map(|e| e.transform().position).collect::<Vec<_>>();
+		let n = world.emitters().len();
+		let a = world.add_emitter(Position::new(5., 6.));
+		let b = world.add_emitter(Position::new(-7., 8.));
+		assert!(a != b);
+		assert_eq!(n + 2, world.emitters().len());
+		assert_eq!(Position::new(-7., 8.), positions(&world)[n + 1]);
+
+		assert!(world.move_emitter(a, Position::new(1., 1.)));
+		assert_eq!(Position::new(1., 1.), positions(&world)[n]);
+		assert!(world.remove_emitter(a));
+		assert!(!world.remove_emitter(a));
+		assert!(!world.move_emitter(a, Position::new(0., 0.)));
+		assert_eq!(n + 1, world.emitters().len());
+		assert_eq!(Position::new(-7., 8.), positions(&world)[n]);
+		assert!(world.add_emitter(Position::new(0., 0.)) != a);
+	}
+
+	#[test]
+	fn populate_spawns_requested_counts() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		let init = WorldInit {
+			minions: 5,
+			resources: 20,
+			obstacles: 3,
+			enemies: 2,
+			..WorldInit::default()
+		};
+		let ids = world.populate(&init);
+		assert_eq!(30, ids.len());
+		assert_eq!(5, world.agents(AgentType::Minion).len());
+		assert_eq!(2, world.agents(AgentType::Enemy).len());
+		assert_eq!(20, world.agents(AgentType::Resource).len());
+		assert_eq!(3, world.agents(AgentType::Prop).len());
+		let extent = world.extent;
+		for id in ids {
+			let p = world.agent(id).unwrap().transform().position;
+			assert!(p.x > extent.min.x && p.x < extent.max.x && p.y > extent.min.y && p.y < extent.max.y);
+		}
+	}
+
+	#[test]
+	fn typed_lookups_find_agents_in_their_own_swarm() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		let resource = world.new_resource(&Transform::new(Position::new(5., 5.), 0.), None);
+		let minion = world.new_minion(Position::new(0., 0.), None);
+		assert_eq!(AgentType::Resource, resource.type_of());
+		assert!(world.agents(AgentType::Resource).contains_key(&resource));
+		assert!(!world.agents(AgentType::Minion).contains_key(&resource));
+		assert!(world.get(AgentType::Resource, resource).is_some());
+		assert!(world.get(AgentType::Minion, resource).is_none());
+		assert_eq!(minion, world.get(AgentType::Minion, minion).unwrap().id());
+
+		// the body moves as one
+		let before = world.get(AgentType::Minion, minion).unwrap().segments().to_vec();
+		assert!(!world.move_to(AgentType::Resource, minion, Position::new(10., 10.)));
+		assert!(world.moved().is_empty());
+		assert!(world.move_to(AgentType::Minion, minion, Position::new(10., 10.)));
+		assert!(world.moved().contains(&minion));
+		let offset = Position::new(10., 10.) - before[0].transform.position;
+		for (was, now) in before.iter().zip(world.get(AgentType::Minion, minion).unwrap().segments().iter()) {
+			assert!((was.transform.position + offset - now.transform.position).length() < 1e-5);
+		}
+		world.clear_moved();
+		assert!(world.moved().is_empty());
+
+		assert!(!world.kill(AgentType::Minion, resource));
+		assert!(world.kill(AgentType::Resource, resource));
+		let swept = world.sweep();
+		assert_eq!(vec![resource], swept.iter().map(|agent| agent.id()).collect::<Vec<_>>());
+		assert!(world.get(AgentType::Resource, resource).is_none());
+		assert!(world.get(AgentType::Minion, minion).is_some());
+	}
+
+	#[test]
+	fn shattering_leaves_a_fading_remain_per_segment() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		let minion = world.new_minion(Position::new(0., 0.), None);
+		let segments = world.agent(minion).unwrap().segments().len();
+		assert!(segments >= 3);
+		let remains = world.shatter(minion);
+		assert_eq!(segments, remains.len());
+		assert!(!world.agent(minion).unwrap().state.is_alive());
+		for id in &remains {
+			let remain = world.agent(*id).unwrap();
+			assert_eq!(AgentType::Resource, id.type_of());
+			assert_eq!(1, remain.segments().len());
+			assert_eq!(1., remain.state.ttl_ratio());
+		}
+		world.sweep();
+		assert!(world.agent(minion).is_none());
+		assert!(world.shatter(minion).is_empty());
+	}
+
+	#[test]
+	fn all_agents_go_through_every_swarm_in_type_order() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		world.populate(&WorldInit {
+			minions: 3,
+			resources: 4,
+			enemies: 2,
+			..WorldInit::default()
+		});
+		let total = AgentType::all().iter().map(|&t| world.swarm(t).agents().len()).fold(0, |a, n| a + n);
+		assert_eq!(9, total);
+		assert_eq!(total, world.all_agents().count());
+		let types = world.all_agents().map(|(swarm, agent)| {
+			assert_eq!(swarm.type_of(), agent.id().type_of());
+			swarm.type_of() as usize
+		}).collect::<Vec<_>>();
+		assert!(types.windows(2).all(|pair| pair[0] <= pair[1]));
+		let ids = world.all_agents().map(|(_, agent)| agent.id()).collect::<Vec<_>>();
+		let mut_ids = world.all_agents_mut()
+			.map(|(agent_type, agent)| {
+				assert_eq!(agent_type, agent.id().type_of());
+				agent.state.die();
+				agent.id()
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(ids, mut_ids);
+		assert!(world.all_agents().all(|(_, agent)| !agent.state.is_alive()));
+	}
+
+	#[test]
+	fn segments_with_counts_across_a_swarm() {
+		let mut world = World::new(gen::GenePool::for_tests(), 7);
+		assert_eq!(0, world.segments_with(AgentType::Minion, segment::MOUTH).count());
+		let minions = (0..3).map(|i| world.new_minion(Position::new(i as f32 * 10., 0.), None)).collect::<Vec<_>>();
+		let mouths = minions.iter().map(|&id| world.agent(id).unwrap().segments_with(segment::MOUTH).count());
+		assert_eq!(mouths.fold(0, |a, n| a + n),
+		           world.segments_with(AgentType::Minion, segment::MOUTH).count());
+		assert_eq!(0, world.segments_with(AgentType::Resource, segment::MOUTH).count());
+	}
+
+	#[test]
+	fn populate_respects_spawn_margin() {
+		let mut world = World::new(gen::GenePool::for_tests(), 3);
+		let mut config = world.config().clone();
+		config.spawn_margin = 30.;
+		world.set_config(config);
+		let area = world.spawn_area();
+		assert_eq!(world.extent.min.x + 30., area.min.x);
+		let ids = world.populate(&WorldInit {
+			resources: 50,
+			obstacles: 5,
+			..WorldInit::default()
+		});
+		let ring = world.populate(&WorldInit {
+			resources: 10,
+			placement: Placement::Ring(1000.),
+			..WorldInit::default()
+		});
+		for id in ids.into_iter().chain(ring) {
+			assert!(area.contains(world.agent(id).unwrap().transform().position));
+		}
+	}
+
+	#[test]
+	fn save_and_load_reproduce_digest() {
+		let mut world = World::new(gen::GenePool::for_tests(), 11);
+		world.populate(&WorldInit {
+			minions: 3,
+			resources: 5,
+			obstacles: 2,
+			..WorldInit::default()
+		});
+		let path = ::std::env::temp_dir().join("rust-oids-save-test.json");
+		world.save(&path).unwrap();
+		let loaded = World::load(&path).unwrap();
+		assert_eq!(world.digest(), loaded.digest());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn save_keeps_segment_state_and_ids() {
+		let mut world = World::new(gen::GenePool::for_tests(), 5);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let gone = world.new_minion(Position::new(10., 0.), None);
+		world.kill(AgentType::Minion, gone);
+		world.sweep();
+		{
+			let agent = world.agent_mut(id).unwrap();
+			let segment = &mut agent.segments_mut()[1];
+			// one ulp off 1, which takes more than 7 digits to keep
+			segment.transform.angle = 1.0000001;
+			segment.state.set_charge(0.125);
+			segment.state.set_age_seconds(0.5);
+			segment.livery.albedo = [0.5, 0.25, 1., 1.];
+			let mut weights = agent.brain().weights()[1].clone();
+			weights[0][3] = 3.5;
+			assert!(agent.brain_mut().set_weights(1, weights));
+			agent.brain_mut().fear = 0.75;
+		}
+		let path = ::std::env::temp_dir().join("rust-oids-segments-test.json");
+		world.save(&path).unwrap();
+		let mut loaded = World::load(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		{
+			let segment = &loaded.agent(id).unwrap().segments()[1];
+			assert_eq!(1.0000001, segment.transform.angle);
+			assert_eq!(0.125, segment.state.get_charge());
+			assert_eq!(0.5, segment.state.age_seconds());
+			assert_eq!([0.5, 0.25, 1., 1.], segment.livery.albedo);
+			let brain = loaded.agent(id).unwrap().brain();
+			assert_eq!(3.5, brain.weights()[1][0][3]);
+			assert_eq!(0.75, brain.fear);
+		}
+		// ids of agents gone before the save are not handed out again
+		let next = loaded.new_minion(Position::new(0., 0.), None);
+		assert!(next > gone);
+	}
+
+	#[test]
+	fn remains_are_left_out_of_saves() {
+		let mut world = World::new(gen::GenePool::for_tests(), 5);
+		let minion = world.new_minion(Position::new(0., 0.), None);
+		let remains = world.shatter(minion);
+		world.sweep();
+		assert!(!remains.is_empty());
+		let path = ::std::env::temp_dir().join("rust-oids-remains-test.json");
+		world.save(&path).unwrap();
+		let mut loaded = World::load(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert!(loaded.agents(AgentType::Resource).is_empty());
+		// nor are their ids handed out again
+		let next = loaded.new_resource(&Transform::default(), None);
+		assert!(remains.iter().all(|&id| next > id));
+	}
+
+	#[test]
+	fn segments_of_saves_from_before_ages_are_grown_up() {
+		let mut world = World::new(gen::GenePool::for_tests(), 5);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let path = ::std::env::temp_dir().join("rust-oids-ages-test.json");
+		world.save(&path).unwrap();
+		let mut data = String::new();
+		fs::File::open(&path).unwrap().read_to_string(&mut data).unwrap();
+		fs::remove_file(&path).unwrap();
+		let (_, payload) = persist::split_header(data.as_bytes()).unwrap();
+		let mut json = Json::from_str(::std::str::from_utf8(payload).unwrap()).unwrap();
+		for agent in json.as_object_mut().unwrap().get_mut("agents").unwrap().as_array_mut().unwrap() {
+			let agent = agent.as_object_mut().unwrap();
+			for segment in agent.get_mut("segments").unwrap().as_array_mut().unwrap() {
+				segment.as_object_mut().unwrap().remove("age");
+			}
+		}
+		let load = |version: persist::SaveVersion| {
+			<World as persist::Versioned>::load(format!("{}{}\n", persist::header(version), json).as_bytes())
+		};
+		let loaded = load(4).unwrap();
+		assert!(loaded.agent(id).unwrap().segments().iter().all(|s| s.state.age_seconds() == ::std::f32::MAX));
+		assert!(load(persist::CURRENT_VERSION).is_err());
+	}
+
+	#[test]
+	fn save_keeps_the_emitters() {
+		let mut world = World::new(gen::GenePool::for_tests(), 5);
+		let removed = world.add_emitter(Position::new(5., 6.));
+		let added = world.add_emitter(Position::new(-7., 8.));
+		world.remove_emitter(removed);
+		world.move_emitter(1, Position::new(1., 2.));
+		let path = ::std::env::temp_dir().join("rust-oids-emitters-test.json");
+		world.save(&path).unwrap();
+		let mut loaded = World::load(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		let emitters = |world: &World| world.emitters().iter().map(save_emitter).collect::<Vec<_>>();
+		assert_eq!(emitters(&world), emitters(&loaded));
+		// nor is the id of one removed before the save handed out again
+		assert!(loaded.add_emitter(Position::new(0., 0.)) > added);
+	}
+
+	#[test]
+	fn truncated_save_is_rejected() {
+		let world = World::new(gen::GenePool::for_tests(), 11);
+		let path = ::std::env::temp_dir().join("rust-oids-truncated-test.json");
+		world.save(&path).unwrap();
+		let mut data = Vec::new();
+		fs::File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+		let len = data.len();
+		fs::File::create(&path).unwrap().write_all(&data[..len / 2]).unwrap();
+		assert!(World::load(&path).is_err());
+		fs::remove_file(&path).unwrap();
+	}
 }
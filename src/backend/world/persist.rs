@@ -0,0 +1,80 @@
+//! Versioned save files.
+//! A save starts with a `#rust-oids <version>` line, files without it predate versioning
+//! and are read as version 1. Loaders upgrade older layouts to the current one.
+use std::fmt;
+use std::io;
+use std::str;
+use std::result;
+
+pub type SaveVersion = u32;
+
+pub const CURRENT_VERSION: SaveVersion = 6;
+
+const MAGIC: &'static str = "#rust-oids";
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Format(String),
+	Unsupported(SaveVersion),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			&Error::Io(ref e) => write!(f, "I/O error: {}", e),
+			&Error::Format(ref e) => write!(f, "malformed save: {}", e),
+			&Error::Unsupported(version) => {
+				write!(f,
+				       "save version {} is not supported (current is {})",
+				       version,
+				       CURRENT_VERSION)
+			}
+		}
+	}
+}
+
+/// Error for a field absent from, or of the wrong type in, a structured save
+pub fn missing(field: &str) -> Error {
+	Error::Format(format!("missing or invalid field \"{}\"", field))
+}
+
+pub fn header(version: SaveVersion) -> String {
+	format!("{} {}\n", MAGIC, version)
+}
+
+/// Strips the version header, returning the version and the remaining payload
+pub fn split_header(data: &[u8]) -> Result<(SaveVersion, &[u8])> {
+	if !data.starts_with(MAGIC.as_bytes()) {
+		return Ok((1, data));
+	}
+	let end = data.iter().position(|&c| c == b'\n').unwrap_or(data.len());
+	let line = try!(str::from_utf8(&data[..end]).map_err(|e| Error::Format(e.to_string())));
+	let version = try!(line[MAGIC.len()..]
+		.trim()
+		.parse::<SaveVersion>()
+		.map_err(|e| Error::Format(e.to_string())));
+	if version > CURRENT_VERSION {
+		Err(Error::Unsupported(version))
+	} else {
+		Ok((version, &data[::std::cmp::min(end + 1, data.len())..]))
+	}
+}
+
+pub trait Versioned: Sized {
+	/// Parses a payload laid out as `version`, upgrading it to the current format
+	fn migrate(version: SaveVersion, data: &[u8]) -> Result<Self>;
+
+	fn load(data: &[u8]) -> Result<Self> {
+		let (version, payload) = try!(split_header(data));
+		Self::migrate(version, payload)
+	}
+}
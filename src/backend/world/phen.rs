@@ -14,16 +14,26 @@ use backend::world::gen::*;
 use cgmath;
 use cgmath::EuclideanVector;
 
+/// thickness of every segment, as a fraction of its radius, so that its rim catches the light
+const SEGMENT_EXTRUSION: f32 = 0.1;
+
 pub trait Phenotype {
-	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32) -> agent::Agent;
+	/// Grows an agent from `gen`, with no more than `max_segments` segments however the genome reads
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent;
 }
 
 pub struct Resource {}
 pub struct Minion {}
 pub struct Spore {}
+pub struct Prop {}
+pub struct Bullet {}
 
 impl Phenotype for Resource {
-	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32) -> agent::Agent {
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent {
 		gen.next_integer::<u8>(0, 3);
 		let albedo = color::YPbPr::new(0.5, gen.next_float(-0.5, 0.5), gen.next_float(-0.5, 0.5));
 		let body = gen.eq_triangle();
@@ -31,43 +41,46 @@ impl Phenotype for Resource {
 		                                    Material { density: 1.0, ..Default::default() },
 		                                    Livery { albedo: albedo.to_rgba(), ..Default::default() },
 		                                    gen.dna(),
-		                                    segment::State::with_charge(charge, 0., charge));
-		builder.start(transform, motion, &body).build()
+		                                    segment::State::with_charge(charge, 0., charge, segment::DEFAULT_TAU));
+		builder.max_segments(max_segments).start(transform, motion, &body).build()
 	}
 }
 
 impl Phenotype for Minion {
-	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32) -> agent::Agent {
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent {
 		let gender = gen.next_integer::<u8>(0, 3);
-		let tint = gen.next_float(0., 1.);
-		let albedo = color::Hsl::new(tint, 0.5, 0.5);
+		// the tint the genome used to spell out, still read so that the rest reads the same; the lineage tints now
+		gen.next_float(0., 1.);
+		let albedo = color::Hsl::new(gen.hue(), 0.5, 0.5);
 		let mut builder = AgentBuilder::new(id,
 		                                    Material { density: 0.2, ..Default::default() },
 		                                    Livery { albedo: albedo.to_rgba(), ..Default::default() },
 		                                    gen.dna(),
-		                                    segment::State::with_charge(0., charge, charge));
-		builder.gender(gender);
+		                                    segment::State::with_charge(0., charge, charge, segment::DEFAULT_TAU));
+		builder.gender(gender).max_segments(max_segments);
 
-		// personality parameters
-		let mut weights_in = [[0.; N_WEIGHTS]; N_WEIGHTS];
-		let mut weights_hidden = [[0.; N_WEIGHTS]; N_WEIGHTS];
-		let mut weights_out = [[0.; N_WEIGHTS]; N_WEIGHTS];
+		// personality parameters, the genome encodes the network of the default spec weight by weight,
+		// the layers interleaved
+		let mut layers = agent::BrainSpec::default()
+			.layers()
+			.iter()
+			.map(|&(inputs, neurons)| vec![vec![0.; inputs]; neurons])
+			.collect::<Vec<_>>();
 		for i in 0..N_WEIGHTS {
 			for j in 0..N_WEIGHTS {
-				weights_in[i][j] = gen.next_float(-4., 4.);
-				weights_hidden[i][j] = gen.next_float(-4., 4.);
-				weights_out[i][j] = gen.next_float(-4., 4.);
+				for layer in layers.iter_mut() {
+					layer[i][j] = gen.next_float(agent::WEIGHT_RANGE.0, agent::WEIGHT_RANGE.1);
+				}
 			}
 		}
-		builder.hunger(&gen.next_float(0., 0.9))
-			.haste(&gen.next_float(0., 0.9))
-			.prudence(&gen.next_float(0., 0.9))
-			.fear(&gen.next_float(0.1, 5.))
-			.rest(&gen.next_float(0.2, 1.))
-			.thrust(&gen.next_float(0.2, 1.))
-			.weights_in(&weights_in)
-			.weights_hidden(&weights_hidden)
-			.weights_out(&weights_out);
+		builder.hunger(&gen.next_float(agent::HUNGER_RANGE.0, agent::HUNGER_RANGE.1))
+			.haste(&gen.next_float(agent::HASTE_RANGE.0, agent::HASTE_RANGE.1))
+			.prudence(&gen.next_float(agent::PRUDENCE_RANGE.0, agent::PRUDENCE_RANGE.1))
+			.fear(&gen.next_float(agent::FEAR_RANGE.0, agent::FEAR_RANGE.1))
+			.rest(&gen.next_float(agent::REST_RANGE.0, agent::REST_RANGE.1))
+			.thrust(&gen.next_float(agent::THRUST_RANGE.0, agent::THRUST_RANGE.1));
 		// body plan and shape
 		let torso_shape = gen.any_poly();
 		let torso = builder.start(transform, motion, &torso_shape).index();
@@ -77,7 +90,7 @@ impl Phenotype for Minion {
 		builder.addr(torso, i, &gen.star(), ARM | JOINT | ACTUATOR | RUDDER)
 			.addl(torso, -i, &gen.star(), ARM | JOINT | ACTUATOR | RUDDER);
 
-		let head = builder.add(torso, 0, &head_shape, HEAD | MOUTH | SENSOR | TRACKER).index();
+		let head = builder.add(torso, 0, &head_shape, HEAD | MOUTH | SENSOR | TRACKER | WEAPON).index();
 		builder.addr(head, 1, &gen.triangle(), HEAD | ACTUATOR | RUDDER)
 			.addl(head, -1, &gen.triangle(), HEAD | ACTUATOR | RUDDER);
 
@@ -103,23 +116,63 @@ impl Phenotype for Minion {
 		let leg_shape = gen.star();
 		builder.addr(belly, belly_mid - 1, &leg_shape, LEG | ACTUATOR | THRUSTER)
 			.addl(belly, 1 - belly_mid, &leg_shape, LEG | ACTUATOR | THRUSTER)
-			.add(belly, belly_mid, &tail_shape, TAIL | ACTUATOR | BRAKE)
-			.build()
+			.add(belly, belly_mid, &tail_shape, TAIL | ACTUATOR | BRAKE);
+		// surface, read last so that existing genomes keep their body plan
+		builder.friction(gen.next_float(0.2, 1.2))
+			.restitution(gen.next_float(0.1, 0.9))
+			.tau(gen.next_float(0.5, 4.));
+		// and the senses added later, after all of it
+		for row in layers[0].iter_mut() {
+			for w in row[N_WEIGHTS..].iter_mut() {
+				*w = gen.next_float(agent::WEIGHT_RANGE.0, agent::WEIGHT_RANGE.1);
+			}
+		}
+		builder.network(layers).build()
 	}
 }
 
 impl Phenotype for Spore {
-	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32) -> agent::Agent {
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent {
 		let gender = gen.next_integer::<u8>(0, 3);
-		let tint = gen.next_float(0., 1.);
-		let albedo = color::Hsl::new(tint, 0.5, 0.5);
+		gen.next_float(0., 1.);
+		let albedo = color::Hsl::new(gen.hue(), 0.5, 0.5);
 
 		let mut builder = AgentBuilder::new(id,
 		                                    Material { density: 0.5, ..Default::default() },
 		                                    Livery { albedo: albedo.to_rgba(), ..Default::default() },
 		                                    gen.dna(),
-		                                    segment::State::with_charge(0., charge, charge));
-		builder.gender(gender).start(transform, motion, &gen.ball()).build()
+		                                    segment::State::with_charge(0., charge, charge, segment::DEFAULT_TAU));
+		builder.gender(gender).max_segments(max_segments).start(transform, motion, &gen.ball()).build()
+	}
+}
+
+impl Phenotype for Prop {
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent {
+		let albedo = color::YPbPr::new(0.2, gen.next_float(-0.1, 0.1), gen.next_float(-0.1, 0.1));
+		let body = Shape::new_box(gen.next_float(1., 3.), gen.next_float(0.25, 1.));
+		let mut builder = AgentBuilder::new(id,
+		                                    Material { density: 10.0, ..Default::default() },
+		                                    Livery { albedo: albedo.to_rgba(), ..Default::default() },
+		                                    gen.dna(),
+		                                    segment::State::with_charge(charge, 0., charge, segment::DEFAULT_TAU));
+		builder.max_segments(max_segments).start(transform, motion, &body).build()
+	}
+}
+
+impl Phenotype for Bullet {
+	fn develop(gen: &mut Genome, id: Id, transform: &Transform, motion: Option<&Motion>, charge: f32,
+	           max_segments: usize)
+	           -> agent::Agent {
+		let mut builder = AgentBuilder::new(id,
+		                                    Material { density: 4.0, ..Default::default() },
+		                                    Livery { albedo: [1., 0.9, 0.6, 1.], ..Default::default() },
+		                                    gen.dna(),
+		                                    segment::State::with_charge(charge, 0., charge, segment::DEFAULT_TAU));
+		builder.max_segments(max_segments).start(transform, motion, &Shape::new_ball(0.2)).build()
 	}
 }
 
@@ -132,6 +185,7 @@ pub struct AgentBuilder {
 	dna: Dna,
 	state: segment::State,
 	segments: Vec<Segment>,
+	max_segments: usize,
 }
 
 impl AgentBuilder {
@@ -145,6 +199,7 @@ impl AgentBuilder {
 			brain: Brain::default(),
 			dna: dna.clone(),
 			segments: Vec::new(),
+			max_segments: usize::max_value(),
 		}
 	}
 
@@ -166,6 +221,13 @@ impl AgentBuilder {
 		self
 	}
 
+	/// Segments past this count are cut when building, so that no genome can grow a giant; the head never is
+	#[inline]
+	pub fn max_segments(&mut self, max_segments: usize) -> &mut Self {
+		self.max_segments = max_segments;
+		self
+	}
+
 	#[inline]
 	pub fn add(&mut self, parent_index: SegmentIndex, attachment_index_offset: isize, shape: &Shape,
 	           flags: segment::Flags)
@@ -228,6 +290,32 @@ impl AgentBuilder {
 		}
 	}
 
+	/// Applies to the segments added so far and to any added later
+	pub fn friction(&mut self, friction: f32) -> &mut Self {
+		self.material.friction = friction;
+		for segment in self.segments.iter_mut() {
+			segment.material.friction = friction;
+		}
+		self
+	}
+
+	pub fn restitution(&mut self, restitution: f32) -> &mut Self {
+		self.material.restitution = restitution;
+		for segment in self.segments.iter_mut() {
+			segment.material.restitution = restitution;
+		}
+		self
+	}
+
+	/// How quickly the charge of every segment follows its target
+	pub fn tau(&mut self, tau: f32) -> &mut Self {
+		self.state.set_tau(tau);
+		for segment in self.segments.iter_mut() {
+			segment.state.set_tau(tau);
+		}
+		self
+	}
+
 	pub fn hunger(&mut self, value: &<Brain as TypedBrain>::Parameter) -> &mut Self {
 		self.brain.hunger = value.clone();
 		self
@@ -258,18 +346,11 @@ impl AgentBuilder {
 		self
 	}
 
-	pub fn weights_in(&mut self, weights_in: &<Brain as TypedBrain>::WeightMatrix) -> &mut Self {
-		self.brain.weights_in = weights_in.clone();
-		self
-	}
-
-	pub fn weights_hidden(&mut self, weights_hidden: &<Brain as TypedBrain>::WeightMatrix) -> &mut Self {
-		self.brain.weights_hidden = weights_hidden.clone();
-		self
-	}
-
-	pub fn weights_out(&mut self, weights_out: &<Brain as TypedBrain>::WeightMatrix) -> &mut Self {
-		self.brain.weights_out = weights_out.clone();
+	/// The weights of every layer, input layer first; layers that don't fit together leave the network as it was
+	pub fn network(&mut self, layers: Vec<<Brain as TypedBrain>::Weights>) -> &mut Self {
+		if !self.brain.set_layers(layers) {
+			warn!("Agent {:?} got a network whose layers don't fit together", self.id);
+		}
 		self
 	}
 
@@ -280,7 +361,7 @@ impl AgentBuilder {
 			index: self.segments.len() as SegmentIndex,
 			transform: transform.clone(),
 			motion: motion.map(|m| m.clone()),
-			mesh: Mesh::from_shape(shape.clone(), winding),
+			mesh: Mesh::from_shape(shape.clone(), winding).with_extrusion(SEGMENT_EXTRUSION),
 			material: self.material.clone(),
 			livery: self.livery.clone(),
 			state: self.state.clone(),
@@ -295,6 +376,133 @@ impl AgentBuilder {
 		           self.gender,
 		           &self.brain,
 		           &self.dna,
-		           self.segments.clone().into_boxed_slice())
+		           self.capped_segments().into_boxed_slice())
+	}
+
+	/// The head and whatever holds it on go first, from the root out, then the other segments in the order
+	/// they were added, as long as there is room and their parent stayed too; a head hanging off a chain longer
+	/// than the cap is cut with the rest of it. The survivors are renumbered and relinked
+	fn capped_segments(&self) -> Vec<Segment> {
+		let mut kept = vec![false; self.segments.len()];
+		let mut room = self.max_segments;
+		for head in self.segments.iter().filter(|s| s.flags.contains(segment::HEAD)) {
+			let mut chain = Vec::new();
+			let mut link = Some(head);
+			while let Some(segment) = link {
+				chain.push(segment.index as usize);
+				link = segment.attached_to.as_ref().map(|a| &self.segments[a.index as usize]);
+			}
+			for &i in chain.iter().rev().filter(|&&i| !kept[i]) {
+				if room == 0 {
+					break;
+				}
+				kept[i] = true;
+				room -= 1;
+			}
+		}
+		for segment in self.segments.iter() {
+			let i = segment.index as usize;
+			let parent_kept = segment.attached_to.as_ref().map_or(true, |a| kept[a.index as usize]);
+			if !kept[i] && parent_kept && room > 0 {
+				kept[i] = true;
+				room -= 1;
+			}
+		}
+		if kept.iter().any(|k| !*k) {
+			debug!("Agent {:?} reached its cap of {} segments, cutting {} of {}",
+			       self.id,
+			       self.max_segments,
+			       kept.iter().filter(|k| !**k).count(),
+			       self.segments.len());
+		}
+		let mut renumbered = vec![0 as SegmentIndex; self.segments.len()];
+		let mut segments = Vec::new();
+		for segment in self.segments.iter().filter(|s| kept[s.index as usize]) {
+			renumbered[segment.index as usize] = segments.len() as SegmentIndex;
+			let mut segment = segment.clone();
+			segment.index = segments.len() as SegmentIndex;
+			if let Some(ref mut attachment) = segment.attached_to {
+				attachment.index = renumbered[attachment.index as usize];
+			}
+			segments.push(segment);
+		}
+		segments
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn builder_stops_at_max_segments() {
+		let dna: Dna = vec![0u8; 16].into_boxed_slice();
+		let mut builder = AgentBuilder::new(1 << 8,
+		                                    Material::default(),
+		                                    Livery::default(),
+		                                    &dna,
+		                                    segment::State::default());
+		builder.max_segments(3).start(&Transform::default(), None, &Shape::new_ball(1.));
+		for _ in 0..5 {
+			builder.add(0, 0, &Shape::new_ball(1.), ARM);
+		}
+		assert_eq!(3, builder.build().segments().len());
+	}
+
+	#[test]
+	fn the_cap_cuts_a_head_chain_longer_than_itself() {
+		let dna: Dna = vec![0u8; 16].into_boxed_slice();
+		let mut builder = AgentBuilder::new(1 << 8,
+		                                    Material::default(),
+		                                    Livery::default(),
+		                                    &dna,
+		                                    segment::State::default());
+		builder.max_segments(3).start(&Transform::default(), None, &Shape::new_ball(1.));
+		for i in 0..5 {
+			builder.add(i, 0, &Shape::new_ball(1.), if i == 4 { segment::HEAD } else { ARM });
+		}
+		let capped = builder.build();
+		assert_eq!(3, capped.segments().len());
+		assert!(capped.first_segment(segment::HEAD).is_none());
+		for (i, segment) in capped.segments().iter().enumerate() {
+			assert_eq!(i, segment.index as usize);
+			assert_eq!(i.checked_sub(1), segment.attached_to.as_ref().map(|a| a.index as usize));
+		}
+	}
+
+	#[test]
+	fn default_minion_fits_the_default_cap() {
+		use backend::config::SimConfig;
+		let cap = SimConfig::default().max_segments;
+		let mut genome = GenePool::for_tests().next();
+		let minion = Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., cap);
+		assert!(minion.segments().len() < cap);
+		let mut genome = GenePool::for_tests().next();
+		let capped = Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., 4);
+		assert_eq!(4, capped.segments().len());
+	}
+
+	#[test]
+	fn the_cap_keeps_the_head_and_relinks_the_survivors() {
+		let mut genome = GenePool::for_tests().next();
+		let capped = Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., 2);
+		assert_eq!(2, capped.segments().len());
+		assert!(capped.first_segment(segment::HEAD).is_some());
+		for (i, segment) in capped.segments().iter().enumerate() {
+			assert_eq!(i, segment.index as usize);
+			if let Some(ref attachment) = segment.attached_to {
+				assert!((attachment.index as usize) < i);
+			}
+		}
+	}
+
+	#[test]
+	fn minions_and_their_spores_wear_the_hue_of_their_dna() {
+		let mut genome = GenePool::for_tests().next();
+		let albedo = color::Hsl::new(genome.hue(), 0.5, 0.5).to_rgba();
+		let minion = Minion::develop(&mut genome, 1 << 8, &Transform::default(), None, 1., 32);
+		let spore = Spore::develop(&mut genome, 2 << 8, &Transform::default(), None, 1., 32);
+		assert_eq!(albedo, minion.segments()[0].livery.albedo);
+		assert_eq!(albedo, spore.segments()[0].livery.albedo);
 	}
 }
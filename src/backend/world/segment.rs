@@ -1,6 +1,8 @@
 use backend::obj;
 use backend::obj::*;
 use backend::world::agent;
+use backend::config::ChargeConfig;
+use core::color::ColorRamp;
 use core::math;
 use core::math::Smooth;
 use core::geometry::*;
@@ -13,6 +15,26 @@ pub enum Intent {
 	RunAway(Position),
 }
 
+/// How far a charge ramp is pushed over display white, so that charged segments still bloom
+const RAMP_BRIGHTNESS: f32 = 2.5;
+
+/// Time constant of the charge response, in seconds
+pub const DEFAULT_TAU: f32 = 2.;
+
+/// Share of its full radius a segment is drawn at when born
+const NEWBORN_SIZE: f32 = 0.25;
+
+/// Where a segment is in its charge cycle
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+	/// heading for the target charge
+	Charging,
+	/// holding at the target for the seconds left
+	Refractory(f32),
+	/// on its way back to the recharge level
+	Ramping,
+}
+
 #[derive(Clone)]
 pub struct State {
 	age_seconds: f32,
@@ -21,8 +43,13 @@ pub struct State {
 	target_charge: f32,
 	recharge: f32,
 	smooth: math::Exponential<f32, f32>,
+	cycle: ChargeConfig,
+	phase: Phase,
+	ramp: math::Linear<f32>,
 	pub intent: Intent,
 	pub last_touched: Option<agent::Key>,
+	/// Normal impulse of the `last_touched` contact, zero when nothing was touched
+	pub last_impulse: f32,
 }
 
 impl Default for State {
@@ -33,9 +60,13 @@ impl Default for State {
 			charge: 1.,
 			target_charge: 0.,
 			recharge: 1.,
-			smooth: math::Exponential::new(1., 1., 2.),
+			smooth: math::Exponential::new(1., 1., DEFAULT_TAU),
+			cycle: ChargeConfig::default(),
+			phase: Phase::Charging,
+			ramp: math::Linear::new(1., 1., 0.),
 			intent: Intent::Idle,
 			last_touched: None,
+			last_impulse: 0.,
 		}
 	}
 }
@@ -45,31 +76,102 @@ impl State {
 		self.charge
 	}
 
+	/// Sets the charge right away, starting the cycle over from it
 	pub fn set_charge(&mut self, charge: f32) {
 		self.charge = charge;
 		self.smooth.reset(self.charge);
+		self.phase = Phase::Charging;
 	}
 
 	pub fn set_target_charge(&mut self, target_charge: f32) {
 		self.target_charge = target_charge;
 	}
 
+	pub fn get_target_charge(&self) -> f32 {
+		self.target_charge
+	}
+
+	/// The charge the segment starts over from once it reaches its target
+	pub fn get_recharge(&self) -> f32 {
+		self.recharge
+	}
+
+	pub fn set_recharge(&mut self, recharge: f32) {
+		self.recharge = recharge;
+	}
+
+	/// How far the segment has grown, from 0 when born to 1 once `growth_time` seconds old, or at once without one
+	pub fn growth(&self, growth_time: f32) -> f32 {
+		if growth_time > 0. { (self.age_seconds / growth_time).min(1.) } else { 1. }
+	}
+
+	pub fn age_seconds(&self) -> f32 {
+		self.age_seconds
+	}
+
+	/// Puts the age back where a save left it, so that loaded agents keep on growing from there
+	pub fn set_age_seconds(&mut self, age_seconds: f32) {
+		self.age_seconds = age_seconds;
+	}
+
+	pub fn set_cycle(&mut self, cycle: ChargeConfig) {
+		self.cycle = cycle;
+	}
+
+	/// Whether the charge has reached its target and is on its way back to the recharge level,
+	/// either holding for the refractory delay or ramping
+	pub fn is_recharging(&self) -> bool {
+		self.phase != Phase::Charging
+	}
+
+	/// The charge closes in on its target with the time constant `tau`, and once there starts over from the
+	/// recharge level; with no refractory delay nor ramp that makes for a sawtooth, one tooth every
+	/// `tau * ln(|target - recharge| / 0.001)` seconds
 	pub fn update(&mut self, dt: f32) {
 		self.age_seconds += dt;
 		self.age_frames += 1;
-		self.charge = self.smooth.dt(dt).smooth(self.target_charge);
-		if (self.charge - self.target_charge).abs() < 0.001 {
+		match self.phase {
+			Phase::Charging => {
+				self.charge = self.smooth.dt(dt).smooth(self.target_charge);
+				if (self.charge - self.target_charge).abs() < 0.001 {
+					self.phase = Phase::Refractory(self.cycle.refractory);
+				}
+			}
+			Phase::Refractory(left) => self.phase = Phase::Refractory(left - dt),
+			Phase::Ramping => self.charge = self.ramp.dt(dt).smooth(self.recharge),
+		}
+		if let Phase::Refractory(left) = self.phase {
+			if left <= 0. {
+				self.start_over(dt);
+			}
+		}
+		if self.phase == Phase::Ramping && (self.charge - self.recharge).abs() < 0.001 {
+			let reset = self.recharge;
+			self.set_charge(reset);
+		}
+	}
+
+	fn start_over(&mut self, dt: f32) {
+		if self.cycle.ramp > 0. {
+			let rate = (self.charge - self.recharge).abs() / self.cycle.ramp;
+			self.ramp = math::Linear::new(self.charge, dt, rate);
+			self.phase = Phase::Ramping;
+		} else {
 			let reset = self.recharge;
 			self.set_charge(reset);
 		}
 	}
 
-	pub fn with_charge(initial: f32, target: f32, recharge: f32) -> Self {
+	pub fn set_tau(&mut self, tau: f32) {
+		self.smooth.tau(tau);
+	}
+
+	pub fn with_charge(initial: f32, target: f32, recharge: f32, tau: f32) -> Self {
 		State {
 			charge: initial,
 			target_charge: target,
 			recharge: recharge,
-			smooth: math::Exponential::new(initial, 1., 2.),
+			smooth: math::Exponential::new(initial, 1., tau),
 			..Self::default()
 		}
 	}
@@ -100,6 +202,7 @@ bitflags! {
 		const THRUSTER     = 0x10000u32,
 		const RUDDER       = 0x20000u32,
 		const BRAKE        = 0x40000u32,
+		const WEAPON       = 0x80000u32,
 	}
 }
 
@@ -124,13 +227,53 @@ impl Segment {
 			attachment_point: if attachment_point < max { attachment_point } else { max - 1 },
 		})
 	}
+
+	/// The shape drawn `growth` of the way from a newborn to the full size, the body keeps the full one
+	pub fn grown_shape(&self, growth: f32) -> Shape {
+		let shape = &self.mesh.shape;
+		shape.with_radius(shape.radius() * NEWBORN_SIZE).lerp(shape, growth)
+	}
+
+	/// The livery brightened by the charge, or with a ramp the charge read off it instead
+	pub fn color_with(&self, ramp: Option<&ColorRamp>) -> Rgba {
+		let alpha = self.livery.albedo[3] * self.material.density;
+		match ramp {
+			Some(ramp) => {
+				let c = ramp.sample(self.state.charge);
+				[c[0] * RAMP_BRIGHTNESS, c[1] * RAMP_BRIGHTNESS, c[2] * RAMP_BRIGHTNESS, alpha]
+			}
+			None => {
+				let rgba = self.livery.albedo;
+				let c = 5. * ((self.state.charge * 0.99) + 0.01);
+				[rgba[0] * c, rgba[1] * c, rgba[2] * c, alpha]
+			}
+		}
+	}
+}
+
+impl Segment {
+	/// The box around the shape as placed by the transform, whichever way it's turned
+	pub fn bounds(&self) -> Rect {
+		let radius = self.mesh.shape.radius();
+		let p = self.transform.position;
+		Rect::new(p.x - radius, p.y - radius, p.x + radius, p.y + radius)
+	}
+
+	/// Whether the world point `p` falls within the shape, as placed by the transform
+	pub fn contains(&self, p: Position) -> bool {
+		let radius = self.mesh.shape.radius();
+		let d = p - self.transform.position;
+		match self.mesh.shape {
+			Shape::Ball { .. } => d.x * d.x + d.y * d.y <= radius * radius,
+			// into the mesh's own frame, where the vertices are laid out for a unit radius
+			_ => polygon_contains(&self.mesh.vertices, self.transform.inverse_transform_point(p) / radius),
+		}
+	}
 }
 
 impl obj::Drawable for Segment {
 	fn color(&self) -> Rgba {
-		let rgba = self.livery.albedo;
-		let c = 5. * ((self.state.charge * 0.99) + 0.01);
-		[rgba[0] * c, rgba[1] * c, rgba[2] * c, rgba[3] * self.material.density]
+		self.color_with(None)
 	}
 }
 
@@ -160,3 +303,169 @@ impl obj::Solid for Segment {
 		&self.livery
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn contains_follows_the_transform() {
+		let mut segment = Segment {
+			transform: Transform::new(Position::new(10., 0.), 0.),
+			motion: None,
+			index: 0,
+			mesh: Mesh::from_shape(Shape::new_box(2., 0.25), Winding::CW),
+			material: Material::default(),
+			livery: Livery::default(),
+			attached_to: None,
+			state: State::default(),
+			flags: Flags::empty(),
+		};
+		// two units tall and half a unit wide either side of the middle
+		assert!(segment.contains(Position::new(10., 1.9)));
+		assert!(!segment.contains(Position::new(11., 0.)));
+		segment.transform.angle = ::std::f32::consts::PI / 2.;
+		assert!(!segment.contains(Position::new(10., 1.9)));
+		assert!(segment.contains(Position::new(11.9, 0.)));
+	}
+
+	#[test]
+	fn smaller_tau_converges_faster() {
+		let mut twitchy = State::with_charge(0., 1., 0., 0.5);
+		let mut sluggish = State::with_charge(0., 1., 0., 4.);
+		for _ in 0..10 {
+			twitchy.update(0.05);
+			sluggish.update(0.05);
+		}
+		assert!(twitchy.get_charge() > sluggish.get_charge());
+	}
+
+	const DT: f32 = 1. / 60.;
+
+	/// Seconds between the second and the third time the charge is back at its recharge level
+	fn period(mut state: State) -> f32 {
+		let mut resets = Vec::new();
+		let mut last = state.get_charge();
+		let mut ticks = 0;
+		while resets.len() < 3 {
+			state.update(DT);
+			ticks += 1;
+			assert!(ticks < 100000, "the charge never started over");
+			let charge = state.get_charge();
+			if charge == state.get_recharge() && last != charge {
+				resets.push(ticks);
+			}
+			last = charge;
+		}
+		(resets[2] - resets[1]) as f32 * DT
+	}
+
+	fn sawtooth(tau: f32, span: f32) -> f32 {
+		tau * (span / 0.001).ln()
+	}
+
+	#[test]
+	fn the_charge_cycle_lasts_longer_with_tau_and_the_span_to_cover() {
+		for &(tau, target, recharge) in &[(0.5, 1., 0.), (2., 1., 0.), (0.5, 0.6, 0.5), (1., 0.2, 0.9)] {
+			let period = period(State::with_charge(recharge, target, recharge, tau));
+			assert!((period - sawtooth(tau, (target - recharge).abs())).abs() < DT * 1.5,
+			        "tau {} from {} to {} took {}",
+			        tau,
+			        recharge,
+			        target,
+			        period);
+		}
+	}
+
+	#[test]
+	fn refractory_delay_holds_the_charge_at_the_target() {
+		let mut state = State::with_charge(0., 1., 0., 0.5);
+		state.set_cycle(ChargeConfig {
+			refractory: 0.5,
+			ramp: 0.,
+		});
+		assert!((period(state.clone()) - (sawtooth(0.5, 1.) + 0.5)).abs() < DT * 2.);
+		while !state.is_recharging() {
+			state.update(DT);
+		}
+		for _ in 0..20 {
+			state.update(DT);
+			assert!(state.is_recharging());
+			assert!((state.get_charge() - 1.).abs() < 0.001);
+		}
+		// an explicit charge starts over
+		state.set_charge(0.5);
+		assert!(!state.is_recharging());
+	}
+
+	#[test]
+	fn a_ramp_takes_the_charge_back_without_a_jump() {
+		const RAMP: f32 = 0.25;
+		let mut state = State::with_charge(0., 1., 0., 0.5);
+		state.set_cycle(ChargeConfig {
+			refractory: 0.,
+			ramp: RAMP,
+		});
+		assert!((period(state.clone()) - (sawtooth(0.5, 1.) + RAMP)).abs() < DT * 2.);
+		while !state.is_recharging() {
+			state.update(DT);
+		}
+		let mut last = state.get_charge();
+		while state.is_recharging() {
+			state.update(DT);
+			assert!(last - state.get_charge() <= DT / RAMP + 1e-4);
+			last = state.get_charge();
+		}
+		assert_eq!(0., state.get_charge());
+	}
+
+	#[test]
+	fn segments_grow_with_age() {
+		let mut segment = Segment {
+			transform: Transform::default(),
+			motion: None,
+			index: 0,
+			mesh: Mesh::from_shape(Shape::new_box(2., 0.5), Winding::CW),
+			material: Material::default(),
+			livery: Livery::default(),
+			attached_to: None,
+			state: State::default(),
+			flags: Flags::empty(),
+		};
+		assert_eq!(0., segment.state.growth(1.));
+		assert_eq!(1., segment.state.growth(0.));
+		assert_eq!(Shape::new_box(2. * NEWBORN_SIZE, 0.5), segment.grown_shape(0.));
+		for _ in 0..5 {
+			segment.state.update(0.1);
+		}
+		assert!((segment.state.growth(1.) - 0.5).abs() < 1e-5);
+		assert!(segment.grown_shape(0.5).radius() < 2.);
+		for _ in 0..10 {
+			segment.state.update(0.1);
+		}
+		assert_eq!(1., segment.state.growth(1.));
+		assert_eq!(segment.mesh.shape, segment.grown_shape(1.));
+	}
+
+	#[test]
+	fn charge_ramp_replaces_the_livery() {
+		let mut segment = Segment {
+			transform: Transform::default(),
+			motion: None,
+			index: 0,
+			mesh: Mesh::from_shape(Shape::new_ball(1.), Winding::CW),
+			material: Material::default(),
+			livery: Livery::default(),
+			attached_to: None,
+			state: State::default(),
+			flags: Flags::empty(),
+		};
+		let ramp = ColorRamp::named("grey").unwrap();
+		segment.state.set_charge(0.);
+		assert_eq!([0., 0., 0.], segment.color_with(Some(&ramp))[..3]);
+		segment.state.set_charge(1.);
+		assert_eq!([RAMP_BRIGHTNESS; 3], segment.color_with(Some(&ramp))[..3]);
+		// the alpha stays that of the livery either way
+		assert_eq!(segment.color()[3], segment.color_with(Some(&ramp))[3]);
+	}
+}
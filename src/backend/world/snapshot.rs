@@ -0,0 +1,92 @@
+//! Segment transforms from the last two simulation steps, so that frames drawn in between can blend them.
+use std::collections::HashMap;
+use std::mem;
+use core::geometry::*;
+use backend::obj::Identified;
+use backend::world::World;
+use backend::world::agent;
+
+/// Linear blend from `prev` at `alpha` 0 to `curr` at `alpha` 1.
+/// Angles are blended as they are, physics keeps them continuous rather than wrapping them.
+pub fn interpolate(prev: &Transform, curr: &Transform, alpha: f32) -> Transform {
+	Transform::new(prev.position + (curr.position - prev.position) * alpha,
+	               prev.angle + (curr.angle - prev.angle) * alpha)
+}
+
+type Transforms = HashMap<agent::Key, Transform>;
+
+#[derive(Default)]
+pub struct WorldSnapshot {
+	previous: Transforms,
+	current: Transforms,
+}
+
+impl WorldSnapshot {
+	/// Called after every step: the current transforms become the previous ones and the world's are taken as current
+	pub fn advance(&mut self, world: &World) {
+		mem::swap(&mut self.previous, &mut self.current);
+		self.current.clear();
+		for (_, agent) in world.all_agents() {
+			for segment in agent.segments() {
+				self.current.insert(agent::Key::with_segment(agent.id(), segment.index), segment.transform.clone());
+			}
+		}
+	}
+
+	/// Forgets both steps, e.g. when the world is replaced and ids start over
+	pub fn clear(&mut self) {
+		self.previous.clear();
+		self.current.clear();
+	}
+
+	/// Where a segment should be drawn `alpha` of the way from the previous step to the current one.
+	/// Segments that appeared in the current step are drawn where they are, those never captured give `None`.
+	pub fn transform_of(&self, key: agent::Key, alpha: f32) -> Option<Transform> {
+		self.current.get(&key).map(|curr| match self.previous.get(&key) {
+			Some(prev) => interpolate(prev, curr, alpha),
+			None => curr.clone(),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::*;
+	use backend::world::World;
+	use backend::world::agent;
+	use backend::world::gen;
+
+	#[test]
+	fn interpolate_blends_linearly() {
+		let prev = Transform::new(Position::new(1., 2.), 0.5);
+		let curr = Transform::new(Position::new(3., -2.), 1.5);
+		let at = |alpha| {
+			let t = interpolate(&prev, &curr, alpha);
+			(t.position.x, t.position.y, t.angle)
+		};
+		assert_eq!((1., 2., 0.5), at(0.));
+		assert_eq!((3., -2., 1.5), at(1.));
+		assert_eq!((2., 0., 1.), at(0.5));
+		assert_eq!((1.5, 1., 0.75), at(0.25));
+	}
+
+	#[test]
+	fn snapshot_blends_the_last_two_steps() {
+		let mut world = World::new(gen::GenePool::for_tests(), 0);
+		let id = world.new_minion(Position::new(0., 0.), None);
+		let key = agent::Key::with_segment(id, 0);
+		let mut snapshot = WorldSnapshot::default();
+		assert!(snapshot.transform_of(key, 0.5).is_none());
+
+		snapshot.advance(&world);
+		let start = world.agent(id).unwrap().segments()[0].transform.position;
+		assert_eq!(start, snapshot.transform_of(key, 0.).unwrap().position);
+
+		world.agent_mut(id).unwrap().segments_mut()[0].transform.position = start + Position::new(10., 0.);
+		snapshot.advance(&world);
+		assert_eq!(start, snapshot.transform_of(key, 0.).unwrap().position);
+		assert_eq!(start + Position::new(5., 0.), snapshot.transform_of(key, 0.5).unwrap().position);
+		assert_eq!(start + Position::new(10., 0.), snapshot.transform_of(key, 1.).unwrap().position);
+	}
+}
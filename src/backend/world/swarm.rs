@@ -1,30 +1,89 @@
 use backend::obj::*;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use rand;
+use rand::SeedableRng;
 use core::geometry::*;
 use backend::world::phen;
 use backend::world::agent;
 use backend::world::agent::Agent;
 use backend::world::agent::AgentType;
 use backend::world::agent::TypedAgent;
+use backend::world::segment;
+use backend::world::segment::Segment;
 use backend::world::gen::*;
+use backend::config::ChargeConfig;
+use backend::spatial;
+use backend::spatial::Broadphase;
+
+/// What a swarm's rng starts from unless it's given a seed
+const DEFAULT_SEED: [u32; 4] = [0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb];
+/// cells along the longer side of the broadphase the segments are indexed in
+const INDEX_RESOLUTION: usize = 32;
 
 pub struct Swarm {
 	seq: Id,
+	rng: rand::XorShiftRng,
 	agent_type: AgentType,
 	agents: agent::AgentMap,
+	max_segments: usize,
+	/// shape of the network every newborn gets drawn at random, `None` keeps the one its genome encodes
+	brain_spec: Option<agent::BrainSpec>,
+	/// how the segments of newborns start their charge over
+	charge_cycle: ChargeConfig,
+	/// where the segments were when last indexed, along with those of the agents inserted since
+	index: Box<Broadphase>,
+	/// the largest radius of the segments indexed, how far past where it's indexed a segment may reach
+	reach: f32,
 }
 
 impl Swarm {
-	pub fn new(agent_type: AgentType) -> Swarm {
+	pub fn new(agent_type: AgentType, max_segments: usize) -> Swarm {
 		Swarm {
 			seq: 0,
+			rng: rand::XorShiftRng::from_seed(DEFAULT_SEED),
 			agent_type: agent_type,
-			agents: HashMap::new(),
+			agents: agent::AgentMap::new(),
+			max_segments: max_segments,
+			brain_spec: None,
+			charge_cycle: ChargeConfig::default(),
+			index: spatial::Kind::Grid.build(Rect::new(-1., -1., 1., 1.), 1),
+			reach: 0.,
 		}
 	}
 
-	#[allow(dead_code)]
+	/// Makes the swarm draw its randomness from `seed`, the same seed always giving the same agents
+	pub fn with_seed(mut self, seed: [u8; 16]) -> Self {
+		let mut words = [0u32; 4];
+		for (i, &b) in seed.iter().enumerate() {
+			words[i / 4] |= (b as u32) << (8 * (i % 4));
+		}
+		// xorshift must not be seeded with all zeroes
+		if words.iter().all(|&w| w == 0) {
+			words = DEFAULT_SEED;
+		}
+		self.rng = rand::XorShiftRng::from_seed(words);
+		self
+	}
+
+	/// Randomness for whatever the swarm's agents develop from
+	pub fn rng(&mut self) -> &mut rand::XorShiftRng {
+		&mut self.rng
+	}
+
+	pub fn set_max_segments(&mut self, max_segments: usize) {
+		self.max_segments = max_segments;
+	}
+
+	/// Networks drawn from the swarm's rng aren't inherited, each newborn draws its own
+	pub fn set_brain_spec(&mut self, brain_spec: Option<agent::BrainSpec>) {
+		self.brain_spec = brain_spec;
+	}
+
+	pub fn set_charge_cycle(&mut self, charge_cycle: ChargeConfig) {
+		self.charge_cycle = charge_cycle;
+	}
+
 	pub fn type_of(&self) -> AgentType {
 		self.agent_type
 	}
@@ -37,11 +96,32 @@ impl Swarm {
 		self.agents.get_mut(&id)
 	}
 
+	/// How many ids were handed out, the next one is made from this plus one
+	pub fn seq(&self) -> Id {
+		self.seq
+	}
+
+	/// Carries on handing out ids from `seq`, never going back on ids already handed out
+	pub fn resume_seq(&mut self, seq: Id) {
+		self.seq = ::std::cmp::max(self.seq, seq);
+	}
+
 	pub fn next_id(&mut self) -> Id {
 		self.seq = self.seq + 1;
 		self.seq << 8 | (self.agent_type as usize)
 	}
 
+	/// Marks the agent dead, it is freed with the others on the next sweep
+	pub fn kill(&mut self, id: Id) -> bool {
+		match self.agents.get_mut(&id) {
+			Some(agent) => {
+				agent.state.die();
+				true
+			}
+			None => false,
+		}
+	}
+
 	pub fn free_resources(&mut self, freed: &mut Vec<Agent>) {
 		let mut dead = HashSet::new();
 
@@ -65,28 +145,244 @@ impl Swarm {
 			AgentType::Minion | AgentType::Spore => info!("spawn: {} as {}", genome, id.type_of()),
 			_ => {}
 		}
-		let entity = T::develop(genome, id, transform, motion, charge);
+		let mut entity = T::develop(genome, id, transform, motion, charge, self.max_segments);
+		if let Some(ref spec) = self.brain_spec {
+			entity.brain_mut().randomize_network(spec, &mut self.rng);
+		}
+		self.start_cycle(&mut entity);
+		self.insert(entity)
+	}
+
+	/// Develops an agent under an id handed out earlier, e.g. by a saved world
+	pub fn respawn<T>(&mut self, id: Id, genome: &mut Genome, transform: &Transform, charge: f32) -> Id
+		where T: phen::Phenotype {
+		self.seq = ::std::cmp::max(self.seq, id >> 8);
+		let mut entity = T::develop(genome, id, transform, None, charge, self.max_segments);
+		self.start_cycle(&mut entity);
+		self.insert(entity)
+	}
+
+	fn start_cycle(&self, entity: &mut Agent) {
+		for segment in entity.segments_mut().iter_mut() {
+			segment.state.set_cycle(self.charge_cycle);
+		}
+	}
+
+	/// Turns a segment severed from another agent into an agent of its own
+	pub fn adopt(&mut self, dna: &Dna, segment: &Segment) -> Id {
+		let id = self.next_id();
+		let remains = Segment {
+			index: 0,
+			attached_to: None,
+			flags: segment::CORE | segment::STORAGE | segment::MIDDLE,
+			..segment.clone()
+		};
+		let entity = Agent::new(id, 0, &agent::Brain::default(), dna, vec![remains].into_boxed_slice());
 		self.insert(entity)
 	}
 
+	/// Copies an agent's body and brain under a fresh id, moved so that its core lies at `position`.
+	/// The copy shares nothing with the original, only its energy and lifecycle start afresh.
+	pub fn clone_agent(&mut self, id: Id, position: Position) -> Option<Id> {
+		let (gender, brain, dna, mut segments) = match self.agents.get(&id) {
+			Some(agent) => (agent.gender(), agent.brain().clone(), agent.dna().clone(), agent.segments().to_vec()),
+			None => return None,
+		};
+		let offset = position - segments[0].transform.position;
+		for segment in segments.iter_mut() {
+			segment.transform.position = segment.transform.position + offset;
+			segment.state.last_touched = None;
+			segment.state.last_impulse = 0.;
+		}
+		let id = self.next_id();
+		let entity = Agent::new(id, gender, &brain, &dna, segments.into_boxed_slice());
+		Some(self.insert(entity))
+	}
+
 	fn insert(&mut self, agent: Agent) -> Id {
 		let id = agent.id();
+		self.reach = self.reach.max(Self::index_segments(&mut *self.index, &agent));
 		self.agents.insert(id, agent);
 		id
 	}
+
+	/// Puts every segment of the agent in the index, giving back the largest radius among them
+	fn index_segments(index: &mut Broadphase, agent: &Agent) -> f32 {
+		let mut reach = 0f32;
+		for segment in agent.segments().iter() {
+			index.insert(agent.id(), segment.transform.position);
+			reach = reach.max(segment.mesh.shape.radius());
+		}
+		reach
+	}
+
+	/// Indexes where the segments are now in a `kind` broadphase over `extent`, once they have moved
+	pub fn reindex(&mut self, kind: spatial::Kind, extent: Rect) {
+		let mut index = kind.build(extent, INDEX_RESOLUTION);
+		let mut reach = 0f32;
+		for agent in self.agents.values() {
+			reach = reach.max(Self::index_segments(&mut *index, agent));
+		}
+		self.index = index;
+		self.reach = reach;
+	}
 	
+	/// The agent with a segment under `p`; where several overlap, the one whose segment is centred nearest
+	pub fn pick(&self, p: Position) -> Option<(Id, f32)> {
+		let mut nearest: Option<(Id, f32)> = None;
+		for (&id, agent) in &self.agents {
+			for segment in agent.segments().iter().filter(|segment| segment.contains(p)) {
+				let d = segment.transform.position - p;
+				let distance = d.x * d.x + d.y * d.y;
+				if nearest.map_or(true, |(_, nearest_distance)| distance < nearest_distance) {
+					nearest = Some((id, distance));
+				}
+			}
+		}
+		nearest
+	}
+
+	/// The agents with a segment at least partly within `rect`, e.g. to leave out those off screen.
+	/// Only those indexed near the rect are looked at, so they are only as current as the last `reindex`.
+	pub fn agents_in_rect<'a>(&'a self, rect: Rect) -> Box<Iterator<Item = &'a Agent> + 'a> {
+		let near = self.index.query_rect(rect.inset(-self.reach));
+		let mut ids = near.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+		ids.sort();
+		ids.dedup();
+		Box::new(ids.into_iter()
+			.filter_map(move |id| self.agents.get(&id))
+			.filter(move |agent| agent.segments().iter().any(|segment| segment.bounds().intersects(&rect))))
+	}
+
 	#[allow(dead_code)]
 	pub fn is_empty(&self) -> bool {
 		self.agents.is_empty()
 	}
 
-	pub fn agents(&self) -> &HashMap<Id, Agent> {
+	pub fn agents(&self) -> &agent::AgentMap {
 		&self.agents
 	}
 
-	pub fn agents_mut(&mut self) -> &mut HashMap<Id, Agent> {
+	pub fn agents_mut(&mut self) -> &mut agent::AgentMap {
 		&mut self.agents
 	}
 }
 
 pub type SwarmMap = HashMap<AgentType, Swarm>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::hash_map::DefaultHasher;
+	use std::hash::{Hash, Hasher};
+
+	fn dna_hash(agent: &Agent) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		agent.dna().hash(&mut hasher);
+		hasher.finish()
+	}
+
+	#[test]
+	fn clones_are_independent_copies() {
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let mut genome = GenePool::for_tests().next();
+		let id = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		let clone_id = swarm.clone_agent(id, Position::new(10., 0.)).unwrap();
+		assert!(clone_id != id);
+		assert_eq!(dna_hash(swarm.get(id).unwrap()), dna_hash(swarm.get(clone_id).unwrap()));
+		{
+			let clone = swarm.get(clone_id).unwrap();
+			let original = swarm.get(id).unwrap();
+			assert_eq!(original.segments().len(), clone.segments().len());
+			assert_eq!(Position::new(10., 0.), clone.segments()[0].transform.position);
+			assert_eq!(original.brain().hunger, clone.brain().hunger);
+		}
+
+		swarm.get_mut(clone_id).unwrap().segments_mut()[0].transform.position = Position::new(-5., -5.);
+		assert_eq!(Position::new(0., 0.), swarm.get(id).unwrap().segments()[0].transform.position);
+		assert!(swarm.clone_agent(0xdead << 8, Position::new(0., 0.)).is_none());
+	}
+
+	#[test]
+	fn same_seed_develops_the_same_minions() {
+		let seed = [7u8; 16];
+		let mut pools = [GenePool::for_tests(), GenePool::for_tests()];
+		let mut swarms = [Swarm::new(AgentType::Minion, usize::max_value()).with_seed(seed),
+		                  Swarm::new(AgentType::Minion, usize::max_value()).with_seed(seed)];
+		for _ in 0..10 {
+			let mut ids = Vec::new();
+			for (pool, swarm) in pools.iter_mut().zip(swarms.iter_mut()) {
+				let mut genome = pool.next_with(swarm.rng());
+				ids.push(swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3));
+			}
+			let (a, b) = (swarms[0].get(ids[0]).unwrap(), swarms[1].get(ids[1]).unwrap());
+			assert_eq!(ids[0], ids[1]);
+			assert_eq!(a.dna(), b.dna());
+			assert_eq!(a.segments().len(), b.segments().len());
+			assert!(a.diff(b).is_empty());
+		}
+	}
+
+	#[test]
+	fn newborns_get_a_network_of_the_configured_shape() {
+		let spec = agent::BrainSpec {
+			inputs: agent::N_INPUTS,
+			hidden: vec![8, 8, 8],
+			outputs: agent::N_WEIGHTS,
+		};
+		let mut genome = GenePool::for_tests().next();
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let encoded = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		swarm.set_brain_spec(Some(spec.clone()));
+		let a = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		let b = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		assert_eq!(agent::BrainSpec::default(), swarm.get(encoded).unwrap().brain().spec());
+		assert_eq!(spec, swarm.get(a).unwrap().brain().spec());
+		// each draws its own weights
+		assert!(swarm.get(a).unwrap().brain().weights() != swarm.get(b).unwrap().brain().weights());
+	}
+
+	#[test]
+	fn pick_finds_the_agent_under_the_point() {
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let mut genome = GenePool::for_tests().next();
+		let id = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		let other = swarm.clone_agent(id, Position::new(100., 0.)).unwrap();
+		for segment in swarm.get(other).unwrap().segments() {
+			assert_eq!(Some(other), swarm.pick(segment.transform.position).map(|(id, _)| id));
+		}
+		assert_eq!(Some(id), swarm.pick(Position::new(0., 0.)).map(|(id, _)| id));
+		assert_eq!(None, swarm.pick(Position::new(50., 0.)));
+	}
+
+	#[test]
+	fn agents_in_rect_leave_out_those_outside() {
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let mut genome = GenePool::for_tests().next();
+		let id = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		let far = swarm.clone_agent(id, Position::new(100., 0.)).unwrap();
+		let ids = |rect: Rect| swarm.agents_in_rect(rect).map(|agent| agent.id()).collect::<Vec<_>>();
+		assert_eq!(vec![id], ids(Rect::new(-10., -10., 10., 10.)));
+		assert_eq!(vec![id, far], ids(Rect::new(-10., -10., 110., 10.)));
+		assert!(ids(Rect::new(40., -10., 60., 10.)).is_empty());
+		// a rect catching no more than the edge of a segment still takes the whole agent
+		let right = swarm.get(far).unwrap().segments().iter().map(|s| s.bounds().max.x).fold(0., f32::max);
+		assert_eq!(vec![far], ids(Rect::new(right - 0.1, -100., right + 50., 100.)));
+		assert!(ids(Rect::new(right + 0.1, -100., right + 50., 100.)).is_empty());
+	}
+
+	#[test]
+	fn agents_in_rect_are_found_where_they_were_last_indexed() {
+		let mut swarm = Swarm::new(AgentType::Minion, usize::max_value());
+		let mut genome = GenePool::for_tests().next();
+		let id = swarm.spawn::<phen::Minion>(&mut genome, &Transform::default(), None, 0.3);
+		for segment in swarm.get_mut(id).unwrap().segments_mut().iter_mut() {
+			segment.transform.position = segment.transform.position + Position::new(100., 0.);
+		}
+		let ids = |swarm: &Swarm, rect: Rect| swarm.agents_in_rect(rect).map(|agent| agent.id()).collect::<Vec<_>>();
+		assert!(ids(&swarm, Rect::new(90., -10., 110., 10.)).is_empty());
+		swarm.reindex(spatial::Kind::QuadTree, Rect::new(-80., -80., 80., 80.));
+		assert_eq!(vec![id], ids(&swarm, Rect::new(90., -10., 110., 10.)));
+		assert!(ids(&swarm, Rect::new(-10., -10., 10., 10.)).is_empty());
+	}
+}
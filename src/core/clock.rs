@@ -1,8 +1,16 @@
 use std::time;
 use std::fmt;
+use std::mem;
 
 pub type SystemStopwatch = time::SystemTime;
 
+/// Time that only passes when the simulation ticks, so that what runs on it keeps to the ticks whatever the
+/// frame rate, stands still while paused and runs as fast as the ticks do with no display
+#[derive(Clone, Debug)]
+pub struct SimStopwatch {
+	elapsed: f32,
+}
+
 pub trait Stopwatch: Sized {
 	fn new() -> Self;
 
@@ -87,3 +95,176 @@ impl Stopwatch for SystemStopwatch {
 		}
 	}
 }
+
+impl Stopwatch for SimStopwatch {
+	fn new() -> Self {
+		SimStopwatch { elapsed: 0. }
+	}
+
+	fn seconds(&self) -> f32 {
+		self.elapsed
+	}
+}
+
+impl Hourglass<SimStopwatch> {
+	/// Lets `dt` seconds of simulation run through
+	pub fn tick(&mut self, dt: f32) {
+		self.stopwatch.elapsed += dt;
+	}
+}
+
+/// The slowest and the fastest a `SimClock` runs, relative to the wall clock
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 8.;
+
+/// Turns the time frames take into whole ticks of a fixed length, carrying what is left over to the next frame.
+/// It can be paused, stepped a tick at a time while paused, and sped up or slowed down: the speed changes
+/// how many ticks a frame owes, never how long a tick is.
+pub struct SimClock {
+	step: f32,
+	max_ticks: u32,
+	speed: f32,
+	paused: bool,
+	pending: u32,
+	accumulator: f32,
+}
+
+impl SimClock {
+	/// Ticks of `step` seconds, at most `max_ticks` of them a frame at normal speed
+	pub fn new(step: f32, max_ticks: u32) -> Self {
+		SimClock {
+			step: step,
+			max_ticks: max_ticks,
+			speed: 1.,
+			paused: false,
+			pending: 0,
+			accumulator: 0.,
+		}
+	}
+
+	pub fn speed(&self) -> f32 {
+		self.speed
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.paused
+	}
+
+	pub fn toggle_pause(&mut self) {
+		self.paused = !self.paused;
+		self.pending = 0;
+	}
+
+	/// Has the next frame run a single tick, when paused
+	pub fn step_once(&mut self) {
+		if self.paused {
+			self.pending += 1;
+		}
+	}
+
+	pub fn speed_up(&mut self) {
+		self.speed = (self.speed * 2.).min(MAX_SPEED);
+	}
+
+	pub fn slow_down(&mut self) {
+		self.speed = (self.speed * 0.5).max(MIN_SPEED);
+	}
+
+	/// The ticks owed after a frame of `frame_time` seconds. A faster clock may run proportionally more ticks
+	/// a frame; time owed past that is dropped, so that a slow frame doesn't leave the next ones further behind.
+	pub fn advance(&mut self, frame_time: f32) -> u32 {
+		if self.paused {
+			return mem::replace(&mut self.pending, 0);
+		}
+		self.accumulator += frame_time * self.speed;
+		let max_ticks = (self.max_ticks as f32 * self.speed.max(1.)).ceil() as u32;
+		let mut ticks = 0;
+		while self.accumulator >= self.step {
+			if ticks == max_ticks {
+				self.accumulator %= self.step;
+				break;
+			}
+			self.accumulator -= self.step;
+			ticks += 1;
+		}
+		ticks
+	}
+
+	/// How far into the next tick the time owed is, in [0, 1)
+	pub fn alpha(&self) -> f32 {
+		self.accumulator / self.step
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const STEP: f32 = 1. / 64.;
+
+	#[test]
+	fn sim_hourglasses_only_run_when_ticked() {
+		let mut hourglass = Hourglass::<SimStopwatch>::new(1.);
+		assert_eq!(1., hourglass.left());
+		for _ in 0..32 {
+			hourglass.tick(STEP);
+		}
+		assert_eq!(0.5, hourglass.left());
+		assert!(!hourglass.is_expired());
+		for _ in 0..40 {
+			hourglass.tick(STEP);
+		}
+		assert!(hourglass.is_expired());
+		assert_eq!(0., hourglass.flip());
+		assert_eq!(1., hourglass.left());
+		hourglass.tick(0.25);
+		hourglass.renew();
+		assert_eq!(1., hourglass.left());
+	}
+
+	#[test]
+	fn speed_scales_the_ticks_owed() {
+		let mut clock = SimClock::new(STEP, 5);
+		assert_eq!(2, clock.advance(STEP * 2.));
+		clock.slow_down();
+		assert_eq!(0, clock.advance(STEP));
+		assert_eq!(0.5, clock.alpha());
+		assert_eq!(1, clock.advance(STEP));
+		for _ in 0..10 {
+			clock.speed_up();
+		}
+		assert_eq!(MAX_SPEED, clock.speed());
+		assert_eq!(16, clock.advance(STEP * 2.));
+		for _ in 0..10 {
+			clock.slow_down();
+		}
+		assert_eq!(MIN_SPEED, clock.speed());
+	}
+
+	#[test]
+	fn fast_clocks_do_not_spiral() {
+		let mut clock = SimClock::new(STEP, 5);
+		for _ in 0..3 {
+			clock.speed_up();
+		}
+		// a second long hitch owes 512 ticks, the frame runs no more than 8 times its usual share
+		assert_eq!(40, clock.advance(1.));
+		assert!(clock.alpha() < 1.);
+		assert_eq!(8, clock.advance(STEP));
+	}
+
+	#[test]
+	fn paused_clocks_only_step_when_asked() {
+		let mut clock = SimClock::new(STEP, 5);
+		clock.step_once();
+		assert_eq!(1, clock.advance(STEP));
+		clock.toggle_pause();
+		assert_eq!(0, clock.advance(1.));
+		clock.step_once();
+		clock.step_once();
+		assert_eq!(2, clock.advance(1.));
+		assert_eq!(0, clock.advance(1.));
+		clock.toggle_pause();
+		assert_eq!(1, clock.advance(STEP));
+	}
+}
@@ -60,11 +60,22 @@ impl<T> YPbPr<T>
 }
 
 impl ToRgb<f32> for YPbPr<f32> {
+	/// BT.601. Colors out of the RGB gamut lose chroma until they fit, rather than having each channel clamped
+	/// on its own, which would shift their hue.
 	fn to_rgb(&self) -> Rgb<f32> {
-		let r = self.y + 1.402000 * self.pr;
-		let g = self.y - 0.344136 * self.pb - 0.714136 * self.pr;
-		let b = self.y + 1.772000 * self.pb;
-		[r.max(0.).min(1.), g.max(0.).min(1.), b.max(0.).min(1.)]
+		let y = self.y.max(0.).min(1.);
+		let chroma = [1.402000 * self.pr, -0.344136 * self.pb - 0.714136 * self.pr, 1.772000 * self.pb];
+		// the largest share of the chroma that keeps every channel in [0, 1]
+		let t = chroma.iter().fold(1f32, |t, &c| {
+			if y + c * t > 1. {
+				(1. - y) / c
+			} else if y + c * t < 0. {
+				-y / c
+			} else {
+				t
+			}
+		});
+		[(y + chroma[0] * t).max(0.).min(1.), (y + chroma[1] * t).max(0.).min(1.), (y + chroma[2] * t).max(0.).min(1.)]
 	}
 }
 
@@ -113,7 +124,7 @@ impl FromRgb<f32> for Hsl<f32> {
 				} else {
 					(r - g) / d + 4.
 				} / 6.,
-				s: if b > 0.5 { d / (2. - max - min) } else { d / (max + min) },
+				s: if m > 0.5 { d / (2. - max - min) } else { d / (max + min) },
 				l: m,
 			}
 		}
@@ -152,7 +163,7 @@ impl ToRgb<f32> for Hsl<f32> {
 		}
 
 		match self {
-			&Hsl { h: 0., l, .. } => [l, l, l],
+			&Hsl { s, l, .. } if s == 0. => [l, l, l],
 			&Hsl { h, s, l } => {
 				let q = if l < 0.5 { l * (1. + s) } else { l + s - l * s };
 				let p = 2. * l - q;
@@ -165,3 +176,194 @@ impl ToRgb<f32> for Hsl<f32> {
 		}
 	}
 }
+
+/// CIE L*a*b*, under D65, from sRGB. Even steps in it look even, which is what gradients want.
+#[derive(Debug, Copy, Clone)]
+pub struct Lab<T: num::Float> {
+	l: T,
+	a: T,
+	b: T,
+}
+
+impl FromRgb<f32> for Lab<f32> {
+	fn from_rgb(c: &Rgb<f32>) -> Self {
+		fn linear(c: f32) -> f32 {
+			if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+		}
+		fn f(t: f32) -> f32 {
+			if t > 216. / 24389. { t.cbrt() } else { (24389. / 27. * t + 16.) / 116. }
+		}
+		let (r, g, b) = (linear(c[0]), linear(c[1]), linear(c[2]));
+		let x = f((0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047);
+		let y = f(0.2126729 * r + 0.7151522 * g + 0.0721750 * b);
+		let z = f((0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883);
+		Lab {
+			l: 116. * y - 16.,
+			a: 500. * (x - y),
+			b: 200. * (y - z),
+		}
+	}
+}
+
+impl ToRgb<f32> for Lab<f32> {
+	/// Colors out of the sRGB gamut have each channel clamped
+	fn to_rgb(&self) -> Rgb<f32> {
+		fn encode(c: f32) -> f32 {
+			let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1. / 2.4) - 0.055 };
+			c.max(0.).min(1.)
+		}
+		fn f_inv(t: f32) -> f32 {
+			if t > 6. / 29. { t * t * t } else { (116. * t - 16.) * 27. / 24389. }
+		}
+		let fy = (self.l + 16.) / 116.;
+		let x = 0.95047 * f_inv(fy + self.a / 500.);
+		let y = f_inv(fy);
+		let z = 1.08883 * f_inv(fy - self.b / 200.);
+		[encode(3.2404542 * x - 1.5371385 * y - 0.4985314 * z),
+		 encode(-0.9692660 * x + 1.8760108 * y + 0.0415560 * z),
+		 encode(0.0556434 * x - 0.2040259 * y + 1.0572252 * z)]
+	}
+}
+
+/// A gradient through evenly spaced stops, blended in L*a*b* so that equal steps along it look equal
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRamp {
+	stops: Box<[Rgb<f32>]>,
+}
+
+/// The built in ramps, by name
+pub const RAMP_NAMES: &'static [&'static str] = &["viridis", "magma", "inferno", "grey"];
+
+impl ColorRamp {
+	/// A ramp from the first of `stops` to the last, black if there are none
+	pub fn new(stops: &[Rgb<f32>]) -> Self {
+		let stops = if stops.is_empty() { vec![[0.; 3]] } else { stops.to_vec() };
+		ColorRamp { stops: stops.into_boxed_slice() }
+	}
+
+	/// One of `RAMP_NAMES`
+	pub fn named(name: &str) -> Option<Self> {
+		fn hex(c: u32) -> Rgb<f32> {
+			[(c >> 16) as f32 / 255., ((c >> 8) & 0xff) as f32 / 255., (c & 0xff) as f32 / 255.]
+		}
+		let stops: &[u32] = match name {
+			"viridis" => &[0x440154, 0x3b528b, 0x21918c, 0x5ec962, 0xfde725],
+			"magma" => &[0x000004, 0x3b0f70, 0x8c2981, 0xde4968, 0xfe9f6d, 0xfcfdbf],
+			"inferno" => &[0x000004, 0x420a68, 0x932667, 0xdd513a, 0xfca50a, 0xfcffa4],
+			"grey" => &[0x000000, 0xffffff],
+			_ => return None,
+		};
+		Some(Self::new(&stops.iter().map(|&c| hex(c)).collect::<Vec<_>>()))
+	}
+
+	/// The color `t` of the way along, `t` in [0, 1]; the ends are the first and last stops exactly
+	pub fn sample(&self, t: f32) -> Rgba<f32> {
+		let n = self.stops.len();
+		let opaque = |c: &Rgb<f32>| [c[0], c[1], c[2], 1.];
+		if !(t > 0.) || n == 1 {
+			return opaque(&self.stops[0]);
+		} else if t >= 1. {
+			return opaque(&self.stops[n - 1]);
+		}
+		let x = t * (n - 1) as f32;
+		let i = ::std::cmp::min(x.floor() as usize, n - 2);
+		let u = x - i as f32;
+		let (a, b) = (Lab::from_rgb(&self.stops[i]), Lab::from_rgb(&self.stops[i + 1]));
+		let mixed = Lab {
+			l: a.l + (b.l - a.l) * u,
+			a: a.a + (b.a - a.a) * u,
+			b: a.b + (b.b - a.b) * u,
+		};
+		mixed.to_rgba()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_close(expected: &Rgb<f32>, actual: &Rgb<f32>) {
+		for i in 0..3 {
+			assert!((expected[i] - actual[i]).abs() < 1e-4, "{:?} came back as {:?}", expected, actual);
+		}
+	}
+
+	fn grid() -> Vec<Rgb<f32>> {
+		let steps = [0., 0.2, 0.5, 0.7, 1.];
+		let mut colors = Vec::new();
+		for &r in &steps {
+			for &g in &steps {
+				for &b in &steps {
+					colors.push([r, g, b]);
+				}
+			}
+		}
+		colors
+	}
+
+	#[test]
+	fn hsl_round_trips() {
+		for c in grid() {
+			assert_close(&c, &Hsl::from_rgb(&c).to_rgb());
+		}
+		// pure red has a hue of 0 but is anything but grey
+		assert_close(&[1., 0., 0.], &Hsl::new(0., 1., 0.5).to_rgb());
+	}
+
+	#[test]
+	fn ypbpr_round_trips() {
+		for c in grid() {
+			assert_close(&c, &YPbPr::from_rgb(&c).to_rgb());
+		}
+		// BT.601 reference values
+		let red = YPbPr::from_rgb(&[1., 0., 0.]);
+		assert!((red.y - 0.299).abs() < 1e-6 && (red.pb + 0.168736).abs() < 1e-6 && (red.pr - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn lab_round_trips() {
+		for c in grid() {
+			assert_close(&c, &Lab::from_rgb(&c).to_rgb());
+		}
+		let white = Lab::from_rgb(&[1., 1., 1.]);
+		assert!((white.l - 100.).abs() < 1e-3 && white.a.abs() < 1e-2 && white.b.abs() < 1e-2);
+	}
+
+	#[test]
+	fn ramps_hit_their_ends_exactly() {
+		for name in RAMP_NAMES {
+			let ramp = ColorRamp::named(name).unwrap();
+			let (first, last) = (ramp.stops[0], ramp.stops[ramp.stops.len() - 1]);
+			assert_eq!([first[0], first[1], first[2], 1.], ramp.sample(0.));
+			assert_eq!([last[0], last[1], last[2], 1.], ramp.sample(1.));
+			// out of range clamps to the ends
+			assert_eq!(ramp.sample(0.), ramp.sample(-3.));
+			assert_eq!(ramp.sample(1.), ramp.sample(7.));
+			// every built in ramp gets lighter all the way along
+			let lightness = |t: f32| {
+				let c = ramp.sample(t);
+				Lab::from_rgb(&[c[0], c[1], c[2]]).l
+			};
+			for i in 0..20 {
+				assert!(lightness(i as f32 / 20.) < lightness((i + 1) as f32 / 20.), "{} at {}", name, i);
+			}
+		}
+		assert_eq!(None, ColorRamp::named("plaid"));
+		assert_eq!([0., 0., 0., 1.], ColorRamp::new(&[]).sample(0.5));
+	}
+
+	#[test]
+	fn saturated_ypbpr_keeps_its_hue() {
+		for &(pb, pr) in &[(-0.5, 0.5), (0.5, 0.5), (0.5, -0.5), (-0.5, -0.5), (0.4, 0.), (0., -0.45)] {
+			let rgb = YPbPr::new(0.5, pb, pr).to_rgb();
+			for &channel in &rgb {
+				assert!(channel >= 0. && channel <= 1.);
+			}
+			let back = YPbPr::from_rgb(&rgb);
+			assert!((back.y - 0.5).abs() < 1e-4, "luma moved to {} for {}, {}", back.y, pb, pr);
+			// chroma only shrinks, along the same direction
+			assert!((back.pb * pr - back.pr * pb).abs() < 1e-4, "hue moved for {}, {}", pb, pr);
+			assert!(back.pb * pb + back.pr * pr > 0.);
+		}
+	}
+}
@@ -18,6 +18,7 @@ pub struct Size {
 	pub height: f32,
 }
 
+/// Where something is and which way it's turned; the one transform the physics, the AI and the renderer share
 #[derive(Clone)]
 pub struct Transform {
 	pub position: Position,
@@ -58,6 +59,57 @@ impl Rect {
 	pub fn top_left(&self) -> Position {
 		Position::new(self.min.x, self.max.y)
 	}
+
+	/// Shrinks the rectangle by `margin` on every side, collapsing onto its center where there is not enough room
+	pub fn inset(&self, margin: f32) -> Rect {
+		fn shrink(min: f32, max: f32, margin: f32) -> (f32, f32) {
+			if max - min > 2. * margin {
+				(min + margin, max - margin)
+			} else {
+				let mid = (min + max) * 0.5;
+				(mid, mid)
+			}
+		}
+		let (left, right) = shrink(self.min.x, self.max.x, margin);
+		let (bottom, top) = shrink(self.min.y, self.max.y, margin);
+		Rect::new(left, bottom, right, top)
+	}
+
+	/// The point inside the rectangle nearest to `p`
+	pub fn clamp(&self, p: Position) -> Position {
+		Position::new(p.x.max(self.min.x).min(self.max.x), p.y.max(self.min.y).min(self.max.y))
+	}
+
+	pub fn contains(&self, p: Position) -> bool {
+		p.x >= self.min.x && p.x <= self.max.x && p.y >= self.min.y && p.y <= self.max.y
+	}
+
+	/// Whether the two rectangles share any point, edges included
+	pub fn intersects(&self, other: &Rect) -> bool {
+		self.min.x <= other.max.x && other.min.x <= self.max.x && self.min.y <= other.max.y &&
+		other.min.y <= self.max.y
+	}
+
+	/// The smallest rectangle holding both this one and `p`
+	pub fn grow(&self, p: Position) -> Rect {
+		Rect::new(self.min.x.min(p.x), self.min.y.min(p.y), self.max.x.max(p.x), self.max.y.max(p.y))
+	}
+}
+
+/// Window dimensions, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Size {
+	pub width: f32,
+	pub height: f32,
+}
+
+impl Size {
+	pub fn new(width: f32, height: f32) -> Self {
+		Size {
+			width: width,
+			height: height,
+		}
+	}
 }
 
 impl Initial for Position {
@@ -85,15 +137,38 @@ impl Transform {
 	pub fn from_position(position: Position) -> Self {
 		Transform { position: position, ..Transform::default() }
 	}
+
+	/// The model matrix of whatever is placed by this transform: turned about Z by `angle`, then moved to
+	/// `position`. Transforms carry no scale, meshes are scaled to their radius on top of it.
+	pub fn to_matrix(&self) -> M44 {
+		use cgmath::Rotation3;
+		let rotation = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::rad(self.angle));
+		M44::from_translation(cgmath::Vector3::new(self.position.x, self.position.y, 0.0)) * M44::from(rotation)
+	}
+
+	/// Where the point `p` of this transform's own frame lies in the world
+	pub fn transform_point(&self, p: Position) -> Position {
+		let (s, c) = self.angle.sin_cos();
+		self.position + Position::new(p.x * c - p.y * s, p.x * s + p.y * c)
+	}
+
+	/// Where the world point `p` lies in this transform's own frame, undoing `transform_point`
+	pub fn inverse_transform_point(&self, p: Position) -> Position {
+		let d = p - self.position;
+		let (s, c) = (-self.angle).sin_cos();
+		Position::new(d.x * c - d.y * s, d.x * s + d.y * c)
+	}
 }
 
 pub fn origin() -> Position {
 	Position::new(0., 0.)
 }
 
-#[derive(Clone, PartialEq)]
-enum VertexType {
+#[derive(Clone, Debug, PartialEq)]
+pub enum VertexType {
+	/// turning counterclockwise
 	Plus,
+	/// turning clockwise
 	Minus,
 	Flat,
 }
@@ -103,7 +178,8 @@ pub struct PolygonType {
 }
 
 impl PolygonType {
-	fn classify_vertex(v0: &Position, v1: &Position, v2: &Position) -> VertexType {
+	/// Which way the outline turns at `v1`, going from `v0` to `v2`
+	pub fn classify_vertex(v0: &Position, v1: &Position, v2: &Position) -> VertexType {
 		let x = (v1 - v0).perp_dot(v2 - v0);
 		if x.approx_eq(&0.) {
 			VertexType::Flat
@@ -140,3 +216,93 @@ impl PolygonType {
 		self.count[VertexType::Flat as usize] > 0
 	}
 }
+
+/// Twice the signed area of the polygon with vertices `v`, positive when they wind counterclockwise
+pub fn signed_area2(v: &[Position]) -> f32 {
+	let n = v.len();
+	(0..n).fold(0., |a, i| a + v[i].perp_dot(v[(i + 1) % n]))
+}
+
+/// Centre of mass of the polygon with vertices `v`, by the shoelace formula
+pub fn polygon_centroid(v: &[Position]) -> Position {
+	let n = v.len();
+	let area2 = signed_area2(v);
+	if area2 == 0. {
+		return v.iter().fold(origin(), |c, p| c + *p) / ::std::cmp::max(n, 1) as f32;
+	}
+	(0..n).fold(origin(), |c, i| {
+		let (a, b) = (v[i], v[(i + 1) % n]);
+		c + (a + b) * a.perp_dot(b)
+	}) / (3. * area2)
+}
+
+/// The smallest convex polygon enclosing `points`, counterclockwise and without collinear vertices
+pub fn convex_hull(points: &[Position]) -> Vec<Position> {
+	let mut sorted = points.to_vec();
+	sorted.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap_or(::std::cmp::Ordering::Equal));
+	if sorted.len() < 3 {
+		return sorted;
+	}
+	// Andrew's monotone chain: the lower hull left to right, then the upper one back
+	let mut hull: Vec<Position> = Vec::with_capacity(sorted.len() * 2);
+	for pass in 0..2 {
+		let start = hull.len();
+		for p in &sorted {
+			loop {
+				let k = hull.len();
+				if k < start + 2 || (hull[k - 1] - hull[k - 2]).perp_dot(*p - hull[k - 2]) > 0. {
+					break;
+				}
+				hull.pop();
+			}
+			hull.push(*p);
+		}
+		// the last point is the first of the other half
+		hull.pop();
+		if pass == 0 {
+			sorted.reverse();
+		}
+	}
+	hull
+}
+
+/// Whether `p` lies inside the polygon with vertices `v`, concave or not, by counting the edges a ray from it crosses
+pub fn polygon_contains(v: &[Position], p: Position) -> bool {
+	let n = v.len();
+	let mut inside = false;
+	for i in 0..n {
+		let (a, b) = (v[i], v[(i + n - 1) % n]);
+		if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y) {
+			inside = !inside;
+		}
+	}
+	inside
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts;
+	use cgmath;
+	use cgmath::ApproxEq;
+
+	#[test]
+	fn transforms_round_trip() {
+		let points = [Position::new(0., 0.), Position::new(1., 0.), Position::new(-3.5, 2.25)];
+		for &(x, y, angle) in &[(0., 0., 0.), (10., -4., consts::PI / 3.), (-2., 7., -2.5)] {
+			let transform = Transform::new(Position::new(x, y), angle);
+			let matrix = transform.to_matrix();
+			for &p in &points {
+				let world = transform.transform_point(p);
+				assert!(transform.inverse_transform_point(world).approx_eq_eps(&p, &1e-4));
+				// the matrix puts the point where the transform does
+				let placed = matrix * cgmath::Vector4::new(p.x, p.y, 0., 1.);
+				assert!(Position::new(placed.x, placed.y).approx_eq_eps(&world, &1e-4));
+				assert!(placed.z.approx_eq_eps(&0., &1e-4));
+			}
+		}
+		// a quarter turn takes x to y
+		let turned = Transform::new(Position::new(1., 1.), consts::PI / 2.);
+		assert!(turned.transform_point(Position::unit_x()).approx_eq_eps(&Position::new(1., 2.), &1e-4));
+	}
+}
@@ -25,6 +25,25 @@ pub struct Exponential<S, T> {
 	last: S,
 }
 
+/// A critically damped spring pulling towards the value: it starts off slower than an `Exponential`
+/// of the same `tau` but closes in sooner, and never overshoots a value it starts still from.
+/// `tau` is about how long it takes to come within a tenth of a step change.
+#[derive(Copy, Clone)]
+pub struct SmoothDamp<S, T> {
+	tau: T,
+	dt: T,
+	last: S,
+	velocity: S,
+}
+
+/// Moves towards the value by at most `rate` per second
+#[derive(Copy, Clone)]
+pub struct Linear<T> {
+	rate: T,
+	dt: T,
+	last: T,
+}
+
 impl<S: num::Num + num::NumCast + Copy> MovingAverage<S> {
 	pub fn new(window_size: usize) -> Self {
 		MovingAverage {
@@ -73,6 +92,11 @@ impl<S, T> Exponential<S, T>
 		self.dt = dt;
 		self
 	}
+
+	pub fn tau(&mut self, tau: T) -> &mut Self {
+		self.tau = tau;
+		self
+	}
 }
 
 impl<S, T> Smooth<S> for Exponential<S, T>
@@ -86,6 +110,75 @@ impl<S, T> Smooth<S> for Exponential<S, T>
 	}
 }
 
+impl<S, T> SmoothDamp<S, T>
+	where S: ops::Add<S, Output = S> + ops::Sub<S, Output = S> + ops::Mul<T, Output = S> + Copy,
+	      T: cgmath::BaseFloat
+{
+	pub fn new(value: S, dt: T, tau: T) -> Self {
+		SmoothDamp {
+			last: value,
+			velocity: value * T::zero(),
+			dt: dt,
+			tau: tau,
+		}
+	}
+
+	/// Jumps to `value` and stops there
+	pub fn reset(&mut self, value: S) {
+		self.last = value;
+		self.velocity = value * T::zero();
+	}
+
+	pub fn dt(&mut self, dt: T) -> &mut Self {
+		self.dt = dt;
+		self
+	}
+
+	#[cfg(test)]
+	pub fn velocity(&self) -> S {
+		self.velocity
+	}
+}
+
+impl<S, T> Smooth<S> for SmoothDamp<S, T>
+	where S: ops::Add<S, Output = S> + ops::Sub<S, Output = S> + ops::Mul<T, Output = S> + Copy,
+	      T: cgmath::BaseFloat
+{
+	/// Steps the spring by the exact solution over `dt`, so that large steps neither blow up nor overshoot
+	fn smooth(&mut self, value: S) -> S {
+		let omega = (T::one() + T::one()) * (T::one() + T::one()) / self.tau;
+		let decay = T::exp(-omega * self.dt);
+		let offset = self.last - value;
+		let drift = self.velocity + offset * omega;
+		self.last = value + (offset + drift * self.dt) * decay;
+		self.velocity = (self.velocity - drift * (omega * self.dt)) * decay;
+		self.last
+	}
+}
+
+impl<T: cgmath::BaseFloat> Linear<T> {
+	pub fn new(value: T, dt: T, rate: T) -> Self {
+		Linear {
+			last: value,
+			dt: dt,
+			rate: rate,
+		}
+	}
+
+	pub fn dt(&mut self, dt: T) -> &mut Self {
+		self.dt = dt;
+		self
+	}
+}
+
+impl<T: cgmath::BaseFloat> Smooth<T> for Linear<T> {
+	fn smooth(&mut self, value: T) -> T {
+		let step = self.rate * self.dt;
+		self.last = self.last + (value - self.last).max(-step).min(step);
+		self.last
+	}
+}
+
 pub enum Direction {
 	Up,
 	Down,
@@ -199,3 +292,58 @@ impl<T> Inertial<T>
 		self.velocity = self.velocity * T::exp(-dt / self.inertia);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DT: f32 = 1. / 60.;
+	const TAU: f32 = 0.5;
+
+	/// Steps `smooth` from 0 towards 1, returning how many steps it took to get past 1 - 1/e, and how far it ever went
+	fn settle<M: Smooth<f32>>(mut smooth: M) -> (usize, f32) {
+		let mut reached = None;
+		let mut highest = 0f32;
+		for i in 0..600 {
+			let value = smooth.smooth(1.);
+			highest = highest.max(value);
+			if reached.is_none() && value >= 1. - (-1f32).exp() {
+				reached = Some(i);
+			}
+		}
+		(reached.expect("never got there"), highest)
+	}
+
+	#[test]
+	fn the_spring_gets_there_sooner_and_does_not_overshoot() {
+		let (exponential, _) = settle(Exponential::new(0., DT, TAU));
+		let (spring, highest) = settle(SmoothDamp::new(0., DT, TAU));
+		assert!(spring < exponential);
+		assert!(highest <= 1.);
+		assert!(highest > 0.999);
+		// nor with steps larger than it takes to settle
+		let (_, highest) = settle(SmoothDamp::new(0., TAU * 4., TAU));
+		assert!(highest <= 1.);
+	}
+
+	#[test]
+	fn the_spring_comes_to_rest_when_reset() {
+		let mut spring = SmoothDamp::new(0., DT, TAU);
+		spring.smooth(1.);
+		assert!(spring.velocity() > 0.);
+		spring.reset(2.);
+		assert_eq!(0., spring.velocity());
+		assert_eq!(2., spring.smooth(2.));
+	}
+
+	#[test]
+	fn linear_moves_at_its_rate_and_stops_on_the_value() {
+		let mut linear = Linear::new(0., 0.1, 2.);
+		assert!((linear.smooth(1.) - 0.2).abs() < 1e-6);
+		assert!((linear.smooth(-1.) - 0.).abs() < 1e-6);
+		for _ in 0..10 {
+			linear.smooth(1.);
+		}
+		assert_eq!(1., linear.smooth(1.));
+	}
+}
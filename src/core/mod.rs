@@ -1,6 +1,7 @@
 pub mod math;
 pub mod util;
 pub mod clock;
+pub mod profiler;
 pub mod resource;
 pub mod color;
 pub mod geometry;
@@ -0,0 +1,154 @@
+//! Rolling timings of named scopes, each keeping its last few samples in a `History` to report their
+//! median and 95th percentile. A disabled profiler reads no clock and takes no lock.
+use std::cmp::Ordering;
+use std::sync::Mutex;
+use std::time::Instant;
+use core::util::History;
+
+/// Samples kept per scope unless told otherwise, two seconds of frames at 60 fps
+pub const DEFAULT_WINDOW: usize = 120;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timing {
+	pub name: &'static str,
+	/// in seconds
+	pub p50: f32,
+	/// in seconds
+	pub p95: f32,
+	pub samples: usize,
+}
+
+pub struct Profiler {
+	enabled: bool,
+	window: usize,
+	/// in the order the scopes were first seen
+	histories: Mutex<Vec<(&'static str, History<f32>)>>,
+}
+
+/// Times from its creation to its drop, recording the duration under its name
+pub struct Scope<'a> {
+	started: Option<(&'a Profiler, &'static str, Instant)>,
+}
+
+impl<'a> Drop for Scope<'a> {
+	fn drop(&mut self) {
+		if let Some((profiler, name, start)) = self.started.take() {
+			profiler.record(name, seconds_since(start));
+		}
+	}
+}
+
+pub fn seconds_since(start: Instant) -> f32 {
+	let elapsed = start.elapsed();
+	elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9
+}
+
+/// The nearest rank `p`th quantile of `sorted`, which must not be empty
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+	let rank = (p * sorted.len() as f32).ceil() as usize;
+	sorted[::std::cmp::min(::std::cmp::max(rank, 1), sorted.len()) - 1]
+}
+
+impl Default for Profiler {
+	fn default() -> Self {
+		Profiler::new(DEFAULT_WINDOW)
+	}
+}
+
+impl Profiler {
+	/// A disabled profiler that will keep the last `window` samples of each scope
+	pub fn new(window: usize) -> Self {
+		Profiler {
+			enabled: false,
+			window: ::std::cmp::max(window, 1),
+			histories: Mutex::new(Vec::new()),
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// Disabling drops whatever was recorded, so that turning it back on starts afresh
+	pub fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		if !enabled {
+			self.histories.lock().unwrap().clear();
+		}
+	}
+
+	pub fn scope(&self, name: &'static str) -> Scope {
+		Scope { started: if self.enabled { Some((self, name, Instant::now())) } else { None } }
+	}
+
+	pub fn record(&self, name: &'static str, seconds: f32) {
+		if !self.enabled {
+			return;
+		}
+		let mut histories = self.histories.lock().unwrap();
+		let i = match histories.iter().position(|&(n, _)| n == name) {
+			Some(i) => i,
+			None => {
+				histories.push((name, History::new(self.window)));
+				histories.len() - 1
+			}
+		};
+		histories[i].1.push(seconds);
+	}
+
+	/// The percentiles of every scope recorded so far, in the order they were first seen
+	pub fn timings(&self) -> Vec<Timing> {
+		let histories = self.histories.lock().unwrap();
+		histories.iter()
+			.filter(|&&(_, ref history)| !history.is_empty())
+			.map(|&(name, ref history)| {
+				let mut sorted = history.into_iter().collect::<Vec<_>>();
+				sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+				Timing {
+					name: name,
+					p50: percentile(&sorted, 0.5),
+					p95: percentile(&sorted, 0.95),
+					samples: sorted.len(),
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn percentiles_roll_over_the_window() {
+		let mut profiler = Profiler::new(100);
+		profiler.record("ignored", 1.);
+		assert!(profiler.timings().is_empty());
+
+		profiler.set_enabled(true);
+		// 1 to 200 in a shuffled order, only the last hundred are kept
+		for i in 0..200 {
+			profiler.record("update", ((i * 37) % 200 + 1) as f32);
+		}
+		for i in 0..10 {
+			profiler.record("render", i as f32);
+		}
+		let update_samples = (100..200).map(|i| ((i * 37) % 200 + 1) as f32).collect::<Vec<_>>();
+		let mut sorted = update_samples.clone();
+		sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		let timings = profiler.timings();
+		assert_eq!(vec!["update", "render"], timings.iter().map(|t| t.name).collect::<Vec<_>>());
+		assert_eq!(100, timings[0].samples);
+		assert_eq!(sorted[49], timings[0].p50);
+		assert_eq!(sorted[94], timings[0].p95);
+		assert_eq!(4., timings[1].p50);
+		assert_eq!(9., timings[1].p95);
+
+		{
+			let _scope = profiler.scope("scoped");
+		}
+		assert_eq!(1, profiler.timings()[2].samples);
+		profiler.set_enabled(false);
+		assert!(profiler.timings().is_empty());
+	}
+}
@@ -1,3 +1,5 @@
+use std::ops::{Add, Div};
+
 #[derive(Clone,Debug)]
 pub struct History<T: Clone> {
 	values: Vec<T>,
@@ -9,6 +11,12 @@ pub trait Initial {
 	fn initial() -> Self;
 }
 
+impl Initial for f32 {
+	fn initial() -> Self {
+		0.
+	}
+}
+
 impl<T> History<T>
     where T: Clone + Initial
 {
@@ -28,6 +36,34 @@ impl<T> History<T>
 		self.values[self.ptr] = value;
 		self.ptr = (self.ptr + 1) % len;
 	}
+
+	/// How many values are held, at most the capacity it was made with
+	pub fn len(&self) -> usize {
+		self.count
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.count == 0
+	}
+}
+
+impl<T> History<T>
+    where T: Clone + Initial + Add<Output = T> + Div<f32, Output = T>
+{
+	/// The mean of the values held, `None` while there are none
+	pub fn mean(&self) -> Option<T> {
+		let mut values = self.into_iter();
+		values.next().map(|first| values.fold(first, |sum, value| sum + value) / self.count as f32)
+	}
+}
+
+impl History<f32> {
+	/// The population variance of the values held, `None` while there are none
+	pub fn variance(&self) -> Option<f32> {
+		self.mean().map(|mean| {
+			self.into_iter().map(|value| (value - mean) * (value - mean)).sum::<f32>() / self.count as f32
+		})
+	}
 }
 
 pub struct HistoryIntoIterator<'a, T>
@@ -97,3 +133,75 @@ impl<T> Cycle<T>
 		self.items[self.index]
 	}
 }
+
+/// Decimal places that keep `digits` significant digits of `value`, negative when whole tens are dropped
+fn significant_places(value: f64, digits: u32) -> i32 {
+	digits as i32 - 1 - value.abs().log10().floor() as i32
+}
+
+/// Rounds to `digits` significant digits, so that whatever is written out doesn't carry
+/// the noise of widening an f32 and stays the same across runs and machines
+pub fn round_significant(value: f32, digits: u32) -> f64 {
+	let value = value as f64;
+	if value == 0. || !value.is_finite() {
+		return value;
+	}
+	let places = significant_places(value, digits);
+	if places >= 0 {
+		let scale = 10f64.powi(places);
+		(value * scale).round() / scale
+	} else {
+		let scale = 10f64.powi(-places);
+		(value / scale).round() * scale
+	}
+}
+
+/// Formats to `digits` significant digits with no trailing zeros, e.g. 1234.5678 to 4 digits is "1235"
+pub fn format_significant(value: f32, digits: u32) -> String {
+	let rounded = round_significant(value, digits);
+	if rounded == 0. || !rounded.is_finite() {
+		return format!("{}", rounded);
+	}
+	let places = ::std::cmp::max(significant_places(rounded, digits), 0) as usize;
+	let text = format!("{:.*}", places, rounded);
+	if text.contains('.') {
+		text.trim_right_matches('0').trim_right_matches('.').to_string()
+	} else {
+		text
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn history_statistics_follow_the_ring() {
+		let mut history = History::<f32>::new(3);
+		assert_eq!(None, history.mean());
+		assert_eq!(None, history.variance());
+		history.push(1.);
+		history.push(2.);
+		assert_eq!(Some(1.5), history.mean());
+		assert_eq!(Some(0.25), history.variance());
+		// the fourth value replaces the first
+		history.push(3.);
+		history.push(7.);
+		assert_eq!(3, history.len());
+		assert_eq!(vec![7., 3., 2.], history.into_iter().collect::<Vec<_>>());
+		assert_eq!(Some(4.), history.mean());
+		assert_eq!(Some(14. / 3.), history.variance());
+	}
+
+	#[test]
+	fn floats_format_to_significant_digits() {
+		assert_eq!("0.3", format_significant(0.1f32 + 0.2f32, 6));
+		assert_eq!("1234.57", format_significant(1234.5678, 6));
+		assert_eq!("1235", format_significant(1234.5678, 4));
+		assert_eq!("123457000", format_significant(123456789., 6));
+		assert_eq!("-0.000123", format_significant(-0.000123456789, 3));
+		assert_eq!("0", format_significant(0., 6));
+		assert_eq!(format_significant(2. / 3., 6), format_significant(2. / 3., 6));
+		assert_eq!(0.3, round_significant(0.3, 7));
+	}
+}
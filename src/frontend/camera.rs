@@ -0,0 +1,265 @@
+//! Where the view looks and how close, moved by the user, by a followed agent or fixed on the whole world.
+use std::f32::consts;
+use core::geometry::*;
+use core::math;
+use core::math::{Direction, Directional, Relative, Smooth};
+use backend::obj::Id;
+use frontend::render::{Camera, Projection};
+
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 8.;
+/// zoom factor of one line of scrolling
+const ZOOM_STEP: f32 = 1.2;
+/// vertical field of view of the perspective projection
+const PERSPECTIVE_FOV: f32 = consts::PI / 4.;
+/// seconds it takes a following camera to come within a tenth of the gap with its target
+const FOLLOW_LAG: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+	/// panned and zoomed by the user
+	Free,
+	/// keeps an agent in the middle of the view
+	Follow(Id),
+	/// frames all of the bounds
+	Overview,
+}
+
+pub struct CameraController {
+	position: math::Inertial<f32>,
+	/// eases the camera after a followed agent, starting and stopping smoothly
+	follow: math::SmoothDamp<Position, f32>,
+	mode: CameraMode,
+	projection: Projection,
+	zoom: f32,
+	/// world units across the view at zoom 1
+	scale: f32,
+	ratio: f32,
+	bounds: Option<Rect>,
+}
+
+#[allow(dead_code)]
+impl CameraController {
+	pub fn new(scale: f32, ratio: f32) -> Self {
+		CameraController {
+			position: math::Inertial::new(10.0, 0.5, 0.5),
+			follow: math::SmoothDamp::new(Position::new(0., 0.), 0., FOLLOW_LAG),
+			mode: CameraMode::Free,
+			projection: Projection::Orthographic,
+			zoom: 1.,
+			scale: scale,
+			ratio: ratio,
+			bounds: None,
+		}
+	}
+
+	pub fn mode(&self) -> CameraMode {
+		self.mode
+	}
+
+	pub fn set_mode(&mut self, mode: CameraMode) {
+		self.mode = mode;
+		self.position.stop();
+		self.follow.reset(self.position.position());
+	}
+
+	pub fn projection(&self) -> Projection {
+		self.projection
+	}
+
+	pub fn set_projection(&mut self, projection: Projection) {
+		self.projection = projection;
+	}
+
+	/// Switches between the orthographic projection, where nothing is distorted towards the edges of the view,
+	/// and the perspective one. Both frame the same area of the ground.
+	pub fn toggle_projection(&mut self) {
+		self.projection = match self.projection {
+			Projection::Orthographic => Projection::Perspective(PERSPECTIVE_FOV),
+			Projection::Perspective(_) => Projection::Orthographic,
+		}
+	}
+
+	/// Width over height of the view
+	pub fn set_ratio(&mut self, ratio: f32) {
+		self.ratio = ratio;
+	}
+
+	pub fn position(&self) -> Position {
+		self.position.position()
+	}
+
+	pub fn zoom(&self) -> f32 {
+		self.zoom
+	}
+
+	/// Zooms in by `lines` of scrolling, out if negative
+	pub fn zoom_by(&mut self, lines: f32) {
+		self.zoom = (self.zoom * ZOOM_STEP.powf(lines)).max(MIN_ZOOM).min(MAX_ZOOM);
+	}
+
+	/// World units across the view
+	pub fn scale(&self) -> f32 {
+		match (self.mode, self.bounds) {
+			(CameraMode::Overview, Some(bounds)) => {
+				f32::max(bounds.max.x - bounds.min.x, (bounds.max.y - bounds.min.y) * self.ratio)
+			}
+			_ => self.scale / self.zoom,
+		}
+	}
+
+	pub fn push(&mut self, direction: Direction) {
+		self.mode = CameraMode::Free;
+		self.position.push(direction);
+	}
+
+	pub fn begin_pan(&mut self) {
+		self.mode = CameraMode::Free;
+		self.position.zero();
+	}
+
+	/// Moves the camera by `offset` from where the pan began
+	pub fn pan(&mut self, offset: Position) {
+		self.mode = CameraMode::Free;
+		self.position.set_relative(offset);
+	}
+
+	/// Ends a pan, leaving the camera drifting at `velocity`
+	pub fn end_pan(&mut self, offset: Position, velocity: Velocity) {
+		self.pan(offset);
+		self.position.velocity(velocity);
+	}
+
+	/// Jumps to `position`, in free mode and at the same zoom
+	pub fn center_on(&mut self, position: Position) {
+		self.mode = CameraMode::Free;
+		self.position.set(position);
+		self.position.stop();
+	}
+
+	/// Back to the middle of the world at zoom 1, in free mode
+	pub fn reset(&mut self) {
+		self.mode = CameraMode::Free;
+		self.zoom = 1.;
+		self.position.reset();
+	}
+
+	/// Moves the camera on by `dt` seconds. `target` is where the followed agent is, if it's still there:
+	/// when it is gone the camera is set free where it is.
+	pub fn update(&mut self, dt: f32, target: Option<Position>) {
+		match self.mode {
+			CameraMode::Free => self.position.update(dt),
+			CameraMode::Follow(_) => {
+				match target {
+					Some(target) => self.position.set(self.follow.dt(dt).smooth(target)),
+					None => self.mode = CameraMode::Free,
+				}
+			}
+			CameraMode::Overview => {
+				if let Some(bounds) = self.bounds {
+					self.position.set((bounds.min + bounds.max) * 0.5);
+				}
+			}
+		}
+	}
+
+	/// Keeps the view within `bounds`, centred on them where the view is larger
+	pub fn keep_inside(&mut self, bounds: Rect) {
+		self.bounds = Some(bounds);
+		let half_view = Position::new(self.scale() * 0.5, self.scale() * 0.5 / self.ratio);
+		// where the view is wider than the bounds the rectangle comes out inverted, inset(0.) collapses it
+		let area = Rect::new(bounds.min.x + half_view.x,
+		                     bounds.min.y + half_view.y,
+		                     bounds.max.x - half_view.x,
+		                     bounds.max.y - half_view.y)
+			.inset(0.);
+		let position = self.position.position();
+		let clamped = area.clamp(position);
+		if clamped != position {
+			self.position.set(clamped);
+			self.position.stop();
+			self.follow.reset(clamped);
+		}
+	}
+
+	pub fn camera(&self) -> Camera {
+		Camera::for_viewport(self.projection, self.position(), self.scale(), self.ratio)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::EuclideanVector;
+	use core::geometry::*;
+	use frontend::render::Projection;
+
+	fn to_screen(camera: &CameraController, world: Position, width: f32, height: f32) -> Position {
+		camera.camera().project(world, Size::new(width, height))
+	}
+
+	#[test]
+	fn orthographic_world_units_are_pixels_at_zoom_1() {
+		let mut camera = CameraController::new(640., 640. / 480.);
+		let origin = to_screen(&camera, Position::new(0., 0.), 640., 480.);
+		assert!((origin - Position::new(320., 240.)).length() < 1e-3);
+		let step = to_screen(&camera, Position::new(10., 10.), 640., 480.) - origin;
+		assert!((step - Position::new(10., -10.)).length() < 1e-3);
+
+		// the perspective projection frames the same ground
+		camera.toggle_projection();
+		match camera.projection() {
+			Projection::Perspective(_) => {}
+			_ => panic!("expected a perspective projection"),
+		}
+		let corner = to_screen(&camera, Position::new(320., 240.), 640., 480.);
+		assert!((corner - Position::new(640., 0.)).length() < 1e-2);
+	}
+
+	#[test]
+	fn zoom_is_clamped() {
+		let mut camera = CameraController::new(100., 1.);
+		camera.zoom_by(1.);
+		assert_eq!(100. / 1.2, camera.scale());
+		camera.zoom_by(100.);
+		assert_eq!(MAX_ZOOM, camera.zoom());
+		camera.zoom_by(-100.);
+		assert_eq!(MIN_ZOOM, camera.zoom());
+	}
+
+	#[test]
+	fn following_closes_in_on_the_target_and_stays_inside() {
+		let bounds = Rect::new(-100., -100., 100., 100.);
+		let mut camera = CameraController::new(20., 1.);
+		camera.set_mode(CameraMode::Follow(1));
+		let target = Position::new(50., 0.);
+		let mut last = camera.position().x;
+		for _ in 0..10 {
+			camera.update(0.1, Some(target));
+			camera.keep_inside(bounds);
+			assert!(camera.position().x > last && camera.position().x <= target.x);
+			last = camera.position().x;
+		}
+		assert!(target.x - last < 1.);
+
+		// out of bounds, the view stops at the edge
+		for _ in 0..100 {
+			camera.update(0.1, Some(Position::new(500., 0.)));
+			camera.keep_inside(bounds);
+		}
+		assert_eq!(90., camera.position().x);
+
+		camera.update(0.1, None);
+		assert_eq!(CameraMode::Free, camera.mode());
+	}
+
+	#[test]
+	fn overview_frames_the_bounds() {
+		let mut camera = CameraController::new(20., 2.);
+		camera.keep_inside(Rect::new(0., 0., 100., 80.));
+		camera.set_mode(CameraMode::Overview);
+		camera.update(0.1, None);
+		assert_eq!(Position::new(50., 40.), camera.position());
+		assert_eq!(160., camera.scale());
+	}
+}
@@ -0,0 +1,137 @@
+//! Dumps rendered frames as a numbered PNG sequence, e.g. for stitching into a video with
+//! `ffmpeg -i frame_%06d.png`. Encoding happens on a writer thread; frames are never dropped,
+//! if the writer falls behind the render loop blocks until it catches up.
+use std::io;
+use std::fs;
+use std::path;
+use std::thread;
+use std::sync::mpsc;
+use image;
+
+const QUEUE_LENGTH: usize = 8;
+
+struct Frame {
+	index: usize,
+	width: u32,
+	height: u32,
+	pixels: Vec<u8>,
+}
+
+pub struct Recorder {
+	every_n: u32,
+	ticks: u32,
+	captured: usize,
+	sender: Option<mpsc::SyncSender<Frame>>,
+	writer: Option<thread::JoinHandle<io::Result<usize>>>,
+}
+
+impl Recorder {
+	pub fn start(dir: &path::Path, every_n: u32) -> io::Result<Recorder> {
+		try!(fs::create_dir_all(dir));
+		let (sender, receiver) = mpsc::sync_channel::<Frame>(QUEUE_LENGTH);
+		let dir = dir.to_owned();
+		let writer = thread::spawn(move || {
+			let mut written = 0;
+			for frame in receiver.iter() {
+				let file_name = dir.join(format!("frame_{:06}.png", frame.index));
+				// GL rows start at the bottom
+				let stride = frame.width as usize * 4;
+				let flipped = frame.pixels
+					.chunks(stride)
+					.rev()
+					.flat_map(|row| row.iter().cloned())
+					.collect::<Vec<_>>();
+				try!(image::save_buffer(&file_name, &flipped, frame.width, frame.height, image::RGBA(8)));
+				written += 1;
+			}
+			Ok(written)
+		});
+		Ok(Recorder {
+			every_n: ::std::cmp::max(every_n, 1),
+			ticks: 0,
+			captured: 0,
+			sender: Some(sender),
+			writer: Some(writer),
+		})
+	}
+
+	/// Called once per rendered frame, true if this one should be captured
+	pub fn wants_frame(&mut self) -> bool {
+		let wanted = self.ticks % self.every_n == 0;
+		self.ticks += 1;
+		wanted
+	}
+
+	/// Queues a tightly packed, bottom-up RGBA frame
+	pub fn push(&mut self, width: u32, height: u32, pixels: Vec<u8>) {
+		let frame = Frame {
+			index: self.captured,
+			width: width,
+			height: height,
+			pixels: pixels,
+		};
+		if let Some(ref sender) = self.sender {
+			let sent = match sender.try_send(frame) {
+				Ok(()) => Ok(()),
+				Err(mpsc::TrySendError::Full(frame)) => {
+					warn!("Recording can't keep up with the frame rate, waiting for the disk");
+					sender.send(frame).map_err(|_| ())
+				}
+				Err(mpsc::TrySendError::Disconnected(_)) => Err(()),
+			};
+			match sent {
+				Ok(()) => self.captured += 1,
+				Err(()) => error!("Recording writer has stopped, frame {} lost", self.captured),
+			}
+		}
+	}
+
+	pub fn captured(&self) -> usize {
+		self.captured
+	}
+
+	/// Flushes the queue and returns how many frames made it to disk
+	pub fn stop(&mut self) -> io::Result<usize> {
+		self.sender = None;
+		match self.writer.take() {
+			Some(writer) => {
+				match writer.join() {
+					Ok(result) => result,
+					Err(_) => Err(io::Error::new(io::ErrorKind::Other, "recording writer panicked")),
+				}
+			}
+			None => Ok(0),
+		}
+	}
+}
+
+impl Drop for Recorder {
+	fn drop(&mut self) {
+		let _ = self.stop();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::fs;
+
+	#[test]
+	fn writes_one_file_per_captured_frame() {
+		let dir = env::temp_dir().join("rust-oids-recorder-test");
+		let _ = fs::remove_dir_all(&dir);
+		let mut recorder = Recorder::start(&dir, 3).unwrap();
+		for _ in 0..10 {
+			if recorder.wants_frame() {
+				recorder.push(2, 2, vec![255u8; 16]);
+			}
+		}
+		let captured = recorder.captured();
+		assert_eq!(4, captured);
+		assert_eq!(captured, recorder.stop().unwrap());
+		assert_eq!(captured, fs::read_dir(&dir).unwrap().count());
+		assert!(dir.join("frame_000003.png").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,140 @@
+//! Maps the game's actions to key chords, so that no physical key is hardcoded into the game logic.
+//! Bindings read from CSV have one `action,chord` row per binding, with the keys of a chord joined
+//! by `+` and the triggering key last, e.g. `Quit,LCtrl+Q`. An action may have several rows.
+use std::fmt;
+use std::str::FromStr;
+use csv;
+use super::Key;
+
+#[derive(Debug)]
+pub enum Error {
+	Csv(String),
+	UnknownAction(String),
+	UnknownKey(String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			&Error::Csv(ref e) => write!(f, "malformed bindings: {}", e),
+			&Error::UnknownAction(ref name) => write!(f, "unknown action {}", name),
+			&Error::UnknownKey(ref name) => write!(f, "unknown key {}", name),
+		}
+	}
+}
+
+pub struct KeyBindings<A> {
+	bindings: Vec<(A, Box<[Key]>)>,
+}
+
+impl<A> Default for KeyBindings<A> {
+	fn default() -> Self {
+		KeyBindings { bindings: Vec::new() }
+	}
+}
+
+#[allow(dead_code)]
+impl<A: Copy + PartialEq> KeyBindings<A> {
+	/// Adds `chord` as one more way of triggering `action`
+	pub fn bind(&mut self, action: A, chord: &[Key]) -> &mut Self {
+		if !chord.is_empty() {
+			self.bindings.push((action, chord.to_vec().into_boxed_slice()));
+		}
+		self
+	}
+
+	pub fn chords<'a>(&'a self, action: A) -> Box<Iterator<Item = &'a [Key]> + 'a> {
+		Box::new(self.bindings.iter().filter(move |&&(a, _)| a == action).map(|&(_, ref chord)| &chord[..]))
+	}
+
+	/// Whether a longer chord bound to another action contains all of `chord`'s keys and is held,
+	/// in which case that action takes the keys for itself
+	pub fn shadowed<F>(&self, action: A, chord: &[Key], held: F) -> bool
+		where F: Fn(&[Key]) -> bool {
+		self.bindings.iter().any(|&(other, ref longer)| {
+			other != action && longer.len() > chord.len() && chord.iter().all(|k| longer.contains(k)) &&
+			held(longer)
+		})
+	}
+}
+
+impl<A: Copy + PartialEq + FromStr> KeyBindings<A> {
+	pub fn load(data: &[u8]) -> Result<Self, Error> {
+		let mut bindings = KeyBindings::default();
+		let mut csv = csv::Reader::from_bytes(data).has_headers(false);
+		for row in csv.records() {
+			let fields = try!(row.map_err(|e| Error::Csv(e.to_string())));
+			if fields.len() < 2 {
+				return Err(Error::Csv(format!("expected action,chord in {:?}", fields)));
+			}
+			let action = try!(fields[0].trim().parse::<A>().map_err(|_| Error::UnknownAction(fields[0].clone())));
+			let mut chord = Vec::new();
+			for name in fields[1].split('+') {
+				chord.push(try!(name.trim().parse::<Key>().map_err(|_| Error::UnknownKey(name.to_string()))));
+			}
+			bindings.bind(action, &chord);
+		}
+		Ok(bindings)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+	use frontend::input::*;
+
+	#[derive(Clone, Copy, Debug, PartialEq)]
+	enum Action {
+		Pause,
+		Quit,
+		SpawnMinion,
+	}
+
+	impl FromStr for Action {
+		type Err = ();
+		fn from_str(s: &str) -> Result<Action, ()> {
+			match s {
+				"Pause" => Ok(Action::Pause),
+				"Quit" => Ok(Action::Quit),
+				"SpawnMinion" => Ok(Action::SpawnMinion),
+				_ => Err(()),
+			}
+		}
+	}
+
+	fn press(input: &mut InputState, keys: &[Key]) {
+		for &key in keys {
+			input.event(&Event::Key(State::Down, key));
+		}
+	}
+
+	#[test]
+	fn load_bindings_from_csv() {
+		let bindings = KeyBindings::<Action>::load(b"Pause,Space\nQuit,Esc\nQuit,LCtrl+Q\n").unwrap();
+		assert_eq!(vec![&[Key::Space][..]], bindings.chords(Action::Pause).collect::<Vec<_>>());
+		assert_eq!(vec![&[Key::Esc][..], &[Key::LCtrl, Key::Q][..]],
+		           bindings.chords(Action::Quit).collect::<Vec<_>>());
+		assert!(KeyBindings::<Action>::load(b"Jump,Space\n").is_err());
+		assert!(KeyBindings::<Action>::load(b"Pause,Spacebar\n").is_err());
+	}
+
+	#[test]
+	fn longer_chords_win() {
+		let bindings = KeyBindings::<Action>::load(b"SpawnMinion,Q\nQuit,LCtrl+Q\n").unwrap();
+		let mut input = InputState::default();
+		press(&mut input, &[Key::Q]);
+		assert!(input.action_pressed(&bindings, Action::SpawnMinion));
+		assert!(!input.action_pressed(&bindings, Action::Quit));
+
+		press(&mut input, &[Key::LCtrl]);
+		assert!(!input.action_pressed(&bindings, Action::SpawnMinion));
+		assert!(input.action_pressed(&bindings, Action::Quit));
+
+		let mut input = InputState::default();
+		press(&mut input, &[Key::LCtrl, Key::Q]);
+		assert!(!input.action_once(&bindings, Action::SpawnMinion));
+		assert!(input.action_once(&bindings, Action::Quit));
+		assert!(!input.action_once(&bindings, Action::Quit));
+	}
+}
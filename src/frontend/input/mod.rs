@@ -1,10 +1,27 @@
 //! Input state, including current mouse position and button click
 //! TODO: add keyboard presses
+use core::clock::{SystemStopwatch, Stopwatch};
 use core::geometry;
-use core::util::History;
-use core::geometry::Position;
+use core::util::{History, Initial};
+use core::geometry::{Position, Velocity};
+use std::str::FromStr;
 use bit_set::BitSet;
 
+pub mod bindings;
+mod sequence;
+
+pub use self::bindings::KeyBindings;
+pub use self::sequence::SequenceMatcher;
+
+pub const DEFAULT_DOUBLE_CLICK_WINDOW: f32 = 0.25;
+/// how far apart in window pixels two presses can be and still make a double click
+const DOUBLE_CLICK_RADIUS: f32 = 4.;
+pub const DEFAULT_MOUSE_HISTORY: usize = 60;
+/// Touchpads report scrolling in pixels, this makes it comparable to a wheel's lines
+const PIXELS_PER_LINE: f32 = 20.;
+/// mouse positions averaged over by `mouse_velocity`, the current one included
+const VELOCITY_SAMPLES: usize = 4;
+
 #[derive(Clone)]
 enum DragState {
 	Nothing,
@@ -16,6 +33,25 @@ pub enum Dragging {
 	Begin(Key, Position),
 	Dragging(Key, Position, Position),
 	End(Key, Position, Position, Position),
+	/// The second of two quick presses of the same key, reported the frame after its `Begin`
+	DoubleClick(Key, Position),
+}
+
+#[derive(Clone)]
+struct Click {
+	key: Option<Key>,
+	position: Position,
+	time: f32,
+}
+
+impl Initial for Click {
+	fn initial() -> Self {
+		Click {
+			key: None,
+			position: geometry::origin(),
+			time: 0.,
+		}
+	}
 }
 
 pub struct InputState {
@@ -24,17 +60,16 @@ pub struct InputState {
 	drag_state: DragState,
 	mouse_history: History<Position>,
 	mouse_position: Position,
+	scroll_delta: f32,
+	clock: SystemStopwatch,
+	click_history: History<Click>,
+	double_click_window: f32,
+	double_click_pending: bool,
 }
 
 impl Default for InputState {
 	fn default() -> Self {
-		InputState {
-			key_pressed: BitSet::new(),
-			key_ack: BitSet::new(),
-			drag_state: DragState::Nothing,
-			mouse_history: History::new(60),
-			mouse_position: geometry::origin(),
-		}
+		InputState::new(DEFAULT_DOUBLE_CLICK_WINDOW)
 	}
 }
 
@@ -44,9 +79,28 @@ pub enum State {
 	Up,
 }
 
-#[allow(dead_code)]
-#[derive(Copy,Clone,Eq,PartialEq)]
-pub enum Key {
+/// Declares the keys along with their names, which are how key bindings refer to them
+macro_rules! keys {
+	($($key:ident,)*) => {
+		#[allow(dead_code)]
+		#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+		pub enum Key {
+			$($key,)*
+		}
+
+		impl FromStr for Key {
+			type Err = ();
+			fn from_str(s: &str) -> Result<Key, ()> {
+				match s {
+					$(stringify!($key) => Ok(Key::$key),)*
+					_ => Err(()),
+				}
+			}
+		}
+	}
+}
+
+keys! {
 	A,
 	B,
 	C,
@@ -157,6 +211,7 @@ pub enum Key {
 	MouseLeft,
 	MouseRight,
 	MouseMiddle,
+	// wheel clicks reported as buttons, superseded by Event::Scroll and kept until the next release
 	MouseScrollUp,
 	MouseScrollDown,
 }
@@ -164,14 +219,51 @@ pub enum Key {
 pub enum Event {
 	Key(State, Key),
 	Mouse(Position),
+	/// Lines scrolled by the wheel, positive away from the user
+	Scroll(f32),
+}
+
+impl Event {
+	/// The wheel turned by `lines`
+	pub fn scroll_lines(lines: f32) -> Self {
+		Event::Scroll(lines)
+	}
+
+	/// A touchpad swiped by `pixels`, scrolling as far as the wheel would
+	pub fn scroll_pixels(pixels: f32) -> Self {
+		Event::Scroll(pixels / PIXELS_PER_LINE)
+	}
 }
 
 #[allow(dead_code)]
 impl InputState {
+	/// Two presses of a key closer than `double_click_window` seconds make a double click
+	pub fn new(double_click_window: f32) -> Self {
+		InputState {
+			key_pressed: BitSet::new(),
+			key_ack: BitSet::new(),
+			drag_state: DragState::Nothing,
+			mouse_history: History::new(DEFAULT_MOUSE_HISTORY),
+			mouse_position: geometry::origin(),
+			scroll_delta: 0.,
+			clock: SystemStopwatch::new(),
+			click_history: History::new(2),
+			double_click_window: double_click_window,
+			double_click_pending: false,
+		}
+	}
+
+	/// Remembers the last `capacity` mouse positions instead of the default number
+	pub fn with_mouse_history(mut self, capacity: usize) -> Self {
+		self.mouse_history = History::new(::std::cmp::max(capacity, 1));
+		self
+	}
+
 	pub fn event(&mut self, event: &Event) {
 		match event {
 			&Event::Key(state, key) => self.key(state, key),
 			&Event::Mouse(position) => self.mouse_at(position),
+			&Event::Scroll(delta) => self.scroll_delta += delta,
 		}
 	}
 
@@ -210,10 +302,52 @@ impl InputState {
 		}
 	}
 
+	/// Whether any of the chords bound to `action` is held, unless a longer chord of another action covering it
+	/// is held too: with both `Q` and `LCtrl+Q` bound, holding `LCtrl+Q` only triggers the latter
+	pub fn action_pressed<A: Copy + PartialEq>(&self, bindings: &KeyBindings<A>, action: A) -> bool {
+		bindings.chords(action)
+			.any(|chord| self.chord_pressed(chord) && !bindings.shadowed(action, chord, |c| self.chord_pressed(c)))
+	}
+
+	/// Like `key_once` for the last key of each chord bound to `action`, once the rest of the chord is held
+	pub fn action_once<A: Copy + PartialEq>(&mut self, bindings: &KeyBindings<A>, action: A) -> bool {
+		let mut triggered = false;
+		for chord in bindings.chords(action) {
+			let (last, modifiers) = chord.split_last().unwrap();
+			if self.chord_pressed(modifiers) && !bindings.shadowed(action, chord, |c| self.chord_pressed(c)) {
+				triggered = self.key_once(*last) || triggered;
+			}
+		}
+		triggered
+	}
+
 	pub fn mouse_position(&self) -> Position {
 		self.mouse_position
 	}
 
+	/// How fast the mouse moved over the last few positions, taken `dt` seconds apart, in world units per second.
+	/// `scale` is the size of a window pixel in world units; the window's y axis grows downwards and the world's up.
+	pub fn mouse_velocity(&self, dt: f32, scale: f32) -> Velocity {
+		let mut samples = 1;
+		let mut oldest = self.mouse_position;
+		for position in self.mouse_history.into_iter().take(VELOCITY_SAMPLES - 1) {
+			oldest = position;
+			samples += 1;
+		}
+		if samples < 2 || dt <= 0. {
+			return Velocity::new(0., 0.);
+		}
+		let d = (self.mouse_position - oldest) * (scale / ((samples - 1) as f32 * dt));
+		Velocity::new(d.x, -d.y)
+	}
+
+	/// The lines scrolled since the last call, to be taken once a frame
+	pub fn take_scroll(&mut self) -> f32 {
+		let delta = self.scroll_delta;
+		self.scroll_delta = 0.;
+		delta
+	}
+
 	fn key(&mut self, state: State, b: Key) {
 		self.key_ack.remove(b as usize);
 		match state {
@@ -228,14 +362,45 @@ impl InputState {
 	}
 
 	pub fn dragging(&mut self, key: Key, pos: Position) -> Dragging {
+		let now = self.clock.seconds();
+		self.dragging_at(key, pos, now)
+	}
+
+	fn is_double_click(&self, key: Key, now: f32) -> bool {
+		match self.click_history.into_iter().next() {
+			Some(Click { key: Some(last), position, time }) => {
+				let d = self.mouse_position - position;
+				last == key && now - time <= self.double_click_window &&
+				d.x * d.x + d.y * d.y <= DOUBLE_CLICK_RADIUS * DOUBLE_CLICK_RADIUS
+			}
+			_ => false,
+		}
+	}
+
+	fn dragging_at(&mut self, key: Key, pos: Position, now: f32) -> Dragging {
 		let (drag_state, displacement) = match &self.drag_state {
 			&DragState::Nothing => {
 				if self.key_pressed(key) {
+					if self.is_double_click(key, now) {
+						// a third press starts over rather than making another double click
+						self.double_click_pending = true;
+						self.click_history.push(Click::initial());
+					} else {
+						self.click_history.push(Click {
+							key: Some(key),
+							position: self.mouse_position,
+							time: now,
+						});
+					}
 					(DragState::Hold(key, pos), Dragging::Begin(key, pos))
 				} else {
 					(DragState::Nothing, Dragging::Nothing)
 				}
 			}
+			&DragState::Hold(held, start) if held == key && self.double_click_pending => {
+				self.double_click_pending = false;
+				(DragState::Hold(held, start), Dragging::DoubleClick(key, start))
+			}
 			&DragState::Hold(held, start) if held == key => {
 				if self.key_pressed(key) {
 					(DragState::Hold(key, start), Dragging::Dragging(key, start, pos))
@@ -254,3 +419,70 @@ impl InputState {
 pub trait EventMapper<T> {
 	fn translate(&self, e: &T) -> Option<Event>;
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn click(input: &mut InputState, at: f32) -> Vec<bool> {
+		let pos = input.mouse_position();
+		input.event(&Event::Key(State::Down, Key::MouseLeft));
+		let mut double = Vec::new();
+		for &dt in &[0., 0.01] {
+			double.push(match input.dragging_at(Key::MouseLeft, pos, at + dt) {
+				Dragging::DoubleClick(..) => true,
+				_ => false,
+			});
+		}
+		input.event(&Event::Key(State::Up, Key::MouseLeft));
+		match input.dragging_at(Key::MouseLeft, pos, at + 0.02) {
+			Dragging::End(..) => {}
+			_ => panic!("release should end the drag"),
+		}
+		double
+	}
+
+	#[test]
+	fn wheel_and_touchpad_scroll_alike() {
+		let mut input = InputState::default();
+		assert_eq!(0., input.take_scroll());
+		input.event(&Event::scroll_lines(2.));
+		assert_eq!(2., input.take_scroll());
+		// a touchpad swipe the length of a line scrolls a line
+		input.event(&Event::scroll_pixels(PIXELS_PER_LINE));
+		input.event(&Event::scroll_pixels(-3. * PIXELS_PER_LINE));
+		input.event(&Event::scroll_lines(0.5));
+		assert_eq!(-1.5, input.take_scroll());
+		assert_eq!(0., input.take_scroll());
+		match Event::scroll_pixels(60.) {
+			Event::Scroll(lines) => assert_eq!(3., lines),
+			_ => panic!("the swipe should scroll"),
+		}
+	}
+
+	#[test]
+	fn quick_second_click_makes_a_double_click() {
+		let mut input = InputState::new(0.25);
+		assert_eq!(vec![false, false], click(&mut input, 0.));
+		assert_eq!(vec![false, true], click(&mut input, 0.2));
+		// the third click starts over
+		assert_eq!(vec![false, false], click(&mut input, 0.3));
+		// too late
+		assert_eq!(vec![false, false], click(&mut input, 1.));
+		// too far
+		input.mouse_at(Position::new(10., 0.));
+		assert_eq!(vec![false, false], click(&mut input, 1.1));
+	}
+
+	#[test]
+	fn mouse_velocity_averages_the_last_positions() {
+		let mut input = InputState::default().with_mouse_history(2);
+		assert_eq!(Velocity::new(0., 0.), input.mouse_velocity(0.1, 1.));
+		input.mouse_at(Position::new(10., 0.));
+		input.mouse_at(Position::new(20., 10.));
+		input.mouse_at(Position::new(30., 30.));
+		// the history holds two positions besides the current one, both used
+		assert_eq!(Velocity::new(5., -7.5), input.mouse_velocity(2., 1.));
+		assert_eq!(Velocity::new(2.5, -3.75), input.mouse_velocity(2., 0.5));
+	}
+}
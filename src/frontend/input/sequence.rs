@@ -0,0 +1,131 @@
+//! Keys pressed one after the other, as opposed to the chords of `InputState` which are held together.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use bit_set::BitSet;
+use super::{Event, Key, State};
+
+pub struct SequenceMatcher {
+	presses: VecDeque<(Key, Duration)>,
+	capacity: usize,
+	down: BitSet,
+	/// monotonic, so that presses are never timed before the ones that came first
+	start: Instant,
+}
+
+impl SequenceMatcher {
+	/// Remembers the last `capacity` presses, which is as long as a sequence can be
+	pub fn new(capacity: usize) -> Self {
+		SequenceMatcher {
+			presses: VecDeque::with_capacity(capacity),
+			capacity: capacity,
+			down: BitSet::new(),
+			start: Instant::now(),
+		}
+	}
+
+	/// Looks at the event without taking it, so it can be given to the `InputState` as well
+	pub fn event(&mut self, event: &Event) {
+		if let &Event::Key(state, key) = event {
+			let now = self.start.elapsed();
+			self.key_at(state, key, now);
+		}
+	}
+
+	fn key_at(&mut self, state: State, key: Key, now: Duration) {
+		match state {
+			State::Down => {
+				// held keys repeat their down events, only the first one is a press
+				if self.down.insert(key as usize) {
+					if self.presses.len() == self.capacity {
+						self.presses.pop_front();
+					}
+					self.presses.push_back((key, now));
+				}
+			}
+			State::Up => {
+				self.down.remove(key as usize);
+			}
+		}
+	}
+
+	/// Whether the last presses were `sequence`, with no more than `timeout` between one and the next.
+	/// It keeps matching until the next press, so it's best checked right after the events of a frame.
+	pub fn matches(&self, sequence: &[Key], timeout: Duration) -> bool {
+		if sequence.is_empty() || sequence.len() > self.presses.len() {
+			return false;
+		}
+		let last = self.presses.iter().skip(self.presses.len() - sequence.len());
+		let mut previous: Option<Duration> = None;
+		for (&(pressed, at), &key) in last.zip(sequence.iter()) {
+			// no subtraction, which would panic on presses timed out of order
+			if pressed != key || previous.map_or(false, |t| at > t + timeout) {
+				return false;
+			}
+			previous = Some(at);
+		}
+		true
+	}
+
+	pub fn clear(&mut self) {
+		self.presses.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+	use frontend::input::{Key, State};
+
+	const IDDQD: &'static [Key] = &[Key::I, Key::D, Key::D, Key::Q, Key::D];
+
+	fn type_keys(matcher: &mut SequenceMatcher, keys: &[Key], from_ms: u64, step_ms: u64) {
+		for (i, &key) in keys.iter().enumerate() {
+			let at = Duration::from_millis(from_ms + i as u64 * step_ms);
+			matcher.key_at(State::Down, key, at);
+			matcher.key_at(State::Up, key, at);
+		}
+	}
+
+	#[test]
+	fn partial_sequences_do_not_match() {
+		let timeout = Duration::from_millis(500);
+		let mut matcher = SequenceMatcher::new(8);
+		type_keys(&mut matcher, &IDDQD[..4], 0, 100);
+		assert!(!matcher.matches(IDDQD, timeout));
+		type_keys(&mut matcher, &[Key::D], 400, 100);
+		assert!(matcher.matches(IDDQD, timeout));
+		assert!(matcher.matches(&[Key::Q, Key::D], timeout));
+	}
+
+	#[test]
+	fn unrelated_keys_and_pauses_reset_the_sequence() {
+		let timeout = Duration::from_millis(500);
+		let mut matcher = SequenceMatcher::new(8);
+		type_keys(&mut matcher, &[Key::I, Key::D, Key::X, Key::D, Key::Q, Key::D], 0, 100);
+		assert!(!matcher.matches(IDDQD, timeout));
+
+		matcher.clear();
+		type_keys(&mut matcher, &IDDQD[..2], 0, 100);
+		type_keys(&mut matcher, &IDDQD[2..], 1000, 100);
+		assert!(!matcher.matches(IDDQD, timeout));
+	}
+
+	#[test]
+	fn presses_timed_out_of_order_still_match() {
+		let mut matcher = SequenceMatcher::new(8);
+		type_keys(&mut matcher, &[Key::Q], 500, 0);
+		type_keys(&mut matcher, &[Key::D], 100, 0);
+		assert!(matcher.matches(&[Key::Q, Key::D], Duration::from_millis(500)));
+	}
+
+	#[test]
+	fn held_keys_press_once() {
+		let mut matcher = SequenceMatcher::new(8);
+		for ms in 0..3 {
+			matcher.key_at(State::Down, Key::D, Duration::from_millis(ms * 30));
+		}
+		assert!(matcher.matches(&[Key::D], Duration::from_millis(500)));
+		assert!(!matcher.matches(&[Key::D, Key::D], Duration::from_millis(500)));
+	}
+}
@@ -1,2 +1,4 @@
 pub mod render;
 pub mod input;
+pub mod capture;
+pub mod camera;
@@ -0,0 +1,77 @@
+//! Holds back the draws of a frame and groups the ones sharing a mesh, so that each group goes out
+//! as one instanced draw call rather than one call per object.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+
+pub struct Batch<M, I> {
+	pub mesh: M,
+	pub instances: Vec<I>,
+}
+
+pub struct Batches<K: Hash + Eq, M, I> {
+	index: HashMap<K, usize>,
+	batches: Vec<Batch<M, I>>,
+}
+
+impl<K: Hash + Eq, M, I> Batches<K, M, I> {
+	pub fn new() -> Self {
+		Batches {
+			index: HashMap::new(),
+			batches: Vec::new(),
+		}
+	}
+
+	/// Queues an instance of the mesh known by `key`, building the mesh with `mesh` the first time the key is seen
+	pub fn push<F>(&mut self, key: K, mesh: F, instance: I)
+		where F: FnOnce() -> M {
+		let batches = &mut self.batches;
+		let i = *self.index.entry(key).or_insert_with(|| {
+			batches.push(Batch {
+				mesh: mesh(),
+				instances: Vec::new(),
+			});
+			batches.len() - 1
+		});
+		batches[i].instances.push(instance);
+	}
+
+	/// How many draw calls the queued instances take
+	pub fn len(&self) -> usize {
+		self.batches.len()
+	}
+
+	/// Takes every batch, in the order its mesh was first queued, and starts over
+	pub fn drain(&mut self) -> Vec<Batch<M, I>> {
+		self.index.clear();
+		mem::replace(&mut self.batches, Vec::new())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn instances_of_a_mesh_share_a_batch() {
+		let mut batches = Batches::new();
+		let mut built = 0;
+		for i in 0..10 {
+			batches.push(i % 3,
+			             || {
+				             built += 1;
+				             i % 3
+			             },
+			             i);
+		}
+		assert_eq!(3, built);
+		assert_eq!(3, batches.len());
+		let drained = batches.drain();
+		assert_eq!(vec![0, 1, 2], drained.iter().map(|b| b.mesh).collect::<Vec<_>>());
+		assert_eq!(vec![0, 3, 6, 9], drained[0].instances);
+		assert_eq!(vec![2, 5, 8], drained[2].instances);
+		assert_eq!(0, batches.len());
+		batches.push(1, || 1, 0);
+		assert_eq!(1, batches.drain()[0].instances.len());
+	}
+}
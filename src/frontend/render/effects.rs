@@ -27,25 +27,69 @@ gfx_defines!{
 		fragment_args: gfx::ConstantBuffer<SmoothFragmentArgs> = "cb_FragmentArgs",
 		dst: gfx::RenderTarget<HDR> = "o_Smooth",
 	}
+	constant HighlightFragmentArgs {
+        threshold: f32 = "u_Threshold",
+    }
+	pipeline highlight {
+		vbuf: gfx::VertexBuffer<BlitVertex> = (),
+		fragment_args: gfx::ConstantBuffer<HighlightFragmentArgs> = "cb_FragmentArgs",
+		src: gfx::TextureSampler<[f32; 4]> = "t_Source",
+		dst: gfx::RenderTarget<HDR> = "o_Color",
+	}
+	pipeline accumulate {
+		vbuf: gfx::VertexBuffer<BlitVertex> = (),
+		src: gfx::TextureSampler<[f32; 4]> = "t_Source",
+		dst: gfx::BlendTarget<HDR> = ("o_Color", gfx::state::MASK_ALL, gfx::preset::blend::ADD),
+	}
 	constant ToneMapVertexArgs {
         white: f32 = "u_White",
         black: f32 = "u_Black",
+    }
+	constant ToneMapFragmentArgs {
+        exposure: f32 = "u_Exposure",
     }
 	pipeline tone_map {
 		vbuf: gfx::VertexBuffer<BlitVertex> = (),
 		vertex_luminance: gfx::TextureSampler<[f32; 4]> = "t_VertexLuminance",
 		vertex_args: gfx::ConstantBuffer<ToneMapVertexArgs> = "cb_VertexArgs",
+		fragment_args: gfx::ConstantBuffer<ToneMapFragmentArgs> = "cb_FragmentArgs",
 		src: gfx::TextureSampler<[f32; 4]> = "t_Source",
 		dst: gfx::RenderTarget<HDR> = "o_Color",
 	}
+	constant ComposeFragmentArgs {
+        bloom: f32 = "u_Bloom",
+    }
 	pipeline compose {
 		vbuf: gfx::VertexBuffer<BlitVertex> = (),
+		fragment_args: gfx::ConstantBuffer<ComposeFragmentArgs> = "cb_FragmentArgs",
 		src1: gfx::TextureSampler<[f32; 4]> = "t_Source1",
 		src2: gfx::TextureSampler<[f32; 4]> = "t_Source2",
 		dst: gfx::RenderTarget<LDR> = "o_Color",
 	}
 }
 
+/// The scene luminance the exposure adapts to is weighed by `WHITE` over a floor of `BLACK`
+const WHITE: f32 = 4.0;
+const BLACK: f32 = 0.5;
+
+/// Glow is spread at as many ever coarser resolutions, each one blurring twice as wide as the last
+const BLOOM_LEVELS: usize = 3;
+
+/// How much brighter than white what glows at `level` must be: every level takes only what is twice as bright
+/// as the last, so that the brighter a segment is lit by its charge, the wider it glows
+fn bloom_threshold(level: usize) -> f32 {
+	(1 << level) as f32
+}
+
+/// The constants of the tone mapping pass: how it adapts to the scene, and the exposure it is scaled by
+fn tone_map_args(exposure: f32) -> (ToneMapVertexArgs, ToneMapFragmentArgs) {
+	let vertex_args = ToneMapVertexArgs {
+		white: WHITE,
+		black: BLACK,
+	};
+	(vertex_args, ToneMapFragmentArgs { exposure: exposure })
+}
+
 use std::marker::PhantomData;
 pub type HDRRenderSurface<R> = (gfx::handle::Texture<R, gfx::format::R16_G16_B16_A16>,
                                 gfx::handle::ShaderResourceView<R, [f32; 4]>,
@@ -59,7 +103,8 @@ pub struct PostLighting<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
 	resolved: HDRRenderSurface<R>,
 	resolve_msaa_pso: gfx::pso::PipelineState<R, postprocess::Meta>,
 
-	ping_pong_half: [HDRRenderSurface<R>; 2],
+	/// a pair to blur back and forth in for each of the `BLOOM_LEVELS`, from half the resolution down
+	bloom_levels: Vec<[HDRRenderSurface<R>; 2]>,
 	ping_pong_full: [HDRRenderSurface<R>; 2],
 
 	mips: Vec<HDRRenderSurface<R>>,
@@ -72,13 +117,17 @@ pub struct PostLighting<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
 	smooth_fragment_args: gfx::handle::Buffer<R, SmoothFragmentArgs>,
 	smooth_pso: gfx::pso::PipelineState<R, smooth::Meta>,
 
-	highlight_pso: gfx::pso::PipelineState<R, postprocess::Meta>,
+	highlight_fragment_args: gfx::handle::Buffer<R, HighlightFragmentArgs>,
+	highlight_pso: gfx::pso::PipelineState<R, highlight::Meta>,
+	accumulate_pso: gfx::pso::PipelineState<R, accumulate::Meta>,
 	blur_h_pso: gfx::pso::PipelineState<R, postprocess::Meta>,
 	blur_v_pso: gfx::pso::PipelineState<R, postprocess::Meta>,
 
 	tone_map_vertex_args: gfx::handle::Buffer<R, ToneMapVertexArgs>,
+	tone_map_fragment_args: gfx::handle::Buffer<R, ToneMapFragmentArgs>,
 	tone_map_pso: gfx::pso::PipelineState<R, tone_map::Meta>,
 
+	compose_fragment_args: gfx::handle::Buffer<R, ComposeFragmentArgs>,
 	compose_pso: gfx::pso::PipelineState<R, compose::Meta>,
 
 	_buffer: PhantomData<C>,
@@ -111,7 +160,10 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 			                                                  gfx::tex::WrapMode::Clamp));
 
 		let tone_map_vertex_args = factory.create_constant_buffer(1);
+		let tone_map_fragment_args = factory.create_constant_buffer(1);
 		let smooth_fragment_args = factory.create_constant_buffer(1);
+		let compose_fragment_args = factory.create_constant_buffer(1);
+		let highlight_fragment_args = factory.create_constant_buffer(1);
 
 		macro_rules! load_pipeline_simple {
 			($v:expr, $f:expr, $s:ident) => { factory.create_pipeline_simple(
@@ -122,7 +174,8 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 
 		let tone_map_pso = try!(load_pipeline_simple!("luminance", "exposure_tone_map", tone_map));
 		let resolve_msaa_pso = try!(load_pipeline_simple!("identity", "msaa4x_resolve", postprocess));
-		let highlight_pso = try!(load_pipeline_simple!("identity", "clip_luminance", postprocess));
+		let highlight_pso = try!(load_pipeline_simple!("identity", "clip_luminance", highlight));
+		let accumulate_pso = try!(load_pipeline_simple!("identity", "simple_blit", accumulate));
 		let blur_h_pso = try!(load_pipeline_simple!("identity", "gaussian_blur_horizontal", postprocess));
 		let blur_v_pso = try!(load_pipeline_simple!("identity", "gaussian_blur_vertical", postprocess));
 		let blit_pso = try!(load_pipeline_simple!("identity", "simple_blit", postprocess));
@@ -132,8 +185,12 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 
 		let resolved = try!(factory.create_render_target::<HDR>(w, h));
 
-		let ping_pong_half = [try!(factory.create_render_target::<HDR>(w / 2, h / 2)),
-		                      try!(factory.create_render_target::<HDR>(w / 2, h / 2))];
+		let mut bloom_levels = Vec::new();
+		for level in 0..BLOOM_LEVELS {
+			let (w2, h2) = (::std::cmp::max(w >> (level + 1), 1), ::std::cmp::max(h >> (level + 1), 1));
+			bloom_levels.push([try!(factory.create_render_target::<HDR>(w2, h2)),
+			                   try!(factory.create_render_target::<HDR>(w2, h2))]);
+		}
 
 		let ping_pong_full = [try!(factory.create_render_target::<HDR>(w, h)),
 		                      try!(factory.create_render_target::<HDR>(w, h))];
@@ -164,21 +221,25 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 			smooth_pso: smooth_pso,
 
 			tone_map_vertex_args: tone_map_vertex_args,
+			tone_map_fragment_args: tone_map_fragment_args,
 			tone_map_pso: tone_map_pso,
 
+			highlight_fragment_args: highlight_fragment_args,
 			highlight_pso: highlight_pso,
+			accumulate_pso: accumulate_pso,
 			blur_h_pso: blur_h_pso,
 			blur_v_pso: blur_v_pso,
 			resolve_msaa_pso: resolve_msaa_pso,
 
 			resolved: resolved,
+			compose_fragment_args: compose_fragment_args,
 			compose_pso: compose_pso,
 
 			mips: mips,
 			luminance_smooth: luminance_smooth,
 			luminance_acc: luminance_acc,
 
-			ping_pong_half: ping_pong_half,
+			bloom_levels: bloom_levels,
 			ping_pong_full: ping_pong_full,
 
 			_buffer: PhantomData,
@@ -197,12 +258,14 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 		             });
 	}
 
+	/// Resolves and tone maps the lit scene into `color_target`, scaling the adapted exposure by `exposure`.
+	/// With a `bloom` intensity, whatever is brighter than white glows by that much. The brighter it is, the more
+	/// of the ever wider bloom levels it shows up in, so the most charged segments glow the furthest.
 	pub fn apply_all(&mut self, encoder: &mut gfx::Encoder<R, C>,
 	                 raw_hdr_src: gfx::handle::ShaderResourceView<R, [f32; 4]>,
-	                 color_target: gfx::handle::RenderTargetView<R, LDR>) {
+	                 color_target: gfx::handle::RenderTargetView<R, LDR>, exposure: f32, bloom: Option<f32>) {
 
 		let ping_pong_full = &self.ping_pong_full[..];
-		let ping_pong_half = &self.ping_pong_half[..];
 
 		// blits smoothed luminance to "acc" buffer. TODO: pingpong
 		self.full_screen_pass(encoder,
@@ -235,17 +298,16 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 		             });
 
 		// Tone mapping
-		encoder.update_constant_buffer(&self.tone_map_vertex_args,
-		                               &ToneMapVertexArgs {
-			                               white: 4.0,
-			                               black: 0.5,
-		                               });
+		let (vertex_args, fragment_args) = tone_map_args(exposure);
+		encoder.update_constant_buffer(&self.tone_map_vertex_args, &vertex_args);
+		encoder.update_constant_buffer(&self.tone_map_fragment_args, &fragment_args);
 		encoder.draw(&self.index_buffer_slice,
 		             &self.tone_map_pso,
 		             &tone_map::Data {
 			             vbuf: self.vertex_buffer.clone(),
 			             vertex_luminance: (self.luminance_smooth.1.clone(), self.nearest_sampler.clone()),
 			             vertex_args: self.tone_map_vertex_args.clone(),
+			             fragment_args: self.tone_map_fragment_args.clone(),
 			             src: (self.resolved.1.clone(), self.nearest_sampler.clone()),
 			             dst: ping_pong_full[0].2.clone(),
 		             });
@@ -255,33 +317,120 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> PostLighting<R, C> {
 		                      &self.blit_pso,
 		                      &self.luminance_smooth.1,
 		                      &self.luminance_acc.2);
-		// Bloom
-		// 1. extract high luminance
-		self.full_screen_pass(encoder,
-		                      &self.highlight_pso,
-		                      &ping_pong_full[0].1,
-		                      &ping_pong_half[0].2);
-		// 2. horizontal 4x, 9x9 gaussian blur
-		self.full_screen_pass(encoder,
-		                      &self.blur_h_pso,
-		                      &ping_pong_half[0].1,
-		                      &ping_pong_half[1].2);
-		// 2. vertical 4x, 9x9 gaussian blur
-		self.full_screen_pass(encoder,
-		                      &self.blur_v_pso,
-		                      &ping_pong_half[1].1,
-		                      &ping_pong_half[0].2);
+		// Bloom, skipped altogether when off so that slow GPUs are spared the passes
+		if bloom.is_some() {
+			for (level, surfaces) in self.bloom_levels.iter().enumerate() {
+				// 1. extract what is bright enough to glow this wide
+				encoder.update_constant_buffer(&self.highlight_fragment_args,
+				                               &HighlightFragmentArgs { threshold: bloom_threshold(level) });
+				encoder.draw(&self.index_buffer_slice,
+				             &self.highlight_pso,
+				             &highlight::Data {
+					             vbuf: self.vertex_buffer.clone(),
+					             fragment_args: self.highlight_fragment_args.clone(),
+					             src: (ping_pong_full[0].1.clone(), self.nearest_sampler.clone()),
+					             dst: surfaces[0].2.clone(),
+				             });
+				// 2. horizontal 9x9 gaussian blur, the coarser the level the wider on screen
+				self.full_screen_pass(encoder, &self.blur_h_pso, &surfaces[0].1, &surfaces[1].2);
+				// 3. vertical 9x9 gaussian blur
+				self.full_screen_pass(encoder, &self.blur_v_pso, &surfaces[1].1, &surfaces[0].2);
+			}
+			// 4. the wider glows add up onto the narrower ones, down to the finest
+			for level in (1..self.bloom_levels.len()).rev() {
+				encoder.draw(&self.index_buffer_slice,
+				             &self.accumulate_pso,
+				             &accumulate::Data {
+					             vbuf: self.vertex_buffer.clone(),
+					             src: (self.bloom_levels[level][0].1.clone(), self.linear_sampler.clone()),
+					             dst: self.bloom_levels[level - 1][0].2.clone(),
+				             });
+			}
+		}
+		encoder.update_constant_buffer(&self.compose_fragment_args,
+		                               &ComposeFragmentArgs { bloom: bloom.unwrap_or(0.).max(0.) });
 
 		// compose tone mapped + bloom and resolve
 		encoder.draw(&self.index_buffer_slice,
 		             &self.compose_pso,
 		             &compose::Data {
 			             vbuf: self.vertex_buffer.clone(),
+			             fragment_args: self.compose_fragment_args.clone(),
 			             // original
 			             src1: (ping_pong_full[0].1.clone(), self.nearest_sampler.clone()),
 			             // bloom
-			             src2: (ping_pong_half[0].1.clone(), self.linear_sampler.clone()),
+			             src2: (self.bloom_levels[0][0].1.clone(), self.linear_sampler.clone()),
 			             dst: color_target.clone(),
 		             });
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Mirrors compose_2.frag
+	fn tone_map(hdr: f32, bloom: f32, intensity: f32) -> f32 {
+		let hdr = hdr + bloom * intensity;
+		hdr / (1. + hdr)
+	}
+
+	/// Mirrors luminance.vert and exposure_tone_map.frag, then compose_2.frag without bloom
+	fn exposed(hdr: f32, luminance: f32, exposure: f32) -> f32 {
+		let (vertex_args, fragment_args) = tone_map_args(exposure);
+		let adapted = 1. / (vertex_args.black + vertex_args.white * luminance);
+		tone_map(fragment_args.exposure * adapted * hdr, 0., 0.)
+	}
+
+	#[test]
+	fn exposure_scales_the_adapted_scene() {
+		let (vertex_args, fragment_args) = tone_map_args(2.);
+		assert_eq!(4., vertex_args.white);
+		assert_eq!(0.5, vertex_args.black);
+		assert_eq!(2., fragment_args.exposure);
+		// a scene of luminance 1/8 adapts to 1, so the exposure alone decides
+		assert_eq!(0.5, exposed(1., 0.125, 1.));
+		assert_eq!(0.75, exposed(1., 0.125, 3.));
+		assert_eq!(0., exposed(1., 0.125, 0.));
+		// a brighter scene is exposed down: 4 / (0.5 + 4 * 0.375) = 2
+		assert_eq!(2. / 3., exposed(4., 0.375, 1.));
+		// in the dark, the exposure is at most doubled
+		assert_eq!(0.5, exposed(0.5, 0., 1.));
+	}
+
+	/// Mirrors clip_luminance.frag: the levels a pixel of `luminance` glows at
+	fn glow_levels(luminance: f32) -> usize {
+		(0..BLOOM_LEVELS).filter(|&level| luminance > bloom_threshold(level)).count()
+	}
+
+	#[test]
+	fn brighter_segments_glow_wider() {
+		assert_eq!(vec![1., 2., 4.], (0..BLOOM_LEVELS).map(bloom_threshold).collect::<Vec<_>>());
+		assert_eq!(0, glow_levels(0.8));
+		// a segment lit ten times over by a full charge glows at every level, a barely charged one at the finest
+		assert_eq!(1, glow_levels(1.5));
+		assert_eq!(BLOOM_LEVELS, glow_levels(10.));
+		let levels = [0.5, 1.5, 2.5, 5., 10.];
+		for pair in levels.windows(2) {
+			assert!(glow_levels(pair[0]) <= glow_levels(pair[1]));
+		}
+	}
+
+	#[test]
+	fn bloom_only_brightens() {
+		assert_eq!(tone_map(0.5, 3., 0.), tone_map(0.5, 0., 1.));
+		assert!(tone_map(0.5, 3., 1.) > tone_map(0.5, 3., 0.5));
+		assert!(tone_map(0.5, 3., 1.) < 1.);
+	}
+
+	#[test]
+	fn tone_mapping_rolls_off_instead_of_clipping() {
+		// the brightest charged segments are lit ten times over
+		let levels = [0.01, 0.05, 0.5, 1., 4., 10., 40.];
+		for pair in levels.windows(2) {
+			assert!(tone_map(pair[0], 0., 0.) < tone_map(pair[1], 0., 0.));
+		}
+		assert!(tone_map(40., 0., 0.) < 1.);
+		assert!(tone_map(0.05, 0., 0.) > 0.04);
+	}
+}
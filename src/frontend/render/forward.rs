@@ -3,7 +3,10 @@ use gfx::traits::FactoryExt;
 use std::result;
 use frontend::render::Result;
 use frontend::render::RenderFactoryExt;
+use frontend::render::HDRColorFormat;
 use core::resource;
+use core::geometry::Rect;
+use core::geometry::M44;
 
 extern crate cgmath;
 extern crate gfx_text;
@@ -27,7 +30,6 @@ impl Default for VertexPosNormal {
 }
 
 pub type Vertex = VertexPosNormal;
-pub type HDRColorFormat = (gfx::format::R16_G16_B16_A16, gfx::format::Float);
 pub type ColorFormat = gfx::format::Rgba8;
 pub type DepthFormat = gfx::format::DepthStencil;
 
@@ -35,8 +37,6 @@ const MAX_NUM_TOTAL_LIGHTS: usize = 16;
 
 pub type GFormat = [f32; 4];
 
-pub type M44 = cgmath::Matrix4<f32>;
-
 gfx_defines!(
     constant PointLight {
         propagation: [f32; 4] = "propagation",
@@ -49,25 +49,29 @@ gfx_defines!(
         view: [[f32; 4]; 4] = "u_View",
     }
 
-    constant ModelArgs {
-        model: [[f32; 4]; 4] = "u_Model",
+    vertex Instance {
+        model0: [f32; 4] = "a_Model0",
+        model1: [f32; 4] = "a_Model1",
+        model2: [f32; 4] = "a_Model2",
+        model3: [f32; 4] = "a_Model3",
+        emissive: [f32; 4] = "a_Emissive",
+        effect: [f32; 4] = "a_Effect",
     }
 
     constant FragmentArgs {
+        light_model: [f32; 4] = "u_LightModel",
+        ambient: [f32; 4] = "u_Ambient",
+        light_direction: [f32; 4] = "u_LightDirection",
+        directional_color: [f32; 4] = "u_DirectionalColor",
         light_count: i32 = "u_LightCount",
+        two_sided: i32 = "u_TwoSided",
     }
 
-	constant MaterialArgs {
-		emissive: [f32; 4] = "u_Emissive",
-		effect: [f32; 4] = "u_Effect",
-	}
-
     pipeline shaded {
         vbuf: gfx::VertexBuffer<VertexPosNormal> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
         camera_args: gfx::ConstantBuffer<CameraArgs> = "cb_CameraArgs",
-        model_args: gfx::ConstantBuffer<ModelArgs> = "cb_ModelArgs",
         fragment_args: gfx::ConstantBuffer<FragmentArgs> = "cb_FragmentArgs",
-        material_args: gfx::ConstantBuffer<MaterialArgs> = "cb_MaterialArgs",
         lights: gfx::ConstantBuffer<PointLight> = "u_Lights",
         color_target: gfx::BlendTarget<HDRColorFormat> = ("o_Color", gfx::state::MASK_ALL, gfx::preset::blend::ADD),
         depth_target: gfx::DepthTarget<gfx::format::DepthStencil> = gfx::preset::depth::LESS_EQUAL_WRITE,
@@ -75,7 +79,31 @@ gfx_defines!(
 );
 
 use std::marker::PhantomData;
+use std::cmp::Ordering;
 
+/// A light from far away, shining the same way everywhere
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+	/// the way it shines, towards the scene
+	pub direction: [f32; 3],
+	pub color: [f32; 4],
+}
+
+impl Instance {
+	pub fn new(transform: &M44, color: [f32; 4], effect: [f32; 4]) -> Self {
+		let model: [[f32; 4]; 4] = (*transform).into();
+		Instance {
+			model0: model[0],
+			model1: model[1],
+			model2: model[2],
+			model3: model[3],
+			emissive: color,
+			effect: effect,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Shader {
 	Ball = 0,
 	Flat = 1,
@@ -87,9 +115,7 @@ pub enum Shader {
 
 pub struct ForwardLighting<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
 	camera: gfx::handle::Buffer<R, CameraArgs>,
-	model: gfx::handle::Buffer<R, ModelArgs>,
 	fragment: gfx::handle::Buffer<R, FragmentArgs>,
-	material: gfx::handle::Buffer<R, MaterialArgs>,
 	lights: gfx::handle::Buffer<R, PointLight>,
 	pso: [gfx::pso::PipelineState<R, shaded::Meta>; Shader::Count as usize],
 	_buffer: PhantomData<C>,
@@ -100,9 +126,7 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> ForwardLighting<R, C> {
 		where F: gfx::Factory<R> {
 		let lights = factory.create_constant_buffer(MAX_NUM_TOTAL_LIGHTS);
 		let camera = factory.create_constant_buffer(1);
-		let model = factory.create_constant_buffer(1);
 		let fragment = factory.create_constant_buffer(1);
-		let material = factory.create_constant_buffer(1);
 
 		macro_rules! load_shaders {
 			($v:expr, $f:expr) => { factory.create_shader_set(
@@ -149,9 +173,7 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> ForwardLighting<R, C> {
 		                                         debug_line_rasterizer));
 		Ok(ForwardLighting {
 			camera: camera,
-			model: model,
 			fragment: fragment,
-			material: material,
 			lights: lights,
 			pso: [ball_pso, poly_pso, wireframe_pso, lines_pso, debug_lines_pso],
 			_buffer: PhantomData,
@@ -165,19 +187,12 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> ForwardLighting<R, C> {
 		factory.create_pipeline_state(&shaders, primitive, rasterizer, shaded::new())
 	}
 
-	pub fn setup(&self, encoder: &mut gfx::Encoder<R, C>, camera_projection: M44, camera_view: M44,
-	             lights: &Vec<PointLight>) {
-
-		let mut lights_buf = lights.clone();
+	/// Sets up the frame, lit by the lights that matter most to the area in `view`
+	pub fn setup(&self, encoder: &mut gfx::Encoder<R, C>, camera_projection: M44, camera_view: M44, view: &Rect,
+	             light_model: [f32; 4], ambient: [f32; 4], directional: &DirectionalLight, two_sided: bool,
+	             lights: &[(usize, PointLight)]) {
 
-		let count = lights_buf.len();
-		while lights_buf.len() < MAX_NUM_TOTAL_LIGHTS {
-			lights_buf.push(PointLight {
-				propagation: [0., 0., 0., 0.],
-				color: [0., 0., 0., 0.],
-				center: [0., 0., 0., 0.],
-			})
-		}
+		let (lights_buf, count) = light_buffer(lights, view, light_model);
 
 		if let Ok(_) = encoder.update_buffer(&self.lights, &lights_buf[..], 0) {
 			encoder.update_constant_buffer(&self.camera,
@@ -185,32 +200,219 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> ForwardLighting<R, C> {
 				                               proj: camera_projection.into(),
 				                               view: camera_view.into(),
 			                               });
-			encoder.update_constant_buffer(&self.fragment, &FragmentArgs { light_count: count as i32 });
+			encoder.update_constant_buffer(&self.fragment,
+			                               &fragment_args(light_model, ambient, directional, two_sided, count));
 		}
 	}
 
+	/// Draws the mesh once for every one of `instances`, in a single call
 	pub fn draw_primitives(&self, shader: Shader, encoder: &mut gfx::Encoder<R, C>,
 	                       vertices: gfx::handle::Buffer<R, VertexPosNormal>, indices: &gfx::Slice<R>,
-	                       transform: &M44, color: [f32; 4], effect: [f32; 4],
+	                       instances: gfx::handle::Buffer<R, Instance>,
 	                       color_buffer: &gfx::handle::RenderTargetView<R, HDRColorFormat>,
 	                       depth_buffer: &gfx::handle::DepthStencilView<R, DepthFormat>) {
-		encoder.update_constant_buffer(&self.model, &ModelArgs { model: (*transform).into() });
-		encoder.update_constant_buffer(&self.material,
-		                               &MaterialArgs {
-			                               emissive: color,
-			                               effect: effect,
-		                               });
-		encoder.draw(indices,
+		let slice = gfx::Slice { instances: Some((instances.len() as gfx::InstanceCount, 0)), ..indices.clone() };
+		encoder.draw(&slice,
 		             &self.pso[shader as usize],
 		             &shaded::Data {
 			             vbuf: vertices,
+			             instances: instances,
 			             fragment_args: self.fragment.clone(),
-			             material_args: self.material.clone(),
 			             camera_args: self.camera.clone(),
-			             model_args: self.model.clone(),
 			             lights: self.lights.clone(),
 			             color_target: color_buffer.clone(),
 			             depth_target: depth_buffer.clone(),
 		             });
 	}
 }
+
+/// The constants of the lighting shaders, for `count` lights in use
+pub fn fragment_args(light_model: [f32; 4], ambient: [f32; 4], directional: &DirectionalLight, two_sided: bool,
+                     count: usize)
+                     -> FragmentArgs {
+	FragmentArgs {
+		light_model: light_model,
+		ambient: ambient,
+		light_direction: [directional.direction[0], directional.direction[1], directional.direction[2], 0.],
+		directional_color: directional.color,
+		light_count: count as i32,
+		two_sided: two_sided as i32,
+	}
+}
+
+/// How bright `light` is at the point of `view` nearest to it, with the falloff of the lighting shaders
+pub fn light_contribution(light: &PointLight, view: &Rect, light_model: [f32; 4]) -> f32 {
+	let nearest = |x: f32, min: f32, max: f32| x - x.max(min).min(max);
+	let dx = nearest(light.center[0], view.min.x, view.max.x);
+	let dy = nearest(light.center[1], view.min.y, view.max.y);
+	let dz = light.center[2];
+	let inv_dist = 1. / (dx * dx + dy * dy + dz * dz).sqrt().max(1e-3);
+	let falloff = light.propagation[0] * light_model[0] + light.propagation[1] * light_model[1] * inv_dist +
+	              light.propagation[2] * light_model[2] * inv_dist * inv_dist;
+	let brightness = light.color[0].max(light.color[1]).max(light.color[2]);
+	brightness * falloff.max(0.)
+}
+
+/// Fills the light buffer with up to `MAX_NUM_TOTAL_LIGHTS` of `lights`, each listed with its id, padded with
+/// dark ones. When there are more, the ones contributing most to `view` are kept; either way the kept ones are
+/// laid out by id, whatever the order they were listed in. Returns the buffer and the number of lights in use.
+pub fn light_buffer(lights: &[(usize, PointLight)], view: &Rect, light_model: [f32; 4]) -> (Vec<PointLight>, usize) {
+	let count = ::std::cmp::min(lights.len(), MAX_NUM_TOTAL_LIGHTS);
+	let mut ranked = (0..lights.len()).collect::<Vec<_>>();
+	if lights.len() > MAX_NUM_TOTAL_LIGHTS {
+		let contributions = lights.iter()
+			.map(|&(_, ref l)| light_contribution(l, view, light_model))
+			.collect::<Vec<_>>();
+		// brightest first, the lower id first between equals
+		ranked.sort_by(|&a, &b| match contributions[b].partial_cmp(&contributions[a]).unwrap_or(Ordering::Equal) {
+			Ordering::Equal => lights[a].0.cmp(&lights[b].0),
+			brighter => brighter,
+		});
+		ranked.truncate(count);
+	}
+	ranked.sort_by_key(|&i| lights[i].0);
+	let mut buffer = ranked.into_iter().map(|i| lights[i].1).collect::<Vec<_>>();
+	while buffer.len() < MAX_NUM_TOTAL_LIGHTS {
+		buffer.push(PointLight {
+			propagation: [0., 0., 0., 0.],
+			color: [0., 0., 0., 0.],
+			center: [0., 0., 0., 0.],
+		})
+	}
+	(buffer, count)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::*;
+
+	fn light_at(x: f32) -> PointLight {
+		PointLight {
+			propagation: [0.2, 0.8, 0.1, 0.1],
+			center: [x, 0., 2., 1.],
+			color: [1.; 4],
+		}
+	}
+
+	/// The lights along the x axis, each with its position as the id
+	fn row(n: usize) -> Vec<(usize, PointLight)> {
+		(0..n).map(|i| (i, light_at(i as f32))).collect()
+	}
+
+	const LIGHT_MODEL: [f32; 4] = [1.; 4];
+
+	#[test]
+	fn lights_past_the_cap_are_the_dimmest() {
+		let lights = row(MAX_NUM_TOTAL_LIGHTS + 4);
+		let centers = |buffer: &[PointLight]| buffer.iter().map(|l| l.center[0]).collect::<Vec<_>>();
+		// looking at either end of the row, the lights at the other end go
+		let near_start = Rect::new(-10., -1., 0., 1.);
+		let (buffer, count) = light_buffer(&lights, &near_start, LIGHT_MODEL);
+		assert_eq!(MAX_NUM_TOTAL_LIGHTS, count);
+		assert_eq!((0..MAX_NUM_TOTAL_LIGHTS).map(|i| i as f32).collect::<Vec<_>>(), centers(&buffer));
+
+		let near_end = Rect::new(MAX_NUM_TOTAL_LIGHTS as f32 + 3., -1., 100., 1.);
+		let (buffer, _) = light_buffer(&lights, &near_end, LIGHT_MODEL);
+		assert_eq!((4..MAX_NUM_TOTAL_LIGHTS + 4).map(|i| i as f32).collect::<Vec<_>>(), centers(&buffer));
+
+		let (buffer, count) = light_buffer(&lights[..3], &near_end, LIGHT_MODEL);
+		assert_eq!(3, count);
+		assert_eq!(MAX_NUM_TOTAL_LIGHTS, buffer.len());
+		assert_eq!(vec![0., 1., 2.], centers(&buffer[..3]));
+	}
+
+	#[test]
+	fn lights_are_laid_out_by_id() {
+		// the ids, not the order they come in, decide where each light goes
+		let lights = vec![(7, light_at(7.)), (2, light_at(2.)), (5, light_at(5.))];
+		let (buffer, count) = light_buffer(&lights, &Rect::new(-1., -1., 1., 1.), LIGHT_MODEL);
+		assert_eq!(3, count);
+		assert_eq!(vec![2., 5., 7.], buffer[..3].iter().map(|l| l.center[0]).collect::<Vec<_>>());
+
+		let mut shuffled = row(MAX_NUM_TOTAL_LIGHTS + 4);
+		shuffled.reverse();
+		let (buffer, _) = light_buffer(&shuffled, &Rect::new(-10., -1., 0., 1.), LIGHT_MODEL);
+		assert_eq!(0., buffer[0].center[0]);
+		assert_eq!((MAX_NUM_TOTAL_LIGHTS - 1) as f32, buffer[MAX_NUM_TOTAL_LIGHTS - 1].center[0]);
+	}
+
+	#[test]
+	fn fragment_constants_carry_the_light_model() {
+		let directional = DirectionalLight {
+			direction: [0.5, -1., -2.],
+			color: [0.2, 0.3, 0.4, 1.],
+		};
+		for &light_model in &[LIGHT_MODEL, [0.; 4], [1., 0., 4., 1.], [0.5, 2., 0.25, 0.]] {
+			let args = fragment_args(light_model, [0.1; 4], &directional, true, 3);
+			assert_eq!(light_model, args.light_model);
+			assert_eq!([0.1; 4], args.ambient);
+			assert_eq!([0.5, -1., -2., 0.], args.light_direction);
+			assert_eq!(directional.color, args.directional_color);
+			assert_eq!(3, args.light_count);
+			assert_eq!(1, args.two_sided);
+		}
+		assert_eq!(0, fragment_args(LIGHT_MODEL, [0.; 4], &directional, false, 0).two_sided);
+		// the linear and quadratic terms fall off with the distance, the constant one doesn't
+		let light = light_at(0.);
+		let view = Rect::new(-1., -1., 1., 1.);
+		let constant = light_contribution(&light, &view, [1., 0., 0., 1.]);
+		assert_eq!(light.propagation[0], constant);
+		let quadratic = light_contribution(&light, &view, [0., 0., 1., 1.]);
+		assert!((quadratic - light.propagation[2] / 4.).abs() < 1e-6);
+		assert_eq!(0., light_contribution(&light, &view, [0.; 4]));
+	}
+
+	#[test]
+	fn a_bright_light_far_away_beats_a_dim_one_nearby() {
+		let view = Rect::new(-1., -1., 1., 1.);
+		let dim = PointLight { color: [0.01, 0.01, 0.01, 1.], ..light_at(0.) };
+		let bright = PointLight { color: [1., 0.5, 0., 1.], ..light_at(5.) };
+		assert!(light_contribution(&bright, &view, LIGHT_MODEL) > light_contribution(&dim, &view, LIGHT_MODEL));
+		// inside the view only the height counts
+		assert_eq!(light_contribution(&light_at(0.5), &view, LIGHT_MODEL),
+		           light_contribution(&light_at(-0.5), &view, LIGHT_MODEL));
+		assert!(light_contribution(&light_at(1.), &view, LIGHT_MODEL) >
+		        light_contribution(&light_at(3.), &view, LIGHT_MODEL));
+
+		let mut lights = (0..MAX_NUM_TOTAL_LIGHTS).map(|i| (i, dim)).collect::<Vec<_>>();
+		lights.push((MAX_NUM_TOTAL_LIGHTS, bright));
+		let (buffer, count) = light_buffer(&lights, &view, LIGHT_MODEL);
+		assert_eq!(MAX_NUM_TOTAL_LIGHTS, count);
+		assert_eq!(bright.color, buffer[MAX_NUM_TOTAL_LIGHTS - 1].color);
+	}
+
+	/// Mirrors lighting_poly.frag: with two-sided lighting on, normals facing away from the viewer are flipped
+	fn lambert(normal: Vector3<f32>, to_light: Vector3<f32>, two_sided: bool) -> f32 {
+		let normal = if two_sided && normal.z < 0. { -normal } else { normal };
+		normal.dot(to_light).max(0.)
+	}
+
+	fn face_normal(p: &[Vector3<f32>; 3]) -> Vector3<f32> {
+		(p[1] - p[0]).cross(p[2] - p[0]).normalize()
+	}
+
+	#[test]
+	fn both_windings_light_alike() {
+		let a = Vector3::new(0., 1., 0.);
+		let b = Vector3::new(-1., -1., 0.);
+		let c = Vector3::new(1., -1., 0.);
+		let ccw = face_normal(&[a, b, c]);
+		let cw = face_normal(&[a, c, b]);
+		// (b - a) x (c - a) = (-1, -2, 0) x (1, -2, 0) = (0, 0, 4)
+		assert_eq!(Vector3::new(0., 0., 1.), ccw);
+		assert_eq!(Vector3::new(0., 0., -1.), cw);
+		let frontal = Vector3::new(0., 0., 1.);
+		assert_eq!(1., lambert(ccw, frontal, false));
+		assert_eq!(0., lambert(cw, frontal, false));
+		assert_eq!(1., lambert(ccw, frontal, true));
+		assert_eq!(1., lambert(cw, frontal, true));
+		// a light 0.6 off to the side and 0.8 up lights either face at cos = 0.8
+		let oblique = Vector3::new(0.6, 0., 0.8);
+		assert_eq!(0.8, lambert(cw, oblique, true));
+		assert_eq!(0.8, lambert(ccw, oblique, true));
+		// a bumped normal facing away, (0.6, 0, -0.8), turns to (-0.6, 0, 0.8): 0.8 for the frontal light
+		assert!((lambert(Vector3::new(0.6, 0., -0.8), frontal, true) - 0.8).abs() < 1e-6);
+		assert_eq!(0., lambert(Vector3::new(0.6, 0., -0.8), frontal, false));
+	}
+}
@@ -0,0 +1,107 @@
+//! Text drawn over the finished frame, after the shaded pass and without a depth test, so that nothing
+//! in the scene hides it. A line is anchored either to the window or to a point in the world, and the lines
+//! sharing an anchor stack downwards in the order they were added.
+use core::geometry::Position;
+use core::geometry::Size;
+use frontend::render::Camera;
+use frontend::render::Draw;
+use frontend::render::Rgba;
+
+/// Pixels from the top of a line to the top of the next
+const LINE_HEIGHT: i32 = 18;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+	/// pixels from the top left corner of the window
+	Screen(i32, i32),
+	/// a point on the z=0 plane, the text following it as the camera moves
+	World(Position),
+}
+
+struct Label {
+	anchor: Anchor,
+	text: String,
+	color: Rgba,
+}
+
+pub struct HudRenderer {
+	labels: Vec<Label>,
+}
+
+impl HudRenderer {
+	pub fn new() -> Self {
+		HudRenderer { labels: Vec::new() }
+	}
+
+	/// Adds a white line under the last one at `anchor`
+	pub fn line(&mut self, anchor: Anchor, text: &str) {
+		self.colored_line(anchor, text, [1.; 4]);
+	}
+
+	pub fn colored_line(&mut self, anchor: Anchor, text: &str, color: Rgba) {
+		self.labels.push(Label {
+			anchor: anchor,
+			text: text.to_string(),
+			color: color,
+		});
+	}
+
+	/// Where each line goes in a window of size `viewport` seen through `camera`, in the order they were added.
+	/// Lines anchored to a point the camera doesn't see are left out.
+	pub fn layout(&self, camera: &Camera, viewport: Size) -> Vec<([i32; 2], &str, Rgba)> {
+		let visible = camera.visible_area();
+		let mut stacks: Vec<(Anchor, i32)> = Vec::new();
+		let mut placed = Vec::new();
+		for label in &self.labels {
+			let origin = match label.anchor {
+				Anchor::Screen(x, y) => [x, y],
+				Anchor::World(p) if visible.contains(p) => {
+					let screen = camera.project(p, viewport);
+					[screen.x as i32, screen.y as i32]
+				}
+				Anchor::World(_) => continue,
+			};
+			let row = match stacks.iter().position(|&(anchor, _)| anchor == label.anchor) {
+				Some(i) => {
+					stacks[i].1 += 1;
+					stacks[i].1
+				}
+				None => {
+					stacks.push((label.anchor, 0));
+					0
+				}
+			};
+			placed.push(([origin[0], origin[1] + row * LINE_HEIGHT], label.text.as_str(), label.color));
+		}
+		placed
+	}
+
+	pub fn draw(&self, renderer: &mut Draw, camera: &Camera, viewport: Size) {
+		for (position, text, color) in self.layout(camera, viewport) {
+			renderer.draw_text(text, position, color);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::Rect;
+
+	#[test]
+	fn lines_stack_under_their_anchor() {
+		let camera = Camera::orthographic(&Rect::new(-10., -10., 10., 10.), 10., -10.);
+		let viewport = Size::new(200., 100.);
+		let mut hud = HudRenderer::new();
+		hud.line(Anchor::Screen(10, 10), "stats");
+		hud.line(Anchor::World(Position::new(0., 0.)), "label");
+		hud.line(Anchor::World(Position::new(50., 0.)), "out of sight");
+		hud.colored_line(Anchor::Screen(10, 10), "more stats", [1., 0., 0., 1.]);
+		hud.line(Anchor::World(Position::new(0., 0.)), "more label");
+		let placed = hud.layout(&camera, viewport);
+		assert_eq!(vec![([10, 10], "stats"), ([100, 50], "label"), ([10, 10 + LINE_HEIGHT], "more stats"),
+		                ([100, 50 + LINE_HEIGHT], "more label")],
+		           placed.iter().map(|&(p, text, _)| (p, text)).collect::<Vec<_>>());
+		assert_eq!([1., 0., 0., 1.], placed[2].2);
+	}
+}
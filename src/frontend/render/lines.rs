@@ -0,0 +1,156 @@
+use gfx;
+use gfx::traits::FactoryExt;
+use std::marker::PhantomData;
+use core::geometry::M44;
+use core::geometry::Position;
+use core::resource;
+use frontend::render::Result;
+use frontend::render::HDRColorFormat;
+
+gfx_defines!(
+	vertex LineVertex {
+		start: [f32; 2] = "a_Start",
+		end: [f32; 2] = "a_End",
+		corner: [f32; 2] = "a_Corner",
+		color: [f32; 4] = "a_Color",
+		width: f32 = "a_Width",
+	}
+
+	constant LineArgs {
+		proj_view: [[f32; 4]; 4] = "u_ProjView",
+		viewport: [f32; 4] = "u_Viewport",
+	}
+
+	pipeline line {
+		vbuf: gfx::VertexBuffer<LineVertex> = (),
+		line_args: gfx::ConstantBuffer<LineArgs> = "cb_LineArgs",
+		color_target: gfx::BlendTarget<HDRColorFormat> = ("o_Color", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+	}
+);
+
+#[derive(Clone, Copy)]
+pub enum Space {
+	/// positions are in world units
+	World,
+	/// positions are in pixels, from the bottom left corner
+	Screen,
+}
+
+#[derive(Clone)]
+pub struct Line {
+	pub start: Position,
+	pub end: Position,
+	pub color: [f32; 4],
+	/// in pixels, whatever the zoom
+	pub width: f32,
+}
+
+impl Line {
+	pub fn new(start: Position, end: Position, color: [f32; 4], width: f32) -> Self {
+		Line {
+			start: start,
+			end: end,
+			color: color,
+			width: width,
+		}
+	}
+}
+
+const CORNERS: [[f32; 2]; 4] = [[0., -1.], [0., 1.], [1., 1.], [1., -1.]];
+
+/// Expands each line into a quad, the vertex shader pushes the corners apart in screen space
+pub fn tessellate(lines: &[Line]) -> (Vec<LineVertex>, Vec<u32>) {
+	let mut vertices = Vec::with_capacity(lines.len() * 4);
+	let mut indices = Vec::with_capacity(lines.len() * 6);
+	for line in lines {
+		let base = vertices.len() as u32;
+		for corner in &CORNERS {
+			vertices.push(LineVertex {
+				start: [line.start.x, line.start.y],
+				end: [line.end.x, line.end.y],
+				corner: *corner,
+				color: line.color,
+				width: line.width,
+			});
+		}
+		indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+	}
+	(vertices, indices)
+}
+
+/// Anti-aliased lines of constant pixel width, for overlays and debug drawing
+pub struct LineRenderer<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
+	args: gfx::handle::Buffer<R, LineArgs>,
+	pso: gfx::pso::PipelineState<R, line::Meta>,
+	_buffer: PhantomData<C>,
+}
+
+impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> LineRenderer<R, C> {
+	pub fn new<F>(factory: &mut F, res: &resource::ResourceLoader<u8>) -> Result<LineRenderer<R, C>>
+		where F: gfx::Factory<R> {
+		let shaders = try!(factory.create_shader_set(&try!(res.load("shaders/lines/line.vert")),
+		                                             &try!(res.load("shaders/lines/line.frag"))));
+		let rasterizer = gfx::state::Rasterizer::new_fill();
+		let pso = try!(factory.create_pipeline_state(&shaders,
+		                                             gfx::Primitive::TriangleList,
+		                                             rasterizer,
+		                                             line::new()));
+		Ok(LineRenderer {
+			args: factory.create_constant_buffer(1),
+			pso: pso,
+			_buffer: PhantomData,
+		})
+	}
+
+	/// Draws the whole list in a single batch
+	pub fn draw_lines<F>(&self, factory: &mut F, encoder: &mut gfx::Encoder<R, C>, proj_view: M44,
+	                     viewport: (u16, u16), space: Space, lines: &[Line],
+	                     color_buffer: &gfx::handle::RenderTargetView<R, HDRColorFormat>)
+		where F: gfx::Factory<R> {
+		if lines.is_empty() {
+			return;
+		}
+		let (vertices, indices) = tessellate(lines);
+		let (vertex_buffer, slice) = factory.create_vertex_buffer_with_slice(&vertices, indices.as_slice());
+		let screen_space = match space {
+			Space::World => 0.,
+			Space::Screen => 1.,
+		};
+		encoder.update_constant_buffer(&self.args,
+		                               &LineArgs {
+			                               proj_view: proj_view.into(),
+			                               viewport: [viewport.0 as f32, viewport.1 as f32, screen_space, 0.],
+		                               });
+		encoder.draw(&slice,
+		             &self.pso,
+		             &line::Data {
+			             vbuf: vertex_buffer,
+			             line_args: self.args.clone(),
+			             color_target: color_buffer.clone(),
+		             });
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::geometry::Position;
+
+	#[test]
+	fn tessellate_empty_list() {
+		let (vertices, indices) = tessellate(&[]);
+		assert!(vertices.is_empty());
+		assert!(indices.is_empty());
+	}
+
+	#[test]
+	fn tessellate_batches_all_lines() {
+		let lines = (0..100)
+			.map(|i| Line::new(Position::new(0., 0.), Position::new(i as f32, 1.), [1.; 4], 2.))
+			.collect::<Vec<_>>();
+		let (vertices, indices) = tessellate(&lines);
+		assert_eq!(400, vertices.len());
+		assert_eq!(600, indices.len());
+		assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+	}
+}
@@ -0,0 +1,115 @@
+//! The whole world in a corner of the window, every agent a dot, with the part the camera sees outlined.
+//! The map is looked at through an orthographic camera of its own, framing the extent in the map's rectangle.
+use core::geometry::Position;
+use core::geometry::Rect;
+use core::geometry::Size;
+use frontend::render::Camera;
+use frontend::render::Draw;
+use frontend::render::Line;
+use frontend::render::LineSpace;
+use frontend::render::Rgba;
+
+/// Pixels between the map and the corner of the window
+const MARGIN: f32 = 10.;
+/// Pixels across the dot of an agent
+const DOT_SIZE: f32 = 3.;
+const BACKDROP: Rgba = [0., 0., 0., 0.6];
+const VIEW_COLOR: Rgba = [1., 1., 1., 0.8];
+
+pub struct MinimapRenderer {
+	extent: Rect,
+	size: f32,
+}
+
+impl MinimapRenderer {
+	/// A map of `extent`, `size` pixels along its longer side
+	pub fn new(extent: Rect, size: f32) -> Self {
+		MinimapRenderer {
+			extent: extent,
+			size: size,
+		}
+	}
+
+	/// Where the map goes in a window of size `window`, in pixels from its top left corner
+	pub fn area(&self, window: Size) -> Rect {
+		let width = self.extent.max.x - self.extent.min.x;
+		let height = self.extent.max.y - self.extent.min.y;
+		let k = self.size / f32::max(width, height).max(1e-3);
+		let right = window.width - MARGIN;
+		let bottom = window.height - MARGIN;
+		Rect::new(right - width * k, bottom - height * k, right, bottom)
+	}
+
+	fn camera(&self) -> Camera {
+		Camera::orthographic(&self.extent, 10., -10.)
+	}
+
+	fn size_of(area: &Rect) -> Size {
+		Size::new(area.max.x - area.min.x, area.max.y - area.min.y)
+	}
+
+	/// The window pixel at which the map shows the world position `world`
+	pub fn project(&self, world: Position, window: Size) -> Position {
+		let area = self.area(window);
+		area.min + self.camera().project(world, Self::size_of(&area))
+	}
+
+	/// The world position the map shows at the window pixel `screen`, `None` off the map
+	pub fn unproject(&self, screen: Position, window: Size) -> Option<Position> {
+		let area = self.area(window);
+		if area.contains(screen) {
+			Some(self.camera().unproject(screen - area.min, Self::size_of(&area)))
+		} else {
+			None
+		}
+	}
+
+	/// Draws a dot for each of `dots` and outlines `view`, the part of the world the main camera sees
+	pub fn draw(&self, renderer: &mut Draw, window: Size, dots: &[(Position, Rgba)], view: Rect) {
+		// the line pass counts pixels from the bottom left corner, the window from the top left
+		let area = self.area(window);
+		let to_lines = |p: Position| Position::new(p.x, window.height - p.y);
+		let mut lines = Vec::with_capacity(dots.len() + 5);
+		// a single line as thick as the map is tall fills it
+		let middle = (area.min.y + area.max.y) * 0.5;
+		lines.push(Line::new(to_lines(Position::new(area.min.x, middle)),
+		                     to_lines(Position::new(area.max.x, middle)),
+		                     BACKDROP,
+		                     area.max.y - area.min.y));
+		let half_dot = Position::new(DOT_SIZE * 0.5, 0.);
+		for &(p, color) in dots {
+			let center = to_lines(self.project(self.extent.clamp(p), window));
+			lines.push(Line::new(center - half_dot, center + half_dot, color, DOT_SIZE));
+		}
+		let corners = [view.bottom_left(), view.bottom_right(), view.top_right(), view.top_left(), view.bottom_left()]
+			.iter()
+			.map(|&p| to_lines(self.project(self.extent.clamp(p), window)))
+			.collect::<Vec<_>>();
+		for edge in corners.windows(2) {
+			lines.push(Line::new(edge[0], edge[1], VIEW_COLOR, 1.));
+		}
+		renderer.draw_thick_lines(LineSpace::Screen, &lines);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::EuclideanVector;
+
+	#[test]
+	fn clicks_on_the_map_land_where_it_shows_them() {
+		let minimap = MinimapRenderer::new(Rect::new(-80., -40., 80., 40.), 160.);
+		let window = Size::new(800., 600.);
+		let area = minimap.area(window);
+		assert_eq!((630., 510., 790., 590.), (area.min.x, area.min.y, area.max.x, area.max.y));
+		// north is up on the map as in the world
+		assert!(minimap.project(Position::new(0., 40.), window).y < minimap.project(Position::new(0., -40.), window).y);
+		for &p in &[Position::new(0., 0.), Position::new(-70., 30.), Position::new(79., -39.)] {
+			let screen = minimap.project(p, window);
+			let back = minimap.unproject(screen, window).unwrap();
+			assert!((back - p).length() < 1e-3, "{:?} came back as {:?}", p, back);
+		}
+		assert!(minimap.unproject(Position::new(400., 300.), window).is_none());
+	}
+}
@@ -1,17 +1,32 @@
+mod batch;
 mod effects;
 mod forward;
+mod hud;
+mod lines;
+mod minimap;
 
 use std::clone::Clone;
 use core::resource::ResourceLoader;
 use core::color;
 use core::geometry::M44;
 use core::geometry::Position;
+use core::geometry::Rect;
+use core::geometry::Size;
 
 use cgmath;
+use cgmath::SquareMatrix;
+use cgmath::EuclideanVector;
 use frontend::render::forward::Vertex;
+pub use frontend::render::forward::DirectionalLight;
+
+pub use frontend::render::hud::{Anchor, HudRenderer};
+pub use frontend::render::lines::Line;
+pub use frontend::render::lines::Space as LineSpace;
+pub use frontend::render::minimap::MinimapRenderer;
 
 use std::convert;
 use std::fmt;
+use std::mem;
 use std::result;
 
 use gfx;
@@ -50,6 +65,18 @@ pub type DepthFormat = gfx::format::DepthStencil;
 pub type GFormat = Rgba;
 
 pub const BACKGROUND: Rgba = [0.01, 0.01, 0.01, 1.0];
+pub const LIGHT_MODEL: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/// how strongly the brightest segments glow
+pub const BLOOM: f32 = 1.0;
+/// dim enough for the point lights to stand out near the emitters
+pub const AMBIENT: Rgba = [0.05, 0.05, 0.06, 1.0];
+/// a faint cool light from the top left, over the viewer's shoulder
+pub const DIRECTIONAL_LIGHT: DirectionalLight = DirectionalLight {
+	direction: [0.4, -0.4, -1.0],
+	color: [0.08, 0.09, 0.12, 1.0],
+};
+/// how far the rim of a flat outline leans outwards, so that it still catches the light from the side
+const FLAT_BEVEL: f32 = 0.5;
 
 const QUAD_VERTICES: [Vertex; 4] = [Vertex {
 	                                    pos: [-1.0, -1.0, 0.0],
@@ -102,24 +129,90 @@ pub struct Camera {
 	pub view: M44,
 }
 
+#[derive(Clone, Copy)]
+pub enum Projection {
+	Orthographic,
+	/// vertical field of view, in radians
+	Perspective(f32),
+}
+
 impl Camera {
-	pub fn ortho(center: Position, scale: f32, ratio: f32) -> Camera {
+	/// Frames a viewport `scale` world units wide centered on `center`.
+	/// The perspective camera is pulled back so that the z=0 plane, where the agents live,
+	/// covers exactly the same area as the orthographic one and panning works the same for both
+	pub fn for_viewport(projection: Projection, center: Position, scale: f32, ratio: f32) -> Camera {
+		let hw = 0.5 * scale;
+		let hh = hw / ratio;
+		match projection {
+			Projection::Orthographic => {
+				Camera::orthographic(&Rect::new(center.x - hw, center.y - hh, center.x + hw, center.y + hh),
+				                     10.0,
+				                     -10.0)
+			}
+			Projection::Perspective(fov) => {
+				let distance = hh / (fov * 0.5).tan();
+				Camera::perspective(fov, ratio, distance * 0.1, distance * 10.0).looking_at(center, distance)
+			}
+		}
+	}
+
+	pub fn orthographic(extent: &Rect, near: f32, far: f32) -> Camera {
+		let center = (extent.min + extent.max) * 0.5;
+		let hw = 0.5 * (extent.max.x - extent.min.x);
+		let hh = 0.5 * (extent.max.y - extent.min.y);
 		Camera {
-			projection: {
-					let hw = 0.5 * scale;
-					let hh = hw / ratio;
-					let near = 10.0;
-					let far = -near;
-					cgmath::ortho(-hw, hw, -hh, hh, near, far)
-				}
-				.into(),
-			view: cgmath::Matrix4::look_at(cgmath::Point3::new(center.x, center.y, 1.0),
-			                               cgmath::Point3::new(center.x, center.y, 0.0),
-			                               cgmath::Vector3::unit_y())
-				.into(),
+			projection: cgmath::ortho(-hw, hw, -hh, hh, near, far),
+			view: Self::look_down(center, 1.0),
 		}
 	}
+
+	pub fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> Camera {
+		Camera {
+			projection: cgmath::perspective(cgmath::rad(fov), aspect, near, far),
+			view: cgmath::Matrix4::identity(),
+		}
+	}
+
+	pub fn looking_at(self, center: Position, distance: f32) -> Camera {
+		Camera { view: Self::look_down(center, distance), ..self }
+	}
+
+	/// The window pixel where `world`, on the z=0 plane, is drawn in a window of size `viewport`
+	pub fn project(&self, world: Position, viewport: Size) -> Position {
+		let clip = self.projection * self.view * cgmath::Vector4::new(world.x, world.y, 0., 1.);
+		Position::new((clip.x / clip.w + 1.) * 0.5 * viewport.width,
+		              (1. - clip.y / clip.w) * 0.5 * viewport.height)
+	}
+
+	/// The point on the z=0 plane drawn at the window pixel `screen`, undoing `project`.
+	/// The pixel is followed from the near to the far plane to where it crosses z=0, which works for either projection
+	pub fn unproject(&self, screen: Position, viewport: Size) -> Position {
+		let x = screen.x / viewport.width * 2. - 1.;
+		let y = 1. - screen.y / viewport.height * 2.;
+		let inverse = (self.projection * self.view).invert().unwrap_or(cgmath::Matrix4::identity());
+		let at_depth = |z: f32| {
+			let p = inverse * cgmath::Vector4::new(x, y, z, 1.);
+			cgmath::Vector3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+		};
+		let (near, far) = (at_depth(-1.), at_depth(1.));
+		let t = if far.z == near.z { 0. } else { near.z / (near.z - far.z) };
+		Position::new(near.x + (far.x - near.x) * t, near.y + (far.y - near.y) * t)
+	}
+
+	/// The part of the z=0 plane that fills the view, for either projection
+	pub fn visible_area(&self) -> Rect {
+		let unit = Size::new(1., 1.);
+		let (a, b) = (self.unproject(Position::new(0., 0.), unit), self.unproject(Position::new(1., 1.), unit));
+		Rect::new(a.x.min(b.x), a.y.min(b.y), a.x.max(b.x), a.y.max(b.y))
+	}
+
+	fn look_down(center: Position, distance: f32) -> M44 {
+		cgmath::Matrix4::look_at(cgmath::Point3::new(center.x, center.y, distance),
+		                         cgmath::Point3::new(center.x, center.y, 0.0),
+		                         cgmath::Vector3::unit_y())
+	}
 }
+
 #[derive(Debug)]
 pub enum RenderError {
 	Shader(String),
@@ -162,19 +255,38 @@ impl<R: gfx::Resources, E: gfx::traits::FactoryExt<R>> RenderFactoryExt<R> for E
 pub trait Draw {
 	fn draw_triangle(&mut self, transform: &cgmath::Matrix4<f32>, p: &[Position], appearance: &Appearance);
 	fn draw_quad(&mut self, transform: &cgmath::Matrix4<f32>, ratio: f32, appearance: &Appearance);
-	fn draw_star(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance);
+	/// Fills the outline `vertices` with the triangles of `indices`, three to a triangle, lit along the outward
+	/// rim `normals` and raised by `depth` off the rim; stars drawn with the same `key` share all of these
+	fn draw_star(&mut self, transform: &cgmath::Matrix4<f32>, key: u64, vertices: &[Position], normals: &[Position],
+	             indices: &[u16], depth: f32, appearance: &Appearance);
 	fn draw_lines(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance);
 	fn draw_debug_lines(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance);
 	fn draw_ball(&mut self, transform: &cgmath::Matrix4<f32>, appearance: &Appearance);
+	fn draw_thick_lines(&mut self, space: LineSpace, lines: &[Line]);
 	fn draw_text(&mut self, text: &str, screen_position: [i32; 2], text_color: Rgba);
 }
 
 pub trait Renderer<R: gfx::Resources, C: gfx::CommandBuffer<R>>: Draw {
-	fn setup_frame(&mut self, camera: &Camera, background_color: Rgba, light_color: Rgba, light_position: &[Position]);
+	/// Lit by a fixed fill light with the id 0 and by one light of `light_color` over each of the `emitters`,
+	/// listed with their ids
+	fn setup_frame(&mut self, camera: &Camera, background_color: Rgba, light_color: Rgba,
+	               emitters: &[(usize, Position)]);
+	fn set_light_model(&mut self, light_model: [f32; 4]);
+	fn set_two_sided_lighting(&mut self, two_sided: bool);
+	/// Sets the fill the whole scene gets, the same from every side
+	fn set_ambient(&mut self, ambient: Rgba);
+	/// Sets the light shining on the whole scene from far away
+	fn set_directional_light(&mut self, light: DirectionalLight);
+	/// Scales the exposure the tone mapping adapts to the scene, brighter over 1
+	fn set_exposure(&mut self, exposure: f32);
+	/// Makes whatever is brighter than white glow with `intensity`, no glow at all when `None`
+	fn set_bloom(&mut self, intensity: Option<f32>);
 	fn begin_frame(&mut self);
 	fn resolve_frame_buffer(&mut self);
 	fn end_frame<D: gfx::Device<Resources = R, CommandBuffer = C>>(&mut self, device: &mut D);
 	fn cleanup<D: gfx::Device<Resources = R, CommandBuffer = C>>(&mut self, device: &mut D);
+	/// How many draw calls the scene took since the frame began
+	fn draw_calls(&self) -> usize;
 }
 
 pub struct ForwardRenderer<'e,
@@ -196,7 +308,7 @@ pub struct ForwardRenderer<'e,
 	hdr_color: gfx::handle::RenderTargetView<R, HDRColorFormat>,
 
 	_quad_vertices: gfx::handle::Buffer<R, Vertex>,
-	quad_indices: gfx::Slice<R>,
+	_quad_indices: gfx::Slice<R>,
 
 	base_vertices: gfx::handle::Buffer<R, Vertex>,
 	base_indices: gfx::Slice<R>,
@@ -204,8 +316,19 @@ pub struct ForwardRenderer<'e,
 	text_renderer: gfx_text::Renderer<R, F>,
 	pass_forward_lighting: forward::ForwardLighting<R, C>,
 	pass_effects: effects::PostLighting<R, C>,
+	pass_lines: lines::LineRenderer<R, C>,
 
+	proj_view: M44,
 	background_color: Rgba,
+	light_model: [f32; 4],
+	ambient: Rgba,
+	directional_light: DirectionalLight,
+	exposure: f32,
+	bloom: Option<f32>,
+	two_sided: bool,
+
+	batches: batch::Batches<MeshKey, (forward::Shader, Geometry), forward::Instance>,
+	draw_calls: usize,
 }
 
 impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R> + Clone,
@@ -224,6 +347,7 @@ impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R> + Clone,
 
 		let forward = try!(forward::ForwardLighting::new(factory, res));
 		let effects = try!(effects::PostLighting::new(factory, res, w, h));
+		let lines = try!(lines::LineRenderer::new(factory, res));
 		let text_renderer = try!(gfx_text::new(factory.clone()).build().map_err(|_| RenderError::TextRenderer));
 
 		Ok(ForwardRenderer {
@@ -236,13 +360,23 @@ impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R> + Clone,
 			frame_buffer: frame_buffer.clone(),
 			text_renderer: text_renderer,
 			_quad_vertices: quad_vertices,
-			quad_indices: quad_indices,
+			_quad_indices: quad_indices,
 			base_vertices: base_vertices,
 			base_indices: base_indices,
 			pass_forward_lighting: forward,
 			pass_effects: effects,
+			pass_lines: lines,
+			proj_view: M44::identity(),
 			background_color: BACKGROUND, /* 			light_color: BLACK,
 			                               * 			light_position: cgmath::Vector2::new(0.0, 0.0), */
+			light_model: LIGHT_MODEL,
+			ambient: AMBIENT,
+			directional_light: DIRECTIONAL_LIGHT,
+			exposure: 1.,
+			bloom: Some(BLOOM),
+			two_sided: true,
+			batches: batch::Batches::new(),
+			draw_calls: 0,
 		})
 	}
 
@@ -252,8 +386,10 @@ impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R> + Clone,
 		let (w, h, _, _) = self.frame_buffer.get_dimensions();
 		let pass_forward_lighting = try!(forward::ForwardLighting::new(factory, self.res));
 		let pass_effects = try!(effects::PostLighting::new(factory, self.res, w, h));
+		let pass_lines = try!(lines::LineRenderer::new(factory, self.res));
 		self.pass_forward_lighting = pass_forward_lighting;
 		self.pass_effects = pass_effects;
+		self.pass_lines = pass_lines;
 		Ok(())
 	}
 
@@ -273,41 +409,82 @@ impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R> + Clone,
 	}
 }
 
-impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R>, L: ResourceLoader<u8>> Draw
-for ForwardRenderer<'e, 'l, R, C, F, L> {
-	fn draw_star(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance) {
-		let mut v: Vec<_> = vertices.iter()
-			.map(|v| {
-				Vertex {
-					pos: [v.x, v.y, 0.0],
-					normal: [0.0, 0.0, 1.0],
-					tangent: [1.0, 0.0, 0.0],
-					tex_coord: [0.5 + v.x * 0.5, 0.5 + v.y * 0.5],
-				}
-			})
-			.collect();
-		let n = v.len();
-		v.push(Vertex::default());
-
-// TODO: these can be cached
-		let mut i: Vec<u16> = Vec::new();
-		for k in 0..n {
-			i.push(n as u16);
-			i.push(((k + 1) % n) as u16);
-			i.push(k as u16);
+/// What a queued mesh is known by, draws with equal keys have the same vertices and go out together
+#[derive(PartialEq, Eq, Hash)]
+enum MeshKey {
+	/// the base vertices, blown up into a ball by the geometry shader
+	Base(forward::Shader),
+	/// an outline, by the key its mesh was given when built
+	Shaped(forward::Shader, u64),
+	/// the few vertices of their own lines, quads and triangles have, by the bits of every field, and their indices
+	Owned(forward::Shader, Box<[u32]>, Box<[u16]>),
+}
+
+enum Geometry {
+	Base,
+	/// drawn in order when there are no indices
+	Owned(Vec<Vertex>, Vec<u16>),
+}
+
+impl MeshKey {
+	/// Keys the mesh by the bits of every field of its vertices, so that equal meshes compare and hash equal
+	fn owned(shader: forward::Shader, vertices: &[Vertex], indices: &[u16]) -> Self {
+		let bits = |x: &f32| unsafe { mem::transmute::<f32, u32>(*x) };
+		let mut key = Vec::with_capacity(vertices.len() * 11);
+		for v in vertices {
+			key.extend(v.pos.iter().map(&bits));
+			key.extend(v.normal.iter().map(&bits));
+			key.extend(v.tangent.iter().map(&bits));
+			key.extend(v.tex_coord.iter().map(&bits));
 		}
+		MeshKey::Owned(shader, key.into_boxed_slice(), indices.to_vec().into_boxed_slice())
+	}
+}
 
-		let (vertex_buffer, index_buffer) = self.factory.create_vertex_buffer_with_slice(v.as_slice(), i.as_slice());
+impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R>, L: ResourceLoader<u8>>
+ForwardRenderer<'e, 'l, R, C, F, L> {
+	fn queue(&mut self, shader: forward::Shader, vertices: Vec<Vertex>, indices: Vec<u16>, transform: &M44,
+	         appearance: &Appearance) {
+		let key = MeshKey::owned(shader, &vertices, &indices);
+		self.batches.push(key,
+		                  move || (shader, Geometry::Owned(vertices, indices)),
+		                  forward::Instance::new(transform, appearance.color, appearance.effect));
+	}
+
+	/// Sends out everything queued so far, one draw call per mesh
+	fn flush(&mut self) {
+		for batch in self.batches.drain() {
+			let instances = self.factory.create_vertex_buffer(&batch.instances);
+			let (shader, geometry) = batch.mesh;
+			let (vertices, indices) = match geometry {
+				Geometry::Base => (self.base_vertices.clone(), self.base_indices.clone()),
+				Geometry::Owned(ref v, ref i) if i.is_empty() => self.factory.create_vertex_buffer_with_slice(v, ()),
+				Geometry::Owned(ref v, ref i) => self.factory.create_vertex_buffer_with_slice(v, &i[..]),
+			};
+			self.pass_forward_lighting.draw_primitives(shader,
+			                                           &mut self.encoder,
+			                                           vertices,
+			                                           &indices,
+			                                           instances,
+			                                           &mut self.hdr_color,
+			                                           &mut self.depth);
+			self.draw_calls += 1;
+		}
+	}
+}
 
-		self.pass_forward_lighting.draw_primitives(forward::Shader::Wireframe,
-		                                           &mut self.encoder,
-		                                           vertex_buffer,
-		                                           &index_buffer,
-		                                           &transform,
-		                                           appearance.color,
-		                                           appearance.effect,
-		                                           &mut self.hdr_color,
-		                                           &mut self.depth);
+impl<'e, 'l, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Factory<R>, L: ResourceLoader<u8>> Draw
+for ForwardRenderer<'e, 'l, R, C, F, L> {
+	fn draw_star(&mut self, transform: &cgmath::Matrix4<f32>, key: u64, vertices: &[Position], normals: &[Position],
+	             indices: &[u16], depth: f32, appearance: &Appearance) {
+		// extruded once a frame for however many stars share the mesh
+		let mesh = || {
+			let (v, i) = extrude(vertices, normals, indices, depth);
+			(forward::Shader::Wireframe, Geometry::Owned(v, i))
+		};
+		self.batches.push(MeshKey::Shaped(forward::Shader::Wireframe, key),
+		                  mesh,
+		                  forward::Instance::new(transform, appearance.color, appearance.effect));
 	}
 
 	fn draw_lines(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance) {
@@ -321,17 +498,7 @@ for ForwardRenderer<'e, 'l, R, C, F, L> {
 				}
 			})
 			.collect();
-		let (vertex_buffer, index_buffer) = self.factory.create_vertex_buffer_with_slice(v.as_slice(), ());
-
-		self.pass_forward_lighting.draw_primitives(forward::Shader::Lines,
-		                                           &mut self.encoder,
-		                                           vertex_buffer,
-		                                           &index_buffer,
-		                                           &transform,
-		                                           appearance.color,
-		                                           appearance.effect,
-		                                           &mut self.hdr_color,
-		                                           &mut self.depth);
+		self.queue(forward::Shader::Lines, v, Vec::new(), transform, appearance);
 	}
 
 	fn draw_debug_lines(&mut self, transform: &cgmath::Matrix4<f32>, vertices: &[Position], appearance: &Appearance) {
@@ -345,29 +512,13 @@ for ForwardRenderer<'e, 'l, R, C, F, L> {
 				}
 			})
 			.collect();
-		let (vertex_buffer, index_buffer) = self.factory.create_vertex_buffer_with_slice(v.as_slice(), ());
-
-		self.pass_forward_lighting.draw_primitives(forward::Shader::DebugLines,
-		                                           &mut self.encoder,
-		                                           vertex_buffer,
-		                                           &index_buffer,
-		                                           &transform,
-		                                           appearance.color,
-		                                           appearance.effect,
-		                                           &mut self.hdr_color,
-		                                           &mut self.depth);
+		self.queue(forward::Shader::DebugLines, v, Vec::new(), transform, appearance);
 	}
 
 	fn draw_ball(&mut self, transform: &cgmath::Matrix4<f32>, appearance: &Appearance) {
-		self.pass_forward_lighting.draw_primitives(forward::Shader::Ball,
-		                                           &mut self.encoder,
-		                                           self.base_vertices.clone(),
-		                                           &self.base_indices,
-		                                           &transform,
-		                                           appearance.color,
-		                                           appearance.effect,
-		                                           &mut self.hdr_color,
-		                                           &mut self.depth);
+		self.batches.push(MeshKey::Base(forward::Shader::Ball),
+		                  || (forward::Shader::Ball, Geometry::Base),
+		                  forward::Instance::new(transform, appearance.color, appearance.effect));
 	}
 
 	fn draw_quad(&mut self, transform: &cgmath::Matrix4<f32>, ratio: f32, appearance: &Appearance) {
@@ -396,17 +547,7 @@ for ForwardRenderer<'e, 'l, R, C, F, L> {
 			          tex_coord: [0.5 - ratio * 0.5, 1.0],
 		          }];
 
-		let vertex_buffer = self.factory.create_vertex_buffer(v);
-
-		self.pass_forward_lighting.draw_primitives(forward::Shader::Flat,
-		                                           &mut self.encoder,
-		                                           vertex_buffer,
-		                                           &self.quad_indices,
-		                                           &transform,
-		                                           appearance.color,
-		                                           appearance.effect,
-		                                           &mut self.hdr_color,
-		                                           &mut self.depth);
+		self.queue(forward::Shader::Flat, v.to_vec(), QUAD_INDICES.to_vec(), transform, appearance);
 	}
 
 	fn draw_triangle(&mut self, transform: &cgmath::Matrix4<f32>, p: &[Position], appearance: &Appearance) {
@@ -427,20 +568,23 @@ for ForwardRenderer<'e, 'l, R, C, F, L> {
 				          ..Vertex::default()
 			          }];
 
-			let (vertices, indices) = self.factory.create_vertex_buffer_with_slice(v, ());
-
-			self.pass_forward_lighting.draw_primitives(forward::Shader::Wireframe,
-			                                           &mut self.encoder,
-			                                           vertices,
-			                                           &indices,
-			                                           transform,
-			                                           appearance.color,
-			                                           appearance.effect,
-			                                           &mut self.hdr_color,
-			                                           &mut self.depth);
+			self.queue(forward::Shader::Wireframe, v.to_vec(), Vec::new(), transform, appearance);
 		}
 	}
 
+	fn draw_thick_lines(&mut self, space: LineSpace, lines: &[Line]) {
+		// over whatever was queued before them
+		self.flush();
+		let (w, h, _, _) = self.frame_buffer.get_dimensions();
+		self.pass_lines.draw_lines(&mut self.factory,
+		                           &mut self.encoder,
+		                           self.proj_view,
+		                           (w, h),
+		                           space,
+		                           lines,
+		                           &self.hdr_color);
+	}
+
 	fn draw_text(&mut self, text: &str, screen_position: [i32; 2], text_color: Rgba) {
 		self.text_renderer.add(&text, screen_position, text_color);
 		self.text_renderer.draw(&mut self.encoder, &mut self.frame_buffer).expect("Failed to write text");
@@ -449,38 +593,71 @@ for ForwardRenderer<'e, 'l, R, C, F, L> {
 
 impl<'e, 'l, R: gfx::Resources, C: 'e + gfx::CommandBuffer<R>, F: Factory<R>, L: ResourceLoader<u8>>
 	Renderer<R, C> for ForwardRenderer<'e, 'l, R, C, F, L> {
-	fn setup_frame(&mut self, camera: &Camera, background_color: Rgba, light_color: Rgba, light_position: &[Position]) {
+	fn setup_frame(&mut self, camera: &Camera, background_color: Rgba, light_color: Rgba,
+	               emitters: &[(usize, Position)]) {
 		self.background_color = background_color;
-// 		self.light_color = light_color;
-// 		self.light_position = light_position;
-		let mut lights: Vec<forward::PointLight> = Vec::new();
-
-		lights.push(forward::PointLight {
-			propagation: [0.3, 0.5, 0.4, 0.0],
-			center: [-15.0, -5.0, 1.0, 1.0],
-			color: [0.3, 0.0, 0.0, 1.0],
-		});
-		for p in light_position {
-			lights.push(forward::PointLight {
-				propagation: [0.2, 0.8, 0.1, 0.1],
-				center: [p.x, p.y, 2.0, 1.0],
-				color: light_color,
-			});
+		self.proj_view = camera.projection * camera.view;
+		let mut lights: Vec<(usize, forward::PointLight)> = Vec::new();
+
+		lights.push((0,
+		             forward::PointLight {
+			             propagation: [0.3, 0.5, 0.4, 0.0],
+			             center: [-15.0, -5.0, 1.0, 1.0],
+			             color: [0.3, 0.0, 0.0, 1.0],
+		             }));
+		for &(id, p) in emitters {
+			lights.push((id, emitter_light(p, light_color)));
 		}
 
-		self.pass_forward_lighting.setup(&mut self.encoder, camera.projection, camera.view, &lights);
+		self.pass_forward_lighting.setup(&mut self.encoder,
+		                                 camera.projection,
+		                                 camera.view,
+		                                 &camera.visible_area(),
+		                                 self.light_model,
+		                                 self.ambient,
+		                                 &self.directional_light,
+		                                 self.two_sided,
+		                                 &lights);
+	}
+
+	fn set_light_model(&mut self, light_model: [f32; 4]) {
+		self.light_model = light_model;
+	}
+
+	fn set_two_sided_lighting(&mut self, two_sided: bool) {
+		self.two_sided = two_sided;
+	}
+
+	fn set_ambient(&mut self, ambient: Rgba) {
+		self.ambient = ambient;
+	}
+
+	fn set_directional_light(&mut self, light: DirectionalLight) {
+		self.directional_light = light;
+	}
+
+	fn set_exposure(&mut self, exposure: f32) {
+		self.exposure = exposure.max(0.);
+	}
+
+	fn set_bloom(&mut self, intensity: Option<f32>) {
+		self.bloom = intensity;
 	}
 
 	fn begin_frame(&mut self) {
+		self.draw_calls = 0;
 		self.encoder.clear(&self.hdr_color, self.background_color);
 		self.encoder.clear_depth(&self.depth, 1.0f32);
 		self.encoder.clear(&self.frame_buffer, self.background_color);
 	}
 
 	fn resolve_frame_buffer(&mut self) {
+		self.flush();
 		self.pass_effects.apply_all(&mut self.encoder,
 		                            self.hdr_srv.clone(),
-		                            self.frame_buffer.clone());
+		                            self.frame_buffer.clone(),
+		                            self.exposure,
+		                            self.bloom);
 	}
 
 	fn end_frame<D: gfx::Device<Resources = R, CommandBuffer = C>>(&mut self, device: &mut D) {
@@ -490,4 +667,193 @@ impl<'e, 'l, R: gfx::Resources, C: 'e + gfx::CommandBuffer<R>, F: Factory<R>, L:
 	fn cleanup<D: gfx::Device<Resources = R, CommandBuffer = C>>(&mut self, device: &mut D) {
 		device.cleanup();
 	}
+
+	fn draw_calls(&self) -> usize {
+		self.draw_calls
+	}
+}
+
+/// The point light hanging over an emitter at `p`
+fn emitter_light(p: Position, color: Rgba) -> forward::PointLight {
+	forward::PointLight {
+		propagation: [0.2, 0.8, 0.1, 0.1],
+		center: [p.x, p.y, 2.0, 1.0],
+		color: color,
+	}
+}
+
+/// A vertex at `p`, `height` above the z=0 plane, facing `normal` with its tangent along x as near as it can
+fn surface_vertex(p: Position, height: f32, normal: cgmath::Vector3<f32>) -> Vertex {
+	let normal = normal.normalize();
+	let tangent = (cgmath::Vector3::unit_x() - normal * normal.x).normalize();
+	Vertex {
+		pos: [p.x, p.y, height],
+		normal: normal.into(),
+		tangent: tangent.into(),
+		tex_coord: [0.5 + p.x * 0.5, 0.5 + p.y * 0.5],
+	}
+}
+
+/// Lays out the faces of an outline for lighting. A flat outline is a single cap whose normals lean outwards
+/// towards the rim; one with some `depth` has an up-facing cap, inset and raised by `depth`, joined to the outline
+/// by a beveled rim.
+fn extrude(vertices: &[Position], normals: &[Position], indices: &[u16], depth: f32) -> (Vec<Vertex>, Vec<u16>) {
+	let rim = |p: Position, n: Position, bevel: f32| {
+		surface_vertex(p, 0., cgmath::Vector3::new(n.x * bevel, n.y * bevel, 1.))
+	};
+	if depth <= 0. {
+		let v = vertices.iter().zip(normals.iter()).map(|(p, n)| rim(*p, *n, FLAT_BEVEL)).collect::<Vec<_>>();
+		return (v, indices.to_vec());
+	}
+	let n = vertices.len();
+	let mut v = Vec::with_capacity(n * 2);
+	for (p, normal) in vertices.iter().zip(normals.iter()) {
+		v.push(surface_vertex(*p - *normal * depth, depth, cgmath::Vector3::unit_z()));
+	}
+	for (p, normal) in vertices.iter().zip(normals.iter()) {
+		v.push(rim(*p, *normal, 1.));
+	}
+	let mut i = indices.to_vec();
+	for k in 0..n {
+		let (cap0, cap1) = (k as u16, ((k + 1) % n) as u16);
+		let (rim0, rim1) = (cap0 + n as u16, cap1 + n as u16);
+		i.extend_from_slice(&[cap0, rim0, rim1, cap0, rim1, cap1]);
+	}
+	(v, i)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts;
+	use cgmath;
+	use cgmath::{EuclideanVector, Vector};
+	use core::geometry::{Position, Size};
+
+	fn square() -> (Vec<Position>, Vec<Position>, Vec<u16>) {
+		let vertices = vec![Position::new(-1., -1.),
+		                    Position::new(1., -1.),
+		                    Position::new(1., 1.),
+		                    Position::new(-1., 1.)];
+		let normals = vertices.iter().map(|v| v.normalize()).collect();
+		(vertices, normals, vec![0, 1, 2, 0, 2, 3])
+	}
+
+	#[test]
+	fn creatures_sharing_meshes_take_a_draw_call_per_mesh() {
+		let (vertices, normals, indices) = square();
+		let bodies = (1..4)
+			.map(|k| (k as u64, vertices.iter().map(|v| *v * k as f32).collect::<Vec<_>>()))
+			.collect::<Vec<_>>();
+		let mut batches = batch::Batches::new();
+		let mut draws = 0;
+		let mut extruded = 0;
+		// a ball for a head and one of three bodies each, two draws a creature when drawn one by one
+		for i in 0..500 {
+			let transform = M44::from_translation(cgmath::Vector3::new(i as f32, 0., 0.));
+			let instance = forward::Instance::new(&transform, [1.; 4], [0.; 4]);
+			batches.push(MeshKey::Base(forward::Shader::Ball),
+			             || (forward::Shader::Ball, Geometry::Base),
+			             instance);
+			let (key, ref scaled) = bodies[i % bodies.len()];
+			let mesh = || {
+				extruded += 1;
+				let (v, idx) = extrude(scaled, &normals, &indices, 0.1);
+				(forward::Shader::Wireframe, Geometry::Owned(v, idx))
+			};
+			batches.push(MeshKey::Shaped(forward::Shader::Wireframe, key), mesh, instance);
+			draws += 2;
+		}
+		assert_eq!(1000, draws);
+		// a body mesh is built the first time its key comes up, not once a creature
+		assert_eq!(3, extruded);
+		assert_eq!(4, batches.len());
+		let drained = batches.drain();
+		assert_eq!(500, drained[0].instances.len());
+		assert_eq!(1000, drained.iter().map(|b| b.instances.len()).sum::<usize>());
+	}
+
+	#[test]
+	fn flat_outlines_lean_towards_the_rim() {
+		let (vertices, normals, indices) = square();
+		let (v, i) = extrude(&vertices, &normals, &indices, 0.);
+		assert_eq!(indices, i);
+		for (vertex, p) in v.iter().zip(vertices.iter()) {
+			let normal = cgmath::Vector3::from(vertex.normal);
+			assert!((normal.length() - 1.).abs() < 1e-5);
+			assert!(normal.z > 0. && normal.x * p.x > 0. && normal.y * p.y > 0.);
+			assert!(normal.dot(cgmath::Vector3::from(vertex.tangent)).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn extruded_outlines_have_a_cap_and_a_rim() {
+		let (vertices, normals, indices) = square();
+		let (v, i) = extrude(&vertices, &normals, &indices, 0.2);
+		assert_eq!(8, v.len());
+		assert_eq!(indices.len() + 4 * 6, i.len());
+		assert!(i.iter().all(|&k| (k as usize) < v.len()));
+		for cap in &v[..4] {
+			assert_eq!([0., 0., 1.], cap.normal);
+			assert_eq!(0.2, cap.pos[2]);
+		}
+		for (rim, p) in v[4..].iter().zip(vertices.iter()) {
+			assert_eq!([p.x, p.y, 0.], rim.pos);
+			assert!(rim.normal[0] * p.x > 0. && rim.normal[1] * p.y > 0. && rim.normal[2] > 0.);
+		}
+	}
+
+	#[test]
+	fn point_lights_outshine_the_fill_near_emitters() {
+		// an emitter as lit in setup_frame, with the dimmest light color
+		let emitter = emitter_light(Position::new(0., 0.), [0.31, 0.31, 0.31, 0.5]);
+		let nearby = Rect::new(-5., -5., 5., 5.);
+		let brightest = |c: &Rgba| c[0].max(c[1]).max(c[2]);
+		let fill = brightest(&AMBIENT) + brightest(&DIRECTIONAL_LIGHT.color);
+		assert!(forward::light_contribution(&emitter, &nearby, LIGHT_MODEL) > fill);
+	}
+
+	#[test]
+	fn visible_area_is_the_viewport() {
+		let center = Position::new(-30., 12.);
+		for &projection in &[Projection::Orthographic, Projection::Perspective(consts::PI / 4.)] {
+			let area = Camera::for_viewport(projection, center, 100., 2.).visible_area();
+			assert!((area.max.x - area.min.x - 100.).abs() < 1e-2);
+			assert!((area.max.y - area.min.y - 50.).abs() < 1e-2);
+			assert!(((area.min + area.max) * 0.5 - center).length() < 1e-3);
+		}
+	}
+
+	#[test]
+	fn world_points_land_where_expected_on_screen() {
+		let viewport = Size::new(640., 480.);
+		let near = |a: Position, b: Position| (a - b).length() < 1e-2;
+		// 100 by 75 units around (-30, 12): a quarter of the way in from the right and top edges
+		for &projection in &[Projection::Orthographic, Projection::Perspective(consts::PI / 4.)] {
+			let camera = Camera::for_viewport(projection, Position::new(-30., 12.), 100., 640. / 480.);
+			assert!(near(Position::new(480., 120.), camera.project(Position::new(-5., 30.75), viewport)));
+			assert!(near(Position::new(0., 480.), camera.project(Position::new(-80., -25.5), viewport)));
+		}
+		let ortho = Camera::orthographic(&Rect::new(0., 0., 10., 10.), 10., -10.);
+		assert!(near(Position::new(640., 480.), ortho.project(Position::new(10., 0.), viewport)));
+		assert!(near(Position::new(160., 240.), ortho.project(Position::new(2.5, 5.), viewport)));
+		// a quarter turn of view 10 units up sees 10 units either side of its center
+		let perspective = Camera::perspective(consts::PI / 2., 1., 0.1, 100.).looking_at(Position::new(0., 0.), 10.);
+		assert!(near(Position::new(480., 120.), perspective.project(Position::new(5., 5.), viewport)));
+		assert!(near(Position::new(320., 480.), perspective.project(Position::new(0., -10.), viewport)));
+	}
+
+	#[test]
+	fn unproject_undoes_project() {
+		let viewport = Size::new(640., 480.);
+		let center = Position::new(-30., 12.);
+		for &projection in &[Projection::Orthographic, Projection::Perspective(consts::PI / 4.)] {
+			let camera = Camera::for_viewport(projection, center, 100., 640. / 480.);
+			assert!((camera.project(center, viewport) - Position::new(320., 240.)).length() < 1e-3);
+			for &world in &[center, Position::new(0., 0.), Position::new(10., -25.)] {
+				let screen = camera.project(world, viewport);
+				assert!((camera.unproject(screen, viewport) - world).length() < 1e-3);
+			}
+		}
+	}
 }
@@ -1,3 +1,4 @@
+#![cfg_attr(feature = "bench", feature(test))]
 mod app;
 mod core;
 mod frontend;
@@ -24,6 +25,7 @@ extern crate wrapped2d;
 #[macro_use]
 extern crate gfx;
 extern crate gfx_device_gl;
+extern crate gfx_gl;
 extern crate gfx_window_glutin;
 extern crate glutin;
 extern crate piston;
@@ -31,10 +33,15 @@ extern crate piston;
 extern crate rand;
 extern crate num;
 extern crate itertools;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(all(test, feature = "bench"))]
+extern crate test;
 
 #[macro_use]
 extern crate enum_primitive;
 extern crate gfx_text;
+extern crate image;
 
 extern crate rustc_serialize as serialize;
 